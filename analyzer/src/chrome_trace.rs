@@ -0,0 +1,291 @@
+// Export of a trace to the Chrome Trace Event format, which can be opened in Perfetto (ui.perfetto.dev) or chrome://tracing.
+// Every thread gets a track with its blocking operations (lock waits, condvar waits, barrier waits, notifies),
+// and a few "locks held" tracks below it with one slice per lock from its acquisition to the drop of its guard.
+// Flow arrows link a notify_one/notify_all to the Condvar::wait it wakes up, a channel send to the receive of its
+// message, and the end of a one-time initialization to the threads which waited for it.
+// Calls of traced functions (SOLCON_TRACE_CALLS) are slices on the track of their thread, around its operations.
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::trace::{format_addr, format_callsite, Addr, Event, EventKind, LockMode, LockOp, ThreadId};
+
+const PID: u64 = 1;
+
+/// Locks held by a thread are drawn on synthetic tracks, their tid is derived from the thread id and a lane number.
+fn lane_tid(thread: ThreadId, lane: usize) -> u64 {
+    (1 << 32) | (thread << 8) | lane as u64
+}
+
+struct Exporter {
+    start: u128,
+    end: u128,
+    out: Vec<Value>,
+}
+
+impl Exporter {
+    fn ts(&self, time: u128) -> f64 {
+        time.saturating_sub(self.start) as f64 / 1000.0
+    }
+
+    fn slice(&mut self, tid: u64, name: String, start: u128, end: u128, args: Value) {
+        self.out.push(json!({
+            "ph": "X", "pid": PID, "tid": tid, "name": name,
+            "ts": self.ts(start), "dur": end.saturating_sub(start) as f64 / 1000.0,
+            "args": args,
+        }));
+    }
+
+    fn instant(&mut self, tid: u64, name: String, time: u128, args: Value) {
+        self.out.push(json!({
+            "ph": "i", "s": "t", "pid": PID, "tid": tid, "name": name, "ts": self.ts(time), "args": args,
+        }));
+    }
+
+    fn metadata(&mut self, tid: u64, name: &str, args: Value) {
+        self.out.push(json!({ "ph": "M", "pid": PID, "tid": tid, "name": name, "args": args }));
+    }
+}
+
+struct OpenHold {
+    lane: usize,
+    mode: LockMode,
+    time: u128,
+    callsite: String,
+}
+
+#[derive(Default)]
+struct ThreadState {
+    name: Option<String>,
+    /// blocking operations in progress, by kind of operation and object
+    blocking: HashMap<(&'static str, Addr), (u128, String)>,
+    holds: HashMap<Addr, Vec<OpenHold>>,
+    busy_lanes: Vec<bool>,
+    /// traced functions entered and not left yet: (function, time of the entry)
+    calls: Vec<(String, u128)>,
+}
+
+impl ThreadState {
+    fn take_lane(&mut self) -> usize {
+        match self.busy_lanes.iter().position(|busy| !busy) {
+            Some(lane) => {
+                self.busy_lanes[lane] = true;
+                lane
+            }
+            None => {
+                self.busy_lanes.push(true);
+                self.busy_lanes.len() - 1
+            }
+        }
+    }
+}
+
+/// The blocking operation an event starts or finishes, with the object it is about.
+fn blocking_op(event: &Event) -> Option<(&'static str, bool)> {
+    use EventKind::*;
+    let op = match event.event {
+        MutexLockBefore | MutexLockAfter => ("Mutex::lock", event.event == MutexLockBefore),
+        MutexTryLockBefore | MutexTryLockAfter => ("Mutex::try_lock", event.event == MutexTryLockBefore),
+        RwLockReadBefore | RwLockReadAfter => ("RwLock::read", event.event == RwLockReadBefore),
+        RwLockTryReadBefore | RwLockTryReadAfter => ("RwLock::try_read", event.event == RwLockTryReadBefore),
+        RwLockWriteBefore | RwLockWriteAfter => ("RwLock::write", event.event == RwLockWriteBefore),
+        RwLockTryWriteBefore | RwLockTryWriteAfter => ("RwLock::try_write", event.event == RwLockTryWriteBefore),
+        BarrierWaitBefore | BarrierWaitAfter => ("Barrier::wait", event.event == BarrierWaitBefore),
+        CondvarWaitBefore | CondvarWaitAfter => ("Condvar::wait", event.event == CondvarWaitBefore),
+        CondvarWaitWhileBefore | CondvarWaitWhileAfter => ("Condvar::wait_while", event.event == CondvarWaitWhileBefore),
+        CondvarNotifyOneBefore | CondvarNotifyOneAfter => ("Condvar::notify_one", event.event == CondvarNotifyOneBefore),
+        CondvarNotifyAllBefore | CondvarNotifyAllAfter => ("Condvar::notify_all", event.event == CondvarNotifyAllBefore),
+        ChannelSendBefore | ChannelSendAfter => ("channel send", event.event == ChannelSendBefore),
+        ChannelRecvBefore | ChannelRecvAfter => ("channel recv", event.event == ChannelRecvBefore),
+        OnceInitBegin | OnceInitEnd => ("once init", event.event == OnceInitBegin),
+        // OnceObserved without a wait before it (already initialized) finishes nothing
+        OnceWait | OnceObserved => ("once wait", event.event == OnceWait),
+        _ => return None,
+    };
+    Some(op)
+}
+
+fn is_notify(kind: EventKind) -> bool {
+    matches!(kind, EventKind::CondvarNotifyOneBefore | EventKind::CondvarNotifyAllBefore)
+}
+
+fn is_condvar_wait_after(kind: EventKind) -> bool {
+    matches!(kind, EventKind::CondvarWaitAfter | EventKind::CondvarWaitWhileAfter)
+}
+
+pub fn export(events: &[Event], out_path: &Path) -> io::Result<()> {
+    let mut exporter = Exporter {
+        start: events.first().map(|e| e.time).unwrap_or_default(),
+        end: events.last().map(|e| e.time).unwrap_or_default(),
+        out: Vec::new(),
+    };
+    let mut threads: BTreeMap<ThreadId, ThreadState> = BTreeMap::new();
+    // latest notify seen on each condvar: (time, thread)
+    let mut last_notify: HashMap<Addr, (u128, ThreadId)> = HashMap::new();
+    // the send or the receive of a message seen first, by channel and message: (time, thread)
+    let mut sent: HashMap<(Addr, u64), (u128, ThreadId)> = HashMap::new();
+    let mut received: HashMap<(Addr, u64), (u128, ThreadId)> = HashMap::new();
+    // end of the initialization of each once cell: (time, thread)
+    let mut init_end: HashMap<Addr, (u128, ThreadId)> = HashMap::new();
+    let mut next_flow_id = 0u64;
+
+    for event in events {
+        let state = threads.entry(event.thread).or_default();
+        if state.name.is_none() {
+            state.name.clone_from(&event.thread_name);
+        }
+
+        if let (Some((op, is_start)), Some(addr)) = (blocking_op(event), event.addr) {
+            if is_start {
+                state.blocking.insert((op, addr), (event.time, event.callsite.clone()));
+            } else if let Some((start, callsite)) = state.blocking.remove(&(op, addr)) {
+                let mut args = json!({ "object": format_addr(Some(addr)), "callsite": format_callsite(&callsite) });
+                if let Some(lock) = event.lock {
+                    args["mutex"] = json!(format_addr(Some(lock)));
+                }
+                if let Some(ok) = event.ok {
+                    args["ok"] = json!(ok);
+                }
+                exporter.slice(event.thread, op.to_owned(), start, event.time, args);
+
+                if is_condvar_wait_after(event.event) && event.ok != Some(false) {
+                    // the wake up is attributed to the latest notify issued by another thread while we were waiting
+                    if let Some(&(notify_time, notifier)) = last_notify.get(&addr) {
+                        if notifier != event.thread && notify_time >= start && notify_time <= event.time {
+                            next_flow_id += 1;
+                            let flow = |ph: &str, tid: ThreadId, ts: f64| {
+                                json!({ "ph": ph, "bp": "e", "cat": "condvar", "name": "notify", "id": next_flow_id, "pid": PID, "tid": tid, "ts": ts })
+                            };
+                            let start_ts = exporter.ts(notify_time);
+                            // bind the arrow end inside the wait slice rather than on its boundary
+                            let finish_ts = (exporter.ts(event.time) - 0.001).max(exporter.ts(start));
+                            exporter.out.push(flow("s", notifier, start_ts));
+                            exporter.out.push(flow("f", event.thread, finish_ts));
+                        }
+                    }
+                }
+                if event.event == EventKind::OnceInitEnd {
+                    init_end.insert(addr, (event.time, event.thread));
+                }
+                if event.event == EventKind::OnceObserved {
+                    if let Some(&(end_time, initializer)) = init_end.get(&addr) {
+                        next_flow_id += 1;
+                        for (ph, tid, time) in [("s", initializer, end_time), ("f", event.thread, event.time)] {
+                            exporter.out.push(json!({ "ph": ph, "bp": "e", "cat": "once", "name": "initialized", "id": next_flow_id,
+                                "pid": PID, "tid": tid, "ts": exporter.ts(time) }));
+                        }
+                    }
+                }
+            }
+        }
+        if let (Some(channel), Some(msg)) = (event.addr, event.msg) {
+            let (seen, other) = match event.event {
+                EventKind::ChannelSendAfter => (&mut sent, &mut received),
+                _ => (&mut received, &mut sent),
+            };
+            match other.remove(&(channel, msg)) {
+                Some(other_end) => {
+                    let this_end = (event.time, event.thread);
+                    let ((send_time, sender), (recv_time, receiver)) = match event.event {
+                        EventKind::ChannelSendAfter => (this_end, other_end),
+                        _ => (other_end, this_end),
+                    };
+                    next_flow_id += 1;
+                    for (ph, tid, time) in [("s", sender, send_time), ("f", receiver, recv_time)] {
+                        exporter.out.push(json!({ "ph": ph, "bp": "e", "cat": "channel", "name": "message", "id": next_flow_id,
+                            "pid": PID, "tid": tid, "ts": exporter.ts(time) }));
+                    }
+                }
+                None => {
+                    seen.insert((channel, msg), (event.time, event.thread));
+                }
+            }
+        }
+        if is_notify(event.event) {
+            if let Some(addr) = event.addr {
+                last_notify.insert(addr, (event.time, event.thread));
+            }
+        }
+
+        if let Some(function) = &event.function {
+            match event.event {
+                EventKind::FnEnter => state.calls.push((function.clone(), event.time)),
+                EventKind::FnExit => {
+                    // calls above the returning function were left by unwinding
+                    if let Some(index) = state.calls.iter().rposition(|(called, _)| called == function) {
+                        for (called, start) in state.calls.drain(index..).rev() {
+                            exporter.slice(event.thread, called, start, event.time, json!({}));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for op in event.lock_ops() {
+            match op {
+                LockOp::Acquired { lock, mode, .. } => {
+                    let lane = state.take_lane();
+                    state.holds.entry(lock).or_default().push(OpenHold { lane, mode, time: event.time, callsite: event.callsite.clone() });
+                }
+                LockOp::Released { lock } => {
+                    let Some(hold) = state.holds.get_mut(&lock).and_then(Vec::pop) else {
+                        continue;
+                    };
+                    state.busy_lanes[hold.lane] = false;
+                    exporter.slice(lane_tid(event.thread, hold.lane), hold_name(lock, hold.mode), hold.time, event.time,
+                        json!({ "acquired at": format_callsite(&hold.callsite), "released at": format_callsite(&event.callsite) }));
+                }
+                LockOp::AcquireFailed { lock, .. } => {
+                    exporter.instant(event.thread, format!("try lock {} failed", format_addr(Some(lock))), event.time,
+                        json!({ "callsite": format_callsite(&event.callsite) }));
+                }
+                LockOp::Acquiring { .. } => {}
+            }
+        }
+    }
+
+    // operations still in progress at the end of the trace, e.g. a deadlock or guards leaked until exit
+    let end = exporter.end;
+    for (&thread, state) in &mut threads {
+        for ((op, addr), (start, callsite)) in state.blocking.drain() {
+            exporter.slice(thread, format!("{op} (unfinished)"), start, end,
+                json!({ "object": format_addr(Some(addr)), "callsite": format_callsite(&callsite) }));
+        }
+        for (function, start) in state.calls.drain(..).rev() {
+            exporter.slice(thread, format!("{function} (unfinished)"), start, end, json!({}));
+        }
+        for (lock, holds) in state.holds.drain() {
+            for hold in holds {
+                exporter.slice(lane_tid(thread, hold.lane), format!("{} (not released)", hold_name(lock, hold.mode)), hold.time, end,
+                    json!({ "acquired at": format_callsite(&hold.callsite) }));
+            }
+        }
+    }
+
+    exporter.metadata(0, "process_name", json!({ "name": "solcon trace" }));
+    for (&thread, state) in &threads {
+        let name = state.name.clone().unwrap_or_else(|| format!("thread {thread}"));
+        exporter.metadata(thread, "thread_name", json!({ "name": format!("{name} [{thread}]") }));
+        exporter.metadata(thread, "thread_sort_index", json!({ "sort_index": thread * 256 }));
+        for lane in 0..state.busy_lanes.len() {
+            let tid = lane_tid(thread, lane);
+            exporter.metadata(tid, "thread_name", json!({ "name": format!("{name} [{thread}] locks held #{lane}") }));
+            exporter.metadata(tid, "thread_sort_index", json!({ "sort_index": thread * 256 + 1 + lane as u64 }));
+        }
+    }
+
+    let trace = json!({ "traceEvents": exporter.out, "displayTimeUnit": "ns" });
+    let writer = BufWriter::new(File::create(out_path)?);
+    serde_json::to_writer(writer, &trace).map_err(io::Error::from)
+}
+
+fn hold_name(lock: Addr, mode: LockMode) -> String {
+    match mode {
+        LockMode::Exclusive => format!("hold {}", format_addr(Some(lock))),
+        LockMode::Shared => format!("hold {} (shared)", format_addr(Some(lock))),
+    }
+}
@@ -0,0 +1,147 @@
+// Per-lock contention statistics.
+// wait time: from the before-lock hook to the after-lock hook of the same thread.
+// hold time: from the after-lock hook to the drop of the guard (or the Condvar::wait releasing it).
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::trace::{format_addr, format_callsite, Addr, Event, LockOp, ThreadId};
+
+#[derive(Debug, Default, Clone)]
+pub struct LockStats {
+    pub first_callsite: String,
+    pub acquisitions: u64,
+    pub contended: u64,
+    pub failed_try_locks: u64,
+    pub total_wait_ns: u128,
+    pub max_wait_ns: u128,
+    pub total_hold_ns: u128,
+    pub max_hold_ns: u128,
+}
+
+#[derive(Debug, Default)]
+pub struct ContentionReport {
+    pub locks: BTreeMap<Addr, LockStats>,
+}
+
+pub fn analyze(events: &[Event], contended_threshold_ns: u128) -> ContentionReport {
+    let mut report = ContentionReport::default();
+    let mut acquiring: HashMap<(ThreadId, Addr), u128> = HashMap::new();
+    let mut holding: HashMap<(ThreadId, Addr), Vec<u128>> = HashMap::new();
+    for event in events {
+        for op in event.lock_ops() {
+            match op {
+                LockOp::Acquiring { lock, .. } => {
+                    acquiring.insert((event.thread, lock), event.time);
+                }
+                LockOp::Acquired { lock, .. } => {
+                    let stats = report.locks.entry(lock).or_default();
+                    if stats.first_callsite.is_empty() {
+                        stats.first_callsite = event.callsite.clone();
+                    }
+                    stats.acquisitions += 1;
+                    // reacquiring after Condvar::wait has no before-lock event
+                    if let Some(start) = acquiring.remove(&(event.thread, lock)) {
+                        let wait = event.time.saturating_sub(start);
+                        stats.total_wait_ns += wait;
+                        stats.max_wait_ns = stats.max_wait_ns.max(wait);
+                        if wait > contended_threshold_ns {
+                            stats.contended += 1;
+                        }
+                    }
+                    holding.entry((event.thread, lock)).or_default().push(event.time);
+                }
+                LockOp::AcquireFailed { lock, .. } => {
+                    acquiring.remove(&(event.thread, lock));
+                    let stats = report.locks.entry(lock).or_default();
+                    stats.contended += 1;
+                    stats.failed_try_locks += 1;
+                }
+                LockOp::Released { lock } => {
+                    let Some(start) = holding.get_mut(&(event.thread, lock)).and_then(Vec::pop) else {
+                        continue;
+                    };
+                    let hold = event.time.saturating_sub(start);
+                    let stats = report.locks.entry(lock).or_default();
+                    stats.total_hold_ns += hold;
+                    stats.max_hold_ns = stats.max_hold_ns.max(hold);
+                }
+            }
+        }
+    }
+    report
+}
+
+impl fmt::Display for ContentionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.locks.is_empty() {
+            return writeln!(f, "no lock acquisition recorded");
+        }
+        let mut locks: Vec<_> = self.locks.iter().collect();
+        locks.sort_by(|a, b| b.1.total_wait_ns.cmp(&a.1.total_wait_ns).then(a.0.cmp(b.0)));
+        writeln!(f, "{:<16} {:>8} {:>9} {:>14} {:>12} {:>14} {:>12}  first acquired at",
+            "lock", "acquired", "contended", "total wait ns", "max wait ns", "total hold ns", "max hold ns")?;
+        for (lock, stats) in locks {
+            writeln!(f, "{:<16} {:>8} {:>9} {:>14} {:>12} {:>14} {:>12}  {}",
+                format_addr(Some(*lock)), stats.acquisitions, stats.contended,
+                stats.total_wait_ns, stats.max_wait_ns, stats.total_hold_ns, stats.max_hold_ns,
+                format_callsite(&stats.first_callsite))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::tests::event_at;
+    use crate::trace::EventKind;
+
+    const LOCK: Addr = 0x10;
+
+    #[test]
+    fn wait_and_hold_times() {
+        let events = [
+            event_at(100, 1, EventKind::MutexLockBefore, LOCK),
+            event_at(110, 1, EventKind::MutexLockAfter, LOCK),
+            event_at(120, 2, EventKind::MutexLockBefore, LOCK),
+            event_at(150, 1, EventKind::MutexGuardDrop, LOCK),
+            event_at(160, 2, EventKind::MutexLockAfter, LOCK),
+            event_at(165, 2, EventKind::MutexGuardDrop, LOCK),
+        ];
+        let report = analyze(&events, 20);
+        let stats = &report.locks[&LOCK];
+        assert_eq!(stats.acquisitions, 2);
+        assert_eq!((stats.total_wait_ns, stats.max_wait_ns), (10 + 40, 40));
+        assert_eq!((stats.total_hold_ns, stats.max_hold_ns), (40 + 5, 40));
+        // only the wait of thread 2 is above the threshold
+        assert_eq!(stats.contended, 1);
+        assert_eq!(stats.first_callsite, events[1].callsite);
+    }
+
+    #[test]
+    fn failed_try_lock_is_contended() {
+        let events = [
+            event_at(1, 1, EventKind::MutexTryLockBefore, LOCK),
+            Event { ok: Some(false), ..event_at(2, 1, EventKind::MutexTryLockAfter, LOCK) },
+        ];
+        let stats = &analyze(&events, 1000).locks[&LOCK];
+        assert_eq!((stats.acquisitions, stats.contended, stats.failed_try_locks), (0, 1, 1));
+        assert_eq!(stats.total_wait_ns, 0);
+    }
+
+    #[test]
+    fn condvar_wait_ends_the_hold() {
+        let condvar = 0x20;
+        let events = [
+            event_at(0, 1, EventKind::MutexLockBefore, LOCK),
+            event_at(10, 1, EventKind::MutexLockAfter, LOCK),
+            Event { lock: Some(LOCK), ..event_at(30, 1, EventKind::CondvarWaitBefore, condvar) },
+            Event { lock: Some(LOCK), ..event_at(1000, 1, EventKind::CondvarWaitAfter, condvar) },
+            event_at(1005, 1, EventKind::MutexGuardDrop, LOCK),
+        ];
+        let stats = &analyze(&events, 1000).locks[&LOCK];
+        // the reacquisition after the wait has no wait time
+        assert_eq!((stats.acquisitions, stats.total_wait_ns), (2, 10));
+        assert_eq!((stats.total_hold_ns, stats.max_hold_ns), (20 + 5, 20));
+    }
+}
@@ -0,0 +1,304 @@
+// Heap blocks recorded by the TrackingAllocator of the monitor (alloc_tracking feature): the block an address belongs to at a
+// point of the trace, and accesses to freed blocks (use-after-free). A block stays freed until an allocation reuses
+// its memory. Allocations made before program_start or by the monitor are not in the trace, so an address outside
+// every known block is not reported.
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use crate::report::{self, SEPARATOR};
+use crate::suppress::Suppressions;
+use crate::trace::{format_addr, Addr, Event, EventKind, ThreadId};
+
+#[derive(Debug, Clone)]
+pub struct HeapBlock {
+    pub addr: Addr,
+    pub size: u64,
+    pub thread: ThreadId,
+    /// frames of the allocating code, empty without SOLCON_ALLOC_BACKTRACE
+    pub stack: Vec<String>,
+}
+
+impl HeapBlock {
+    /// end of the block, a block of 0 bytes still has its address
+    fn end(&self) -> Addr {
+        self.addr + (self.size as Addr).max(1)
+    }
+
+    fn contains(&self, addr: Addr) -> bool {
+        addr >= self.addr && addr < self.end()
+    }
+
+    /// e.g. "heap object of 64 bytes at 0x10+8, allocated by thread 1"
+    pub fn describe(&self, addr: Addr) -> String {
+        let mut description = format!("heap object of {} bytes at {}", self.size, format_addr(Some(self.addr)));
+        if addr != self.addr {
+            description.push_str(&format!("+{}", addr - self.addr));
+        }
+        description.push_str(&format!(", allocated by thread {}", self.thread));
+        description
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FreedBlock {
+    pub block: HeapBlock,
+    pub thread: ThreadId,
+    pub stack: Vec<String>,
+}
+
+/// Replays heap events to know the live and freed blocks at each point of the trace.
+#[derive(Debug, Default)]
+pub struct HeapState {
+    live: BTreeMap<Addr, HeapBlock>,
+    /// disjoint, an allocation removes the freed blocks it overlaps
+    freed: BTreeMap<Addr, FreedBlock>,
+}
+
+impl HeapState {
+    pub fn update(&mut self, event: &Event) {
+        let Some(addr) = event.addr else {
+            return;
+        };
+        match event.event {
+            EventKind::HeapAlloc => self.allocate(event, addr),
+            EventKind::HeapDealloc => self.free(event, addr),
+            EventKind::HeapRealloc => {
+                if let Some(old_addr) = event.old_addr {
+                    self.free(event, old_addr);
+                }
+                self.allocate(event, addr);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn live_block(&self, addr: Addr) -> Option<&HeapBlock> {
+        self.live.range(..=addr).next_back().map(|(_, block)| block).filter(|block| block.contains(addr))
+    }
+
+    pub fn freed_block(&self, addr: Addr) -> Option<&FreedBlock> {
+        self.freed.range(..=addr).next_back().map(|(_, freed)| freed).filter(|freed| freed.block.contains(addr))
+    }
+
+    fn allocate(&mut self, event: &Event, addr: Addr) {
+        let block = HeapBlock {
+            addr,
+            size: event.size.unwrap_or_default(),
+            thread: event.thread,
+            stack: event.stack.clone(),
+        };
+        let reused: Vec<Addr> = self.freed.range(..block.end()).rev()
+            .take_while(|(_, freed)| freed.block.end() > addr)
+            .map(|(start, _)| *start)
+            .collect();
+        for start in reused {
+            self.freed.remove(&start);
+        }
+        self.live.insert(addr, block);
+    }
+
+    fn free(&mut self, event: &Event, addr: Addr) {
+        // blocks allocated before tracking started are only known by their deallocation
+        let block = self.live.remove(&addr).unwrap_or(HeapBlock {
+            addr,
+            size: event.size.unwrap_or_default(),
+            thread: event.thread,
+            stack: Vec::new(),
+        });
+        self.freed.insert(addr, FreedBlock { block, thread: event.thread, stack: event.stack.clone() });
+    }
+}
+
+#[derive(Debug)]
+pub struct UseAfterFree {
+    pub addr: Addr,
+    pub thread: ThreadId,
+    pub is_write: bool,
+    pub callsite: String,
+    pub stack: Vec<String>,
+    pub freed: FreedBlock,
+}
+
+impl UseAfterFree {
+    fn suppression_candidates(&self) -> impl Iterator<Item = &str> {
+        report::stack_candidates(&self.stack, &self.callsite)
+            .chain(self.freed.block.stack.iter().map(String::as_str))
+            .chain(self.freed.stack.iter().map(String::as_str))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct UseAfterFreeReport {
+    pub has_heap_events: bool,
+    pub accesses: Vec<UseAfterFree>,
+    pub suppressed: usize,
+}
+
+/// The first access to each freed block.
+pub fn analyze(events: &[Event], suppressions: &Suppressions) -> UseAfterFreeReport {
+    let mut heap = HeapState::default();
+    let mut report = UseAfterFreeReport::default();
+    let mut reported: HashSet<Addr> = HashSet::new();
+    for event in events {
+        if event.event.is_heap() {
+            report.has_heap_events = true;
+            heap.update(event);
+            if event.event != EventKind::HeapDealloc {
+                // the memory is in use again
+                reported.retain(|start| heap.freed_block(*start).is_some());
+            }
+            continue;
+        }
+        if !event.event.is_mem_access() {
+            continue;
+        }
+        let Some(addr) = event.addr else {
+            continue;
+        };
+        let Some(freed) = heap.freed_block(addr) else {
+            continue;
+        };
+        if heap.live_block(addr).is_some() || !reported.insert(freed.block.addr) {
+            continue;
+        }
+        let access = UseAfterFree {
+            addr,
+            thread: event.thread,
+            is_write: matches!(event.event, EventKind::MemWrite | EventKind::MemAtomicWrite),
+            callsite: event.callsite.clone(),
+            stack: event.stack.clone(),
+            freed: freed.clone(),
+        };
+        if suppressions.is_suppressed("use-after-free", access.suppression_candidates()) {
+            report.suppressed += 1;
+        } else {
+            report.accesses.push(access);
+        }
+    }
+    report
+}
+
+impl fmt::Display for UseAfterFreeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.has_heap_events {
+            return writeln!(f, "no heap events in the trace (build the monitor with --features alloc_tracking_system, or wrap the global allocator of the program in TrackingAllocator)");
+        }
+        if self.accesses.is_empty() && self.suppressed == 0 {
+            return writeln!(f, "no use-after-free found");
+        }
+        for access in &self.accesses {
+            writeln!(f, "{SEPARATOR}")?;
+            writeln!(f, "WARNING: solcon: heap-use-after-free")?;
+            writeln!(f, "  {} at {} by thread {}:", if access.is_write { "Write" } else { "Read" },
+                format_addr(Some(access.addr)), access.thread)?;
+            report::write_stack(f, &access.stack, &access.callsite)?;
+            writeln!(f)?;
+            let block = &access.freed.block;
+            report::write_optional_stack(f, &format!("Location is {}", block.describe(access.addr)), &block.stack)?;
+            report::write_optional_stack(f, &format!("Freed by thread {}", access.freed.thread), &access.freed.stack)?;
+            writeln!(f)?;
+            report::write_summary(f, "heap-use-after-free", &report::location(&access.stack, &access.callsite))?;
+        }
+        report::write_count(f, self.accesses.len(), self.suppressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::tests::event_at;
+
+    fn alloc(time: u128, thread: ThreadId, addr: Addr, size: u64) -> Event {
+        Event { size: Some(size), ..event_at(time, thread, EventKind::HeapAlloc, addr) }
+    }
+
+    fn dealloc(time: u128, thread: ThreadId, addr: Addr) -> Event {
+        event_at(time, thread, EventKind::HeapDealloc, addr)
+    }
+
+    #[test]
+    fn access_to_freed_block() {
+        let events = [
+            alloc(1, 1, 0x100, 16),
+            event_at(2, 1, EventKind::MemWrite, 0x108),
+            dealloc(3, 2, 0x100),
+            event_at(4, 1, EventKind::MemRead, 0x108),
+            event_at(5, 1, EventKind::MemWrite, 0x100),
+        ];
+        let report = analyze(&events, &Suppressions::default());
+        assert!(report.has_heap_events);
+        // only the first access to the freed block
+        assert_eq!(report.accesses.len(), 1);
+        let access = &report.accesses[0];
+        assert_eq!((access.addr, access.thread, access.is_write), (0x108, 1, false));
+        assert_eq!((access.freed.block.addr, access.freed.block.size, access.freed.thread), (0x100, 16, 2));
+        assert_eq!(access.freed.block.describe(access.addr), "heap object of 16 bytes at 0x100+8, allocated by thread 1");
+    }
+
+    #[test]
+    fn access_outside_the_block_is_not_reported() {
+        let events = [alloc(1, 1, 0x100, 16), dealloc(2, 1, 0x100), event_at(3, 1, EventKind::MemRead, 0x110)];
+        assert!(analyze(&events, &Suppressions::default()).accesses.is_empty());
+    }
+
+    #[test]
+    fn reused_memory_is_not_freed() {
+        let events = [
+            alloc(1, 1, 0x100, 16),
+            dealloc(2, 1, 0x100),
+            alloc(3, 2, 0x108, 8),
+            event_at(4, 2, EventKind::MemWrite, 0x108),
+            // the rest of the old block is not freed memory anymore either
+            event_at(5, 2, EventKind::MemWrite, 0x100),
+        ];
+        assert!(analyze(&events, &Suppressions::default()).accesses.is_empty());
+    }
+
+    #[test]
+    fn block_freed_again_is_reported_again() {
+        let events = [
+            alloc(1, 1, 0x100, 16),
+            dealloc(2, 1, 0x100),
+            event_at(3, 1, EventKind::MemRead, 0x100),
+            alloc(4, 1, 0x100, 16),
+            dealloc(5, 1, 0x100),
+            event_at(6, 1, EventKind::MemRead, 0x100),
+        ];
+        assert_eq!(analyze(&events, &Suppressions::default()).accesses.len(), 2);
+    }
+
+    #[test]
+    fn realloc_frees_the_old_block() {
+        let events = [
+            alloc(1, 1, 0x100, 16),
+            Event { size: Some(64), old_addr: Some(0x100), ..event_at(2, 1, EventKind::HeapRealloc, 0x200) },
+            event_at(3, 1, EventKind::MemWrite, 0x230),
+            event_at(4, 1, EventKind::MemAtomicWrite, 0x100),
+        ];
+        let report = analyze(&events, &Suppressions::default());
+        assert_eq!(report.accesses.len(), 1);
+        assert_eq!((report.accesses[0].addr, report.accesses[0].is_write), (0x100, true));
+    }
+
+    #[test]
+    fn blocks_of_the_heap_state() {
+        let mut heap = HeapState::default();
+        for event in [alloc(1, 1, 0x100, 16), alloc(2, 1, 0x200, 0), dealloc(3, 1, 0x100)] {
+            heap.update(&event);
+        }
+        assert!(heap.live_block(0x100).is_none());
+        assert_eq!(heap.freed_block(0x10f).map(|freed| freed.block.addr), Some(0x100));
+        assert!(heap.freed_block(0x110).is_none());
+        // an empty block still has its address
+        assert_eq!(heap.live_block(0x200).map(|block| block.size), Some(0));
+        assert!(heap.live_block(0x201).is_none());
+    }
+
+    #[test]
+    fn without_heap_events() {
+        let events = [event_at(1, 1, EventKind::MemRead, 0x100)];
+        let report = analyze(&events, &Suppressions::default());
+        assert!(!report.has_heap_events);
+        assert!(report.to_string().contains("alloc_tracking"));
+    }
+}
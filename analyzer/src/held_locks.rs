@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+
+use crate::trace::{Addr, Event, LockMode, LockOp, ThreadId};
+
+#[derive(Debug, Clone)]
+pub struct HeldLock {
+    pub lock: Addr,
+    pub mode: LockMode,
+    pub callsite: String,
+    pub stack: Vec<String>,
+}
+
+/// Replays lock operations to know which locks every thread holds at each point of the trace.
+#[derive(Debug, Default)]
+pub struct HeldLocks {
+    held: HashMap<ThreadId, Vec<HeldLock>>,
+}
+
+impl HeldLocks {
+    pub fn held_by(&self, thread: ThreadId) -> &[HeldLock] {
+        self.held.get(&thread).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn update(&mut self, event: &Event) {
+        for op in event.lock_ops() {
+            match op {
+                LockOp::Acquired { lock, mode, .. } => {
+                    self.held.entry(event.thread).or_default().push(HeldLock {
+                        lock,
+                        mode,
+                        callsite: event.callsite.clone(),
+                        stack: event.stack.clone(),
+                    });
+                }
+                LockOp::Released { lock } => {
+                    if let Some(held) = self.held.get_mut(&event.thread) {
+                        // guards may be dropped in any order, release the most recent acquisition of this lock
+                        if let Some(pos) = held.iter().rposition(|h| h.lock == lock) {
+                            held.remove(pos);
+                        }
+                    }
+                }
+                LockOp::Acquiring { .. } | LockOp::AcquireFailed { .. } => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,338 @@
+// Lock-order (potential deadlock) detection, lockdep style:
+// whenever a thread attempts to acquire lock B while holding lock A, we add an edge A -> B to the lock-order graph.
+// A cycle in this graph whose edges come from more than one thread is a potential deadlock,
+// even if the bad interleaving did not happen in the recorded run.
+// One-time initializations (Once, OnceLock, LazyLock ...) are held like locks by their initializer, so a re-entrant
+// initialization is reported as a self deadlock and an initializer taking a lock can be part of a cycle.
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+use crate::held_locks::HeldLocks;
+use crate::report::{self, SEPARATOR};
+use crate::suppress::Suppressions;
+use crate::trace::{Addr, Event, LockMode, LockOp, ThreadId};
+
+/// Cycles longer than this are not reported, they are rare and expensive to enumerate.
+const MAX_CYCLE_LEN: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct EdgeExample {
+    pub thread: ThreadId,
+    /// where the already held lock was acquired
+    pub held_callsite: String,
+    pub held_stack: Vec<String>,
+    /// where the second lock is being acquired
+    pub acquire_callsite: String,
+    pub acquire_stack: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct Edge {
+    threads: BTreeSet<ThreadId>,
+    example: Option<EdgeExample>,
+}
+
+impl EdgeExample {
+    fn suppression_candidates(&self) -> impl Iterator<Item = &str> {
+        report::stack_candidates(&self.acquire_stack, &self.acquire_callsite)
+            .chain(report::stack_candidates(&self.held_stack, &self.held_callsite))
+    }
+}
+
+#[derive(Debug)]
+pub struct LockCycle {
+    pub locks: Vec<Addr>,
+    pub edges: Vec<EdgeExample>,
+}
+
+#[derive(Debug)]
+pub struct DoubleLock {
+    pub thread: ThreadId,
+    pub lock: Addr,
+    pub held_callsite: String,
+    pub held_stack: Vec<String>,
+    pub acquire_callsite: String,
+    pub acquire_stack: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct LockOrderReport {
+    pub cycles: Vec<LockCycle>,
+    pub double_locks: Vec<DoubleLock>,
+    /// names of the static locks
+    pub names: HashMap<Addr, String>,
+    pub suppressed: usize,
+}
+
+pub fn analyze(events: &[Event], suppressions: &Suppressions) -> LockOrderReport {
+    let mut held_locks = HeldLocks::default();
+    let mut graph: BTreeMap<Addr, BTreeMap<Addr, Edge>> = BTreeMap::new();
+    let mut report = LockOrderReport::default();
+    let mut reported_double_locks = HashSet::new();
+    for event in events {
+        if let (Some(addr), Some(name)) = (event.addr, &event.name) {
+            report.names.entry(addr).or_insert_with(|| name.clone());
+        }
+        for op in event.lock_ops() {
+            // try_lock never blocks, so it cannot take part in a deadlock
+            let LockOp::Acquiring { lock, mode, try_lock: false } = op else {
+                continue;
+            };
+            for held in held_locks.held_by(event.thread) {
+                if held.lock == lock {
+                    // re-acquiring a held lock blocks forever unless both are read locks
+                    let blocks = held.mode == LockMode::Exclusive || mode == LockMode::Exclusive;
+                    if blocks && reported_double_locks.insert((lock, event.callsite.clone())) {
+                        report.double_locks.push(DoubleLock {
+                            thread: event.thread,
+                            lock,
+                            held_callsite: held.callsite.clone(),
+                            held_stack: held.stack.clone(),
+                            acquire_callsite: event.callsite.clone(),
+                            acquire_stack: event.stack.clone(),
+                        });
+                    }
+                    continue;
+                }
+                let edge = graph.entry(held.lock).or_default().entry(lock).or_default();
+                edge.threads.insert(event.thread);
+                if edge.example.is_none() {
+                    edge.example = Some(EdgeExample {
+                        thread: event.thread,
+                        held_callsite: held.callsite.clone(),
+                        held_stack: held.stack.clone(),
+                        acquire_callsite: event.callsite.clone(),
+                        acquire_stack: event.stack.clone(),
+                    });
+                }
+            }
+        }
+        held_locks.update(event);
+    }
+    report.cycles = find_cycles(&graph);
+    let names = &report.names;
+    let lock_names = |locks: &[Addr]| -> Vec<&str> { locks.iter().filter_map(|lock| names.get(lock).map(String::as_str)).collect() };
+    let cycles = report.cycles.len();
+    report.cycles.retain(|cycle| {
+        let candidates = cycle.edges.iter().flat_map(EdgeExample::suppression_candidates).chain(lock_names(&cycle.locks));
+        !suppressions.is_suppressed("deadlock", candidates)
+    });
+    let double_locks = report.double_locks.len();
+    report.double_locks.retain(|double_lock| {
+        let candidates = report::stack_candidates(&double_lock.acquire_stack, &double_lock.acquire_callsite)
+            .chain(report::stack_candidates(&double_lock.held_stack, &double_lock.held_callsite))
+            .chain(lock_names(&[double_lock.lock]));
+        !suppressions.is_suppressed("deadlock", candidates)
+    });
+    report.suppressed = cycles - report.cycles.len() + double_locks - report.double_locks.len();
+    report
+}
+
+fn find_cycles(graph: &BTreeMap<Addr, BTreeMap<Addr, Edge>>) -> Vec<LockCycle> {
+    let mut cycles = Vec::new();
+    // enumerate every elementary cycle once, starting from its smallest lock
+    for &start in graph.keys() {
+        let mut path = vec![start];
+        search_cycles(graph, start, &mut path, &mut cycles);
+    }
+    cycles
+}
+
+fn search_cycles(graph: &BTreeMap<Addr, BTreeMap<Addr, Edge>>, start: Addr, path: &mut Vec<Addr>, cycles: &mut Vec<LockCycle>) {
+    let current = *path.last().unwrap();
+    let Some(successors) = graph.get(&current) else {
+        return;
+    };
+    for &next in successors.keys() {
+        if next == start {
+            if let Some(cycle) = build_cycle(graph, path) {
+                cycles.push(cycle);
+            }
+        } else if next > start && !path.contains(&next) && path.len() < MAX_CYCLE_LEN {
+            path.push(next);
+            search_cycles(graph, start, path, cycles);
+            path.pop();
+        }
+    }
+}
+
+fn build_cycle(graph: &BTreeMap<Addr, BTreeMap<Addr, Edge>>, path: &[Addr]) -> Option<LockCycle> {
+    let mut threads = BTreeSet::new();
+    let mut edges = Vec::new();
+    for (i, &from) in path.iter().enumerate() {
+        let to = path[(i + 1) % path.len()];
+        let edge = &graph[&from][&to];
+        threads.extend(edge.threads.iter().copied());
+        edges.push(edge.example.clone()?);
+    }
+    // a single thread taking locks in different orders at different times cannot deadlock with itself
+    if threads.len() < 2 {
+        return None;
+    }
+    Some(LockCycle { locks: path.to_vec(), edges })
+}
+
+impl fmt::Display for LockOrderReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.cycles.is_empty() && self.double_locks.is_empty() && self.suppressed == 0 {
+            return writeln!(f, "no lock-order problem found");
+        }
+        for cycle in &self.cycles {
+            let locks: Vec<_> = cycle.locks.iter().map(|l| report::format_object(*l, &self.names)).collect();
+            writeln!(f, "{SEPARATOR}")?;
+            writeln!(f, "WARNING: solcon: lock-order-inversion (potential deadlock)")?;
+            writeln!(f, "  Cycle in lock order graph: {} => {}", locks.join(" => "), locks[0])?;
+            for (j, edge) in cycle.edges.iter().enumerate() {
+                let from = cycle.locks[j];
+                let to = cycle.locks[(j + 1) % cycle.locks.len()];
+                writeln!(f)?;
+                writeln!(f, "  Lock {} acquired here while holding lock {} in thread {}:",
+                    report::format_object(to, &self.names), report::format_object(from, &self.names), edge.thread)?;
+                report::write_stack(f, &edge.acquire_stack, &edge.acquire_callsite)?;
+                writeln!(f, "  Lock {} previously acquired by the same thread here:", report::format_object(from, &self.names))?;
+                report::write_stack(f, &edge.held_stack, &edge.held_callsite)?;
+            }
+            writeln!(f)?;
+            let first = &cycle.edges[0];
+            report::write_summary(f, "lock-order-inversion (potential deadlock)",
+                &report::location(&first.acquire_stack, &first.acquire_callsite))?;
+        }
+        for double_lock in &self.double_locks {
+            let lock = report::format_object(double_lock.lock, &self.names);
+            writeln!(f, "{SEPARATOR}")?;
+            writeln!(f, "WARNING: solcon: double lock (self deadlock)")?;
+            writeln!(f, "  Lock {lock} acquired here by thread {} while already holding it:", double_lock.thread)?;
+            report::write_stack(f, &double_lock.acquire_stack, &double_lock.acquire_callsite)?;
+            writeln!(f, "  Lock {lock} previously acquired by the same thread here:")?;
+            report::write_stack(f, &double_lock.held_stack, &double_lock.held_callsite)?;
+            writeln!(f)?;
+            report::write_summary(f, "double lock (self deadlock)",
+                &report::location(&double_lock.acquire_stack, &double_lock.acquire_callsite))?;
+        }
+        report::write_count(f, self.cycles.len() + self.double_locks.len(), self.suppressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::tests::event_at;
+    use crate::trace::EventKind;
+
+    /// `thread` takes `locks` nested in this order, then releases them.
+    fn nested(time: &mut u128, thread: ThreadId, locks: &[Addr]) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut next = |kind, addr| {
+            *time += 1;
+            event_at(*time, thread, kind, addr)
+        };
+        for &lock in locks {
+            events.push(next(EventKind::MutexLockBefore, lock));
+            events.push(next(EventKind::MutexLockAfter, lock));
+        }
+        for &lock in locks.iter().rev() {
+            events.push(next(EventKind::MutexGuardDrop, lock));
+        }
+        events
+    }
+
+    /// Each thread takes two consecutive locks of `locks` in a ring, thread i takes locks[i] then locks[i + 1].
+    fn ring(locks: &[Addr]) -> Vec<Event> {
+        let mut time = 0;
+        (0..locks.len())
+            .flat_map(|i| nested(&mut time, i as ThreadId + 1, &[locks[i], locks[(i + 1) % locks.len()]]))
+            .collect()
+    }
+
+    #[test]
+    fn inversion_between_two_threads() {
+        let report = analyze(&ring(&[0x20, 0x10]), &Suppressions::default());
+        assert_eq!(report.cycles.len(), 1);
+        assert_eq!(report.cycles[0].locks, vec![0x10, 0x20]);
+        let threads: BTreeSet<ThreadId> = report.cycles[0].edges.iter().map(|edge| edge.thread).collect();
+        assert_eq!(threads, BTreeSet::from([1, 2]));
+        assert!(report.double_locks.is_empty());
+    }
+
+    #[test]
+    fn inversion_in_one_thread_is_not_a_deadlock() {
+        let mut time = 0;
+        let mut events = nested(&mut time, 1, &[0x10, 0x20]);
+        events.extend(nested(&mut time, 1, &[0x20, 0x10]));
+        let report = analyze(&events, &Suppressions::default());
+        assert!(report.cycles.is_empty());
+    }
+
+    #[test]
+    fn consistent_order_has_no_cycle() {
+        let mut time = 0;
+        let mut events = nested(&mut time, 1, &[0x10, 0x20, 0x30]);
+        events.extend(nested(&mut time, 2, &[0x10, 0x30]));
+        let report = analyze(&events, &Suppressions::default());
+        assert!(report.cycles.is_empty());
+    }
+
+    #[test]
+    fn cycles_up_to_the_maximum_length() {
+        let report = analyze(&ring(&[0x10, 0x20, 0x30]), &Suppressions::default());
+        assert_eq!(report.cycles.len(), 1);
+        assert_eq!(report.cycles[0].locks, vec![0x10, 0x20, 0x30]);
+        assert_eq!(report.cycles[0].edges.len(), 3);
+        let report = analyze(&ring(&[0x10, 0x20, 0x30, 0x40]), &Suppressions::default());
+        assert_eq!(report.cycles.len(), 1);
+        let report = analyze(&ring(&[0x10, 0x20, 0x30, 0x40, 0x50]), &Suppressions::default());
+        assert!(report.cycles.is_empty());
+    }
+
+    #[test]
+    fn try_lock_does_not_take_part_in_cycles() {
+        let mut time = 0;
+        let mut events = nested(&mut time, 1, &[0x10, 0x20]);
+        events.push(event_at(10, 2, EventKind::MutexLockBefore, 0x20));
+        events.push(event_at(11, 2, EventKind::MutexLockAfter, 0x20));
+        events.push(event_at(12, 2, EventKind::MutexTryLockBefore, 0x10));
+        events.push(Event { ok: Some(true), ..event_at(13, 2, EventKind::MutexTryLockAfter, 0x10) });
+        let report = analyze(&events, &Suppressions::default());
+        assert!(report.cycles.is_empty());
+    }
+
+    #[test]
+    fn double_lock() {
+        let events = [
+            event_at(1, 1, EventKind::MutexLockBefore, 0x10),
+            event_at(2, 1, EventKind::MutexLockAfter, 0x10),
+            event_at(3, 1, EventKind::MutexLockBefore, 0x10),
+        ];
+        let report = analyze(&events, &Suppressions::default());
+        assert_eq!(report.double_locks.len(), 1);
+        let double_lock = &report.double_locks[0];
+        assert_eq!((double_lock.thread, double_lock.lock), (1, 0x10));
+        assert_eq!(double_lock.held_callsite, events[1].callsite);
+        assert_eq!(double_lock.acquire_callsite, events[2].callsite);
+        assert!(report.cycles.is_empty());
+    }
+
+    #[test]
+    fn read_lock_twice_is_not_a_double_lock() {
+        let events = [
+            event_at(1, 1, EventKind::RwLockReadBefore, 0x10),
+            event_at(2, 1, EventKind::RwLockReadAfter, 0x10),
+            event_at(3, 1, EventKind::RwLockReadBefore, 0x10),
+            event_at(4, 1, EventKind::RwLockReadAfter, 0x10),
+            event_at(5, 1, EventKind::RwLockWriteBefore, 0x10),
+        ];
+        let report = analyze(&events[..4], &Suppressions::default());
+        assert!(report.double_locks.is_empty());
+        let report = analyze(&events, &Suppressions::default());
+        assert_eq!(report.double_locks.len(), 1);
+    }
+
+    #[test]
+    fn released_lock_is_not_a_double_lock() {
+        let mut time = 0;
+        let mut events = nested(&mut time, 1, &[0x10]);
+        events.extend(nested(&mut time, 1, &[0x10]));
+        let report = analyze(&events, &Suppressions::default());
+        assert!(report.double_locks.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+// solcon-analyze: offline analysis of traces recorded by the solcon monitor runtime (SOLCON_TRACE_FILE).
+// Keeping the detectors here keeps the monitor runtime lean,
+// and one recorded trace can be analysed again with other detectors without re-running the program.
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use suppress::Suppressions;
+
+mod trace;
+mod held_locks;
+mod lock_order;
+mod race;
+mod contention;
+mod timeline;
+mod shared_objects;
+mod heap;
+mod report;
+mod suppress;
+mod chrome_trace;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Detector {
+    LockOrder,
+    Race,
+    Contention,
+    Timeline,
+    SharedObjects,
+    UseAfterFree,
+}
+
+impl Detector {
+    const ALL: [Detector; 6] = [Detector::LockOrder, Detector::Race, Detector::UseAfterFree, Detector::Contention,
+        Detector::SharedObjects, Detector::Timeline];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lock-order" => Some(Detector::LockOrder),
+            "race" => Some(Detector::Race),
+            "contention" => Some(Detector::Contention),
+            "timeline" => Some(Detector::Timeline),
+            "shared-objects" => Some(Detector::SharedObjects),
+            "use-after-free" => Some(Detector::UseAfterFree),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Detector::LockOrder => "lock-order",
+            Detector::Race => "race",
+            Detector::Contention => "contention",
+            Detector::Timeline => "timeline",
+            Detector::SharedObjects => "shared-objects",
+            Detector::UseAfterFree => "use-after-free",
+        }
+    }
+}
+
+struct Options {
+    traces: Vec<PathBuf>,
+    detectors: Vec<Detector>,
+    contended_threshold_ns: u128,
+    timeline_with_mem: bool,
+    chrome_trace: Option<PathBuf>,
+    suppressions: Option<PathBuf>,
+    fail_on_warnings: bool,
+}
+
+const USAGE: &str = "\
+usage: solcon-analyze [OPTIONS] <TRACE>...
+
+Analyse traces recorded by the solcon monitor runtime (set SOLCON_TRACE_FILE when running the instrumented program).
+
+options:
+  -d, --detector <NAME>            run only the given detector, may be repeated.
+                                   one of: lock-order, race, use-after-free, contention, shared-objects, timeline
+                                   (default: all)
+      --contention-threshold <NS>  a lock acquisition waiting longer than NS nanoseconds counts as contended (default: 10000)
+      --timeline-mem               include memory accesses and heap allocations in the per-thread timelines
+      --chrome-trace <OUT>         write the trace to OUT in Chrome Trace Event format, for Perfetto or chrome://tracing.
+                                   only one trace may be given, detectors only run if requested with -d
+      --suppressions <FILE>        do not print the reports matched by FILE, only count them (default: SOLCON_SUPPRESSIONS)
+      --fail-on-warnings           exit with 1 if a race, deadlock or use-after-free is reported, suppressed ones do not count
+  -h, --help                       print this help";
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        traces: Vec::new(),
+        detectors: Vec::new(),
+        contended_threshold_ns: 10_000,
+        timeline_with_mem: false,
+        chrome_trace: None,
+        suppressions: std::env::var_os("SOLCON_SUPPRESSIONS").map(PathBuf::from),
+        fail_on_warnings: false,
+    };
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            "-d" | "--detector" => {
+                let name = args.next().ok_or("missing detector name")?;
+                let detector = Detector::from_name(&name).ok_or(format!("unknown detector {name}"))?;
+                options.detectors.push(detector);
+            }
+            "--contention-threshold" => {
+                let value = args.next().ok_or("missing contention threshold")?;
+                options.contended_threshold_ns = value.parse().map_err(|_| format!("invalid contention threshold {value}"))?;
+            }
+            "--timeline-mem" => options.timeline_with_mem = true,
+            "--chrome-trace" => {
+                let out = args.next().ok_or("missing chrome trace output path")?;
+                options.chrome_trace = Some(PathBuf::from(out));
+            }
+            "--suppressions" => {
+                let file = args.next().ok_or("missing suppressions file")?;
+                options.suppressions = Some(PathBuf::from(file));
+            }
+            "--fail-on-warnings" => options.fail_on_warnings = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}")),
+            _ => options.traces.push(PathBuf::from(arg)),
+        }
+    }
+    if options.traces.is_empty() {
+        return Err("no trace file given".to_owned());
+    }
+    if options.chrome_trace.is_some() && options.traces.len() > 1 {
+        return Err("--chrome-trace takes exactly one trace file".to_owned());
+    }
+    if options.detectors.is_empty() && options.chrome_trace.is_none() {
+        options.detectors = Detector::ALL.to_vec();
+    }
+    Ok(options)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let suppressions = match &options.suppressions {
+        Some(path) => match Suppressions::load(path) {
+            Ok(suppressions) => suppressions,
+            Err(err) => {
+                eprintln!("error: {err}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Suppressions::default(),
+    };
+    let mut result = ExitCode::SUCCESS;
+    let mut warnings = 0;
+    for path in &options.traces {
+        let events = match trace::load_trace(path) {
+            Ok(events) => events,
+            Err(err) => {
+                eprintln!("error: cannot read trace {}: {err}", path.display());
+                result = ExitCode::FAILURE;
+                continue;
+            }
+        };
+        if let Some(out) = &options.chrome_trace {
+            match chrome_trace::export(&events, out) {
+                Ok(()) => eprintln!("wrote chrome trace {}", out.display()),
+                Err(err) => {
+                    eprintln!("error: cannot write chrome trace {}: {err}", out.display());
+                    result = ExitCode::FAILURE;
+                }
+            }
+            if options.detectors.is_empty() {
+                continue;
+            }
+        }
+        println!("# trace {} ({} events)", path.display(), events.len());
+        for detector in &options.detectors {
+            println!("\n== {} ==", detector.name());
+            match detector {
+                Detector::LockOrder => {
+                    let report = lock_order::analyze(&events, &suppressions);
+                    warnings += report.cycles.len() + report.double_locks.len();
+                    print!("{report}");
+                }
+                Detector::Race => {
+                    let report = race::analyze(&events, &suppressions);
+                    warnings += report.races.len();
+                    print!("{report}");
+                }
+                Detector::Contention => print!("{}", contention::analyze(&events, options.contended_threshold_ns)),
+                Detector::Timeline => print!("{}", timeline::analyze(&events, options.timeline_with_mem)),
+                Detector::SharedObjects => print!("{}", shared_objects::analyze(&events)),
+                Detector::UseAfterFree => {
+                    let report = heap::analyze(&events, &suppressions);
+                    warnings += report.accesses.len();
+                    print!("{report}");
+                }
+            }
+        }
+    }
+    if suppressions.any_used() {
+        print!("\n# suppressions used\n{suppressions}");
+    }
+    if options.fail_on_warnings && warnings > 0 {
+        result = ExitCode::FAILURE;
+    }
+    result
+}
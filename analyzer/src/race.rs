@@ -0,0 +1,553 @@
+// Data race detection with the Eraser lockset algorithm.
+// Each address starts owned by the first thread touching it (initialization is not a race),
+// once shared between threads it must be consistently protected by at least one common lock.
+// Writes are only protected by exclusively held locks, reads by any held lock.
+// Atomic accesses never race. Channels, spawn and join order their threads with vector clocks: what a thread did
+// before sending the message msg=N happens before what the thread receiving msg=N does after it, what the parent did
+// before spawning a thread happens before the child, and what the child did happens before join returns. An address
+// handed over this way changes owner instead of becoming shared, and ordered accesses are not reported.
+// Happens-before through scoped threads, condvars and barriers is not modelled, so reports are potential races and
+// should be confirmed by the callsites.
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::heap::{HeapBlock, HeapState};
+use crate::held_locks::HeldLocks;
+use crate::report::{self, SEPARATOR};
+use crate::suppress::Suppressions;
+use crate::trace::{format_addr, Addr, Event, EventKind, LockMode, ThreadId};
+
+#[derive(Debug, Clone)]
+pub struct Access {
+    pub thread: ThreadId,
+    pub is_write: bool,
+    pub time: u128,
+    pub callsite: String,
+    pub stack: Vec<String>,
+    pub lockset: BTreeSet<Addr>,
+    /// clock of the thread at the access
+    pub clock: u64,
+}
+
+#[derive(Debug)]
+pub struct RacyPair {
+    pub addr: Addr,
+    pub previous: Access,
+    pub current: Access,
+    /// the heap block of the address at the current access, if the trace has heap events
+    pub heap: Option<HeapBlock>,
+    /// the static containing the address
+    pub name: Option<String>,
+}
+
+impl RacyPair {
+    fn suppression_candidates(&self) -> impl Iterator<Item = &str> {
+        report::stack_candidates(&self.current.stack, &self.current.callsite)
+            .chain(report::stack_candidates(&self.previous.stack, &self.previous.callsite))
+            .chain(self.heap.iter().flat_map(|block| block.stack.iter().map(String::as_str)))
+            .chain(self.name.as_deref())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RaceReport {
+    pub races: Vec<RacyPair>,
+    pub suppressed: usize,
+}
+
+#[derive(Debug)]
+enum State {
+    Exclusive(ThreadId),
+    Shared,
+    SharedModified,
+    /// a race was reported, do not report this address again
+    Reported,
+}
+
+#[derive(Debug)]
+struct Shadow {
+    state: State,
+    candidate_locks: Option<BTreeSet<Addr>>,
+    last_access_of_thread: HashMap<ThreadId, Access>,
+}
+
+/// The clocks of the other threads a thread has seen, missing threads are at 0.
+#[derive(Debug, Clone, Default)]
+struct VectorClock(HashMap<ThreadId, u64>);
+
+impl VectorClock {
+    fn get(&self, thread: ThreadId) -> u64 {
+        self.0.get(&thread).copied().unwrap_or(0)
+    }
+
+    fn join(&mut self, other: &VectorClock) {
+        for (&thread, &clock) in &other.0 {
+            let entry = self.0.entry(thread).or_insert(0);
+            *entry = (*entry).max(clock);
+        }
+    }
+
+    fn happened_before(&self, access: &Access) -> bool {
+        access.clock <= self.get(access.thread)
+    }
+}
+
+/// Indexes of the channel_send_before events of the successful sends, with the channel and msg of the send.
+/// The receive of a message may be recorded before the after-hook of its send, but never before its before-hook.
+fn numbered_sends(events: &[Event]) -> HashMap<usize, (Addr, u64)> {
+    let mut sending: HashMap<(ThreadId, Addr), usize> = HashMap::new();
+    let mut sends = HashMap::new();
+    for (index, event) in events.iter().enumerate() {
+        let Some(addr) = event.addr else {
+            continue;
+        };
+        match event.event {
+            EventKind::ChannelSendBefore => {
+                sending.insert((event.thread, addr), index);
+            }
+            EventKind::ChannelSendAfter => {
+                let before = sending.remove(&(event.thread, addr));
+                if let (Some(before), Some(msg), true) = (before, event.msg, event.ok != Some(false)) {
+                    sends.insert(before, (addr, msg));
+                }
+            }
+            _ => {}
+        }
+    }
+    sends
+}
+
+/// Indexes of the thread_spawn_before events, with the spawned thread named by the matching thread_spawn_after.
+/// The child may run before the after-hook of spawn is recorded, but never before its before-hook.
+fn spawned_threads(events: &[Event]) -> HashMap<usize, ThreadId> {
+    let mut spawning: HashMap<ThreadId, usize> = HashMap::new();
+    let mut spawns = HashMap::new();
+    for (index, event) in events.iter().enumerate() {
+        match event.event {
+            EventKind::ThreadSpawnBefore => {
+                spawning.insert(event.thread, index);
+            }
+            EventKind::ThreadSpawnAfter => {
+                if let (Some(before), Some(child)) = (spawning.remove(&event.thread), event.child) {
+                    spawns.insert(before, child);
+                }
+            }
+            _ => {}
+        }
+    }
+    spawns
+}
+
+pub fn analyze(events: &[Event], suppressions: &Suppressions) -> RaceReport {
+    let mut held_locks = HeldLocks::default();
+    let mut heap = HeapState::default();
+    let mut shadows: HashMap<Addr, Shadow> = HashMap::new();
+    let mut report = RaceReport::default();
+    let sends = numbered_sends(events);
+    let mut clocks: HashMap<ThreadId, VectorClock> = HashMap::new();
+    let mut sent: HashMap<(Addr, u64), VectorClock> = HashMap::new();
+    let spawns = spawned_threads(events);
+    let mut spawned: HashMap<ThreadId, VectorClock> = HashMap::new();
+    for (index, event) in events.iter().enumerate() {
+        held_locks.update(event);
+        heap.update(event);
+        // the child finished, join returns even if it panicked
+        let joined = match (event.event, event.child) {
+            (EventKind::ThreadJoinAfter, Some(child)) => clocks.get(&child).cloned(),
+            _ => None,
+        };
+        let clock = clocks.entry(event.thread).or_insert_with(|| {
+            // a spawned thread starts with what its parent did before spawning it
+            let mut clock = spawned.remove(&event.thread).unwrap_or_default();
+            clock.0.insert(event.thread, 1);
+            clock
+        });
+        if let Some(&message) = sends.get(&index) {
+            // release: later accesses of the sender are not ordered with the receiver
+            sent.insert(message, clock.clone());
+            *clock.0.entry(event.thread).or_insert(0) += 1;
+        }
+        if let Some(&child) = spawns.get(&index) {
+            spawned.insert(child, clock.clone());
+            *clock.0.entry(event.thread).or_insert(0) += 1;
+        }
+        if let Some(child) = joined {
+            clock.join(&child);
+        }
+        if let (EventKind::ChannelRecvAfter, Some(addr), Some(msg)) = (event.event, event.addr, event.msg) {
+            // acquire
+            if let Some(sender) = sent.remove(&(addr, msg)) {
+                clock.join(&sender);
+            }
+        }
+        let is_write = match event.event {
+            EventKind::MemRead => false,
+            EventKind::MemWrite => true,
+            _ => continue,
+        };
+        let Some(addr) = event.addr else {
+            continue;
+        };
+        let lockset: BTreeSet<Addr> = held_locks.held_by(event.thread).iter()
+            .filter(|h| !is_write || h.mode == LockMode::Exclusive)
+            .map(|h| h.lock)
+            .collect();
+        let access = Access {
+            thread: event.thread,
+            is_write,
+            time: event.time,
+            callsite: event.callsite.clone(),
+            stack: event.stack.clone(),
+            lockset,
+            clock: clock.get(event.thread),
+        };
+        let shadow = shadows.entry(addr).or_insert_with(|| Shadow {
+            state: State::Exclusive(event.thread),
+            candidate_locks: None,
+            last_access_of_thread: HashMap::new(),
+        });
+        if let State::Exclusive(owner) = shadow.state {
+            // handed over, e.g. sent through a channel
+            if owner != event.thread && shadow.last_access_of_thread.values().all(|previous| clock.happened_before(previous)) {
+                shadow.state = State::Exclusive(event.thread);
+            }
+        }
+        match shadow.state {
+            State::Reported => continue,
+            State::Exclusive(owner) if owner == event.thread => {}
+            State::Exclusive(_) | State::Shared | State::SharedModified => {
+                if is_write || matches!(shadow.state, State::SharedModified) {
+                    shadow.state = State::SharedModified;
+                } else {
+                    shadow.state = State::Shared;
+                }
+                let candidates = match shadow.candidate_locks.take() {
+                    None => access.lockset.clone(),
+                    Some(candidates) => candidates.intersection(&access.lockset).copied().collect(),
+                };
+                if matches!(shadow.state, State::SharedModified) && candidates.is_empty() {
+                    if let Some(previous) = find_conflicting_access(shadow, &access, clock) {
+                        let race = RacyPair {
+                            addr,
+                            previous: previous.clone(),
+                            current: access.clone(),
+                            heap: heap.live_block(addr).cloned(),
+                            name: event.name.clone(),
+                        };
+                        if suppressions.is_suppressed("race", race.suppression_candidates()) {
+                            report.suppressed += 1;
+                        } else {
+                            report.races.push(race);
+                        }
+                        shadow.state = State::Reported;
+                    }
+                }
+                shadow.candidate_locks = Some(candidates);
+            }
+        }
+        shadow.last_access_of_thread.insert(access.thread, access);
+    }
+    report
+}
+
+fn find_conflicting_access<'a>(shadow: &'a Shadow, access: &Access, clock: &VectorClock) -> Option<&'a Access> {
+    shadow.last_access_of_thread.values()
+        .filter(|prev| prev.thread != access.thread)
+        .filter(|prev| !clock.happened_before(prev))
+        .filter(|prev| prev.is_write || access.is_write)
+        .filter(|prev| prev.lockset.is_disjoint(&access.lockset))
+        .max_by_key(|prev| prev.time)
+}
+
+/// e.g. "Previous write at 0x10 by thread 2 (holding 0x20)"
+fn access_heading(access: &Access, addr: Addr, previous: bool) -> String {
+    let kind = match (previous, access.is_write) {
+        (false, true) => "Write",
+        (false, false) => "Read",
+        (true, true) => "Previous write",
+        (true, false) => "Previous read",
+    };
+    let locks: Vec<_> = access.lockset.iter().map(|l| format_addr(Some(*l))).collect();
+    let held = if locks.is_empty() { "no lock".to_owned() } else { locks.join(", ") };
+    format!("{kind} at {} by thread {} (holding {held})", format_addr(Some(addr)), access.thread)
+}
+
+impl fmt::Display for RaceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.races.is_empty() && self.suppressed == 0 {
+            return writeln!(f, "no data race found");
+        }
+        for race in &self.races {
+            writeln!(f, "{SEPARATOR}")?;
+            writeln!(f, "WARNING: solcon: data race (potential, lockset)")?;
+            writeln!(f, "  {}:", access_heading(&race.current, race.addr, false))?;
+            report::write_stack(f, &race.current.stack, &race.current.callsite)?;
+            writeln!(f)?;
+            writeln!(f, "  {}:", access_heading(&race.previous, race.addr, true))?;
+            report::write_stack(f, &race.previous.stack, &race.previous.callsite)?;
+            if let Some(block) = &race.heap {
+                writeln!(f)?;
+                report::write_optional_stack(f, &format!("Location is {}", block.describe(race.addr)), &block.stack)?;
+            } else if let Some(name) = &race.name {
+                writeln!(f)?;
+                writeln!(f, "  Location is static '{name}'")?;
+            }
+            writeln!(f)?;
+            report::write_summary(f, "data race", &report::location(&race.current.stack, &race.current.callsite))?;
+        }
+        report::write_count(f, self.races.len(), self.suppressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::tests::{event, event_at};
+
+    const DATA: Addr = 0x100;
+
+    fn write(time: u128, thread: ThreadId) -> Event {
+        event_at(time, thread, EventKind::MemWrite, DATA)
+    }
+
+    fn read(time: u128, thread: ThreadId) -> Event {
+        event_at(time, thread, EventKind::MemRead, DATA)
+    }
+
+    /// `access` with the mutex `lock` held around it.
+    fn locked(lock: Addr, access: Event) -> Vec<Event> {
+        let (time, thread) = (access.time, access.thread);
+        vec![
+            event_at(time * 10, thread, EventKind::MutexLockBefore, lock),
+            event_at(time * 10 + 1, thread, EventKind::MutexLockAfter, lock),
+            Event { time: time * 10 + 2, ..access },
+            event_at(time * 10 + 3, thread, EventKind::MutexGuardDrop, lock),
+        ]
+    }
+
+    #[test]
+    fn unprotected_writes_of_two_threads_race() {
+        let events = [write(1, 1), write(2, 2)];
+        let report = analyze(&events, &Suppressions::default());
+        assert_eq!(report.races.len(), 1);
+        let race = &report.races[0];
+        assert_eq!(race.addr, DATA);
+        assert_eq!((race.previous.thread, race.current.thread), (1, 2));
+        assert!(race.previous.is_write && race.current.is_write);
+    }
+
+    #[test]
+    fn accesses_of_one_thread_do_not_race() {
+        let events = [write(1, 1), read(2, 1), write(3, 1)];
+        assert!(analyze(&events, &Suppressions::default()).races.is_empty());
+    }
+
+    #[test]
+    fn shared_reads_do_not_race() {
+        let events = [write(1, 1), read(2, 2), read(3, 3), read(4, 1)];
+        assert!(analyze(&events, &Suppressions::default()).races.is_empty());
+    }
+
+    #[test]
+    fn common_lock_protects() {
+        let events: Vec<Event> = [write(1, 1), write(2, 2), read(3, 3)].into_iter().flat_map(|access| locked(0x10, access)).collect();
+        assert!(analyze(&events, &Suppressions::default()).races.is_empty());
+    }
+
+    #[test]
+    fn different_locks_do_not_protect() {
+        let mut events = locked(0x10, write(1, 1));
+        events.extend(locked(0x20, write(2, 2)));
+        // the first shared access sets the candidate locks
+        assert!(analyze(&events, &Suppressions::default()).races.is_empty());
+        events.extend(locked(0x10, write(3, 1)));
+        let report = analyze(&events, &Suppressions::default());
+        assert_eq!(report.races.len(), 1);
+        assert_eq!(report.races[0].previous.lockset, BTreeSet::from([0x20]));
+        assert_eq!(report.races[0].current.lockset, BTreeSet::from([0x10]));
+    }
+
+    #[test]
+    fn read_lock_does_not_protect_a_write() {
+        let read_locked = |time: u128, thread, access: Event| vec![
+            event_at(time * 10, thread, EventKind::RwLockReadBefore, 0x10),
+            event_at(time * 10 + 1, thread, EventKind::RwLockReadAfter, 0x10),
+            Event { time: time * 10 + 2, ..access },
+            event_at(time * 10 + 3, thread, EventKind::RwLockReadGuardDrop, 0x10),
+        ];
+        let mut events = read_locked(1, 1, read(1, 1));
+        events.extend(read_locked(2, 2, read(2, 2)));
+        assert!(analyze(&events, &Suppressions::default()).races.is_empty());
+        events.extend(read_locked(3, 3, write(3, 3)));
+        assert_eq!(analyze(&events, &Suppressions::default()).races.len(), 1);
+    }
+
+    #[test]
+    fn atomic_accesses_do_not_race() {
+        let events = [
+            event_at(1, 1, EventKind::MemAtomicWrite, DATA),
+            event_at(2, 2, EventKind::MemAtomicWrite, DATA),
+            event_at(3, 3, EventKind::MemAtomicRead, DATA),
+        ];
+        assert!(analyze(&events, &Suppressions::default()).races.is_empty());
+    }
+
+    #[test]
+    fn address_is_reported_once() {
+        let events = [write(1, 1), write(2, 2), write(3, 3), write(4, 1)];
+        assert_eq!(analyze(&events, &Suppressions::default()).races.len(), 1);
+    }
+
+    const CHANNEL: Addr = 0x30;
+
+    fn send(time: u128, thread: ThreadId, msg: u64) -> [Event; 2] {
+        [
+            event_at(time, thread, EventKind::ChannelSendBefore, CHANNEL),
+            Event { ok: Some(true), msg: Some(msg), ..event_at(time + 1, thread, EventKind::ChannelSendAfter, CHANNEL) },
+        ]
+    }
+
+    fn recv(time: u128, thread: ThreadId, msg: u64) -> [Event; 2] {
+        [
+            event_at(time, thread, EventKind::ChannelRecvBefore, CHANNEL),
+            Event { ok: Some(true), msg: Some(msg), ..event_at(time + 1, thread, EventKind::ChannelRecvAfter, CHANNEL) },
+        ]
+    }
+
+    fn sorted(mut events: Vec<Event>) -> Vec<Event> {
+        events.sort_by_key(|event| event.time);
+        events
+    }
+
+    #[test]
+    fn message_hands_the_data_over() {
+        let mut events = vec![write(1, 1)];
+        events.extend(send(10, 1, 0));
+        events.extend(recv(20, 2, 0));
+        events.extend([write(30, 2), read(31, 2)]);
+        assert!(analyze(&events, &Suppressions::default()).races.is_empty());
+    }
+
+    #[test]
+    fn receive_recorded_before_the_end_of_the_send() {
+        // the receiver got the message while the sender was still in send()
+        let [send_before, send_after] = send(10, 1, 0);
+        let send_after = Event { time: 25, ..send_after };
+        let mut events = vec![write(1, 1), send_before, send_after];
+        events.extend(recv(20, 2, 0));
+        events.push(write(22, 2));
+        assert!(analyze(&sorted(events), &Suppressions::default()).races.is_empty());
+    }
+
+    #[test]
+    fn producer_consumer_with_several_messages() {
+        let mut events = Vec::new();
+        for msg in 0..3 {
+            let (time, item) = (100 * (msg as u128 + 1), DATA + 0x10 * msg as Addr);
+            events.push(event_at(time, 1, EventKind::MemWrite, item));
+            events.extend(send(time + 10, 1, msg));
+            events.extend(recv(time + 20, 2, msg));
+            events.push(event_at(time + 30, 2, EventKind::MemWrite, item));
+        }
+        assert!(analyze(&events, &Suppressions::default()).races.is_empty());
+        // the producer reusing the first item after the consumer wrote it, nothing orders them
+        events.push(write(1000, 1));
+        assert_eq!(analyze(&events, &Suppressions::default()).races.len(), 1);
+    }
+
+    #[test]
+    fn access_after_the_send_races() {
+        let mut events = vec![write(1, 1)];
+        events.extend(send(10, 1, 0));
+        events.push(write(15, 1));
+        events.extend(recv(20, 2, 0));
+        events.push(write(30, 2));
+        let report = analyze(&events, &Suppressions::default());
+        assert_eq!(report.races.len(), 1);
+        assert_eq!(report.races[0].previous.time, 15);
+    }
+
+    #[test]
+    fn other_message_does_not_order() {
+        let mut events = vec![write(1, 1)];
+        events.extend(send(10, 1, 0));
+        events.extend(send(12, 3, 1));
+        events.extend(recv(20, 2, 1));
+        events.push(write(30, 2));
+        assert_eq!(analyze(&events, &Suppressions::default()).races.len(), 1);
+    }
+
+    #[test]
+    fn failed_send_does_not_order() {
+        let mut events = vec![write(1, 1), event_at(10, 1, EventKind::ChannelSendBefore, CHANNEL)];
+        events.push(Event { ok: Some(false), ..event_at(11, 1, EventKind::ChannelSendAfter, CHANNEL) });
+        events.push(Event { ok: Some(false), ..event_at(21, 2, EventKind::ChannelRecvAfter, CHANNEL) });
+        events.push(write(30, 2));
+        assert_eq!(analyze(&events, &Suppressions::default()).races.len(), 1);
+    }
+
+    fn spawn(time: u128, parent: ThreadId, child: ThreadId) -> [Event; 2] {
+        [
+            event(time, parent, EventKind::ThreadSpawnBefore),
+            Event { child: Some(child), ..event(time + 1, parent, EventKind::ThreadSpawnAfter) },
+        ]
+    }
+
+    fn join(time: u128, parent: ThreadId, child: ThreadId) -> [Event; 2] {
+        [
+            Event { child: Some(child), ..event(time, parent, EventKind::ThreadJoinBefore) },
+            Event { child: Some(child), ok: Some(true), ..event(time + 1, parent, EventKind::ThreadJoinAfter) },
+        ]
+    }
+
+    #[test]
+    fn spawn_and_join_hand_the_data_over() {
+        // v[0] = 1; let h = spawn(move || { v[0] += 1; v }); let mut v = h.join().unwrap(); v[0] += 1;
+        let mut events = vec![write(1, 1)];
+        events.extend(spawn(10, 1, 2));
+        events.extend([read(20, 2), write(21, 2)]);
+        events.extend(join(30, 1, 2));
+        events.extend([read(40, 1), write(41, 1)]);
+        assert!(analyze(&events, &Suppressions::default()).races.is_empty());
+    }
+
+    #[test]
+    fn child_recorded_before_the_end_of_the_spawn() {
+        let [spawn_before, spawn_after] = spawn(10, 1, 2);
+        let spawn_after = Event { time: 25, ..spawn_after };
+        let events = vec![write(1, 1), spawn_before, spawn_after, write(20, 2)];
+        assert!(analyze(&sorted(events), &Suppressions::default()).races.is_empty());
+    }
+
+    #[test]
+    fn access_while_the_child_runs_races() {
+        let mut events = vec![write(1, 1)];
+        events.extend(spawn(10, 1, 2));
+        events.push(write(15, 1));
+        events.push(write(20, 2));
+        events.extend(join(30, 1, 2));
+        let report = analyze(&events, &Suppressions::default());
+        assert_eq!(report.races.len(), 1);
+        assert_eq!((report.races[0].previous.time, report.races[0].current.time), (15, 20));
+    }
+
+    #[test]
+    fn unjoined_child_races_with_the_parent() {
+        let mut events = vec![write(1, 1)];
+        events.extend(spawn(10, 1, 2));
+        events.push(write(20, 2));
+        events.push(write(30, 1));
+        assert_eq!(analyze(&events, &Suppressions::default()).races.len(), 1);
+    }
+
+    #[test]
+    fn joining_another_thread_does_not_order() {
+        let mut events = vec![write(1, 1)];
+        events.extend(spawn(10, 1, 2));
+        events.extend(spawn(12, 1, 3));
+        events.push(write(20, 2));
+        events.extend(join(30, 1, 3));
+        events.push(write(40, 1));
+        assert_eq!(analyze(&events, &Suppressions::default()).races.len(), 1);
+    }
+}
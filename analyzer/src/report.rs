@@ -0,0 +1,66 @@
+// ThreadSanitizer-like layout of the reports: a WARNING line, one stack per access or lock acquisition involved, a
+// SUMMARY line with the innermost location, between separator lines. Stacks are only in the trace if the monitor
+// recorded them (SOLCON_BACKTRACE, SOLCON_ALLOC_BACKTRACE), otherwise the callsite stands in for the stack.
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::trace::{format_addr, format_callsite, Addr};
+
+pub const SEPARATOR: &str = "==================";
+
+/// `#N frame` lines, indented below a heading of the report.
+pub fn write_stack(f: &mut fmt::Formatter<'_>, stack: &[String], callsite: &str) -> fmt::Result {
+    if stack.is_empty() {
+        return writeln!(f, "    #0 {}", format_callsite(callsite));
+    }
+    for (i, frame) in stack.iter().enumerate() {
+        writeln!(f, "    #{i} {frame}")?;
+    }
+    Ok(())
+}
+
+/// A heading followed by the stack, alone if no stack was recorded (heap events have no callsite).
+pub fn write_optional_stack(f: &mut fmt::Formatter<'_>, heading: &str, stack: &[String]) -> fmt::Result {
+    if stack.is_empty() {
+        return writeln!(f, "  {heading}");
+    }
+    writeln!(f, "  {heading}:")?;
+    write_stack(f, stack, "")
+}
+
+/// "src/main.rs:10 in main" for a frame "main at src/main.rs:10", the callsite without a stack.
+pub fn location(stack: &[String], callsite: &str) -> String {
+    match stack.first() {
+        Some(frame) => match frame.rsplit_once(" at ") {
+            Some((function, file_line)) => format!("{file_line} in {function}"),
+            None => frame.clone(),
+        },
+        None => format_callsite(callsite).to_owned(),
+    }
+}
+
+/// e.g. "0x10 (my_crate::STATE)" for a static
+pub fn format_object(addr: Addr, names: &HashMap<Addr, String>) -> String {
+    match names.get(&addr) {
+        Some(name) => format!("{} ({name})", format_addr(Some(addr))),
+        None => format_addr(Some(addr)),
+    }
+}
+
+pub fn write_summary(f: &mut fmt::Formatter<'_>, kind: &str, location: &str) -> fmt::Result {
+    writeln!(f, "SUMMARY: solcon: {kind} {location}")?;
+    writeln!(f, "{SEPARATOR}")
+}
+
+pub fn write_count(f: &mut fmt::Formatter<'_>, warnings: usize, suppressed: usize) -> fmt::Result {
+    if suppressed > 0 {
+        writeln!(f, "solcon: reported {warnings} warning(s), {suppressed} suppressed")
+    } else {
+        writeln!(f, "solcon: reported {warnings} warning(s)")
+    }
+}
+
+/// The callsite and frames of a stack, matched against the suppressions.
+pub fn stack_candidates<'a>(stack: &'a [String], callsite: &'a str) -> impl Iterator<Item = &'a str> {
+    std::iter::once(callsite).chain(stack.iter().map(String::as_str))
+}
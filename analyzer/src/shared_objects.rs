@@ -0,0 +1,87 @@
+// Lifetimes of Arc/Rc allocations: where each one was created, which threads had a pointer to it (they cloned, upgraded,
+// dropped ... it) and where its last strong reference went away. Allocations seen in a single thread are only counted.
+// An address reused by a later allocation starts a new object.
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::trace::{format_addr, format_callsite, Addr, Event, EventKind, ThreadId};
+
+#[derive(Debug)]
+pub struct SharedObject {
+    pub addr: Addr,
+    /// thread and callsite of Arc::new / Rc::new, None if the allocation was created where we do not see it
+    pub created: Option<(ThreadId, String)>,
+    pub threads: BTreeSet<ThreadId>,
+    /// thread and callsite dropping the last strong reference
+    pub released: Option<(ThreadId, String)>,
+    /// strong count after the latest event
+    pub refs: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct SharedObjectsReport {
+    /// objects seen in more than one thread
+    pub objects: Vec<SharedObject>,
+    pub single_thread_objects: usize,
+}
+
+pub fn analyze(events: &[Event]) -> SharedObjectsReport {
+    let mut objects: Vec<SharedObject> = Vec::new();
+    let mut live: HashMap<Addr, usize> = HashMap::new();
+    for event in events {
+        // a failed upgrade or get_mut does not hold a reference
+        if !event.event.is_refcount() || event.ok == Some(false) {
+            continue;
+        }
+        let Some(addr) = event.addr else {
+            continue;
+        };
+        if event.event == EventKind::RefCountNew {
+            live.remove(&addr);
+        }
+        let index = *live.entry(addr).or_insert_with(|| {
+            objects.push(SharedObject { addr, created: None, threads: BTreeSet::new(), released: None, refs: None });
+            objects.len() - 1
+        });
+        let object = &mut objects[index];
+        if event.event == EventKind::RefCountNew {
+            object.created = Some((event.thread, event.callsite.clone()));
+        }
+        object.threads.insert(event.thread);
+        if event.refs.is_some() {
+            object.refs = event.refs;
+        }
+        if event.refs == Some(0) && matches!(event.event, EventKind::RefCountDrop | EventKind::RefCountTryUnwrap) {
+            object.released = Some((event.thread, event.callsite.clone()));
+            live.remove(&addr);
+        }
+    }
+    let (objects, single_thread_objects): (Vec<_>, Vec<_>) = objects.into_iter().partition(|object| object.threads.len() > 1);
+    SharedObjectsReport { objects, single_thread_objects: single_thread_objects.len() }
+}
+
+impl fmt::Display for SharedObjectsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.objects.is_empty() {
+            writeln!(f, "no Arc/Rc allocation used by more than one thread")?;
+        }
+        for object in &self.objects {
+            match &object.created {
+                Some((thread, callsite)) => writeln!(f, "shared object {} created by thread {thread} at {}",
+                    format_addr(Some(object.addr)), format_callsite(callsite))?,
+                None => writeln!(f, "shared object {} (created before or outside the instrumented code)", format_addr(Some(object.addr)))?,
+            }
+            let threads: Vec<_> = object.threads.iter().map(ThreadId::to_string).collect();
+            writeln!(f, "  used by threads {}", threads.join(", "))?;
+            match (&object.released, object.refs) {
+                (Some((thread, callsite)), _) => writeln!(f, "  last reference dropped by thread {thread} at {}", format_callsite(callsite))?,
+                (None, Some(refs)) => writeln!(f, "  still {refs} strong reference(s) at the end of the trace")?,
+                (None, None) => writeln!(f, "  still referenced at the end of the trace")?,
+            }
+        }
+        if self.single_thread_objects > 0 {
+            writeln!(f, "{} Arc/Rc allocation(s) used by a single thread not listed", self.single_thread_objects)?;
+        }
+        Ok(())
+    }
+}
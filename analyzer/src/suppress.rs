@@ -0,0 +1,159 @@
+// Suppressions of known or accepted reports, in the style of ThreadSanitizer suppression files, the same file as for the
+// monitor runtime (SOLCON_SUPPRESSIONS): one `<kind>:<pattern>` per line, lines starting with `#` are comments.
+// solcon-analyze uses the kinds race, deadlock (lock-order cycles and double locks) and use-after-free. A pattern
+// matches a callsite, a frame of a stack of the report ("function at file:line") or the name of a static involved,
+// anywhere in the string unless anchored with ^ or $, `*` matches any characters.
+use std::cell::Cell;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+struct Suppression {
+    kind: String,
+    pattern: String,
+    hits: Cell<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct Suppressions {
+    suppressions: Vec<Suppression>,
+}
+
+impl Suppressions {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|err| format!("cannot read suppressions {}: {err}", path.display()))?;
+        let mut suppressions = Vec::new();
+        for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            match line.split_once(':') {
+                Some((kind, pattern)) if !pattern.trim().is_empty() => suppressions.push(Suppression {
+                    kind: kind.trim().to_owned(),
+                    pattern: pattern.trim().to_owned(),
+                    hits: Cell::new(0),
+                }),
+                _ => return Err(format!("malformed suppression {line} in {}, expect <kind>:<pattern>", path.display())),
+            }
+        }
+        Ok(Suppressions { suppressions })
+    }
+
+    /// Whether a report of `kind` with these callsites, frames and names is suppressed, counts the hit if so.
+    pub fn is_suppressed<'a>(&self, kind: &str, candidates: impl IntoIterator<Item = &'a str>) -> bool {
+        let candidates: Vec<&str> = candidates.into_iter().filter(|candidate| !candidate.is_empty()).collect();
+        let matching = self.suppressions.iter()
+            .filter(|suppression| suppression.kind == kind)
+            .find(|suppression| candidates.iter().any(|candidate| matches(&suppression.pattern, candidate)));
+        match matching {
+            Some(suppression) => {
+                suppression.hits.set(suppression.hits.get() + 1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn any_used(&self) -> bool {
+        self.suppressions.iter().any(|suppression| suppression.hits.get() > 0)
+    }
+}
+
+/// How often each suppression was used.
+impl fmt::Display for Suppressions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for suppression in self.suppressions.iter().filter(|suppression| suppression.hits.get() > 0) {
+            writeln!(f, "  {} {}:{}", suppression.hits.get(), suppression.kind, suppression.pattern)?;
+        }
+        Ok(())
+    }
+}
+
+/// Glob match of `pattern` anywhere in `text`, ^ and $ anchor it at the start and end.
+fn matches(pattern: &str, text: &str) -> bool {
+    let (anchored_start, pattern) = match pattern.strip_prefix('^') {
+        Some(pattern) => (true, pattern),
+        None => (false, pattern),
+    };
+    let (anchored_end, pattern) = match pattern.strip_suffix('$') {
+        Some(pattern) => (true, pattern),
+        None => (false, pattern),
+    };
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let mut rest = text;
+    for (i, part) in parts.iter().enumerate() {
+        if i == 0 && anchored_start {
+            let Some(after) = rest.strip_prefix(part) else {
+                return false;
+            };
+            rest = after;
+        } else if i == parts.len() - 1 && anchored_end {
+            return rest.ends_with(part);
+        } else {
+            let Some(pos) = rest.find(part) else {
+                return false;
+            };
+            rest = &rest[pos + part.len()..];
+        }
+    }
+    // a single part anchored at both ends
+    !anchored_end || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unanchored_pattern_matches_anywhere() {
+        assert!(matches("worker", "app::worker::run at src/worker.rs:10"));
+        assert!(!matches("writer", "app::worker::run at src/worker.rs:10"));
+        assert!(matches("", "anything"));
+    }
+
+    #[test]
+    fn glob() {
+        assert!(matches("app::*::run", "app::worker::run at src/worker.rs:10"));
+        assert!(matches("src/*.rs:1*", "src/main.rs:12:5"));
+        assert!(!matches("app::*::stop", "app::worker::run at src/worker.rs:10"));
+        // the parts must appear in order
+        assert!(!matches("run*app", "app::worker::run"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(matches("^app::", "app::worker::run"));
+        assert!(!matches("^worker", "app::worker::run"));
+        assert!(matches("::run$", "app::worker::run"));
+        assert!(!matches("::worker$", "app::worker::run"));
+        assert!(matches("^app::worker::run$", "app::worker::run"));
+        assert!(!matches("^app::worker$", "app::worker::run"));
+        assert!(matches("^app*run$", "app::worker::run"));
+        assert!(!matches("^app*stop$", "app::worker::run"));
+        assert!(matches("^*$", ""));
+    }
+
+    #[test]
+    fn suppressions_by_kind() {
+        let suppressions = Suppressions {
+            suppressions: vec![Suppression { kind: "race".to_owned(), pattern: "^src/log.rs".to_owned(), hits: Cell::new(0) }],
+        };
+        assert!(!suppressions.is_suppressed("deadlock", ["src/log.rs:3:1"]));
+        assert!(!suppressions.is_suppressed("race", ["src/main.rs:3:1", ""]));
+        assert!(!suppressions.any_used());
+        assert!(suppressions.is_suppressed("race", ["src/main.rs:3:1", "src/log.rs:3:1"]));
+        assert!(suppressions.any_used());
+        assert_eq!(suppressions.to_string(), "  1 race:^src/log.rs\n");
+    }
+
+    #[test]
+    fn load_file() {
+        let path = std::env::temp_dir().join(format!("solcon_suppressions_{}.txt", std::process::id()));
+        std::fs::write(&path, "# known\n\nrace: src/log.rs \ndeadlock:^app::init$\n").unwrap();
+        let loaded = Suppressions::load(&path);
+        std::fs::write(&path, "race\n").unwrap();
+        let malformed = Suppressions::load(&path);
+        let _ = std::fs::remove_file(&path);
+        let suppressions = loaded.unwrap();
+        let entries: Vec<(&str, &str)> = suppressions.suppressions.iter().map(|s| (s.kind.as_str(), s.pattern.as_str())).collect();
+        assert_eq!(entries, vec![("race", "src/log.rs"), ("deadlock", "^app::init$")]);
+        assert!(malformed.is_err());
+    }
+}
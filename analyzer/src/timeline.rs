@@ -0,0 +1,132 @@
+// Per-thread timelines: the events of each thread in order, with time relative to the start of the trace.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::trace::{format_addr, format_callsite, Event, ThreadId};
+
+#[derive(Debug, Default)]
+pub struct Timeline {
+    pub start: u128,
+    pub threads: BTreeMap<ThreadId, (Option<String>, Vec<Event>)>,
+}
+
+pub fn analyze(events: &[Event], with_mem_accesses: bool) -> Timeline {
+    let mut timeline = Timeline {
+        start: events.first().map(|e| e.time).unwrap_or_default(),
+        ..Default::default()
+    };
+    for event in events {
+        if (event.event.is_mem_access() || event.event.is_heap()) && !with_mem_accesses {
+            continue;
+        }
+        let (name, thread_events) = timeline.threads.entry(event.thread).or_default();
+        if name.is_none() {
+            name.clone_from(&event.thread_name);
+        }
+        thread_events.push(event.clone());
+    }
+    timeline
+}
+
+impl fmt::Display for Timeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (thread, (name, events)) in &self.threads {
+            writeln!(f, "thread {thread} ({}):", name.as_deref().unwrap_or("unnamed"))?;
+            for event in events {
+                let offset_us = event.time.saturating_sub(self.start) as f64 / 1000.0;
+                write!(f, "  +{offset_us:>12.3}us  {:<24}", event.event.name())?;
+                if event.addr.is_some() {
+                    write!(f, " {}", format_addr(event.addr))?;
+                }
+                if event.lock.is_some() {
+                    write!(f, " lock={}", format_addr(event.lock))?;
+                }
+                if let Some(ok) = event.ok {
+                    write!(f, " ok={ok}")?;
+                }
+                if let Some(msg) = event.msg {
+                    write!(f, " msg={msg}")?;
+                }
+                if let Some(child) = event.child {
+                    write!(f, " thread={child}")?;
+                }
+                if let Some(refs) = event.refs {
+                    write!(f, " refs={refs}")?;
+                }
+                if let Some(size) = event.size {
+                    write!(f, " size={size}")?;
+                }
+                if event.old_addr.is_some() {
+                    write!(f, " from={}", format_addr(event.old_addr))?;
+                }
+                // call tracing events have the traced function instead of a callsite
+                if let Some(function) = &event.function {
+                    writeln!(f, "  {function}")?;
+                    continue;
+                }
+                // heap events have no callsite, only the stack of the allocating code
+                let callsite = match event.stack.first() {
+                    Some(frame) if event.callsite.is_empty() => frame,
+                    _ => &event.callsite,
+                };
+                writeln!(f, "  {}", format_callsite(callsite))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::tests::{event, event_at};
+    use crate::trace::EventKind;
+
+    fn events() -> Vec<Event> {
+        vec![
+            Event { thread_name: Some("main".to_owned()), ..event(1000, 1, EventKind::ProgramStart) },
+            event_at(1500, 2, EventKind::MutexLockBefore, 0x10),
+            event_at(2000, 1, EventKind::MemWrite, 0x100),
+            Event {
+                size: Some(8),
+                callsite: String::new(),
+                stack: vec!["app::spawn at src/main.rs:7".to_owned()],
+                ..event_at(2500, 2, EventKind::HeapAlloc, 0x200)
+            },
+            Event { msg: Some(0), ..event_at(3000, 2, EventKind::ChannelSendAfter, 0x30) },
+            Event { function: Some("app::work".to_owned()), ..event(3500, 1, EventKind::FnEnter) },
+        ]
+    }
+
+    #[test]
+    fn events_grouped_per_thread() {
+        let timeline = analyze(&events(), false);
+        assert_eq!(timeline.start, 1000);
+        let kinds = |thread| -> Vec<EventKind> { timeline.threads[&thread].1.iter().map(|event| event.event).collect() };
+        assert_eq!(kinds(1), vec![EventKind::ProgramStart, EventKind::FnEnter]);
+        assert_eq!(kinds(2), vec![EventKind::MutexLockBefore, EventKind::ChannelSendAfter]);
+        assert_eq!(timeline.threads[&1].0.as_deref(), Some("main"));
+        assert_eq!(timeline.threads[&2].0, None);
+    }
+
+    #[test]
+    fn memory_accesses_on_request() {
+        let timeline = analyze(&events(), true);
+        assert_eq!(timeline.threads[&1].1.len(), 3);
+        assert_eq!(timeline.threads[&2].1.len(), 3);
+    }
+
+    #[test]
+    fn display() {
+        let text = analyze(&events(), true).to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "thread 1 (main):");
+        assert!(lines[1].starts_with("  +       0.000us  program_start"));
+        assert!(lines[2].contains("mem_write") && lines[2].ends_with("0x100  src/main.rs:2000:1"));
+        assert!(lines[3].contains("fn_enter") && lines[3].ends_with("  app::work"));
+        assert_eq!(lines[4], "thread 2 (unnamed):");
+        // heap events show the innermost frame of the allocating code
+        assert!(lines[6].contains("heap_alloc") && lines[6].ends_with("0x200 size=8  app::spawn at src/main.rs:7"));
+        assert!(lines[7].contains("msg=0"));
+    }
+}
@@ -0,0 +1,358 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use serde::Deserialize;
+
+pub type ThreadId = u64;
+pub type Addr = usize;
+
+// keep in sync with this_is_our_monitor_function/src/trace.rs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    ProgramStart,
+    ProgramExit,
+    MutexLockBefore,
+    MutexLockAfter,
+    MutexTryLockBefore,
+    MutexTryLockAfter,
+    #[serde(rename = "mutexguard_drop")]
+    MutexGuardDrop,
+    #[serde(rename = "rwlock_read_before")]
+    RwLockReadBefore,
+    #[serde(rename = "rwlock_read_after")]
+    RwLockReadAfter,
+    #[serde(rename = "rwlock_try_read_before")]
+    RwLockTryReadBefore,
+    #[serde(rename = "rwlock_try_read_after")]
+    RwLockTryReadAfter,
+    #[serde(rename = "rwlock_readguard_drop")]
+    RwLockReadGuardDrop,
+    #[serde(rename = "rwlock_write_before")]
+    RwLockWriteBefore,
+    #[serde(rename = "rwlock_write_after")]
+    RwLockWriteAfter,
+    #[serde(rename = "rwlock_try_write_before")]
+    RwLockTryWriteBefore,
+    #[serde(rename = "rwlock_try_write_after")]
+    RwLockTryWriteAfter,
+    #[serde(rename = "rwlock_writeguard_drop")]
+    RwLockWriteGuardDrop,
+    BarrierWaitBefore,
+    BarrierWaitAfter,
+    CondvarWaitBefore,
+    CondvarWaitAfter,
+    CondvarWaitWhileBefore,
+    CondvarWaitWhileAfter,
+    CondvarNotifyOneBefore,
+    CondvarNotifyOneAfter,
+    CondvarNotifyAllBefore,
+    CondvarNotifyAllAfter,
+    ThreadSpawnBefore,
+    ThreadSpawnAfter,
+    ThreadJoinBefore,
+    ThreadJoinAfter,
+    ChannelSendBefore,
+    ChannelSendAfter,
+    ChannelRecvBefore,
+    ChannelRecvAfter,
+    OnceInitBegin,
+    OnceInitEnd,
+    OnceWait,
+    OnceObserved,
+    #[serde(rename = "refcount_new")]
+    RefCountNew,
+    #[serde(rename = "refcount_clone")]
+    RefCountClone,
+    #[serde(rename = "refcount_drop")]
+    RefCountDrop,
+    #[serde(rename = "refcount_try_unwrap")]
+    RefCountTryUnwrap,
+    #[serde(rename = "refcount_get_mut")]
+    RefCountGetMut,
+    #[serde(rename = "refcount_upgrade")]
+    RefCountUpgrade,
+    FnEnter,
+    FnExit,
+    HeapAlloc,
+    HeapDealloc,
+    HeapRealloc,
+    MemRead,
+    MemWrite,
+    MemAtomicRead,
+    MemAtomicWrite,
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::ProgramStart => "program_start",
+            EventKind::ProgramExit => "program_exit",
+            EventKind::MutexLockBefore => "mutex_lock_before",
+            EventKind::MutexLockAfter => "mutex_lock_after",
+            EventKind::MutexTryLockBefore => "mutex_try_lock_before",
+            EventKind::MutexTryLockAfter => "mutex_try_lock_after",
+            EventKind::MutexGuardDrop => "mutexguard_drop",
+            EventKind::RwLockReadBefore => "rwlock_read_before",
+            EventKind::RwLockReadAfter => "rwlock_read_after",
+            EventKind::RwLockTryReadBefore => "rwlock_try_read_before",
+            EventKind::RwLockTryReadAfter => "rwlock_try_read_after",
+            EventKind::RwLockReadGuardDrop => "rwlock_readguard_drop",
+            EventKind::RwLockWriteBefore => "rwlock_write_before",
+            EventKind::RwLockWriteAfter => "rwlock_write_after",
+            EventKind::RwLockTryWriteBefore => "rwlock_try_write_before",
+            EventKind::RwLockTryWriteAfter => "rwlock_try_write_after",
+            EventKind::RwLockWriteGuardDrop => "rwlock_writeguard_drop",
+            EventKind::BarrierWaitBefore => "barrier_wait_before",
+            EventKind::BarrierWaitAfter => "barrier_wait_after",
+            EventKind::CondvarWaitBefore => "condvar_wait_before",
+            EventKind::CondvarWaitAfter => "condvar_wait_after",
+            EventKind::CondvarWaitWhileBefore => "condvar_wait_while_before",
+            EventKind::CondvarWaitWhileAfter => "condvar_wait_while_after",
+            EventKind::CondvarNotifyOneBefore => "condvar_notify_one_before",
+            EventKind::CondvarNotifyOneAfter => "condvar_notify_one_after",
+            EventKind::CondvarNotifyAllBefore => "condvar_notify_all_before",
+            EventKind::CondvarNotifyAllAfter => "condvar_notify_all_after",
+            EventKind::ThreadSpawnBefore => "thread_spawn_before",
+            EventKind::ThreadSpawnAfter => "thread_spawn_after",
+            EventKind::ThreadJoinBefore => "thread_join_before",
+            EventKind::ThreadJoinAfter => "thread_join_after",
+            EventKind::ChannelSendBefore => "channel_send_before",
+            EventKind::ChannelSendAfter => "channel_send_after",
+            EventKind::ChannelRecvBefore => "channel_recv_before",
+            EventKind::ChannelRecvAfter => "channel_recv_after",
+            EventKind::OnceInitBegin => "once_init_begin",
+            EventKind::OnceInitEnd => "once_init_end",
+            EventKind::OnceWait => "once_wait",
+            EventKind::OnceObserved => "once_observed",
+            EventKind::RefCountNew => "refcount_new",
+            EventKind::RefCountClone => "refcount_clone",
+            EventKind::RefCountDrop => "refcount_drop",
+            EventKind::RefCountTryUnwrap => "refcount_try_unwrap",
+            EventKind::RefCountGetMut => "refcount_get_mut",
+            EventKind::RefCountUpgrade => "refcount_upgrade",
+            EventKind::FnEnter => "fn_enter",
+            EventKind::FnExit => "fn_exit",
+            EventKind::HeapAlloc => "heap_alloc",
+            EventKind::HeapDealloc => "heap_dealloc",
+            EventKind::HeapRealloc => "heap_realloc",
+            EventKind::MemRead => "mem_read",
+            EventKind::MemWrite => "mem_write",
+            EventKind::MemAtomicRead => "mem_atomic_read",
+            EventKind::MemAtomicWrite => "mem_atomic_write",
+        }
+    }
+
+    pub fn is_refcount(&self) -> bool {
+        matches!(self, EventKind::RefCountNew | EventKind::RefCountClone | EventKind::RefCountDrop
+            | EventKind::RefCountTryUnwrap | EventKind::RefCountGetMut | EventKind::RefCountUpgrade)
+    }
+
+    pub fn is_heap(&self) -> bool {
+        matches!(self, EventKind::HeapAlloc | EventKind::HeapDealloc | EventKind::HeapRealloc)
+    }
+
+    pub fn is_mem_access(&self) -> bool {
+        matches!(self, EventKind::MemRead | EventKind::MemWrite | EventKind::MemAtomicRead | EventKind::MemAtomicWrite)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Event {
+    pub time: u128,
+    pub thread: ThreadId,
+    #[serde(default)]
+    pub thread_name: Option<String>,
+    pub event: EventKind,
+    #[serde(default)]
+    pub callsite: String,
+    #[serde(default)]
+    pub addr: Option<Addr>,
+    #[serde(default)]
+    pub lock: Option<Addr>,
+    #[serde(default)]
+    pub ok: Option<bool>,
+    /// sequence number of a message sent or received on the channel `addr`
+    #[serde(default)]
+    pub msg: Option<u64>,
+    /// strong references to the Arc/Rc allocation `addr` left after the event
+    #[serde(default)]
+    pub refs: Option<u64>,
+    /// size in bytes of the heap block `addr`
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// previous address of a reallocated heap block
+    #[serde(default)]
+    pub old_addr: Option<Addr>,
+    /// frames of the code causing the event, innermost first, if the monitor recorded them
+    #[serde(default)]
+    pub stack: Vec<String>,
+    /// static containing `addr`, if the monitor named statics
+    #[serde(default)]
+    pub name: Option<String>,
+    /// def path of the traced function entered or left (SOLCON_TRACE_CALLS)
+    #[serde(default)]
+    pub function: Option<String>,
+    /// thread spawned or joined
+    #[serde(default)]
+    pub child: Option<ThreadId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockMode {
+    Exclusive, // Mutex or RwLock write
+    Shared, // RwLock read
+}
+
+/// What an event means for the set of locks held by its thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockOp {
+    /// a thread is going to acquire the lock and may block
+    Acquiring { lock: Addr, mode: LockMode, try_lock: bool },
+    Acquired { lock: Addr, mode: LockMode, try_lock: bool },
+    /// only for try_lock/try_read/try_write
+    AcquireFailed { lock: Addr, mode: LockMode },
+    Released { lock: Addr },
+}
+
+impl Event {
+    pub fn lock_ops(&self) -> Vec<LockOp> {
+        use EventKind::*;
+        let Some(addr) = self.addr else {
+            return vec![];
+        };
+        let (mode, try_lock) = match self.event {
+            MutexLockBefore | MutexLockAfter | RwLockWriteBefore | RwLockWriteAfter => (LockMode::Exclusive, false),
+            MutexTryLockBefore | MutexTryLockAfter | RwLockTryWriteBefore | RwLockTryWriteAfter => (LockMode::Exclusive, true),
+            RwLockReadBefore | RwLockReadAfter => (LockMode::Shared, false),
+            RwLockTryReadBefore | RwLockTryReadAfter => (LockMode::Shared, true),
+            MutexGuardDrop | RwLockReadGuardDrop | RwLockWriteGuardDrop => return vec![LockOp::Released { lock: addr }],
+            // Condvar::wait and wait_while release the mutex while waiting and reacquire it before returning
+            CondvarWaitBefore | CondvarWaitWhileBefore => return self.lock.map(|lock| LockOp::Released { lock }).into_iter().collect(),
+            CondvarWaitAfter | CondvarWaitWhileAfter => return self.lock.map(|lock| LockOp::Acquired { lock, mode: LockMode::Exclusive, try_lock: false }).into_iter().collect(),
+            // a one-time initialization is held like a lock by its initializer, other threads entering it wait for it,
+            // so re-entrant initialization is a double lock and initializers taking locks are part of lock-order cycles
+            OnceInitBegin => return vec![
+                LockOp::Acquiring { lock: addr, mode: LockMode::Exclusive, try_lock: false },
+                LockOp::Acquired { lock: addr, mode: LockMode::Exclusive, try_lock: false },
+            ],
+            OnceWait => return vec![LockOp::Acquiring { lock: addr, mode: LockMode::Exclusive, try_lock: false }],
+            OnceInitEnd => return vec![LockOp::Released { lock: addr }],
+            _ => return vec![],
+        };
+        let lock = addr;
+        match self.event {
+            MutexLockBefore | MutexTryLockBefore | RwLockReadBefore | RwLockTryReadBefore | RwLockWriteBefore | RwLockTryWriteBefore => {
+                vec![LockOp::Acquiring { lock, mode, try_lock }]
+            }
+            _ => {
+                // a poisoned lock is still acquired, so only try_* may fail
+                if try_lock && self.ok == Some(false) {
+                    vec![LockOp::AcquireFailed { lock, mode }]
+                } else {
+                    vec![LockOp::Acquired { lock, mode, try_lock }]
+                }
+            }
+        }
+    }
+}
+
+/// Load a trace written by the monitor runtime (one JSON object per line), ordered by time.
+pub fn load_trace(path: &Path) -> io::Result<Vec<Event>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut events = Vec::new();
+    for (lineno, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Event>(&line) {
+            Ok(event) => events.push(event),
+            Err(err) => {
+                // the last line may be truncated if the program was killed
+                eprintln!("warning: {}:{}: skip malformed event: {err}", path.display(), lineno + 1);
+            }
+        }
+    }
+    // events of different threads may be written slightly out of order
+    events.sort_by_key(|e| e.time);
+    Ok(events)
+}
+
+pub fn format_addr(addr: Option<Addr>) -> String {
+    match addr {
+        Some(addr) => format!("{addr:#x}"),
+        None => "<unknown>".to_owned(),
+    }
+}
+
+pub fn format_callsite(callsite: &str) -> &str {
+    if callsite.is_empty() {
+        "<unknown callsite>"
+    } else {
+        callsite
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// An event without any optional field, set them with `Event { addr: Some(..), ..event(..) }`.
+    pub(crate) fn event(time: u128, thread: ThreadId, kind: EventKind) -> Event {
+        Event {
+            time,
+            thread,
+            thread_name: None,
+            event: kind,
+            callsite: format!("src/main.rs:{time}:1"),
+            addr: None,
+            lock: None,
+            ok: None,
+            msg: None,
+            refs: None,
+            size: None,
+            old_addr: None,
+            stack: Vec::new(),
+            name: None,
+            function: None,
+            child: None,
+        }
+    }
+
+    /// An event on the lock, channel or memory at `addr`.
+    pub(crate) fn event_at(time: u128, thread: ThreadId, kind: EventKind, addr: Addr) -> Event {
+        Event { addr: Some(addr), ..event(time, thread, kind) }
+    }
+
+    #[test]
+    fn failed_try_lock_is_not_acquired() {
+        let failed = Event { ok: Some(false), ..event_at(1, 1, EventKind::MutexTryLockAfter, 0x10) };
+        assert_eq!(failed.lock_ops(), vec![LockOp::AcquireFailed { lock: 0x10, mode: LockMode::Exclusive }]);
+        let poisoned = Event { ok: Some(false), ..event_at(2, 1, EventKind::MutexLockAfter, 0x10) };
+        assert_eq!(poisoned.lock_ops(), vec![LockOp::Acquired { lock: 0x10, mode: LockMode::Exclusive, try_lock: false }]);
+    }
+
+    #[test]
+    fn condvar_wait_releases_and_reacquires_the_mutex() {
+        let waits = [
+            (EventKind::CondvarWaitBefore, EventKind::CondvarWaitAfter),
+            (EventKind::CondvarWaitWhileBefore, EventKind::CondvarWaitWhileAfter),
+        ];
+        for (before, after) in waits {
+            let before = Event { lock: Some(0x20), ..event_at(1, 1, before, 0x10) };
+            assert_eq!(before.lock_ops(), vec![LockOp::Released { lock: 0x20 }]);
+            let after = Event { lock: Some(0x20), ..event_at(2, 1, after, 0x10) };
+            assert_eq!(after.lock_ops(), vec![LockOp::Acquired { lock: 0x20, mode: LockMode::Exclusive, try_lock: false }]);
+        }
+    }
+
+    #[test]
+    fn parse_trace_line() {
+        let line = r#"{"time":5,"thread":2,"thread_name":"worker","event":"rwlock_read_before","callsite":"src/main.rs:3:5","addr":16}"#;
+        let event: Event = serde_json::from_str(line).unwrap();
+        assert_eq!(event.event, EventKind::RwLockReadBefore);
+        assert_eq!(event.thread_name.as_deref(), Some("worker"));
+        assert_eq!(event.lock_ops(), vec![LockOp::Acquiring { lock: 16, mode: LockMode::Shared, try_lock: false }]);
+    }
+}
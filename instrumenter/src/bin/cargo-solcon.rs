@@ -0,0 +1,77 @@
+// `cargo solcon`: run cargo with solcon_instrumenter as RUSTC_WRAPPER.
+//   cargo solcon check [CARGO ARGS]  static lock-order analysis only (SOLCON_STATIC_CHECK=only), nothing is instrumented
+//   cargo solcon build [CARGO ARGS]  build an instrumented program, same as setting RUSTC_WRAPPER by hand
+// solcon_instrumenter is expected next to this executable (both are installed by `cargo install --path .`).
+use std::env;
+use std::path::PathBuf;
+use std::process::{Command, ExitCode};
+
+const USAGE: &str = "\
+usage: cargo solcon <check|build> [CARGO ARGS]...
+
+  check   report potential deadlocks found by the static lock-order analysis, without instrumenting
+  build   build the program instrumented with the solcon monitors (SOLCON_MONITOR_LIB_PATH must be set)";
+
+fn find_sysroot() -> Option<String> {
+    if let Some(sysroot) = option_env!("RUST_SYSROOT") {
+        return Some(sysroot.to_owned());
+    }
+    let home = option_env!("RUSTUP_HOME");
+    let toolchain = option_env!("RUSTUP_TOOLCHAIN");
+    if let (Some(home), Some(toolchain)) = (home, toolchain) {
+        return Some(format!("{home}/toolchains/{toolchain}"));
+    }
+    None
+}
+
+fn main() -> ExitCode {
+    // cargo runs us as `cargo-solcon solcon <subcommand> ...`
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("solcon") {
+        args.remove(0);
+    }
+    let Some(subcommand) = args.first().cloned() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    let static_check = match subcommand.as_str() {
+        "check" => "only".to_owned(),
+        "build" => env::var("SOLCON_STATIC_CHECK").unwrap_or_else(|_| "off".to_owned()),
+        "-h" | "--help" => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        _ => {
+            eprintln!("error: unknown subcommand {subcommand}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let instrumenter: PathBuf = match env::current_exe() {
+        Ok(exe) => exe.with_file_name(format!("solcon_instrumenter{}", env::consts::EXE_SUFFIX)),
+        Err(err) => {
+            eprintln!("error: cannot locate solcon_instrumenter: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut cargo = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned()));
+    cargo.args(&args)
+        .env("RUSTC_WRAPPER", &instrumenter)
+        .env("SOLCON_STATIC_CHECK", static_check);
+    // solcon_instrumenter links to rustc_driver of the toolchain it was built with
+    if let Some(sysroot) = find_sysroot() {
+        let lib = format!("{sysroot}/lib");
+        let library_path = match env::var("LD_LIBRARY_PATH") {
+            Ok(path) if !path.is_empty() => format!("{path}:{lib}"),
+            _ => lib,
+        };
+        cargo.env("LD_LIBRARY_PATH", library_path);
+    }
+    match cargo.status() {
+        Ok(status) if status.success() => ExitCode::SUCCESS,
+        Ok(_) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("error: cannot run cargo: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
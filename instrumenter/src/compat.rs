@@ -0,0 +1,166 @@
+// The rustc internals whose API changes between nightlies, so that a toolchain bump (rust-toolchain.toml) only
+// touches this module: renamed or moved items are re-exported here and changed signatures are wrapped.
+// Built against nightly-2026-05-20.
+mod mir_patch;
+
+use std::io::{self, Write};
+
+use rustc_driver::Compilation;
+use rustc_middle::mir::{self, BasicBlock, BasicBlockData, Body, ConstValue, Operand, Place, RawPtrKind, Rvalue, Statement, Terminator, WithRetag};
+use rustc_middle::mono::CodegenUnit;
+use rustc_middle::ty::{self, GenericArgsRef, Instance, Ty, TyCtxt, Unnormalized};
+use rustc_middle::util::Providers;
+use rustc_session::config::Input;
+use rustc_span::def_id::{DefId, LocalDefId};
+use rustc_span::fatal_error::FatalError;
+use rustc_span::{Span, Symbol};
+
+pub(crate) use mir_patch::MirPatch;
+pub(crate) use rustc_const_eval::util::sub_types;
+pub(crate) use rustc_index::bit_set::DenseBitSet as BitSet;
+pub(crate) use rustc_middle::mono::MonoItem;
+pub(crate) use rustc_middle::ty::TypingEnv;
+pub(crate) use rustc_span::Spanned;
+
+/// Our driver callbacks. rustc passed `Queries` to enter the TyCtxt before, now it passes the TyCtxt itself.
+pub(crate) trait DriverCallbacks {
+    fn config(&mut self, config: &mut rustc_interface::interface::Config);
+    fn after_expansion(&mut self, tcx: TyCtxt<'_>) -> Compilation;
+    fn after_analysis(&mut self, tcx: TyCtxt<'_>) -> Compilation;
+}
+
+struct Driver<'a, C>(&'a mut C);
+
+impl<C: DriverCallbacks + Send> rustc_driver::Callbacks for Driver<'_, C> {
+    fn config(&mut self, config: &mut rustc_interface::interface::Config) {
+        self.0.config(config)
+    }
+
+    fn after_expansion<'tcx>(&mut self, _compiler: &rustc_interface::interface::Compiler, tcx: TyCtxt<'tcx>) -> Compilation {
+        self.0.after_expansion(tcx)
+    }
+
+    fn after_analysis<'tcx>(&mut self, _compiler: &rustc_interface::interface::Compiler, tcx: TyCtxt<'tcx>) -> Compilation {
+        self.0.after_analysis(tcx)
+    }
+}
+
+/// Run rustc with our callbacks, Err if the compilation failed.
+pub(crate) fn run_compiler(args: &[String], callbacks: &mut (impl DriverCallbacks + Send)) -> Result<(), FatalError> {
+    rustc_driver::catch_fatal_errors(|| rustc_driver::run_compiler(args, &mut Driver(callbacks)))
+}
+
+/// The file name of the input, as shown in diagnostics.
+pub(crate) fn input_name(input: &Input) -> String {
+    match input {
+        Input::File(path) => path.display().to_string(),
+        Input::Str { name, .. } => name.prefer_remapped_unconditionally().to_string(),
+    }
+}
+
+pub(crate) fn override_optimized_mir(providers: &mut Providers, provider: for<'tcx> fn(TyCtxt<'tcx>, LocalDefId) -> &'tcx Body<'tcx>) {
+    providers.queries.optimized_mir = provider;
+}
+
+/// The optimized_mir query as rustc provides it.
+pub(crate) fn original_optimized_mir(tcx: TyCtxt<'_>, did: LocalDefId) -> &Body<'_> {
+    let mut providers = Providers::default();
+    rustc_mir_transform::provide(&mut providers);
+    (providers.queries.optimized_mir)(tcx, did)
+}
+
+pub(crate) fn ensure_analysis(tcx: TyCtxt<'_>) {
+    tcx.ensure_ok().analysis(());
+}
+
+pub(crate) fn codegen_units(tcx: TyCtxt<'_>) -> &[CodegenUnit<'_>] {
+    tcx.collect_and_partition_mono_items(()).codegen_units
+}
+
+/// The span as in diagnostics, with remapped paths.
+pub(crate) fn span_to_string(tcx: TyCtxt<'_>, span: Span) -> String {
+    tcx.sess.source_map().span_to_diagnostic_string(span)
+}
+
+/// BasicBlockData is non_exhaustive (it has debug info of its own now).
+pub(crate) fn basic_block_data<'tcx>(statements: Vec<Statement<'tcx>>, terminator: Option<Terminator<'tcx>>, is_cleanup: bool)
+    -> BasicBlockData<'tcx> {
+    BasicBlockData::new_stmts(statements, terminator, is_cleanup)
+}
+
+/// `Terminator::successors_mut` takes a callback instead of returning an iterator.
+pub(crate) fn for_each_successor_mut(terminator: &mut Terminator<'_>, f: impl FnMut(&mut BasicBlock)) {
+    terminator.successors_mut(f)
+}
+
+/// `Rvalue::AddressOf` became `Rvalue::RawPtr`.
+pub(crate) fn raw_ptr(mutability: ty::Mutability, place: Place<'_>) -> Rvalue<'_> {
+    let kind = match mutability {
+        ty::Mutability::Not => RawPtrKind::Const,
+        ty::Mutability::Mut => RawPtrKind::Mut,
+    };
+    Rvalue::RawPtr(kind, place)
+}
+
+/// `Rvalue::Use` of an operand, retagged like an assignment in the source.
+pub(crate) fn use_operand(operand: Operand<'_>) -> Rvalue<'_> {
+    Rvalue::Use(operand, WithRetag::Yes)
+}
+
+/// A `&str` constant, e.g. the callsite passed to monitors.
+pub(crate) fn str_const_value<'tcx>(tcx: TyCtxt<'tcx>, s: &str) -> ConstValue {
+    let alloc_id = tcx.allocate_bytes_dedup(s.as_bytes(), 0);
+    ConstValue::Slice { alloc_id, meta: s.len() as u64 }
+}
+
+/// `core::ptr::drop_in_place` is no longer a lang item.
+pub(crate) fn drop_in_place_fn(tcx: TyCtxt<'_>) -> Option<DefId> {
+    tcx.get_diagnostic_item(Symbol::intern("ptr_drop_in_place"))
+}
+
+/// The typing environment of optimized MIR (reveal all, was `param_env_reveal_all_normalized`).
+pub(crate) fn typing_env(tcx: TyCtxt<'_>, def_id: DefId) -> TypingEnv<'_> {
+    TypingEnv::post_analysis(tcx, def_id)
+}
+
+/// Normalize and erase regions, or only erase them if `ty` cannot be normalized.
+pub(crate) fn normalize_erasing_regions<'tcx>(tcx: TyCtxt<'tcx>, typing_env: TypingEnv<'tcx>, ty: Ty<'tcx>) -> Ty<'tcx> {
+    tcx.try_normalize_erasing_regions(typing_env, Unnormalized::new_wip(ty))
+        .unwrap_or_else(|_| tcx.erase_and_anonymize_regions(ty))
+}
+
+/// The signature of `def_id` instantiated with `args` (its own generics if None), late bound regions erased.
+pub(crate) fn fn_sig<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId, args: Option<GenericArgsRef<'tcx>>) -> ty::FnSig<'tcx> {
+    let sig = match args {
+        Some(args) => tcx.fn_sig(def_id).instantiate(tcx, args),
+        None => tcx.fn_sig(def_id).instantiate_identity(),
+    };
+    tcx.instantiate_bound_regions_with_erased(sig.skip_normalization())
+}
+
+/// Resolve a call of `def_id` with `args` to the called instance, None if it is not known yet (e.g. a trait method
+/// called on a type parameter).
+pub(crate) fn resolve_instance<'tcx>(tcx: TyCtxt<'tcx>, typing_env: TypingEnv<'tcx>, def_id: DefId, args: GenericArgsRef<'tcx>)
+    -> Option<Instance<'tcx>> {
+    Instance::try_resolve(tcx, typing_env, def_id, args).ok().flatten()
+}
+
+/// Write the MIR of `body` like `-Zdump-mir`, with the lines returned by `before_block` in front of the blocks.
+/// rustc no longer takes a callback for this, so the lines are inserted into its output.
+pub(crate) fn write_mir_fn<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, before_block: &dyn Fn(BasicBlock) -> Option<String>,
+    w: &mut dyn Write) -> io::Result<()> {
+    let mut mir = Vec::new();
+    mir::pretty::MirWriter::new(tcx).write_mir_fn(body, &mut mir)?;
+    for line in String::from_utf8_lossy(&mir).lines() {
+        // blocks start with `    bb3: {` or `    bb3 (cleanup): {`
+        let block = line.strip_prefix("    bb")
+            .filter(|_| line.ends_with('{'))
+            .and_then(|rest| rest.split([':', ' ']).next())
+            .and_then(|index| index.parse::<usize>().ok());
+        if let Some(extra) = block.and_then(|index| before_block(BasicBlock::from_usize(index))) {
+            writeln!(w, "{extra}")?;
+        }
+        writeln!(w, "{line}")?;
+    }
+    Ok(())
+}
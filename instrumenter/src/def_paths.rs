@@ -0,0 +1,119 @@
+// Resolve the def paths our handlers target (e.g. `std::sync::Mutex::<T>::lock`, `std::sync::MutexGuard`) to DefIds,
+// so that calls and drops are matched on identity instead of printed paths, which depend on re-exports, visibility
+// and trimming (e.g. `core::mem::drop` is re-exported as `std::mem::drop`).
+// A path is resolved like a `use` path: the first segment is a crate (otherwise the path is relative to the local
+// crate, as def_path_str prints local items), then module children (following re-exports), then associated items of
+// inherent impls or of traits. Generic arguments (`::<T>`) are ignored. Each path is resolved once per crate.
+use std::sync::Mutex;
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_hir::def::DefKind;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::def_id::{CrateNum, DefId, LOCAL_CRATE};
+
+static RESOLVED: Mutex<Option<FxHashMap<String, Option<DefId>>>> = Mutex::new(None);
+
+/// The DefId of the item at `path`, None if the path does not name an item of this crate or its dependencies.
+pub(crate) fn resolve(tcx: TyCtxt<'_>, path: &str) -> Option<DefId> {
+    let mut resolved = RESOLVED.lock().unwrap();
+    let resolved = resolved.get_or_insert_with(FxHashMap::default);
+    if let Some(def_id) = resolved.get(path) {
+        return *def_id;
+    }
+    let def_id = resolve_uncached(tcx, path);
+    match def_id {
+        Some(def_id) => debug!("resolved def path {path} to {def_id:?}"),
+        None => info!("cannot resolve def path {path}"),
+    }
+    resolved.insert(path.to_owned(), def_id);
+    def_id
+}
+
+/// Whether `def_id` is the item at one of `paths`.
+pub(crate) fn matches_any(tcx: TyCtxt<'_>, def_id: DefId, paths: &[&str]) -> bool {
+    paths.iter().any(|path| resolve(tcx, path) == Some(def_id))
+}
+
+fn resolve_uncached(tcx: TyCtxt<'_>, path: &str) -> Option<DefId> {
+    let segments = segments(path);
+    let (first, rest) = segments.split_first()?;
+    if *first == "crate" {
+        return resolve_in(tcx, LOCAL_CRATE.as_def_id(), rest);
+    }
+    match find_crate(tcx, first) {
+        Some(krate) => {
+            let def_id = resolve_in(tcx, krate.as_def_id(), rest);
+            if def_id.is_none() {
+                // the crate is there but the item is not, e.g. it was moved or renamed in a newer toolchain
+                tcx.dcx().warn(format!("solcon: `{path}` does not name an item of crate `{first}`, it is not instrumented"));
+            }
+            def_id
+        }
+        None => resolve_in(tcx, LOCAL_CRATE.as_def_id(), &segments),
+    }
+}
+
+/// Split at `::` outside of angle brackets, dropping generic arguments like `<T>`.
+fn segments(path: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let bytes = path.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'<' => depth += 1,
+            b'>' => depth = depth.saturating_sub(1),
+            b':' if depth == 0 && bytes.get(i + 1) == Some(&b':') => {
+                segments.push(&path[start..i]);
+                i += 2;
+                start = i;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    segments.push(&path[start..]);
+    segments.into_iter().map(str::trim).filter(|segment| !segment.is_empty() && !segment.starts_with('<')).collect()
+}
+
+fn find_crate(tcx: TyCtxt<'_>, name: &str) -> Option<CrateNum> {
+    if tcx.crate_name(LOCAL_CRATE).as_str() == name {
+        return Some(LOCAL_CRATE);
+    }
+    tcx.crates(()).iter().copied().find(|krate| tcx.crate_name(*krate).as_str() == name)
+}
+
+/// Resolve `segments` starting from the item `parent`, trying every child of a name (e.g. a type and a function).
+fn resolve_in(tcx: TyCtxt<'_>, parent: DefId, segments: &[&str]) -> Option<DefId> {
+    let Some((name, rest)) = segments.split_first() else {
+        return Some(parent);
+    };
+    children(tcx, parent)
+        .into_iter()
+        .filter(|(child_name, _)| child_name == name)
+        .find_map(|(_, child)| resolve_in(tcx, child, rest))
+}
+
+fn children(tcx: TyCtxt<'_>, parent: DefId) -> Vec<(String, DefId)> {
+    let named = |def_ids: &[DefId]| -> Vec<(String, DefId)> {
+        def_ids.iter().filter_map(|def_id| Some((tcx.opt_item_name(*def_id)?.to_string(), *def_id))).collect()
+    };
+    match tcx.def_kind(parent) {
+        DefKind::Mod => {
+            let module_children = match parent.as_local() {
+                Some(local) => tcx.module_children_local(local),
+                None => tcx.module_children(parent),
+            };
+            module_children.iter()
+                .filter_map(|child| Some((child.ident.to_string(), child.res.opt_def_id()?)))
+                .collect()
+        }
+        DefKind::Struct | DefKind::Enum | DefKind::Union => {
+            tcx.inherent_impls(parent).iter().flat_map(|impl_def_id| named(tcx.associated_item_def_ids(*impl_def_id))).collect()
+        }
+        DefKind::Trait => named(tcx.associated_item_def_ids(parent)),
+        _ => Vec::new(),
+    }
+}
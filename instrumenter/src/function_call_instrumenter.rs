@@ -1,11 +1,9 @@
 use crate::monitors_finder::MonitorsInfo;
 use rustc_span::def_id::DefId;
-use rustc_span::source_map::Spanned;
 use rustc_span::Span;
 use rustc_middle::ty::Ty;
 use rustc_middle::ty::TyCtxt;
 use rustc_middle::mir::BasicBlock;
-use rustc_middle::mir::BasicBlockData;
 use rustc_middle::mir::Body;
 use rustc_middle::mir::BorrowKind;
 use rustc_middle::mir::CallSource;
@@ -14,7 +12,7 @@ use rustc_middle::mir::ConstOperand;
 use rustc_middle::mir::ConstValue;
 use rustc_middle::mir::Operand;
 use rustc_middle::mir::Place;
-use rustc_middle::mir::patch::MirPatch;
+use crate::compat::{self, MirPatch, Spanned};
 use rustc_middle::mir::Rvalue;
 use rustc_middle::mir::Statement;
 use rustc_middle::mir::StatementKind;
@@ -22,12 +20,13 @@ use rustc_middle::mir::SourceInfo;
 use rustc_middle::mir::Terminator;
 use rustc_middle::mir::TerminatorKind;
 use rustc_middle::ty::TyKind;
+use rustc_middle::ty::{GenericArg, GenericArgs};
 use rustc_middle::mir::MutBorrowKind;
 
-use crate::utils;
+use crate::{monitor_signature, utils};
 
 pub(crate) fn build_monitor_args<'tcx>(patch: &mut MirPatch<'tcx>, 
-    original_args: &Vec<Spanned<Operand<'tcx>>>, no_instantiate_func_args_tys: Vec<&Ty>,
+    original_args: &[Spanned<Operand<'tcx>>], no_instantiate_func_args_tys: Vec<&Ty>,
     tcx: TyCtxt<'tcx>, 
     body: &Body<'tcx>, assign_ref_block: BasicBlock, 
     fn_span: &Span,
@@ -45,7 +44,7 @@ pub(crate) fn build_monitor_args<'tcx>(patch: &mut MirPatch<'tcx>,
                 // 对于reference类型，虽然仅&T实现了Copy trait而&mut T没有Copy trait（https://doc.rust-lang.org/stable/src/core/marker.rs.html#437），
                 // 但是我们在这里仍可以安全地复制&mut T。理由是，Copy trait的意义在于保证可以按位复制不需要deconstructor（drop），而我们的pass运行在optimized_mir（MIR to Binaries阶段）已经运行过analysis阶段，所以可以对任意类型（无论是否实现Copy trait）进行bitwise Copy而不会影响drop elaboration
                 Operand::Move(place) => Operand::Copy(place),
-                Operand::Copy(..) | Operand::Constant(..) => arg.node.clone(),
+                Operand::Copy(..) | Operand::Constant(..) | Operand::RuntimeChecks(..) => arg.node.clone(),
             }
         } else {
             // debug!("!call_arg_ty.is_any_ptr()");
@@ -61,10 +60,10 @@ pub(crate) fn build_monitor_args<'tcx>(patch: &mut MirPatch<'tcx>,
                     ));
                     Operand::Move(local_temp_ref_to_this_arg.into())
                 }
-                Operand::Constant(box const_operand) => {
+                Operand::Constant(..) | Operand::RuntimeChecks(..) => {
                     // 生成一条语句将 const 的值赋给局部变量
-                    let const_local = patch.new_temp(const_operand.ty(), fn_span.clone());
-                    patch.add_assign(patch.terminator_loc(body, assign_ref_block), const_local.into(), Rvalue::Use(arg.node.clone()));
+                    let const_local = patch.new_temp(arg_ty, fn_span.clone());
+                    patch.add_assign(patch.terminator_loc(body, assign_ref_block), const_local.into(), compat::use_operand(arg.node.clone()));
                     let local_temp_ref_to_this_arg = patch.new_temp(Ty::new_imm_ref(tcx, tcx.lifetimes.re_erased, arg_ty), fn_span.clone());
                     patch.add_assign(patch.terminator_loc(body, assign_ref_block), local_temp_ref_to_this_arg.into(), Rvalue::Ref(
                        tcx.lifetimes.re_erased,
@@ -85,13 +84,8 @@ pub(crate) fn build_monitor_args<'tcx>(patch: &mut MirPatch<'tcx>,
     result.push({
         // build fn_callsite_span_str as first arg
         let fn_callsite_span_str = utils::span_to_string(tcx, *fn_span);
-        let alloc = rustc_middle::mir::interpret::Allocation::from_bytes_byte_aligned_immutable(fn_callsite_span_str.as_bytes());
-        let const_alloc = tcx.mk_const_alloc(alloc);
-        let const_val = ConstValue::Slice{
-            data: const_alloc,
-            meta: fn_callsite_span_str.len() as u64,
-        };
-        let const_ty = Ty::new_static_str(tcx);
+        let const_val = compat::str_const_value(tcx, &fn_callsite_span_str);
+        let const_ty = utils::callsite_str_ty(tcx);
         Spanned {
             node: Operand::Constant(
                 Box::new(ConstOperand{
@@ -108,6 +102,18 @@ pub(crate) fn build_monitor_args<'tcx>(patch: &mut MirPatch<'tcx>,
 }
 
 
+/// The types of the arguments build_monitor_args passes, without the return value.
+fn monitor_arg_tys<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>,
+    original_args: &[Spanned<Operand<'tcx>>], no_instantiate_func_args_tys: &[&Ty<'tcx>],
+) -> Vec<Ty<'tcx>> {
+    let mut arg_tys = vec![utils::callsite_str_ty(tcx)];
+    arg_tys.extend(original_args.iter().zip(no_instantiate_func_args_tys).map(|(arg, call_arg_ty)| {
+        let arg_ty = arg.node.ty(&body.local_decls, tcx);
+        if call_arg_ty.is_any_ptr() { arg_ty } else { Ty::new_imm_ref(tcx, tcx.lifetimes.re_erased, arg_ty) }
+    }));
+    arg_tys
+}
+
  // tyobj.fn_sig() 可以获得函数签名。但倘若tyobj包含了泛型参数，会获得具体化的函数参数类型列表
  // 本函数可以获得保持泛型的函数参数类型列表
 pub(crate) fn get_no_instantiate_func_args_tys_from_fn_ty<'tcx>(tcx: TyCtxt<'tcx>, func_ty_with_generic_args: &Ty) -> Option<Vec<&'tcx Ty<'tcx>>> {
@@ -118,22 +124,44 @@ pub(crate) fn get_no_instantiate_func_args_tys_from_fn_ty<'tcx>(tcx: TyCtxt<'tcx
             TyKind::Closure(def_id, _args) => def_id, // // The anonymous type of a closure. Used to represent the type of |a| a.
             TyKind::CoroutineClosure(def_id, _args) => def_id,  // The anonymous type of a closure. Used to represent the type of async |a| a.
             TyKind::Coroutine(def_id, _args) => def_id, // The anonymous type of a coroutine. Used to represent the type of |a| yield a.
-            TyKind::FnPtr(_) => {
+            TyKind::FnPtr(..) => {
                 warn!("get_no_instantiate_func_args_tys failed: we cannot infer FnPtr point to what");
                 return None;
             },
             _ => unreachable!(),
         }
     };
-    let func_sig = tcx.fn_sig(func_def_id).skip_binder();
+    let func_sig = tcx.fn_sig(*func_def_id).skip_binder();
     let func_sig_arg_tys : Vec<_> = func_sig.inputs().iter().map(|binder| binder.skip_binder() ).collect();
     Some(func_sig_arg_tys)
 }
 
 pub trait FunctionCallInstrumenter<'pass> {
     fn target_function(&self) -> &'pass str;
+    /// For a trait method target (e.g. `std::ops::Deref::deref`), only calls with this Self type are instrumented.
+    fn target_self_ty(&self) -> Option<&'pass str> {
+        None
+    }
+    /// The target in logs and statistics, e.g. `<std::sync::LazyLock as std::ops::Deref>::deref`.
+    fn target_name(&self) -> String {
+        match (self.target_self_ty(), self.target_function().rsplit_once("::")) {
+            (Some(self_ty), Some((trait_path, method))) => format!("<{self_ty} as {trait_path}>::{method}"),
+            _ => self.target_function().to_owned(),
+        }
+    }
     fn before_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId>;
     fn after_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId>;
+    /// Whether the monitors get the arguments (and the return value) of the call and its generic args.
+    /// If not, the monitors are non-generic `fn(callsite: &str)`.
+    fn monitor_takes_args(&self) -> bool {
+        true
+    }
+    /// The generic args of the monitors when they take the arguments, by default those of the target. `arg_tys` are the
+    /// types of the arguments of the call, `ret_ty` is its return type for the after monitor and None for the before one.
+    fn monitor_generic_args<'tcx>(&self, _tcx: TyCtxt<'tcx>, target_generic_args: &'tcx GenericArgs<'tcx>,
+        _arg_tys: &[Ty<'tcx>], _ret_ty: Option<Ty<'tcx>>) -> Vec<GenericArg<'tcx>> {
+        target_generic_args.to_vec()
+    }
 
     fn instrument_call_before<'tcx>(&self, 
         tcx: TyCtxt<'tcx>, 
@@ -155,15 +183,29 @@ pub trait FunctionCallInstrumenter<'pass> {
                 warn!("target_function {} generic_args.is_none", self.target_function());
                 return None;
             }
-            let generic_args = generic_args.unwrap();
+            let call_arg_tys: Vec<_> = args.iter().map(|arg| arg.node.ty(&body.local_decls, tcx)).collect();
+            let generic_args = self.monitor_generic_args(tcx, generic_args.unwrap(), &call_arg_tys, None);
+            let (monitor_arg_tys, monitor_generic_args) = if self.monitor_takes_args() {
+                (monitor_arg_tys(tcx, body, args, &no_instantiate_func_args_tys), generic_args.as_slice())
+            } else {
+                (vec![utils::callsite_str_ty(tcx)], &[][..])
+            };
+            if !monitor_signature::check_monitor_call(tcx, compat::typing_env(tcx, body.source.def_id()), our_func_def_id,
+                monitor_generic_args, &monitor_arg_tys, *fn_span) {
+                return None;
+            }
             let mut patch = MirPatch::new(body);
             // 在函数调用之前插入我们的函数调用需要
             // 1. 把原函数调用移动到下一个我们新生成的基本块，terminator-kind为call，target到当前块的原target
             // 2 .更改当前块的terminator call的func到我们的函数，target到我们的新块以便我们的函数返回后继续在新块执行原调用
-            let our_call_args = build_monitor_args(&mut patch, args, no_instantiate_func_args_tys, tcx, body, call_at_block, fn_span);
-            let new_bb_run_call = patch.new_block(BasicBlockData {
-                statements: vec![],
-                terminator: Some(Terminator {
+            let (our_call_args, our_generic_args) = if self.monitor_takes_args() {
+                (build_monitor_args(&mut patch, args, no_instantiate_func_args_tys, tcx, body, call_at_block, fn_span), generic_args.as_slice())
+            } else {
+                (build_monitor_args(&mut patch, &[], vec![], tcx, body, call_at_block, fn_span), &[][..])
+            };
+            let new_bb_run_call = patch.new_block(compat::basic_block_data(
+                vec![],
+                Some(Terminator {
                     kind: TerminatorKind::Call { 
                         func: func.clone(), 
                         args: args.clone(), 
@@ -174,12 +216,12 @@ pub trait FunctionCallInstrumenter<'pass> {
                         fn_span: fn_span.clone() },
                     source_info: terminator.source_info.clone(),
                 }),
-                is_cleanup: false,
-            });
+                body.basic_blocks[call_at_block].is_cleanup,
+            ));
             let temp_ret = patch.new_temp(tcx.types.unit, fn_span.clone());
             patch.patch_terminator(call_at_block, TerminatorKind::Call{
-                func: utils::instantiate_our_func(tcx, our_func_def_id, generic_args, fn_span.clone()),
-                args: our_call_args,
+                func: utils::instantiate_our_func(tcx, our_func_def_id, our_generic_args.iter().copied(), fn_span.clone()),
+                args: our_call_args.into(),
                 destination: Place::from(temp_ret),
                 target: Some(new_bb_run_call),
                 unwind: unwind.clone(),
@@ -210,38 +252,56 @@ pub trait FunctionCallInstrumenter<'pass> {
                 warn!("target_function {} generic_args.is_none", self.target_function());
                 return None;
             }
-            let generic_args = generic_args.unwrap();
+            let call_arg_tys: Vec<_> = args.iter().map(|arg| arg.node.ty(&body.local_decls, tcx)).collect();
+            let generic_args = self.monitor_generic_args(tcx, generic_args.unwrap(), &call_arg_tys,
+                Some(destination.ty(&body.local_decls, tcx).ty));
+            let (monitor_arg_tys, monitor_generic_args) = if self.monitor_takes_args() {
+                let mut arg_tys = monitor_arg_tys(tcx, body, args, &no_instantiate_func_args_tys);
+                arg_tys.push(Ty::new_mut_ref(tcx, tcx.lifetimes.re_erased, destination.ty(&body.local_decls, tcx).ty));
+                (arg_tys, generic_args.as_slice())
+            } else {
+                (vec![utils::callsite_str_ty(tcx)], &[][..])
+            };
+            if !monitor_signature::check_monitor_call(tcx, compat::typing_env(tcx, body.source.def_id()), our_func_def_id,
+                monitor_generic_args, &monitor_arg_tys, *fn_span) {
+                return None;
+            }
             let mut patch = MirPatch::new(body);
             // 在函数调用之后插入我们的函数调用需要
             // 1 .更改当前块的terminator call的target到我们的新块
             // 2. 在我们新生成的基本块中，terminator-kind为call，func为我们的函数，target到当前块的原target
 
-            // 为了传入返回值，先构造一条创建引用的statement并插到我们的函数调用前
-            let ty_dest = destination.ty(&body.local_decls, tcx).ty;
-            let local_tmp_ref_to_dest = patch.new_temp(Ty::new_mut_ref(tcx, tcx.lifetimes.re_erased, ty_dest), fn_span.clone());
-            let statements = vec![Statement{
-                source_info: SourceInfo::outermost(fn_span.clone()),
-                kind: StatementKind::Assign(
-                    Box::new((Place::from(local_tmp_ref_to_dest), Rvalue::Ref(
-                        tcx.lifetimes.re_erased,
-                        BorrowKind::Mut { kind: MutBorrowKind::Default },
-                        destination.clone(),
-                    )))
-                ),
-            }];
-            // Notice: here may cause problems because a reference to obj may be passed after moving and dropping it. This behavior may change in the future.
-            let mut our_call_args = build_monitor_args(&mut patch, args, no_instantiate_func_args_tys, tcx, body, call_at_block, fn_span);
-            our_call_args.push(Spanned{
-                node: Operand::Move(Place::from(local_tmp_ref_to_dest)),
-                span: fn_span.clone(),
-            });
+            let (statements, our_call_args, our_generic_args) = if self.monitor_takes_args() {
+                // 为了传入返回值，先构造一条创建引用的statement并插到我们的函数调用前
+                let ty_dest = destination.ty(&body.local_decls, tcx).ty;
+                let local_tmp_ref_to_dest = patch.new_temp(Ty::new_mut_ref(tcx, tcx.lifetimes.re_erased, ty_dest), fn_span.clone());
+                let statements = vec![Statement::new(
+                    SourceInfo::outermost(fn_span.clone()),
+                    StatementKind::Assign(
+                        Box::new((Place::from(local_tmp_ref_to_dest), Rvalue::Ref(
+                            tcx.lifetimes.re_erased,
+                            BorrowKind::Mut { kind: MutBorrowKind::Default },
+                            destination.clone(),
+                        )))
+                    ),
+                )];
+                // Notice: here may cause problems because a reference to obj may be passed after moving and dropping it. This behavior may change in the future.
+                let mut our_call_args = build_monitor_args(&mut patch, args, no_instantiate_func_args_tys, tcx, body, call_at_block, fn_span);
+                our_call_args.push(Spanned{
+                    node: Operand::Move(Place::from(local_tmp_ref_to_dest)),
+                    span: fn_span.clone(),
+                });
+                (statements, our_call_args, generic_args.as_slice())
+            } else {
+                (vec![], build_monitor_args(&mut patch, &[], vec![], tcx, body, call_at_block, fn_span), &[][..])
+            };
             let temp_our_dest = patch.new_temp(tcx.types.unit, fn_span.clone());
-            let new_bb_run_our_func_call = patch.new_block(BasicBlockData {
-                statements: statements,
-                terminator: Some(Terminator {
+            let new_bb_run_our_func_call = patch.new_block(compat::basic_block_data(
+                statements,
+                Some(Terminator {
                     kind: TerminatorKind::Call { 
-                        func: utils::instantiate_our_func(tcx, our_func_def_id, generic_args, fn_span.clone()), 
-                        args: our_call_args, 
+                        func: utils::instantiate_our_func(tcx, our_func_def_id, our_generic_args.iter().copied(), fn_span.clone()), 
+                        args: our_call_args.into(), 
                         destination: Place::from(temp_our_dest), 
                         target: target.clone(),
                         unwind: unwind.clone(), 
@@ -249,8 +309,8 @@ pub trait FunctionCallInstrumenter<'pass> {
                         fn_span: fn_span.clone() },
                     source_info: terminator.source_info.clone(),
                 }),
-                is_cleanup: false,
-            });
+                body.basic_blocks[call_at_block].is_cleanup,
+            ));
             patch.patch_terminator(call_at_block, TerminatorKind::Call{
                 func: func.clone(),
                 args: args.clone(),
@@ -34,21 +34,33 @@ fn get_defpath_from_marker_and_remove_marker(field: &mut syn::Field) -> Option<S
 #[proc_macro_attribute]
 pub fn generate_impl_monitors_finder_from_monitors_info(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let mut struct_def: ItemStruct = syn::parse_macro_input!(item);
-    let matchbranches = struct_def.fields.iter_mut().filter_map(|field| {
-        let defpath = get_defpath_from_marker_and_remove_marker(field);
-        if let Some(def_path) = defpath {
-            let Some(ref ident) = field.ident else { return None; }; // skip fields without name
-            let branch = quote! {
-                #def_path => {
+    let mut matchbranches = Vec::new();
+    let mut kindbranches = Vec::new();
+    let mut kindfields = Vec::new();
+    for field in struct_def.fields.iter_mut() {
+        let Some(def_path) = get_defpath_from_marker_and_remove_marker(field) else { continue; };
+        let Some(ref ident) = field.ident else { continue; }; // skip fields without name
+        // the hook kind used by #[solcon::monitor(kind = "...")] is the field name without `_fn`
+        let field_name = ident.to_string();
+        let kind = field_name.strip_suffix("_fn").unwrap_or(&field_name).to_owned();
+        matchbranches.push(quote! {
+            #def_path => {
+                if self.#ident.is_none() {
                     self.#ident = Some(def_id);
                     info!(concat!("configure monitors.", stringify!(#ident)));
                 }
-            };
-            Some(branch)
-        } else {
-            None
-        }
-    });
+            }
+        });
+        kindfields.push(quote! { (#kind, self.#ident) });
+        kindbranches.push(quote! {
+            #kind => {
+                if self.#ident.is_none() {
+                    self.#ident = Some(def_id);
+                    info!(concat!("configure monitors.", stringify!(#ident), " by kind"));
+                }
+            }
+        });
+    }
     let struct_name = &struct_def.ident;
     let expanded_impl = quote! {
         impl MonitorsFinder for #struct_name {
@@ -56,7 +68,7 @@ pub fn generate_impl_monitors_finder_from_monitors_info(_attr: TokenStream, item
                 let def_id = fn_def_id.clone();
                 let fn_defpath_str = tcx.def_path_str(def_id);
                 trace!("try_match_with_our_function {}", fn_defpath_str);
-                let prefix = format!("{}::", config::MONITORS_LIB_CRATE_NAME);
+                let prefix = format!("{}::", tcx.crate_name(def_id.krate));
                 let Some(fn_defpath_str) = fn_defpath_str.strip_prefix(&prefix) else { 
                     return false;
                 };
@@ -66,6 +78,19 @@ pub fn generate_impl_monitors_finder_from_monitors_info(_attr: TokenStream, item
                 }
                 return true;
            }
+
+            fn try_match_with_monitor_kind(&mut self, kind: &str, fn_def_id: &DefId) -> bool {
+                let def_id = fn_def_id.clone();
+                match kind {
+                    #(#kindbranches)*
+                    &_=> { return false; }
+                }
+                return true;
+            }
+
+            fn monitors_by_kind(&self) -> Vec<(&'static str, Option<DefId>)> {
+                vec![#(#kindfields),*]
+            }
         }
     };
     for field in struct_def.fields.iter_mut() {
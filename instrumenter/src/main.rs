@@ -10,9 +10,9 @@
 
 #[macro_use]
 extern crate tracing; // shared from rustc
+extern crate rustc_const_eval;
 extern crate rustc_data_structures;
 extern crate rustc_driver;
-extern crate rustc_hash;
 extern crate rustc_hir;
 extern crate rustc_index;
 extern crate rustc_interface;
@@ -30,25 +30,32 @@ use rustc_session::Session;
 use rustc_session::EarlyDiagCtxt;
 use rustc_session::config::ErrorOutputType;
 use rustc_middle::bug;
-use rustc_middle::ty::Instance;
-use rustc_middle::mir::mono::MonoItem;
+use rustc_middle::ty::{Instance, TyCtxt};
 use rustc_middle::util::Providers;
 use std::env;
 use std::io::{self, IsTerminal};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+mod compat;
 mod config;
+mod def_paths;
 #[cfg(feature = "input_filter")]
 mod input_filter;
+mod mir_dump;
+mod mir_validation;
 mod mirpass;
+mod monitor_signature;
 mod mem_instrumenter;
 mod function_call_instrumenter;
 mod obj_drop_instrumenter;
 pub(crate) mod monitors_finder;
 mod utils;
+mod static_lock_order;
+mod rules;
+mod stats;
 
 fn override_queries(_session: &Session, local: &mut Providers) {
-    local.queries.optimized_mir = mirpass::our_optimized_mir;
+    compat::override_optimized_mir(local, mirpass::our_optimized_mir);
 }
 
 // inspired by lockbud & miri
@@ -61,7 +68,7 @@ fn main() {
     let _env_snapshot = std::env::vars_os().collect::<Vec<_>>();
 
     // Add an ICE hook.
-    let using_internal_features = rustc_driver::install_ice_hook("internal complier error", |_| ());
+    rustc_driver::install_ice_hook("internal complier error", |_| ());
     
     // Install the ctrlc handler that sets `rustc_const_eval::CTRL_C_RECEIVED`
     rustc_driver::install_ctrlc_handler();
@@ -70,7 +77,7 @@ fn main() {
     let early_dcx = EarlyDiagCtxt::new(ErrorOutputType::default());
 
     // Check & collect arguments
-    let mut args = rustc_driver::args::raw_args(&early_dcx).unwrap_or_else(|_| std::process::exit(rustc_driver::EXIT_FAILURE));
+    let mut args = rustc_driver::args::raw_args(&early_dcx);
     assert!(!args.is_empty());
 
     // Initialize loggers.
@@ -115,22 +122,33 @@ fn main() {
         };
         rustc_command_line_arguments.push(sysroot_path);
     }
-    // note filepath must start with lib & end with .rlib(e.g lib*.rlib)
-    // see https://github.com/rust-lang/rust/blob/a71c3ffce9ca505af27f43cd3bad7606a72e3ec8/compiler/rustc_metadata/src/locator.rs#L731
-    let Some((solcon_monitor_function_rlib_filepath, solcon_monitor_function_rlib_dirpath, solcon_monitor_function_deps_dirpath)) = utils::find_our_monitor_lib() else {
-       early_dcx.early_fatal("solcon monitor function rlib not exist")
+    let static_check_only = static_lock_order::mode() == static_lock_order::StaticCheckMode::Only;
+    let rules = match rules::load() {
+        Ok(rules) => rules,
+        Err(err) => early_dcx.early_fatal(format!("solcon: {err}")),
     };
-    let solcon_monitors_lib_crate_name = config::MONITORS_LIB_CRATE_NAME;
-    // forcely make our monitor lib become dependency of each crate & linked to each crate
-    // see https://github.com/rust-lang/rust/blob/a71c3ffce9ca505af27f43cd3bad7606a72e3ec8/compiler/rustc_metadata/src/locator.rs#L127
-    // use --extern to specify direct dependency
-    rustc_command_line_arguments.push("--extern".to_owned());
-    rustc_command_line_arguments.push(format!("force:{solcon_monitors_lib_crate_name}={solcon_monitor_function_rlib_filepath}"));
-    // because our monitor lib is dependency of each crate, so downstream crate also transmitively dependent on ur monitor lib and search on directories of -L
-    rustc_command_line_arguments.push("-L".to_owned());
-    rustc_command_line_arguments.push(format!("dependency={solcon_monitor_function_rlib_dirpath}"));
-    rustc_command_line_arguments.push("-L".to_owned());
-    rustc_command_line_arguments.push(format!("dependency={solcon_monitor_function_deps_dirpath}"));
+    // static check only mode does not instrument, so it does not need our monitor lib
+    if !static_check_only {
+        // note filepath must start with lib & end with .rlib(e.g lib*.rlib)
+        // see https://github.com/rust-lang/rust/blob/a71c3ffce9ca505af27f43cd3bad7606a72e3ec8/compiler/rustc_metadata/src/locator.rs#L731
+        let monitor_libs = utils::monitor_libs();
+        if !monitor_libs.iter().any(|monitor_lib| monitor_lib.provides_hooks) {
+           early_dcx.early_fatal("solcon monitor function rlib not exist")
+        }
+        // our monitor libs and the monitor crates of the SOLCON_RULES file
+        for monitor_lib in monitor_libs {
+            // forcely make our monitor lib become dependency of each crate & linked to each crate
+            // see https://github.com/rust-lang/rust/blob/a71c3ffce9ca505af27f43cd3bad7606a72e3ec8/compiler/rustc_metadata/src/locator.rs#L127
+            // use --extern to specify direct dependency
+            rustc_command_line_arguments.push("--extern".to_owned());
+            rustc_command_line_arguments.push(format!("force:{}={}", monitor_lib.crate_name, monitor_lib.rlib_path));
+            // because our monitor lib is dependency of each crate, so downstream crate also transmitively dependent on ur monitor lib and search on directories of -L
+            rustc_command_line_arguments.push("-L".to_owned());
+            rustc_command_line_arguments.push(format!("dependency={}", monitor_lib.dir_path));
+            rustc_command_line_arguments.push("-L".to_owned());
+            rustc_command_line_arguments.push(format!("dependency={}", monitor_lib.deps_dir_path));
+        }
+    }
    
     let always_encode_mir: String = "always-encode-mir".into();
     if !rustc_command_line_arguments
@@ -171,17 +189,15 @@ fn main() {
         rustc_command_line_arguments.push("unstable-options".into());
     }
 
-    let mut callbacks = Callbacks::new(!logger_inited);
-    let result = rustc_driver::catch_fatal_errors( || {
-        info!("rustc_command_line_arguments {:?}", rustc_command_line_arguments.join(" "));
-        let compiler = rustc_driver::RunCompiler::new(&rustc_command_line_arguments, &mut callbacks);
-        compiler
-        .set_using_internal_features(using_internal_features)
-        .run()
-    });
+    let mut callbacks = Callbacks::new(!logger_inited, static_check_only);
+    info!("rustc_command_line_arguments {:?}", rustc_command_line_arguments.join(" "));
+    let result = compat::run_compiler(&rustc_command_line_arguments, &mut callbacks);
 
     let exit_code = match result {
-        Ok(_) => rustc_driver::EXIT_SUCCESS,
+        Ok(_) => {
+            stats::report(&early_dcx);
+            rustc_driver::EXIT_SUCCESS
+        }
         Err(_) => rustc_driver::EXIT_FAILURE,
     };
     std::process::exit(exit_code);
@@ -222,16 +238,18 @@ struct Callbacks {
     file_name: String,
     output_directory: PathBuf,
     test_mode: bool,
+    static_check_only: bool,
 }
 
 impl Callbacks {
-    pub fn new(need_init_logger: bool) -> Self {
+    pub fn new(need_init_logger: bool, static_check_only: bool) -> Self {
         Self {
             work: true,
             need_init_logger,
             file_name: String::new(),
             output_directory: PathBuf::default(),
             test_mode: false,
+            static_check_only,
         }
     }
 }
@@ -240,26 +258,27 @@ fn is_root<'tcx>(tcx: rustc_middle::ty::TyCtxt<'tcx>, def_id: rustc_hir::def_id:
     !tcx.generics_of(def_id).requires_monomorphization(tcx)
 }
 
-impl rustc_driver::Callbacks for Callbacks {
+impl compat::DriverCallbacks for Callbacks {
     fn config(&mut self, config: &mut rustc_interface::interface::Config) {
         if std::env::var("SOLCON_BE_RUSTC").is_ok() {
             info!("Notice: SOLCON_BE_RUSTC is set");
             self.work = false;
-        }else if let rustc_session::config::Input::File(filepath) = &config.input {
+        } else if let rustc_session::config::Input::File(filepath) = &config.input {
             info!("Real file input {:?}", filepath);
             // skip denpendency from cargo registry
             let filepath = String::from(filepath.to_str().unwrap());
             if filepath.contains("/.cargo/registry/") {
                 info!("Skip Processing denpendency from cargo registry");
                 self.work = false;
-            } else if filepath.contains("this_is_our_monitor_function") {
-                info!("Skip Processing this_is_our_monitor_function");
+            } else if filepath.contains("this_is_our_monitor_function")
+                || config.opts.crate_name.as_deref().is_some_and(utils::is_monitor_crate) {
+                info!("Skip Processing monitor crate");
                 self.work = false;
-            }else {
+            } else {
                 self.work = true;
             }
         } else {
-            info!("Skip Processing input: {:?}", config.input.source_name());
+            info!("Skip Processing input: {}", compat::input_name(&config.input));
             self.work = false;
         }
         if !self.work {
@@ -267,7 +286,7 @@ impl rustc_driver::Callbacks for Callbacks {
             info!("Removing extern");
             let entrys = config.opts.externs.iter().filter_map(
                 |(crate_name, entry)| {
-                    if crate_name == "this_is_our_monitor_function" {
+                    if utils::is_monitor_crate(crate_name) {
                         return None;
                     }
                     Some((crate_name.clone(), entry.clone()))
@@ -277,8 +296,7 @@ impl rustc_driver::Callbacks for Callbacks {
             config.opts.externs = rustc_session::config::Externs::new(entrys);
             return;
         }
-        let input_source_name = config.input.source_name();
-        self.file_name = input_source_name.prefer_remapped_unconditionaly().to_string();
+        self.file_name = compat::input_name(&config.input);
         info!("Processing input file: {}", self.file_name);
         for c in &config.crate_check_cfg {
             debug!("config.crate_check_cfg {c}");
@@ -294,32 +312,25 @@ impl rustc_driver::Callbacks for Callbacks {
             }
             Some(path_buf) => self.output_directory.push(path_buf.as_path()),
         }
+        if self.static_check_only {
+            return;
+        }
         // override_queries inspired by miri & rustc compiler team
         config.override_queries = Some(override_queries);
     }
 
-    fn after_expansion<'tcx>(
-        &mut self,
-        _compiler: &rustc_interface::interface::Compiler,
-        queries: &'tcx rustc_interface::Queries<'tcx>,
-    ) -> rustc_driver::Compilation {
-        if !self.work {
+    fn after_expansion(&mut self, tcx: TyCtxt<'_>) -> Compilation {
+        if !self.work || self.static_check_only {
             return Compilation::Continue;
         }
-        let mut global_ctxt = queries.global_ctxt().unwrap();
-        global_ctxt.enter(|tcx: rustc_middle::ty::TyCtxt| {
-            mirpass::find_entry_fn(tcx);
-            mirpass::find_all_monitors(tcx);
-            tcx.dcx().abort_if_errors();
-        });
+        mirpass::find_entry_fn(tcx);
+        mirpass::find_all_monitors(tcx);
+        rules::resolve_monitors(tcx);
+        tcx.dcx().abort_if_errors();
         Compilation::Continue
     }
     
-    fn after_analysis<'tcx>(
-        &mut self,
-        _compiler: &rustc_interface::interface::Compiler,
-        queries: &'tcx rustc_interface::Queries<'tcx>,
-    ) -> rustc_driver::Compilation {
+    fn after_analysis(&mut self, tcx: TyCtxt<'_>) -> Compilation {
         if !self.work {
             return Compilation::Continue;
         }
@@ -333,96 +344,105 @@ impl rustc_driver::Callbacks for Callbacks {
             return Compilation::Continue;
         }
         info!("after_analysis input file: {}", self.file_name);
-        let mut global_ctxt = queries.global_ctxt().unwrap();
-        global_ctxt.enter(|tcx: rustc_middle::ty::TyCtxt| {
-            info!("entering input file: {}", self.file_name);
-            let dcx = tcx.dcx();
-            let opts = &tcx.sess.opts;
-            let externs = &opts.externs;
-            for (str, extern_entry) in externs.iter() {
-                info!("externs {}, force={}", str, extern_entry.force);
-            }
-            if tcx.sess.dcx().has_errors_or_delayed_bugs().is_some() {
-                dcx.fatal("solcon_instrumenter cannot be run on programs that fail compilation");
-            }
-            dcx.abort_if_errors();
+        info!("entering input file: {}", self.file_name);
+        let dcx = tcx.dcx();
+        let opts = &tcx.sess.opts;
+        let externs = &opts.externs;
+        for (str, extern_entry) in externs.iter() {
+            info!("externs {}, force={}", str, extern_entry.force);
+        }
+        if tcx.sess.dcx().has_errors_or_delayed_bugs().is_some() {
+            dcx.fatal("solcon_instrumenter cannot be run on programs that fail compilation");
+        }
+        dcx.abort_if_errors();
 
-            for item_id in tcx.hir_crate_items(()).free_items() {
-                if matches!(tcx.def_kind(item_id.owner_id), rustc_hir::def::DefKind::Fn) {
-                    trace!("free_items Function: {}, isroot={}", tcx.def_path_str(item_id.owner_id.def_id), is_root(tcx, item_id.owner_id.def_id));
-                }
+        for item_id in tcx.hir_crate_items(()).free_items() {
+            if matches!(tcx.def_kind(item_id.owner_id), rustc_hir::def::DefKind::Fn) {
+                trace!("free_items Function: {}, isroot={}", tcx.def_path_str(item_id.owner_id.def_id), is_root(tcx, item_id.owner_id.def_id));
             }
-            for item_id in tcx.hir_crate_items(()).trait_items() {
-                if matches!(tcx.def_kind(item_id.owner_id), rustc_hir::def::DefKind::Fn) {
-                    trace!("trait_items Function: {}, isroot={}", tcx.def_path_str(item_id.owner_id.def_id), is_root(tcx, item_id.owner_id.def_id));
-                }
+        }
+        for item_id in tcx.hir_crate_items(()).trait_items() {
+            if matches!(tcx.def_kind(item_id.owner_id), rustc_hir::def::DefKind::Fn) {
+                trace!("trait_items Function: {}, isroot={}", tcx.def_path_str(item_id.owner_id.def_id), is_root(tcx, item_id.owner_id.def_id));
             }
-            for item_id in tcx.hir_crate_items(()).impl_items() {
-                if matches!(tcx.def_kind(item_id.owner_id), rustc_hir::def::DefKind::Fn) {
-                    trace!("impl_items Function: {}, isroot={}", tcx.def_path_str(item_id.owner_id.def_id), is_root(tcx, item_id.owner_id.def_id));
-                }
+        }
+        for item_id in tcx.hir_crate_items(()).impl_items() {
+            if matches!(tcx.def_kind(item_id.owner_id), rustc_hir::def::DefKind::Fn) {
+                trace!("impl_items Function: {}, isroot={}", tcx.def_path_str(item_id.owner_id.def_id), is_root(tcx, item_id.owner_id.def_id));
             }
-            for item_id in tcx.hir_crate_items(()).foreign_items() {
-                if matches!(tcx.def_kind(item_id.owner_id), rustc_hir::def::DefKind::Fn) {
-                    trace!("foreign_items Function: {}, isroot={}", tcx.def_path_str(item_id.owner_id.def_id), is_root(tcx, item_id.owner_id.def_id));
-                }
+        }
+        for item_id in tcx.hir_crate_items(()).foreign_items() {
+            if matches!(tcx.def_kind(item_id.owner_id), rustc_hir::def::DefKind::Fn) {
+                trace!("foreign_items Function: {}, isroot={}", tcx.def_path_str(item_id.owner_id.def_id), is_root(tcx, item_id.owner_id.def_id));
             }
+        }
 
-            // init late logger
-            if self.need_init_logger {
-                let early_dcx = EarlyDiagCtxt::new(tcx.sess.opts.error_format);
-                if env::var_os("RUSTC_LOG").is_none() {
-                    info!("init late loggers");
-                    rustc_driver::init_logger(&early_dcx, utils::rustc_logger_config());
-                }
+        // init late logger
+        if self.need_init_logger {
+            let early_dcx = EarlyDiagCtxt::new(tcx.sess.opts.error_format);
+            if env::var_os("RUSTC_LOG").is_none() {
+                info!("init late loggers");
+                rustc_driver::init_logger(&early_dcx, utils::rustc_logger_config());
             }
+        }
 
-            // Pre-check
-            if dcx.has_errors_or_delayed_bugs().is_some() {
-                dcx.fatal("solcon_instructmenter cannot be run on programs that fail compilation");
-            }
-            if tcx.sess.mir_opt_level() > 0 {
-                dcx.warn("Notice: You have explicitly enabled MIR optimizations!");
-            }
+        // Pre-check
+        if dcx.has_errors_or_delayed_bugs().is_some() {
+            dcx.fatal("solcon_instructmenter cannot be run on programs that fail compilation");
+        }
+        if tcx.sess.mir_opt_level() > 0 {
+            dcx.warn("Notice: You have explicitly enabled MIR optimizations!");
+        }
 
-            #[cfg(feature = "input_filter")]
-            {
-                // filter input
-                if !input_filter::should_process(tcx) {
-                    dcx.note(format!("skip to instrument compiling unit {}, because should_process report false", self.file_name));
-                    return;
-                }
-            }
+        if self.static_check_only {
+            static_lock_order::check_crate(tcx);
+            return Compilation::Continue;
+        }
 
-            // Transform
-            info!("our pass is running");
-            if mirpass::MONITORS.get().is_none() {
-                bug!("mirpass::MONITORS.get().is_none(), this should be set when call mirpass::find_all_monitors");
+        #[cfg(feature = "input_filter")]
+        {
+            // filter input
+            if !input_filter::should_process(tcx) {
+                dcx.note(format!("skip to instrument compiling unit {}, because should_process report false", self.file_name));
+                return Compilation::Continue;
             }
-            mirpass::START_INSTRUMENT.store(true, std::sync::atomic::Ordering::Release);
-            dcx.abort_if_errors();
-
-            // Post-check
-            tcx.ensure().analysis(());
-            dcx.abort_if_errors();
-
-            // Build
-            let (_items, cgus) = tcx.collect_and_partition_mono_items(());
-            info!("code generation units nums #{}", cgus.len());
-            let _instances: Vec<Instance<'tcx>> = cgus
-            .iter()
-            .flat_map(|cgu| {
-                cgu.items().iter().filter_map(|(mono_item, _)| {
-                    if let MonoItem::Fn(instance) = mono_item {
-                        Some(*instance)
-                    } else {
-                        None
-                    }
-                })
+        }
+
+        // Transform
+        info!("our pass is running");
+        if mirpass::MONITORS.get().is_none() {
+            bug!("mirpass::MONITORS.get().is_none(), this should be set when call mirpass::find_all_monitors");
+        }
+        mirpass::START_INSTRUMENT.store(true, std::sync::atomic::Ordering::Release);
+        stats::start(tcx.crate_name(rustc_span::def_id::LOCAL_CRATE).to_string(),
+            tcx.output_filenames(()).with_extension("solcon_stats.json"));
+        dcx.abort_if_errors();
+
+        // after START_INSTRUMENT, so that the bodies we query are the instrumented ones used by codegen
+        if static_lock_order::mode() == static_lock_order::StaticCheckMode::WithInstrument {
+            static_lock_order::check_crate(tcx);
+        }
+
+        // Post-check
+        compat::ensure_analysis(tcx);
+        dcx.abort_if_errors();
+
+        // Build
+        let cgus = compat::codegen_units(tcx);
+        info!("code generation units nums #{}", cgus.len());
+        let _instances: Vec<Instance<'_>> = cgus
+        .iter()
+        .flat_map(|cgu| {
+            cgu.items().iter().filter_map(|(mono_item, _)| {
+                if let compat::MonoItem::Fn(instance) = mono_item {
+                    Some(*instance)
+                } else {
+                    None
+                }
             })
-            .collect();
-            dcx.abort_if_errors();
-        });
+        })
+        .collect();
+        dcx.abort_if_errors();
         Compilation::Continue
     }
 }
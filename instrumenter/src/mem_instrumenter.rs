@@ -1,16 +1,15 @@
 use std::collections::BTreeMap;
 
-use rustc_hash::FxHashMap;
+use rustc_data_structures::fx::FxHashMap;
 use rustc_middle::{bug, span_bug};
 use rustc_middle::ty::{Ty, TyCtxt, TyKind};
-use rustc_middle::mir::{BasicBlock, BasicBlockData, Body, CallSource, Local, Location, Operand, Place, Rvalue, SourceInfo, StatementKind, Terminator, TerminatorKind, UnwindAction, UnwindTerminateReason};
+use rustc_middle::mir::{BasicBlock, Body, CallSource, Local, Location, Operand, Place, Rvalue, SourceInfo, StatementKind, Terminator, TerminatorKind, UnwindAction, UnwindTerminateReason};
 use rustc_middle::mir::visit::{PlaceContext, Visitor, NonMutatingUseContext, MutatingUseContext};
-use rustc_middle::mir::patch::MirPatch;
-use rustc_span::source_map::Spanned;
+use crate::compat::{self, MirPatch, Spanned};
 use rustc_span::{Span, DUMMY_SP};
 
 use crate::monitors_finder::MonitorsInfo;
-use crate::utils;
+use crate::{stats, utils};
 
 pub fn instrument_mem_acesses<'tcx>(tcx: TyCtxt<'tcx>, body: &mut Body<'tcx>, 
 monitors: &MonitorsInfo) {
@@ -48,6 +47,13 @@ monitors: &MonitorsInfo) {
     for (block, deref_pointers) in deref_pointers_locations {
         for (statement_index, read_local, write_local) in deref_pointers {
             let mut patch = MirPatch::new(body);
+            // `&raw const *local` has the type `*const pointee`, it is cast to a thin pointer before exposing its address
+            let raw_pointer_ty = |local: Local| {
+                let pointee_ty = body.local_decls[local].ty.builtin_deref(true).expect("derefed local is a pointer");
+                Ty::new_imm_ptr(tcx, pointee_ty)
+            };
+            let read_raw_pointer_ty = read_local.map(raw_pointer_ty);
+            let write_raw_pointer_ty = write_local.map(raw_pointer_ty);
             let bb_data = &mut body.basic_blocks_mut()[block];
             let bb_terminator = &mut bb_data.terminator;
             let bb_statements = &mut bb_data.statements;
@@ -59,12 +65,13 @@ monitors: &MonitorsInfo) {
                 info!("split_off {block:?} {statement_index:?}");
                 let new_bb_statements = bb_statements.split_off(statement_index);
                 info!("instrumenting read {:?} {:?} {:?}", block, statement_index, read_local);
-                let read_raw_pointer = patch.new_temp(tcx.types.usize, span);
+                stats::mem_read_instrumented();
+                let read_raw_pointer = patch.new_temp(read_raw_pointer_ty.unwrap(), span);
                 patch.add_statement(Location{block, statement_index}, StatementKind::Assign(
                     Box::new(
                         (
                             Place::from(read_raw_pointer),
-                            Rvalue::AddressOf(
+                            compat::raw_ptr(
                                 rustc_middle::ty::Mutability::Not,
                                 Place {
                                     local: read_local,
@@ -76,6 +83,19 @@ monitors: &MonitorsInfo) {
                         )
                     )
                 ));
+                let read_thin_pointer = patch.new_temp(Ty::new_imm_ptr(tcx, tcx.types.unit), span);
+                patch.add_statement(Location{block, statement_index}, StatementKind::Assign(
+                    Box::new(
+                        (
+                            Place::from(read_thin_pointer),
+                            Rvalue::Cast(
+                                rustc_middle::mir::CastKind::PtrToPtr,
+                                Operand::Move(Place::from(read_raw_pointer).into()),
+                                Ty::new_imm_ptr(tcx, tcx.types.unit)
+                            ),
+                        )
+                    )
+                ));
                 let read_addr = patch.new_temp(tcx.types.usize, span);
                 patch.add_statement(Location{block, statement_index}, StatementKind::Assign(
                     Box::new(
@@ -83,7 +103,7 @@ monitors: &MonitorsInfo) {
                             Place::from(read_addr),
                             Rvalue::Cast(
                                 rustc_middle::mir::CastKind::PointerExposeProvenance,
-                                Operand::Move(Place::from(read_raw_pointer).into()),
+                                Operand::Move(Place::from(read_thin_pointer).into()),
                                 tcx.types.usize
                             ),
                         )
@@ -95,16 +115,16 @@ monitors: &MonitorsInfo) {
                         node: Operand::Copy(Place::from(read_addr).into()),
                     }
                 ];
-                let newbb = patch.new_block(BasicBlockData {
-                    statements: new_bb_statements,
-                    terminator: bb_terminator.clone(),
-                    is_cleanup: bb_is_cleanup,
-                });
+                let newbb = patch.new_block(compat::basic_block_data(
+                    new_bb_statements,
+                    bb_terminator.clone(),
+                    bb_is_cleanup,
+                ));
                 *bb_terminator = Some(Terminator{
                     source_info: statement_source_info,
                     kind: TerminatorKind::Call {
                         func: utils::instantiate_our_func(tcx, mem_read_before_fn_defid, [], DUMMY_SP),
-                        args: monitor_args,
+                        args: monitor_args.into(),
                         destination: useless_temp.into(),
                         target: Some(newbb),
                         unwind: UnwindAction::Terminate(UnwindTerminateReason::Abi),
@@ -117,12 +137,13 @@ monitors: &MonitorsInfo) {
                 info!("split_off {block:?} {statement_index:?}");
                 let new_bb_statements = bb_statements.split_off(statement_index);
                 info!("instrumenting write {:?} {:?} {:?}", block, statement_index, write_local);
-                let write_raw_pointer = patch.new_temp(tcx.types.usize, span);
+                stats::mem_write_instrumented();
+                let write_raw_pointer = patch.new_temp(write_raw_pointer_ty.unwrap(), span);
                 patch.add_statement(Location{block, statement_index}, StatementKind::Assign(
                     Box::new(
                         (
                             Place::from(write_raw_pointer),
-                            Rvalue::AddressOf(
+                            compat::raw_ptr(
                                 rustc_middle::ty::Mutability::Not,
                                 Place {
                                     local: write_local,
@@ -134,6 +155,19 @@ monitors: &MonitorsInfo) {
                         )
                     )
                 ));
+                let write_thin_pointer = patch.new_temp(Ty::new_imm_ptr(tcx, tcx.types.unit), span);
+                patch.add_statement(Location{block, statement_index}, StatementKind::Assign(
+                    Box::new(
+                        (
+                            Place::from(write_thin_pointer),
+                            Rvalue::Cast(
+                                rustc_middle::mir::CastKind::PtrToPtr,
+                                Operand::Move(Place::from(write_raw_pointer).into()),
+                                Ty::new_imm_ptr(tcx, tcx.types.unit)
+                            ),
+                        )
+                    )
+                ));
                 let write_addr = patch.new_temp(tcx.types.usize, span);
                 patch.add_statement(Location{block, statement_index}, StatementKind::Assign(
                     Box::new(
@@ -141,7 +175,7 @@ monitors: &MonitorsInfo) {
                             Place::from(write_addr),
                             Rvalue::Cast(
                                 rustc_middle::mir::CastKind::PointerExposeProvenance,
-                                Operand::Move(Place::from(write_raw_pointer).into()),
+                                Operand::Move(Place::from(write_thin_pointer).into()),
                                 tcx.types.usize
                             ),
                         )
@@ -153,17 +187,17 @@ monitors: &MonitorsInfo) {
                         node: Operand::Copy(Place::from(write_addr).into()),
                     }
                 ];
-                let newbb = patch.new_block(BasicBlockData {
-                    statements: new_bb_statements,
-                    terminator: bb_terminator.clone(),
-                    is_cleanup: bb_is_cleanup,
-                });
+                let newbb = patch.new_block(compat::basic_block_data(
+                    new_bb_statements,
+                    bb_terminator.clone(),
+                    bb_is_cleanup,
+                ));
                 *bb_terminator = Some(Terminator{
                     source_info: statement_source_info,
                     // kind: TerminatorKind::Goto { target: newbb },
                     kind: TerminatorKind::Call {
                         func: utils::instantiate_our_func(tcx, mem_write_before_fn_defid, [], DUMMY_SP),
-                        args: monitor_args,
+                        args: monitor_args.into(),
                         destination: useless_temp.into(),
                         target: Some(newbb),
                         unwind: UnwindAction::Terminate(UnwindTerminateReason::Abi),
@@ -241,7 +275,7 @@ impl<'tcx> Visitor<'tcx> for PlaceDerefCollector<'tcx> {
                     info!("collected read {:?} {:?}", location, place);
                     self.collect_read(location.block, location.statement_index, pointer);
                 },
-                NonMutatingUseContext::SharedBorrow | NonMutatingUseContext::AddressOf => {},
+                NonMutatingUseContext::SharedBorrow | NonMutatingUseContext::RawBorrow => {},
                 NonMutatingUseContext::PlaceMention | NonMutatingUseContext::Projection => {},
                 NonMutatingUseContext::FakeBorrow => unreachable!("FakeBorrow is disallowed here")
             },
@@ -250,12 +284,12 @@ impl<'tcx> Visitor<'tcx> for PlaceDerefCollector<'tcx> {
                 | MutatingUseContext::Call | MutatingUseContext::Yield
                 | MutatingUseContext::AsmOutput
                 | MutatingUseContext::SetDiscriminant
-                | MutatingUseContext::Drop | MutatingUseContext::Deinit => {
+                | MutatingUseContext::Drop => {
                     let pointer = place.local;
                     info!("collected write {:?} {:?}", location, place);
                     self.collect_write(location.block, location.statement_index, pointer);
                 },
-                MutatingUseContext::Borrow | MutatingUseContext::AddressOf => {}
+                MutatingUseContext::Borrow | MutatingUseContext::RawBorrow => {}
                 MutatingUseContext::Projection| MutatingUseContext::Retag => {},
             },
             PlaceContext::NonUse(_) => {},
@@ -293,7 +327,7 @@ impl<'tcx> Visitor<'tcx> for PlaceRWACollector<'tcx> {
                     }
                 },
                 NonMutatingUseContext::SharedBorrow
-                | NonMutatingUseContext::AddressOf => {
+                | NonMutatingUseContext::RawBorrow => {
                     if let Some(v) = self.mem_acesses.get_mut(&location) {
                         v.2.push(*place);
                     } else {
@@ -309,7 +343,7 @@ impl<'tcx> Visitor<'tcx> for PlaceRWACollector<'tcx> {
                 | MutatingUseContext::Call | MutatingUseContext::Yield
                 | MutatingUseContext::AsmOutput
                 | MutatingUseContext::SetDiscriminant
-                | MutatingUseContext::Drop | MutatingUseContext::Deinit => {
+                | MutatingUseContext::Drop => {
                     // debug!("store {u:?} {:?}", place);
                     if let Some(v) = self.mem_acesses.get_mut(&location) {
                         v.1.push(*place);
@@ -318,7 +352,7 @@ impl<'tcx> Visitor<'tcx> for PlaceRWACollector<'tcx> {
                     }
                 },
                 MutatingUseContext::Borrow
-                | MutatingUseContext::AddressOf => {
+                | MutatingUseContext::RawBorrow => {
                     if let Some(v) = self.mem_acesses.get_mut(&location) {
                         v.2.push(*place);
                     } else {
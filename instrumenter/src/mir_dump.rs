@@ -0,0 +1,130 @@
+// Dump the MIR of functions before and after our passes, like -Zdump-mir but only for our passes.
+//   SOLCON_DUMP_MIR=<filters>      comma separated substrings of def paths (e.g. `main,Bank::transfer`), `*` for all functions
+//   SOLCON_DUMP_MIR_DIR=<dir>      where to write, by default <output dir>/<crate>.solcon_mir (e.g. target/debug/deps)
+//   SOLCON_DUMP_MIR_GRAPHVIZ=1     also write Graphviz CFGs (.dot), blocks inserted by us are filled
+// Files are <def path>.before.mir and <def path>.after.mir, blocks inserted by our passes are annotated with the
+// instrumenter which inserted them.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use rustc_middle::mir::{self, BasicBlock, Body};
+use rustc_middle::ty::TyCtxt;
+
+use crate::compat;
+
+static FILTERS: LazyLock<Option<Vec<String>>> = LazyLock::new(|| std::env::var("SOLCON_DUMP_MIR").ok()
+    .map(|filters| filters.split(',').map(str::trim).filter(|filter| !filter.is_empty()).map(str::to_owned).collect()));
+static GRAPHVIZ: LazyLock<bool> = LazyLock::new(|| std::env::var("SOLCON_DUMP_MIR_GRAPHVIZ").is_ok_and(|value| value != "0"));
+
+/// The instrumenter which inserted each block, recorded only when the body is dumped.
+pub(crate) struct BlockOrigins {
+    enabled: bool,
+    recorded_blocks: usize,
+    /// (first block, end block (exclusive), origin)
+    origins: Vec<(usize, usize, String)>,
+}
+
+impl BlockOrigins {
+    pub(crate) fn new(enabled: bool, body: &Body<'_>) -> Self {
+        BlockOrigins { enabled, recorded_blocks: body.basic_blocks.len(), origins: Vec::new() }
+    }
+
+    /// Attribute the blocks appended to the body since the last call to `origin`.
+    pub(crate) fn record(&mut self, body: &Body<'_>, origin: impl FnOnce() -> String) {
+        if !self.enabled {
+            return;
+        }
+        let blocks = body.basic_blocks.len();
+        if blocks > self.recorded_blocks {
+            self.origins.push((self.recorded_blocks, blocks, origin()));
+            self.recorded_blocks = blocks;
+        }
+    }
+
+    fn origin_of(&self, block: BasicBlock) -> Option<&str> {
+        let index = block.index();
+        self.origins.iter()
+            .find(|(start, end, _)| (*start..*end).contains(&index))
+            .map(|(_, _, origin)| origin.as_str())
+    }
+}
+
+pub(crate) fn should_dump(tcx: TyCtxt<'_>, body: &Body<'_>) -> bool {
+    let Some(filters) = FILTERS.as_ref() else {
+        return false;
+    };
+    let def_path_str = tcx.def_path_str(body.source.def_id());
+    filters.iter().any(|filter| filter == "*" || def_path_str.contains(filter.as_str()))
+}
+
+fn dump_dir(tcx: TyCtxt<'_>) -> PathBuf {
+    match std::env::var_os("SOLCON_DUMP_MIR_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => tcx.output_filenames(()).with_extension("solcon_mir"),
+    }
+}
+
+fn file_stem(tcx: TyCtxt<'_>, body: &Body<'_>) -> String {
+    let def_path_str = tcx.def_path_str(body.source.def_id()).replace("::", ".");
+    def_path_str.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "._-#{}".contains(c) { c } else { '_' })
+        .collect()
+}
+
+/// `stage` is `before` or `after`, origins are given for the instrumented body.
+pub(crate) fn dump<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, stage: &str, origins: Option<&BlockOrigins>) {
+    let dir = dump_dir(tcx);
+    let stem = file_stem(tcx, body);
+    let result = std::fs::create_dir_all(&dir)
+        .and_then(|_| write_mir(tcx, body, origins, &dir.join(format!("{stem}.{stage}.mir"))))
+        .and_then(|_| if *GRAPHVIZ {
+            write_graphviz(tcx, body, origins, &dir.join(format!("{stem}.{stage}.dot")))
+        } else {
+            Ok(())
+        });
+    match result {
+        Ok(_) => info!("dumped {stage} MIR of {} to {}", tcx.def_path_str(body.source.def_id()), dir.display()),
+        Err(err) => tcx.dcx().warn(format!("solcon: cannot dump MIR to {}: {err}", dir.display())),
+    }
+}
+
+fn write_mir<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, origins: Option<&BlockOrigins>, path: &Path) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "// MIR of {} {}", tcx.def_path_str(body.source.def_id()),
+        if origins.is_some() { "after solcon instrumentation" } else { "before solcon instrumentation" })?;
+    if let Some(origins) = origins {
+        for (start, end, origin) in &origins.origins {
+            if end - start == 1 {
+                writeln!(file, "// bb{start} inserted by {origin}")?;
+            } else {
+                writeln!(file, "// bb{start}..bb{} inserted by {origin}", end - 1)?;
+            }
+        }
+    }
+    compat::write_mir_fn(tcx, body, &|block| {
+        let origin = origins?.origin_of(block)?;
+        Some(format!("    // {block:?} inserted by {origin}"))
+    }, &mut file)?;
+    file.flush()
+}
+
+fn write_graphviz(tcx: TyCtxt<'_>, body: &Body<'_>, origins: Option<&BlockOrigins>, path: &Path) -> io::Result<()> {
+    let mut dot = Vec::new();
+    mir::graphviz::write_mir_fn_graphviz(tcx, body, false, &mut dot)?;
+    let mut dot = String::from_utf8_lossy(&dot).into_owned();
+    // fill our blocks, the node attributes are added before the closing brace of the digraph
+    if let (Some(origins), Some(closing_brace)) = (origins, dot.rfind('}')) {
+        let graph_name = mir::graphviz_safe_def_name(body.source.def_id());
+        let mut attrs = String::new();
+        for block in body.basic_blocks.indices() {
+            if let Some(origin) = origins.origin_of(block) {
+                attrs.push_str(&format!("    bb{}__{graph_name} [style=filled fillcolor=\"lightyellow\" tooltip=\"inserted by {}\"];\n",
+                    block.index(), origin.replace('"', "'")));
+            }
+        }
+        dot.insert_str(closing_brace, &attrs);
+    }
+    std::fs::write(path, dot)
+}
@@ -0,0 +1,126 @@
+// SOLCON_VALIDATE_MIR=1 checks the MIR after each of our passes on a body, so that a pass building broken MIR is
+// reported right away instead of as an obscure ICE during codegen.
+// rustc's MIR validator is private since nightly-2025, so these are our own checks of what our passes build:
+// the control flow graph (targets in bounds, cleanup blocks only reached by unwinding and only left to cleanup
+// blocks), assignments (rvalue type is a subtype of the place type) and calls of fn items (number and types of the
+// arguments and of the destination).
+// Failures are reported as compile errors naming the pass, the function and the location (bbN[statement index]).
+use std::sync::LazyLock;
+
+use rustc_middle::mir::{Body, Location, StatementKind, TerminatorKind, UnwindAction};
+use rustc_middle::ty::{self, Ty, TyCtxt};
+
+use crate::compat::{self, TypingEnv};
+
+static ENABLED: LazyLock<bool> = LazyLock::new(|| std::env::var("SOLCON_VALIDATE_MIR").is_ok_and(|value| value != "0"));
+
+pub(crate) fn enabled() -> bool {
+    *ENABLED
+}
+
+pub(crate) fn validate_after_pass<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, pass: &str) {
+    if !enabled() {
+        return;
+    }
+    let def_id = body.source.def_id();
+    let def_path_str = tcx.def_path_str(def_id);
+    debug!("validating MIR of {def_path_str} after {pass}");
+    let mut failures = check_cfg(body);
+    // types are only meaningful on a sound graph
+    if failures.is_empty() {
+        failures = check_types(tcx, compat::typing_env(tcx, def_id), body);
+    }
+    for (location, msg) in failures {
+        let mut diag = tcx.dcx().struct_span_err(body.source_info(location).span,
+            format!("solcon: broken MIR after pass `{pass}` in `{def_path_str}` at {location:?}: {msg}"));
+        if let Some(terminator) = &body.basic_blocks[location.block].terminator {
+            diag.note(format!("the terminator of {:?} is `{:?}`", location.block, terminator.kind));
+        }
+        diag.emit();
+    }
+}
+
+fn check_cfg(body: &Body<'_>) -> Vec<(Location, String)> {
+    let blocks = &body.basic_blocks;
+    let mut failures = Vec::new();
+    for (bb, data) in blocks.iter_enumerated() {
+        let location = body.terminator_loc(bb);
+        let Some(terminator) = &data.terminator else {
+            failures.push((location, "block without terminator".to_owned()));
+            continue;
+        };
+        let mut fail = |msg: String| failures.push((location, msg));
+        let unwind_target = match terminator.unwind() {
+            Some(UnwindAction::Cleanup(target)) => {
+                if data.is_cleanup {
+                    fail(format!("cleanup block unwinds to {target:?}"));
+                } else if !blocks.get(*target).is_some_and(|target| target.is_cleanup) {
+                    fail(format!("unwinds to {target:?} which is not a cleanup block"));
+                }
+                Some(*target)
+            }
+            Some(UnwindAction::Continue) if data.is_cleanup => {
+                fail("cleanup block unwinds to the caller".to_owned());
+                None
+            }
+            _ => None,
+        };
+        for target in terminator.successors().filter(|target| Some(*target) != unwind_target) {
+            match blocks.get(target) {
+                None => fail(format!("target {target:?} out of bounds, the body has {} blocks", blocks.len())),
+                Some(target_data) if target_data.is_cleanup != data.is_cleanup => {
+                    fail(format!("normal edge to {target:?} between a cleanup and a non cleanup block"));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    failures
+}
+
+fn check_types<'tcx>(tcx: TyCtxt<'tcx>, typing_env: TypingEnv<'tcx>, body: &Body<'tcx>) -> Vec<(Location, String)> {
+    let normalize = |ty: Ty<'tcx>| compat::normalize_erasing_regions(tcx, typing_env, ty);
+    let is_subtype = |src: Ty<'tcx>, dest: Ty<'tcx>| compat::sub_types(tcx, typing_env, normalize(src), normalize(dest));
+    let mut failures = Vec::new();
+    for (bb, data) in body.basic_blocks.iter_enumerated() {
+        for (statement_index, statement) in data.statements.iter().enumerate() {
+            let StatementKind::Assign(box (place, rvalue)) = &statement.kind else {
+                continue;
+            };
+            let (place_ty, rvalue_ty) = (place.ty(body, tcx).ty, rvalue.ty(body, tcx));
+            if !is_subtype(rvalue_ty, place_ty) {
+                failures.push((Location { block: bb, statement_index },
+                    format!("assignment of `{rvalue_ty}` to `{place:?}` of type `{place_ty}`")));
+            }
+        }
+        let TerminatorKind::Call { func, args, destination, .. } = &data.terminator().kind else {
+            continue;
+        };
+        // fn pointers and closures called through Fn* are left to codegen
+        let ty::FnDef(callee, generic_args) = *normalize(func.ty(body, tcx)).kind() else {
+            continue;
+        };
+        let location = body.terminator_loc(bb);
+        let sig = compat::fn_sig(tcx, callee, Some(generic_args));
+        let inputs = sig.inputs();
+        if args.len() < inputs.len() || (args.len() > inputs.len() && !sig.c_variadic()) {
+            failures.push((location, format!("call of `{}` with {} arguments, it takes {}",
+                tcx.def_path_str(callee), args.len(), inputs.len())));
+            continue;
+        }
+        for (i, (arg, input_ty)) in args.iter().zip(inputs).enumerate() {
+            let arg_ty = arg.node.ty(body, tcx);
+            if !is_subtype(arg_ty, *input_ty) {
+                failures.push((location, format!("argument {i} of the call of `{}` is `{arg_ty}`, expected `{input_ty}`",
+                    tcx.def_path_str(callee))));
+            }
+        }
+        let destination_ty = destination.ty(body, tcx).ty;
+        if !is_subtype(sig.output(), destination_ty) {
+            failures.push((location, format!("call of `{}` returns `{}` to `{destination:?}` of type `{destination_ty}`",
+                tcx.def_path_str(callee), sig.output())));
+        }
+    }
+    failures
+}
+
@@ -5,12 +5,13 @@ use rustc_hir::def::DefKind;
 use rustc_metadata::creader::CStore;
 use rustc_middle::mir::{*};
 use rustc_middle::ty::{TyCtxt, TyKind};
-use rustc_middle::mir::patch::MirPatch;
+use crate::compat::{self, MirPatch};
 use rustc_span::def_id::{CrateNum, DefId, DefIndex, LocalDefId, LOCAL_CRATE};
+use rustc_span::Symbol;
 
 pub(crate) use crate::utils;
 use crate::monitors_finder::{MonitorsFinder, MonitorsInfo};
-use crate::{config, mem_instrumenter};
+use crate::{config, def_paths, mem_instrumenter, mir_dump, mir_validation, monitor_signature, rules, stats};
 
 use crate::function_call_instrumenter;
 pub use function_call_instrumenter::FunctionCallInstrumenter;
@@ -37,13 +38,27 @@ mod condvar_wait_timeout_handler;
 mod condvar_wait_timeout_ms_handler;
 mod condvar_wait_while_handler;
 mod condvar_wait_timeout_while_handler;
-mod entry_fn_handler;
+mod condvar_notify_one_handler;
+mod condvar_notify_all_handler;
+mod thread_spawn_handler;
+mod thread_join_handler;
+mod channel_handler;
+mod once_handler;
+mod refcount_handler;
+mod refcount_drop_handler;
+mod rule_call_handler;
+mod condvar_wait_loop_check;
+mod fn_entry_exit_handler;
 
 pub trait OurMirPass {
     fn run_pass<'tcx>(&self, 
         tcx: TyCtxt<'tcx>,
         body: &Body<'tcx>, monitors: &MonitorsInfo)
     -> Option< MirPatch<'tcx> >;
+
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
 }
 
 pub(crate) static START_INSTRUMENT : std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
@@ -54,51 +69,64 @@ pub(crate) static ENTRY_FN_DEF_ID : std::sync::OnceLock<DefId> = std::sync::Once
 pub fn find_all_monitors(tcx: TyCtxt<'_>) {
     info!("prescaning");
     let mut monitors = MonitorsInfo::default();
-    let crates = tcx.crates(());
     let crate_store_untracked = tcx.cstore_untracked();
     let crate_store = crate_store_untracked
         .as_any()
         .downcast_ref::<CStore>()
         .unwrap();
-    for krate in crates {
-        let krate = krate.clone();
-        if krate != LOCAL_CRATE {
-            let crate_name = tcx.crate_name(krate);
-            if crate_name.as_str() != config::MONITORS_LIB_CRATE_NAME {
-                trace!("skip mismatch crate name {crate_name}");
+    // earlier monitor crates take precedence, a monitor is only set when it is not found yet
+    for monitor_crate_name in utils::hook_monitor_crate_names() {
+        let Some(&krate) = tcx.crates(()).iter().find(|&&krate| tcx.crate_name(krate).as_str() == monitor_crate_name) else {
+            warn!("monitor crate {monitor_crate_name} is not loaded");
+            continue;
+        };
+        let crate_dep_kind = tcx.crate_dep_kind(krate);
+        info!("traversaling crate {} ({crate_dep_kind:?})", monitor_crate_name);
+        // Only public-facing way to traverse all the definitions in a non-local crate.
+        // inspired by hacspec(https://github.com/rust-lang/rust/pull/85889)
+        let crate_num_def_ids = crate_store.num_def_ids_untracked(krate);
+        let def_ids = (0..crate_num_def_ids).map(|id| DefId {
+            krate,
+            index: DefIndex::from_usize(id),
+        });
+        for def_id in def_ids {
+            let def_path_str = tcx.def_path_str(def_id);
+            let def_kind = tcx.def_kind(def_id);
+            trace!("found external {def_kind:?} : {}", def_path_str);
+            if !matches!(def_kind, DefKind::Fn) {
                 continue;
             }
-            let crate_dep_kind = tcx.dep_kind(krate);
-            info!("traversaling crate {} ({crate_dep_kind:?})", crate_name);
-            // Only public-facing way to traverse all the definitions in a non-local crate.
-            // inspired by hacspec(https://github.com/rust-lang/rust/pull/85889)
-            let crate_num_def_ids = crate_store.num_def_ids_untracked(krate);
-            let def_ids = (0..crate_num_def_ids).into_iter().map(|id| DefId {
-                krate: krate,
-                index: DefIndex::from_usize(id),
-            });
-            for def_id in def_ids {
-                let def_path_str = tcx.def_path_str(def_id);
-                let def_kind = tcx.def_kind(def_id);
-                trace!("found external {def_kind:?} : {}", def_path_str);
-                if matches!(def_kind, DefKind::Fn) {
+            // #[solcon::monitor(kind = "...")] wins over the name of the function
+            match monitor_kind_of(tcx, def_id) {
+                Some(kind) => {
+                    if !monitors.try_match_with_monitor_kind(kind.as_str(), &def_id) {
+                        tcx.dcx().warn(format!("solcon: unknown monitor kind `{kind}` of `{def_path_str}`"));
+                    }
+                }
+                None => {
                     monitors.try_match_with_our_function(tcx, &def_id);
                 }
             }
         }
     }
     info!("{monitors:#?}");
+    monitor_signature::check_monitors(tcx, &monitors);
     MONITORS.set(monitors).unwrap()
 }
 
-fn original_optimized_mir(tcx: TyCtxt<'_>, did: LocalDefId) -> &Body<'_> {
-    let original_providers : _ = {
-        let mut provider = rustc_middle::util::Providers::default();
-        rustc_mir_transform::provide(&mut provider);
-        provider
-    };
-    let original_optimized_mir = original_providers.optimized_mir;
-    original_optimized_mir(tcx, did)
+/// The kind of `#[solcon::monitor(kind = "mutex_lock_before")]`, monitor crates register the tool with
+/// `#![feature(register_tool)] #![register_tool(solcon)]`.
+fn monitor_kind_of(tcx: TyCtxt<'_>, def_id: DefId) -> Option<Symbol> {
+    let attr_path = [Symbol::intern("solcon"), Symbol::intern("monitor")];
+    let attr = tcx.get_attrs_by_path(def_id, &attr_path).next()?;
+    let kind = attr.meta_item_list()?.iter()
+        .filter_map(|item| item.meta_item())
+        .find(|item| item.has_name(Symbol::intern("kind")))
+        .and_then(|item| item.value_str());
+    if kind.is_none() {
+        tcx.dcx().warn(format!("solcon: expect #[solcon::monitor(kind = \"...\")] on `{}`", tcx.def_path_str(def_id)));
+    }
+    kind
 }
 
 unsafe fn get_mut_ref_body<'bodyref, 'tcx>(body: &Body<'tcx>) -> &'bodyref mut Body<'tcx> {
@@ -114,19 +142,30 @@ unsafe fn get_mut_ref_body<'bodyref, 'tcx>(body: &Body<'tcx>) -> &'bodyref mut B
 /// The original query is "Optimize the MIR and prepare it for codegen."
 /// here We instrument mir::Body
 pub(crate) fn our_optimized_mir(tcx: TyCtxt<'_>, did: LocalDefId) -> &Body<'_> {
-    let body = original_optimized_mir(tcx, did);
+    let body = compat::original_optimized_mir(tcx, did);
     if START_INSTRUMENT.load(std::sync::atomic::Ordering::Acquire) {
         // we should be the only reference holder in the window from the end of the original query until the return, according to the current implementation of optimized_mir .
         let body_mut = unsafe {get_mut_ref_body(body)};
         let monitors = MONITORS.get().unwrap();
+        let dump_mir = mir_dump::should_dump(tcx, body_mut);
+        if dump_mir {
+            mir_dump::dump(tcx, body_mut, "before", None);
+        }
+        let mut origins = mir_dump::BlockOrigins::new(dump_mir, body_mut);
+        run_our_pass_on_body(tcx, &monitors, did, body_mut, &mut origins);
+        // after the other passes, so that program start and exit enclose the call tracing hooks of the entry fn
         if let Some(entry_fn_def_id) = ENTRY_FN_DEF_ID.get() {
             let entry_fn_local_def_id = entry_fn_def_id.expect_local();
             if entry_fn_local_def_id == did {
-                let entry_fn_handler = entry_fn_handler::EntryFnBodyInstrumenter::default();
-                entry_fn_handler.instrument_body(tcx, body_mut, monitors);
+                let entry_fn_handler = fn_entry_exit_handler::FnEntryExitInstrumenter::entry_fn(monitors);
+                entry_fn_handler.instrument_body(tcx, body_mut);
+                origins.record(body_mut, || "entry fn".to_owned());
+                mir_validation::validate_after_pass(tcx, body_mut, "entry fn");
             }
         }
-        run_our_pass_on_body(tcx, &monitors, did, body_mut);
+        if dump_mir {
+            mir_dump::dump(tcx, body_mut, "after", Some(&origins));
+        }
     }
     body
 }
@@ -142,15 +181,16 @@ pub fn find_entry_fn(tcx: TyCtxt<'_>) {
 }
 
 pub fn run_our_pass_on_body<'tcx>(tcx: TyCtxt<'tcx>, monitors: &MonitorsInfo,
-  local_def_id:LocalDefId, body: &mut Body<'tcx>
+  local_def_id:LocalDefId, body: &mut Body<'tcx>, origins: &mut mir_dump::BlockOrigins,
 ) {
     let def_id = local_def_id.to_def_id();
     let def_path = tcx.def_path(def_id);
     let def_path_str = tcx.def_path_str(def_id);
-    let body_owner_kind = tcx.hir().body_owner_kind(local_def_id);
+    let body_owner_kind = tcx.hir_body_owner_kind(local_def_id);
     match body_owner_kind {
-        BodyOwnerKind::Const{..} | BodyOwnerKind::Static(..) => {
+        BodyOwnerKind::Const{..} | BodyOwnerKind::Static(..) | BodyOwnerKind::GlobalAsm => {
             warn!("skip body kind {:?}", body_owner_kind);
+            stats::body_skipped(stats::SkipReason::ConstOrStatic);
             return;
         }
         BodyOwnerKind::Fn | BodyOwnerKind::Closure => {}
@@ -160,15 +200,18 @@ pub fn run_our_pass_on_body<'tcx>(tcx: TyCtxt<'tcx>, monitors: &MonitorsInfo,
     if tcx.is_foreign_item(def_id) {
         // 跳过外部函数(例如 extern "C")
         debug!("skip body instance of {} because is_foreign_item", def_path_str);
+        stats::body_skipped(stats::SkipReason::Foreign);
         return;
     }
     // Skip promoted src
     if body.source.promoted.is_some() {
         debug!("skip body instance of {} because promoted.is_some", def_path_str);
+        stats::body_skipped(stats::SkipReason::Promoted);
         return;
     }
     if is_filtered_def_path(tcx, &def_path) {
         debug!("skip body instance of {:?} because utils::is_filtered_def_path", def_path_str);
+        stats::body_skipped(stats::SkipReason::FilteredCrate);
         return;
     }
     // dont know why enable here leads to undefined symbol. unfinished
@@ -177,7 +220,12 @@ pub fn run_our_pass_on_body<'tcx>(tcx: TyCtxt<'tcx>, monitors: &MonitorsInfo,
     //     continue;
     // }
     info!("--------- running pass on function body of {}", def_path_str);
-    inject_for_body(tcx, body, &monitors, &[
+    stats::body_processed();
+    let channel_call_handlers: Vec<_> = channel_handler::CHANNEL_TARGETS.iter().map(channel_handler::ChannelCallHandler::new).collect();
+    let once_call_handlers: Vec<_> = once_handler::ONCE_TARGETS.iter().map(once_handler::OnceCallHandler::new).collect();
+    let refcount_call_handlers: Vec<_> = refcount_handler::REFCOUNT_TARGETS.iter().map(refcount_handler::RefCountCallHandler::new).collect();
+    let rule_call_handlers: Vec<_> = rules::resolved_call_rules().iter().map(rule_call_handler::RuleCallHandler::new).collect();
+    let builtin_call_handlers: &[&dyn FunctionCallInstrumenter] = &[
         #[cfg(feature = "enable_debug_passes")]
         &debug_use_test_target_handler::TestTargetCallHandler::default(),
         #[cfg(feature = "enable_debug_passes")]
@@ -195,14 +243,37 @@ pub fn run_our_pass_on_body<'tcx>(tcx: TyCtxt<'tcx>, monitors: &MonitorsInfo,
         &condvar_wait_timeout_ms_handler::CondvarWaitTimeoutMsCallHandler::default(), 
         &condvar_wait_while_handler::CondvarWaitWhileCallHandler::default(), 
         &condvar_wait_timeout_while_handler::CondvarWaitTimeoutWhileCallHandler::default(), 
-    ],
-    &[
+        &condvar_notify_one_handler::CondvarNotifyOneCallHandler::default(), 
+        &condvar_notify_all_handler::CondvarNotifyAllCallHandler::default(), 
+        &thread_spawn_handler::ThreadSpawnCallHandler::default(), 
+        &thread_join_handler::ThreadJoinCallHandler::default(), 
+    ];
+    let call_handlers: Vec<&dyn FunctionCallInstrumenter> = builtin_call_handlers.iter().copied()
+        .chain(channel_call_handlers.iter().map(|handler| handler as &dyn FunctionCallInstrumenter))
+        .chain(once_call_handlers.iter().map(|handler| handler as &dyn FunctionCallInstrumenter))
+        .chain(refcount_call_handlers.iter().map(|handler| handler as &dyn FunctionCallInstrumenter))
+        .chain(rule_call_handlers.iter().map(|handler| handler as &dyn FunctionCallInstrumenter))
+        .collect();
+    let drop_handlers: &[&dyn ObjectDropInstrumenter] = &[
         &mutexguard_drop_handler::MutexGuardDropInstrumenter::default(),
         &rwlock_readguard_drop_handler::RwLockReadGuardDropInstrumenter::default(),
         &rwlock_writeguard_drop_handler::RwLockWriteGuardDropInstrumenter::default(),
-        
-    ]
-    , &[]);
+        &refcount_drop_handler::ArcDropInstrumenter::default(),
+        &refcount_drop_handler::RcDropInstrumenter::default(),
+    ];
+    let call_handler_names: Vec<_> = call_handlers.iter().map(|handler| handler.target_name()).collect();
+    stats::register_handlers(call_handler_names.iter().map(String::as_str),
+        drop_handlers.iter().map(|handler| handler.target_ty()));
+    inject_for_body(tcx, body, &monitors, origins, &call_handlers, drop_handlers, &[
+        &condvar_wait_loop_check::CondvarWaitLoopCheck::default(),
+    ]);
+    // last, so that the exit hook runs after the monitors of everything else in the body
+    if fn_entry_exit_handler::should_trace_calls(tcx, def_id) {
+        fn_entry_exit_handler::FnEntryExitInstrumenter::traced_fn(tcx, monitors, body).instrument_body(tcx, body);
+        origins.record(body, || "call tracing".to_owned());
+        mir_validation::validate_after_pass(tcx, body, "call tracing");
+        stats::fn_traced();
+    }
 }
 
 fn is_filtered_def_path(tcx: TyCtxt<'_>, def_path: &DefPath) -> bool {
@@ -251,7 +322,7 @@ fn is_filtered_crate(tcx: TyCtxt<'_>, krate: &CrateNum) -> bool {
         // our runtime library
         "this_is_our_monitor_function",
     ];
-    if FILTERED_CRATES.contains(&crate_name_str) {
+    if FILTERED_CRATES.contains(&crate_name_str) || utils::is_monitor_crate(crate_name_str) {
         debug!("filtered crate_name {crate_name_str}");
         return true;
     } else {
@@ -260,68 +331,104 @@ fn is_filtered_crate(tcx: TyCtxt<'_>, krate: &CrateNum) -> bool {
     false
 }
 
-fn inject_for_body<'tcx>(tcx: TyCtxt<'tcx>, body: &mut Body<'tcx>, monitors: &MonitorsInfo, 
+fn inject_for_body<'tcx>(tcx: TyCtxt<'tcx>, body: &mut Body<'tcx>, monitors: &MonitorsInfo, origins: &mut mir_dump::BlockOrigins,
     function_call_instrumenters: &[&dyn FunctionCallInstrumenter],
     object_drop_instrumenters: &[&dyn ObjectDropInstrumenter],
     our_passes: &[&dyn OurMirPass],
 ) {
     // Instrument memory acesses
     mem_instrumenter::instrument_mem_acesses(tcx, body, monitors);
+    origins.record(body, || "memory accesses".to_owned());
+    mir_validation::validate_after_pass(tcx, body, "memory accesses");
     // Execute function call instrumenters
-    execute_all_function_call_instrumenters(tcx, body, monitors, function_call_instrumenters);
+    execute_all_function_call_instrumenters(tcx, body, monitors, origins, function_call_instrumenters);
+    mir_validation::validate_after_pass(tcx, body, "function calls");
     // Execute object drop instrumenters
-    execute_all_obj_drop_instrumenters(tcx, body, monitors, object_drop_instrumenters);
+    execute_all_obj_drop_instrumenters(tcx, body, monitors, origins, object_drop_instrumenters);
+    mir_validation::validate_after_pass(tcx, body, "object drops");
     // Execute our other passes
     for p in our_passes.iter() {
         let patch = p.run_pass(tcx, body, monitors);
         if let Some(patch) = patch {
             patch.apply(body);
+            origins.record(body, || p.name().to_owned());
+            mir_validation::validate_after_pass(tcx, body, p.name());
         }
     }
 }
 
 fn execute_all_function_call_instrumenters<'tcx>(tcx: TyCtxt<'tcx>, body: &mut Body<'tcx>, 
-monitors: &MonitorsInfo, function_call_instrumenters: &[&dyn FunctionCallInstrumenter]
+monitors: &MonitorsInfo, origins: &mut mir_dump::BlockOrigins, function_call_instrumenters: &[&dyn FunctionCallInstrumenter]
 ) {
+    // resolved once per crate by def_paths, targets which are not in the dependencies of this crate are None
+    let target_def_ids: Vec<Option<DefId>> = function_call_instrumenters.iter()
+        .map(|instrumenter| def_paths::resolve(tcx, instrumenter.target_function()))
+        .collect();
+    // None for targets without a Self type, Some(None) if the Self type is not in the dependencies of this crate
+    let target_self_ty_def_ids: Vec<Option<Option<DefId>>> = function_call_instrumenters.iter()
+        .map(|instrumenter| instrumenter.target_self_ty().map(|self_ty| def_paths::resolve(tcx, self_ty)))
+        .collect();
     let mut instruement_pos = Vec::new();
     for (bb, bb_data) in body.basic_blocks.iter_enumerated() {
         let terminator = bb_data.terminator();
         if let TerminatorKind::Call { func, ..} = &terminator.kind {
-            // let func_def_path = utils::get_function_path(tcx, &body.local_decls, &func);
-            let Some(func_def_path_str) = utils::get_function_path_str(tcx, &body.local_decls, &func) else {
-                warn!("Found call to function but fail to get function DefPath");
+            let Some(func_def_id) = utils::get_function_def_id(tcx, &body.local_decls, func) else {
+                debug!("Found call to function but fail to get function DefId");
                 continue;
             };
-            debug!("Found call to function: {:?}", func_def_path_str);
-            for instrumenter in function_call_instrumenters.iter() {
-                let target_function = instrumenter.target_function();
-                if func_def_path_str == target_function {
+            debug!("Found call to function: {:?}", func_def_id);
+            for ((instrumenter, target_def_id), self_ty_def_id) in function_call_instrumenters.iter().zip(&target_def_ids).zip(&target_self_ty_def_ids) {
+                if *target_def_id == Some(func_def_id) && self_ty_matches(tcx, body, func, *self_ty_def_id) {
+                    let target_function = instrumenter.target_name();
                     let caller_def_id = body.source.def_id();
                     let caller_def_path_str = tcx.def_path_str(caller_def_id);
                     info!("Found call to {} in {:?}  (should instrumented)", target_function, caller_def_path_str);
+                    stats::call_site_found(&target_function);
                     instruement_pos.push((bb, instrumenter, caller_def_id));
                 }
             }
         }
     }
     for (bb, instrumenter, caller_def_path_str) in instruement_pos.into_iter() {
-        let target_function = instrumenter.target_function();
+        let target_function = instrumenter.target_name();
         let mut loc_bb = bb;
         info!("Instrumenting before handler for call to function {} in {:?}", target_function, caller_def_path_str);
         if let Some((patch, moved_new_block)) = instrumenter.instrument_call_before(tcx, body, monitors, loc_bb) {
             loc_bb = moved_new_block;
             patch.apply(body);
+            origins.record(body, || format!("before monitor of call to {target_function} in {bb:?}"));
+            stats::call_instrumented(&target_function, stats::Hook::Before);
         };
         info!("Instrumenting after handler for call to function {} in {:?}", target_function, caller_def_path_str);
         if let Some((patch, _moved_new_block)) = instrumenter.instrument_call_after(tcx, body, monitors, loc_bb) {
             patch.apply(body);
+            origins.record(body, || format!("after monitor of call to {target_function} in {bb:?}"));
+            stats::call_instrumented(&target_function, stats::Hook::After);
         }
     }
 }
 
+/// Whether the Self type of a call to a trait method (its first generic arg) is the ADT `self_ty_def_id`.
+fn self_ty_matches<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, func: &Operand<'tcx>, self_ty_def_id: Option<Option<DefId>>) -> bool {
+    let Some(self_ty_def_id) = self_ty_def_id else {
+        return true;
+    };
+    let self_ty = utils::get_function_generic_args(tcx, &body.local_decls, func)
+        .and_then(|generic_args| generic_args.types().next());
+    match (self_ty.map(|ty| ty.kind()), self_ty_def_id) {
+        (Some(TyKind::Adt(adt_def, _)), Some(self_ty_def_id)) => adt_def.did() == self_ty_def_id,
+        _ => false,
+    }
+}
+
 fn execute_all_obj_drop_instrumenters<'tcx>(tcx: TyCtxt<'tcx>, body: &mut Body<'tcx>, 
-monitors: &MonitorsInfo, object_drop_instrumenters: &[&dyn ObjectDropInstrumenter]) 
+monitors: &MonitorsInfo, origins: &mut mir_dump::BlockOrigins, object_drop_instrumenters: &[&dyn ObjectDropInstrumenter]) 
 {
+    let target_def_ids: Vec<Option<DefId>> = object_drop_instrumenters.iter()
+        .map(|instrumenter| def_paths::resolve(tcx, instrumenter.target_ty()))
+        .collect();
+    let mem_drop_def_id = tcx.get_diagnostic_item(rustc_span::sym::mem_drop);
+    let drop_in_place_def_id = compat::drop_in_place_fn(tcx);
     let mut instruement_pos = Vec::new();
     for (bb, bb_data) in body.basic_blocks.iter_enumerated() {
         let terminator = bb_data.terminator();
@@ -330,25 +437,25 @@ monitors: &MonitorsInfo, object_drop_instrumenters: &[&dyn ObjectDropInstrumente
                 let ty = place.ty(&body.local_decls, tcx).ty;
                 if let TyKind::Adt(adt_def, generic_args) = ty.kind() {
                     let ty_def_id = adt_def.did();
-                    let ty_def_path_str = tcx.def_path_str(ty_def_id);
-                    info!("found drop of type {}", ty_def_path_str);
-                    for instrumenter in object_drop_instrumenters.iter() {
+                    info!("found drop of type {}", tcx.def_path_str(ty_def_id));
+                    for (instrumenter, target_def_id) in object_drop_instrumenters.iter().zip(&target_def_ids) {
                         let target_ty = instrumenter.target_ty();
-                        if ty_def_path_str == target_ty {
+                        if *target_def_id == Some(ty_def_id) {
                             let caller_def_id = body.source.def_id();
                             let caller_def_path_str = tcx.def_path_str(caller_def_id);
                             info!("Found drop of {} in {:?}  (should instrumented)", target_ty, caller_def_path_str);
+                            stats::drop_site_found(target_ty);
                             instruement_pos.push((bb, instrumenter, caller_def_id, generic_args));
                         }
                     }
                 }
             }
             TerminatorKind::Call{ func, ..} => {
-                let Some(func_def_path_str) = utils::get_function_path_str(tcx, &body.local_decls, &func) else {
-                    warn!("Found call to function but fail to get function DefPath");
+                let Some(func_def_id) = utils::get_function_def_id(tcx, &body.local_decls, func) else {
+                    debug!("Found call to function but fail to get function DefId");
                     continue;
                 };
-                if func_def_path_str == "std::mem::drop" || func_def_path_str == "core::mem::drop" {
+                if Some(func_def_id) == mem_drop_def_id {
                     let Some(generic_args) = utils::get_function_generic_args(tcx, &body.local_decls, &func) else {
                         warn!("Found call to std/core::mem::drop but fail to get function generic_args");
                         continue;
@@ -358,22 +465,22 @@ monitors: &MonitorsInfo, object_drop_instrumenters: &[&dyn ObjectDropInstrumente
                     // only structs, enums, and unions can implement Drop.
                     if let TyKind::Adt(adt_def, generic_args) = arg_ty.kind() {
                         let ty_def_id = adt_def.did();
-                        let ty_def_path_str = tcx.def_path_str(ty_def_id);
-                        info!("found call to drop function {func_def_path_str} for type {ty_def_path_str}");
-                        for instrumenter in object_drop_instrumenters.iter() {
+                        info!("found call to std::mem::drop for type {}", tcx.def_path_str(ty_def_id));
+                        for (instrumenter, target_def_id) in object_drop_instrumenters.iter().zip(&target_def_ids) {
                             let target_ty = instrumenter.target_ty();
-                            if ty_def_path_str == target_ty {
+                            if *target_def_id == Some(ty_def_id) {
                                 let caller_def_id = body.source.def_id();
                                 let caller_def_path_str = tcx.def_path_str(caller_def_id);
                                 info!("Found drop of {} in {:?}  (should instrumented)", target_ty, caller_def_path_str);
+                                stats::drop_site_found(target_ty);
                                 instruement_pos.push((bb, instrumenter, caller_def_id, generic_args));
                             }
                         }
                     } else {
-                        warn!("found call to drop function {func_def_path_str} but type is not adt");
+                        warn!("found call to std::mem::drop but type is not adt");
                     }
                 }
-                if func_def_path_str == "std::ptr::drop_in_place" || func_def_path_str == "core::ptr::drop_in_place" {
+                if Some(func_def_id) == drop_in_place_def_id {
                         unimplemented!("unimplement process for ptr::drop_in_place");
                 }
             }
@@ -387,10 +494,14 @@ monitors: &MonitorsInfo, object_drop_instrumenters: &[&dyn ObjectDropInstrumente
         if let Some((patch, moved_new_block)) = instrumenter.instrument_drop_before(tcx, body, monitors, loc_bb) {
             loc_bb = moved_new_block;
             patch.apply(body);
+            origins.record(body, || format!("before monitor of drop of {target_function} in {bb:?}"));
+            stats::drop_instrumented(target_function, stats::Hook::Before);
         };
         info!("Instrumenting after handler for drop of type {} in {:?}", target_function, caller_def_path_str);
         if let Some((patch, _moved_new_block)) = instrumenter.instrument_drop_after(tcx, body, monitors, loc_bb) {
             patch.apply(body);
+            origins.record(body, || format!("after monitor of drop of {target_function} in {bb:?}"));
+            stats::drop_instrumented(target_function, stats::Hook::After);
         }
     }
 }
@@ -0,0 +1,86 @@
+// Channel send/recv of std::sync::mpsc and crossbeam_channel, which order the sender before the receiver like a lock.
+// The monitors take the endpoint type as an extra first generic parameter (e.g. `std::sync::mpsc::Sender<T>` or
+// `crossbeam_channel::Sender<T>`) and the after monitors the return type as an extra last one, so that the same
+// monitors serve both crates without naming their types:
+//   fn send_before<C, T>(callsite: &str, sender: &C, msg: &T)
+//   fn send_after<C, T, R>(callsite: &str, sender: &C, msg: &T, ret: &mut R)
+use rustc_middle::ty::{GenericArg, GenericArgs, Ty, TyCtxt};
+use rustc_span::def_id::DefId;
+use crate::{mirpass::FunctionCallInstrumenter, monitors_finder::MonitorsInfo};
+
+#[derive(Clone, Copy)]
+pub enum ChannelOp {
+    Send,
+    Recv,
+    RecvTimeout,
+    TryRecv,
+}
+
+pub const CHANNEL_TARGETS: &[(&str, ChannelOp)] = &[
+    ("std::sync::mpsc::Sender::<T>::send", ChannelOp::Send),
+    ("std::sync::mpsc::SyncSender::<T>::send", ChannelOp::Send),
+    ("std::sync::mpsc::Receiver::<T>::recv", ChannelOp::Recv),
+    ("std::sync::mpsc::Receiver::<T>::recv_timeout", ChannelOp::RecvTimeout),
+    ("std::sync::mpsc::Receiver::<T>::try_recv", ChannelOp::TryRecv),
+    ("crossbeam_channel::Sender::<T>::send", ChannelOp::Send),
+    ("crossbeam_channel::Receiver::<T>::recv", ChannelOp::Recv),
+    ("crossbeam_channel::Receiver::<T>::recv_timeout", ChannelOp::RecvTimeout),
+    ("crossbeam_channel::Receiver::<T>::try_recv", ChannelOp::TryRecv),
+];
+
+pub struct ChannelCallHandler {
+    target: &'static str,
+    op: ChannelOp,
+}
+
+impl ChannelCallHandler {
+    pub fn new(&(target, op): &(&'static str, ChannelOp)) -> Self {
+        Self { target, op }
+    }
+}
+
+impl FunctionCallInstrumenter<'static> for ChannelCallHandler {
+    #[inline]
+    fn target_function(&self) -> &'static str {
+        self.target
+    }
+
+    #[inline]
+    fn before_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        let (monitor, kind) = match self.op {
+            ChannelOp::Send => (monitors.channel_send_before_fn, "channel_send_before"),
+            ChannelOp::Recv => (monitors.channel_recv_before_fn, "channel_recv_before"),
+            ChannelOp::RecvTimeout => (monitors.channel_recv_timeout_before_fn, "channel_recv_timeout_before"),
+            ChannelOp::TryRecv => (monitors.channel_try_recv_before_fn, "channel_try_recv_before"),
+        };
+        if monitor.is_none() {
+            warn!("monitors.{kind}_fn.is_none");
+        }
+        monitor
+    }
+
+    #[inline]
+    fn after_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        let (monitor, kind) = match self.op {
+            ChannelOp::Send => (monitors.channel_send_after_fn, "channel_send_after"),
+            ChannelOp::Recv => (monitors.channel_recv_after_fn, "channel_recv_after"),
+            ChannelOp::RecvTimeout => (monitors.channel_recv_timeout_after_fn, "channel_recv_timeout_after"),
+            ChannelOp::TryRecv => (monitors.channel_try_recv_after_fn, "channel_try_recv_after"),
+        };
+        if monitor.is_none() {
+            warn!("monitors.{kind}_fn.is_none");
+        }
+        monitor
+    }
+
+    fn monitor_generic_args<'tcx>(&self, _tcx: TyCtxt<'tcx>, target_generic_args: &'tcx GenericArgs<'tcx>,
+        arg_tys: &[Ty<'tcx>], ret_ty: Option<Ty<'tcx>>) -> Vec<GenericArg<'tcx>> {
+        // the endpoint is `self`, passed as `&Sender<T>` / `&Receiver<T>`
+        let endpoint_ty = arg_tys.first().and_then(|ty| ty.builtin_deref(true))
+            .expect("channel operations take the endpoint by reference");
+        let mut generic_args = vec![endpoint_ty.into()];
+        generic_args.extend(target_generic_args.iter());
+        generic_args.extend(ret_ty.map(GenericArg::from));
+        generic_args
+    }
+}
@@ -0,0 +1,27 @@
+use rustc_span::def_id::DefId;
+use crate::{mirpass::FunctionCallInstrumenter, monitors_finder::MonitorsInfo};
+
+#[derive(Default)]
+pub struct CondvarNotifyAllCallHandler<'pass>{
+    __marker: std::marker::PhantomData<&'pass str>,
+}
+
+impl<'pass> FunctionCallInstrumenter<'_> for CondvarNotifyAllCallHandler<'_> {
+    #[inline]
+    fn target_function(&self) -> &'static str {
+        "std::sync::Condvar::notify_all"
+    }
+
+    #[inline]
+    fn before_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        let Some(our_func_def_id) = monitors.condvar_notify_all_before_fn else { warn!("monitors.condvar_notify_all_before_fn.is_none"); return None; };
+        Some(our_func_def_id)
+    }
+
+    #[inline]
+    fn after_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        let Some(our_func_def_id) = monitors.condvar_notify_all_after_fn else { warn!("monitors.condvar_notify_all_after_fn.is_none"); return None; };
+        Some(our_func_def_id)
+    }
+}
+
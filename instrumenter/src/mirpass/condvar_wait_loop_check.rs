@@ -0,0 +1,63 @@
+use rustc_middle::ty::TyCtxt;
+use rustc_middle::mir::BasicBlock;
+use rustc_middle::mir::Body;
+use rustc_middle::mir::TerminatorKind;
+use crate::compat::{BitSet, MirPatch};
+
+use crate::mirpass::OurMirPass;
+use crate::monitors_finder::MonitorsInfo;
+use crate::{def_paths, utils};
+
+// Condvar::wait may wake up spuriously, so it must be called in a loop re-checking the condition (or use wait_while instead).
+// We warn on calls to Condvar::wait whose basic block is not part of any cycle of the control flow graph.
+// This pass only reports, it never patches the body.
+#[derive(Default)]
+pub struct CondvarWaitLoopCheck();
+
+const CHECKED_FUNCTIONS: [&str; 1] = ["std::sync::Condvar::wait"];
+
+impl OurMirPass for CondvarWaitLoopCheck {
+    fn run_pass<'tcx>(&self,
+        tcx: TyCtxt<'tcx>,
+        body: &Body<'tcx>, _monitors: &MonitorsInfo)
+    -> Option< MirPatch<'tcx> > {
+        for (bb, bb_data) in body.basic_blocks.iter_enumerated() {
+            let TerminatorKind::Call { func, fn_span, .. } = &bb_data.terminator().kind else {
+                continue;
+            };
+            let Some(func_def_id) = utils::get_function_def_id(tcx, &body.local_decls, func) else {
+                continue;
+            };
+            if !def_paths::matches_any(tcx, func_def_id, &CHECKED_FUNCTIONS) {
+                continue;
+            }
+            let func_def_path_str = tcx.def_path_str(func_def_id);
+            if !is_in_cycle(body, bb) {
+                warn!("{} called outside a loop in {:?}", func_def_path_str, tcx.def_path_str(body.source.def_id()));
+                tcx.dcx().struct_span_warn(*fn_span, format!("solcon: `{func_def_path_str}` is not called in a loop"))
+                    .with_note("Condvar::wait may return on a spurious wakeup, re-check the condition in a loop or use `Condvar::wait_while`")
+                    .emit();
+            }
+        }
+        None
+    }
+}
+
+/// Whether `start` can reach itself again, ignoring unwind (cleanup) edges.
+fn is_in_cycle(body: &Body<'_>, start: BasicBlock) -> bool {
+    let mut visited = BitSet::new_empty(body.basic_blocks.len());
+    let mut worklist: Vec<BasicBlock> = body.basic_blocks[start].terminator().successors().collect();
+    while let Some(bb) = worklist.pop() {
+        if body.basic_blocks[bb].is_cleanup {
+            continue;
+        }
+        if bb == start {
+            return true;
+        }
+        if !visited.insert(bb) {
+            continue;
+        }
+        worklist.extend(body.basic_blocks[bb].terminator().successors());
+    }
+    false
+}
@@ -1,6 +1,6 @@
 use rustc_middle::mir::{BasicBlock, Operand, TerminatorKind};
 use rustc_middle::mir::Body;
-use rustc_middle::mir::patch::MirPatch;
+use crate::compat::MirPatch;
 use rustc_middle::ty::TyCtxt;
 use rustc_span::def_id::DefId;
 
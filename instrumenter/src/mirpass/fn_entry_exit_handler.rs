@@ -0,0 +1,161 @@
+// Hooks at the entry and before the returns of a function body, for the program entry fn (program start and exit) and
+// for the functions selected for call tracing:
+//   SOLCON_TRACE_CALLS=<filters>   comma separated substrings of def paths (e.g. `bank::,worker`), `*` for all functions
+//                                  of the instrumented crates
+// A traced function calls the fn_enter monitor with its def path on entry and the fn_exit monitor before each return.
+// Unwinding out of a function is not captured, coroutine bodies (async fn, gen blocks) are not traced.
+use std::sync::LazyLock;
+use std::vec;
+
+use crate::monitors_finder::MonitorsInfo;
+use rustc_span::def_id::DefId;
+use rustc_middle::bug;
+use rustc_middle::ty::TyCtxt;
+use rustc_middle::mir::Body;
+use rustc_middle::mir::CallSource;
+use rustc_middle::mir::Const;
+use rustc_middle::mir::ConstOperand;
+use rustc_middle::mir::Operand;
+use rustc_middle::mir::Place;
+use rustc_middle::mir::START_BLOCK;
+use crate::compat::{self, MirPatch, Spanned};
+use rustc_middle::mir::TerminatorKind;
+use rustc_middle::mir::UnwindAction;
+use tracing::debug;
+
+use crate::utils;
+
+static TRACE_CALLS: LazyLock<Option<Vec<String>>> = LazyLock::new(|| std::env::var("SOLCON_TRACE_CALLS").ok()
+    .map(|filters| filters.split(',').map(str::trim).filter(|filter| !filter.is_empty()).map(str::to_owned).collect()));
+
+/// Whether the body of def_id is selected by SOLCON_TRACE_CALLS.
+pub(crate) fn should_trace_calls(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    let Some(filters) = TRACE_CALLS.as_ref() else {
+        return false;
+    };
+    let def_path_str = tcx.def_path_str(def_id);
+    if !filters.iter().any(|filter| filter == "*" || def_path_str.contains(filter.as_str())) {
+        return false;
+    }
+    if tcx.is_coroutine(def_id) {
+        debug!("skip call tracing of {def_path_str} because it is a coroutine");
+        return false;
+    }
+    true
+}
+
+pub struct FnEntryExitInstrumenter<'tcx> {
+    before_fn: Option<DefId>,
+    after_fn: Option<DefId>,
+    /// arguments of both monitors
+    args: Vec<Spanned<Operand<'tcx>>>,
+}
+
+impl<'tcx> FnEntryExitInstrumenter<'tcx> {
+    /// program start and exit, monitors without arguments
+    pub fn entry_fn(monitors: &MonitorsInfo) -> Self {
+        FnEntryExitInstrumenter { before_fn: monitors.entry_fn_before_fn, after_fn: monitors.entry_fn_after_fn, args: vec![] }
+    }
+
+    /// call tracing, the monitors get the def path of the function
+    pub fn traced_fn(tcx: TyCtxt<'tcx>, monitors: &MonitorsInfo, body: &Body<'tcx>) -> Self {
+        let def_path_str = tcx.def_path_str(body.source.def_id());
+        let function = Spanned {
+            node: Operand::Constant(Box::new(ConstOperand {
+                span: body.span,
+                user_ty: None,
+                const_: Const::Val(compat::str_const_value(tcx, &def_path_str), utils::callsite_str_ty(tcx)),
+            })),
+            span: body.span,
+        };
+        FnEntryExitInstrumenter { before_fn: monitors.fn_enter_fn, after_fn: monitors.fn_exit_fn, args: vec![function] }
+    }
+
+    pub fn instrument_body(&self, tcx: TyCtxt<'tcx>, body: &mut Body<'tcx>) {
+        self.instrument_body_before(tcx, body);
+        self.instrument_body_after(tcx, body);
+    }
+
+    fn instrument_body_before(&self, tcx: TyCtxt<'tcx>, body: &mut Body<'tcx>) {
+        let Some(our_func_def_id) = self.before_fn else {
+            return;
+        };
+        let Some((first_bb, first_bb_data)) = body.basic_blocks.iter_enumerated().next() else {
+            return;
+        };
+        let body_span = body.span;
+        let mut patch = MirPatch::new(body);
+        let new_bb_run_origin_first = patch.new_block(first_bb_data.clone());
+        let temp_ret = patch.new_temp(tcx.types.unit, body_span.clone());
+        patch.patch_terminator(first_bb, TerminatorKind::Call{
+            func: Operand::function_handle(tcx, our_func_def_id, [], body_span.clone()),
+            args: self.args.clone().into_boxed_slice(),
+            destination: Place::from(temp_ret),
+            target: Some(new_bb_run_origin_first),
+            unwind: UnwindAction::Continue,
+            call_source: CallSource::Misc,
+            fn_span: body_span.clone(),
+        });
+        patch.apply(body);
+        let Some(first_bb_data) = body.basic_blocks.as_mut().iter_mut().next() else {
+            unreachable!()
+        };
+        first_bb_data.statements.clear();
+        // a loop back to the first block would call the monitor again, it continues in the moved block instead
+        for (bb, bb_data) in body.basic_blocks.as_mut().iter_enumerated_mut() {
+            if bb == START_BLOCK {
+                continue;
+            }
+            compat::for_each_successor_mut(bb_data.terminator_mut(), |target| {
+                if *target == START_BLOCK {
+                    *target = new_bb_run_origin_first;
+                }
+            });
+        }
+    }
+
+    fn instrument_body_after(&self, tcx: TyCtxt<'tcx>, body: &mut Body<'tcx>) {
+        let Some(our_func_def_id) = self.after_fn else {
+            return ;
+        };
+        let body_span = body.span;
+        let mut patch = MirPatch::new(body);
+        for (bb, bb_data) in body.basic_blocks.iter_enumerated() {
+            let terminator = bb_data.terminator();
+            match &terminator.kind {
+                TerminatorKind::Return => {
+                    // 捕获函数正常返回。
+                    let new_bb_run_original_return = patch.new_block(compat::basic_block_data(
+                        vec![],
+                        Some(terminator.clone()),
+                        bb_data.is_cleanup,
+                    ));
+                    let temp_ret = patch.new_temp(tcx.types.unit, body_span.clone());
+                    patch.patch_terminator(bb, TerminatorKind::Call{
+                        func: Operand::function_handle(tcx, our_func_def_id, [], body_span.clone()),
+                        args: self.args.clone().into_boxed_slice(),
+                        destination: Place::from(temp_ret),
+                        target: Some(new_bb_run_original_return),
+                        unwind: UnwindAction::Continue,
+                        call_source: CallSource::Misc,
+                        fn_span: body_span.clone(),
+                    });
+                }
+                TerminatorKind::UnwindResume | TerminatorKind::UnwindTerminate(..) => {
+                    // 当前不捕获unwind引发的函数调用结束
+                    // 即使我们可以捕获入口函数中的所有UnwindResume和UnwindTerminate
+                    // 但无法捕获子函数调用中的UnwindTerminate（通常是因为处理unwind进行cleanup时再次unwind）
+                    // (一种可能的实现：可以查找所有Termiantor中unwind: UnwindAction==Terminate，更换为UnwindAction::Cleanup(bb)再在bb中调用monitor和TermiantorKind::UnwindTerminate(orignalReason)，但这需要修改所有函数，且我们也无法覆盖到所有函数)
+                    // 可以在monitor中使用std::panic::set_hook捕获整个程序的panic
+                }
+                TerminatorKind::Goto{..} | TerminatorKind::SwitchInt{..} | TerminatorKind::Unreachable => {}, // safe and donot cause the end of this invocation of the function.
+                TerminatorKind::Drop{..} | TerminatorKind::Call{..} | TerminatorKind::Assert{..} => {}, // only end this basic blocks, we process unwind if any panics
+                TerminatorKind::Yield{..} | TerminatorKind::CoroutineDrop => bug!("coroutines are not instrumented at entry and exit"),
+                TerminatorKind::FalseEdge{..} | TerminatorKind::FalseUnwind{..} => bug!("TerminatorKind::FalseEdge | TerminatorKind::FalseUnwind is disallowed after drop elaboration"),
+                TerminatorKind::InlineAsm{..} => {}
+                TerminatorKind::TailCall{..} => {}, // `become` (explicit_tail_calls) ends this invocation without returning here, not captured like unwinding
+            }
+        }
+        patch.apply(body);
+    }
+}
\ No newline at end of file
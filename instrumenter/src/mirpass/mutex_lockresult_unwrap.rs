@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use rustc_middle::mir::Body;
 use rustc_middle::mir::{self, BasicBlock, BasicBlockData, BorrowKind, ConstOperand, Local, LocalDecl, MutBorrowKind, Operand, Place, ProjectionElem, Rvalue, SourceInfo, Statement, StatementKind, Terminator, TerminatorKind};
-use rustc_middle::mir::patch::MirPatch;
+use crate::compat::MirPatch;
 use rustc_middle::ty::{self, Ty, TyCtxt};
 use rustc_span::{source_map::Spanned, DUMMY_SP};
 use rustc_span::def_id::DefId;
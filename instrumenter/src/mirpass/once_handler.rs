@@ -0,0 +1,89 @@
+// One-time initialization: std::sync::{Once, OnceLock, LazyLock} and once_cell::sync::{OnceCell, Lazy}.
+// Like the channel monitors, the monitors take the type of the cell as an extra first generic parameter and the after
+// monitors the return type as an extra last one, the other generic parameters are the types of the other arguments:
+//   fn once_call_before<C, F>(callsite: &str, cell: &C, f: &F)            call_once, call_once_force, get_or_init
+//   fn once_call_after<C, F, R>(callsite: &str, cell: &C, f: &F, ret: &mut R)
+//   fn lazy_force_before<C>(callsite: &str, lazy: &C)                     force and Deref::deref of the lazy types
+//   fn lazy_force_after<C, R>(callsite: &str, lazy: &C, ret: &mut R)
+use rustc_middle::ty::{GenericArg, GenericArgs, Ty, TyCtxt};
+use rustc_span::def_id::DefId;
+use crate::{mirpass::FunctionCallInstrumenter, monitors_finder::MonitorsInfo};
+
+#[derive(Clone, Copy)]
+pub enum OnceOp {
+    /// runs the closure argument unless the cell is initialized
+    Call,
+    /// initializes with the closure stored in the lazy value
+    Force,
+}
+
+/// (target, Self type for trait method targets, op)
+pub const ONCE_TARGETS: &[(&str, Option<&str>, OnceOp)] = &[
+    ("std::sync::Once::call_once", None, OnceOp::Call),
+    ("std::sync::Once::call_once_force", None, OnceOp::Call),
+    ("std::sync::OnceLock::<T>::get_or_init", None, OnceOp::Call),
+    ("std::sync::LazyLock::<T, F>::force", None, OnceOp::Force),
+    ("std::ops::Deref::deref", Some("std::sync::LazyLock"), OnceOp::Force),
+    ("once_cell::sync::OnceCell::<T>::get_or_init", None, OnceOp::Call),
+    ("once_cell::sync::Lazy::<T, F>::force", None, OnceOp::Force),
+    ("std::ops::Deref::deref", Some("once_cell::sync::Lazy"), OnceOp::Force),
+];
+
+pub struct OnceCallHandler {
+    target: &'static str,
+    self_ty: Option<&'static str>,
+    op: OnceOp,
+}
+
+impl OnceCallHandler {
+    pub fn new(&(target, self_ty, op): &(&'static str, Option<&'static str>, OnceOp)) -> Self {
+        Self { target, self_ty, op }
+    }
+}
+
+impl FunctionCallInstrumenter<'static> for OnceCallHandler {
+    #[inline]
+    fn target_function(&self) -> &'static str {
+        self.target
+    }
+
+    #[inline]
+    fn target_self_ty(&self) -> Option<&'static str> {
+        self.self_ty
+    }
+
+    #[inline]
+    fn before_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        let (monitor, kind) = match self.op {
+            OnceOp::Call => (monitors.once_call_before_fn, "once_call_before"),
+            OnceOp::Force => (monitors.lazy_force_before_fn, "lazy_force_before"),
+        };
+        if monitor.is_none() {
+            warn!("monitors.{kind}_fn.is_none");
+        }
+        monitor
+    }
+
+    #[inline]
+    fn after_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        let (monitor, kind) = match self.op {
+            OnceOp::Call => (monitors.once_call_after_fn, "once_call_after"),
+            OnceOp::Force => (monitors.lazy_force_after_fn, "lazy_force_after"),
+        };
+        if monitor.is_none() {
+            warn!("monitors.{kind}_fn.is_none");
+        }
+        monitor
+    }
+
+    fn monitor_generic_args<'tcx>(&self, _tcx: TyCtxt<'tcx>, _target_generic_args: &'tcx GenericArgs<'tcx>,
+        arg_tys: &[Ty<'tcx>], ret_ty: Option<Ty<'tcx>>) -> Vec<GenericArg<'tcx>> {
+        // the cell is `self` (or `this` of force), passed by reference
+        let (cell_ty, other_arg_tys) = arg_tys.split_first().expect("initialization takes the cell");
+        let cell_ty = cell_ty.builtin_deref(true).expect("initialization takes the cell by reference");
+        let mut generic_args = vec![cell_ty.into()];
+        generic_args.extend(other_arg_tys.iter().map(|ty| GenericArg::from(*ty)));
+        generic_args.extend(ret_ty.map(GenericArg::from));
+        generic_args
+    }
+}
@@ -0,0 +1,40 @@
+use rustc_span::def_id::DefId;
+use crate::monitors_finder::MonitorsInfo;
+
+// only before the drop, the pointer and its allocation may be gone after it
+
+#[derive(Default)]
+pub struct ArcDropInstrumenter{}
+
+impl crate::mirpass::ObjectDropInstrumenter for ArcDropInstrumenter {
+    #[inline]
+    fn target_ty(&self) -> &'static str {
+        "std::sync::Arc"
+    }
+    #[inline]
+    fn before_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        monitors.arc_drop_before_fn
+    }
+    #[inline]
+    fn after_monitor_def_id(&self, _monitors: &MonitorsInfo) -> Option<DefId> {
+        None
+    }
+}
+
+#[derive(Default)]
+pub struct RcDropInstrumenter{}
+
+impl crate::mirpass::ObjectDropInstrumenter for RcDropInstrumenter {
+    #[inline]
+    fn target_ty(&self) -> &'static str {
+        "std::rc::Rc"
+    }
+    #[inline]
+    fn before_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        monitors.rc_drop_before_fn
+    }
+    #[inline]
+    fn after_monitor_def_id(&self, _monitors: &MonitorsInfo) -> Option<DefId> {
+        None
+    }
+}
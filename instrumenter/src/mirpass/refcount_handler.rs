@@ -0,0 +1,93 @@
+// Reference counting of std::sync::Arc and std::rc::Rc: new, clone, try_unwrap, get_mut and Weak::upgrade (drops go
+// through ArcDropInstrumenter and RcDropInstrumenter). The same monitors serve Arc and Rc, their generic parameters are
+// the types of the arguments (without the reference for `&self` / `&mut self`) and the after monitors the return type:
+//   fn refcount_clone_after<P, R>(callsite: &str, this: &P, ret: &mut R)
+//   fn refcount_get_mut_after<P, R>(callsite: &str, this: &mut P, ret: &mut R)
+use rustc_middle::ty::{GenericArg, GenericArgs, Ty, TyCtxt};
+use rustc_span::def_id::DefId;
+use crate::{mirpass::FunctionCallInstrumenter, monitors_finder::MonitorsInfo};
+
+#[derive(Clone, Copy)]
+pub enum RefCountOp {
+    New,
+    Clone,
+    TryUnwrap,
+    GetMut,
+    Upgrade,
+}
+
+/// (target, Self type for trait method targets, op)
+pub const REFCOUNT_TARGETS: &[(&str, Option<&str>, RefCountOp)] = &[
+    ("std::sync::Arc::<T>::new", None, RefCountOp::New),
+    ("std::clone::Clone::clone", Some("std::sync::Arc"), RefCountOp::Clone),
+    ("std::sync::Arc::<T, A>::try_unwrap", None, RefCountOp::TryUnwrap),
+    ("std::sync::Arc::<T, A>::get_mut", None, RefCountOp::GetMut),
+    ("std::sync::Weak::<T, A>::upgrade", None, RefCountOp::Upgrade),
+    ("std::rc::Rc::<T>::new", None, RefCountOp::New),
+    ("std::clone::Clone::clone", Some("std::rc::Rc"), RefCountOp::Clone),
+    ("std::rc::Rc::<T, A>::try_unwrap", None, RefCountOp::TryUnwrap),
+    ("std::rc::Rc::<T, A>::get_mut", None, RefCountOp::GetMut),
+    ("std::rc::Weak::<T, A>::upgrade", None, RefCountOp::Upgrade),
+];
+
+pub struct RefCountCallHandler {
+    target: &'static str,
+    self_ty: Option<&'static str>,
+    op: RefCountOp,
+}
+
+impl RefCountCallHandler {
+    pub fn new(&(target, self_ty, op): &(&'static str, Option<&'static str>, RefCountOp)) -> Self {
+        Self { target, self_ty, op }
+    }
+}
+
+impl FunctionCallInstrumenter<'static> for RefCountCallHandler {
+    #[inline]
+    fn target_function(&self) -> &'static str {
+        self.target
+    }
+
+    #[inline]
+    fn target_self_ty(&self) -> Option<&'static str> {
+        self.self_ty
+    }
+
+    #[inline]
+    fn before_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        // try_unwrap moves the pointer, so its allocation is only known before the call
+        match self.op {
+            RefCountOp::TryUnwrap => {
+                if monitors.refcount_try_unwrap_before_fn.is_none() {
+                    warn!("monitors.refcount_try_unwrap_before_fn.is_none");
+                }
+                monitors.refcount_try_unwrap_before_fn
+            }
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn after_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        let (monitor, kind) = match self.op {
+            RefCountOp::New => (monitors.refcount_new_after_fn, "refcount_new_after"),
+            RefCountOp::Clone => (monitors.refcount_clone_after_fn, "refcount_clone_after"),
+            RefCountOp::TryUnwrap => (monitors.refcount_try_unwrap_after_fn, "refcount_try_unwrap_after"),
+            RefCountOp::GetMut => (monitors.refcount_get_mut_after_fn, "refcount_get_mut_after"),
+            RefCountOp::Upgrade => (monitors.refcount_upgrade_after_fn, "refcount_upgrade_after"),
+        };
+        if monitor.is_none() {
+            warn!("monitors.{kind}_fn.is_none");
+        }
+        monitor
+    }
+
+    fn monitor_generic_args<'tcx>(&self, _tcx: TyCtxt<'tcx>, _target_generic_args: &'tcx GenericArgs<'tcx>,
+        arg_tys: &[Ty<'tcx>], ret_ty: Option<Ty<'tcx>>) -> Vec<GenericArg<'tcx>> {
+        let mut generic_args: Vec<GenericArg<'tcx>> = arg_tys.iter()
+            .map(|ty| ty.builtin_deref(true).unwrap_or(*ty).into())
+            .collect();
+        generic_args.extend(ret_ty.map(GenericArg::from));
+        generic_args
+    }
+}
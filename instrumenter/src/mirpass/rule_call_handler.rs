@@ -0,0 +1,36 @@
+use rustc_span::def_id::DefId;
+use crate::{mirpass::FunctionCallInstrumenter, monitors_finder::MonitorsInfo};
+use crate::rules::ResolvedCallRule;
+
+// Built from a call rule of the SOLCON_RULES file, the monitors live in a user monitor crate instead of MonitorsInfo.
+pub struct RuleCallHandler {
+    rule: &'static ResolvedCallRule,
+}
+
+impl RuleCallHandler {
+    pub fn new(rule: &'static ResolvedCallRule) -> Self {
+        Self { rule }
+    }
+}
+
+impl FunctionCallInstrumenter<'static> for RuleCallHandler {
+    #[inline]
+    fn target_function(&self) -> &'static str {
+        self.rule.target
+    }
+
+    #[inline]
+    fn before_monitor_def_id(&self, _monitors: &MonitorsInfo) -> Option<DefId> {
+        self.rule.before
+    }
+
+    #[inline]
+    fn after_monitor_def_id(&self, _monitors: &MonitorsInfo) -> Option<DefId> {
+        self.rule.after
+    }
+
+    #[inline]
+    fn monitor_takes_args(&self) -> bool {
+        self.rule.takes_args
+    }
+}
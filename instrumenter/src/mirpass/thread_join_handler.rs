@@ -0,0 +1,27 @@
+use rustc_span::def_id::DefId;
+use crate::{mirpass::FunctionCallInstrumenter, monitors_finder::MonitorsInfo};
+
+// join consumes the JoinHandle, the after monitor must not look at it.
+#[derive(Default)]
+pub struct ThreadJoinCallHandler<'pass>{
+    __marker: std::marker::PhantomData<&'pass str>,
+}
+
+impl FunctionCallInstrumenter<'_> for ThreadJoinCallHandler<'_> {
+    #[inline]
+    fn target_function(&self) -> &'static str {
+        "std::thread::JoinHandle::<T>::join"
+    }
+
+    #[inline]
+    fn before_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        let Some(our_func_def_id) = monitors.thread_join_before_fn else { warn!("monitors.thread_join_before_fn.is_none"); return None; };
+        Some(our_func_def_id)
+    }
+
+    #[inline]
+    fn after_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        let Some(our_func_def_id) = monitors.thread_join_after_fn else { warn!("monitors.thread_join_after_fn.is_none"); return None; };
+        Some(our_func_def_id)
+    }
+}
@@ -0,0 +1,28 @@
+use rustc_span::def_id::DefId;
+use crate::{mirpass::FunctionCallInstrumenter, monitors_finder::MonitorsInfo};
+
+// The closure is moved into the new thread: the after monitor gets a reference to it like to any argument, but must
+// only look at the returned JoinHandle.
+#[derive(Default)]
+pub struct ThreadSpawnCallHandler<'pass>{
+    __marker: std::marker::PhantomData<&'pass str>,
+}
+
+impl<'pass> FunctionCallInstrumenter<'_> for ThreadSpawnCallHandler<'_> {
+    #[inline]
+    fn target_function(&self) -> &'static str {
+        "std::thread::spawn"
+    }
+
+    #[inline]
+    fn before_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        let Some(our_func_def_id) = monitors.thread_spawn_before_fn else { warn!("monitors.thread_spawn_before_fn.is_none"); return None; };
+        Some(our_func_def_id)
+    }
+
+    #[inline]
+    fn after_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId> {
+        let Some(our_func_def_id) = monitors.thread_spawn_after_fn else { warn!("monitors.thread_spawn_after_fn.is_none"); return None; };
+        Some(our_func_def_id)
+    }
+}
@@ -0,0 +1,165 @@
+// Check monitors before calls to them are injected: a monitor with other generic parameters or argument types than
+// the call we build gives ill-typed MIR, which ICEs or miscompiles much later. Mismatches are reported as errors
+// naming the monitor, at the first call site, and the call is not instrumented.
+use std::sync::Mutex;
+
+use rustc_data_structures::fx::FxHashSet;
+use rustc_middle::ty::{self, GenericArg, Ty, TyCtxt};
+use rustc_span::def_id::DefId;
+use rustc_span::Span;
+
+use crate::monitors_finder::{MonitorsFinder, MonitorsInfo};
+use crate::compat::{self, TypingEnv};
+use crate::stats;
+
+static REPORTED_MONITORS: Mutex<Option<FxHashSet<DefId>>> = Mutex::new(None);
+
+/// Shapes which do not depend on the instrumented call.
+enum Shape {
+    /// entry_fn hooks: `fn()`
+    NoArgs,
+    /// memory hooks: `fn(addr: usize)`
+    Addr,
+    /// call tracing hooks: `fn(function: &str)`
+    Function,
+    /// call and drop hooks: `fn<generics of the target>(callsite: &str, ...)`
+    Callsite,
+}
+
+fn shape_of_kind(kind: &str) -> Shape {
+    if kind.starts_with("entry_fn_") {
+        Shape::NoArgs
+    } else if kind.starts_with("mem_") {
+        Shape::Addr
+    } else if kind.starts_with("fn_") {
+        Shape::Function
+    } else {
+        Shape::Callsite
+    }
+}
+
+/// Called after find_all_monitors: warn about missing monitors and check what can be checked without a call site.
+pub(crate) fn check_monitors(tcx: TyCtxt<'_>, monitors: &MonitorsInfo) {
+    let mut missing = Vec::new();
+    for (kind, def_id) in monitors.monitors_by_kind() {
+        let Some(def_id) = def_id else {
+            missing.push(kind);
+            continue;
+        };
+        let monitor = tcx.def_path_str(def_id);
+        let sig = compat::fn_sig(tcx, def_id, None);
+        let mut problems = Vec::new();
+        if !sig.output().is_unit() {
+            problems.push(format!("returns `{}` instead of `()`", sig.output()));
+        }
+        if sig.c_variadic() {
+            problems.push("is variadic".to_owned());
+        }
+        let inputs = sig.inputs();
+        let own_generics = tcx.generics_of(def_id).count();
+        match shape_of_kind(kind) {
+            Shape::NoArgs => {
+                if !inputs.is_empty() || own_generics != 0 {
+                    problems.push("must be a non-generic `fn()`".to_owned());
+                }
+            }
+            Shape::Addr => {
+                if inputs != [tcx.types.usize] || own_generics != 0 {
+                    problems.push("must be a non-generic `fn(addr: usize)`".to_owned());
+                }
+            }
+            Shape::Function => {
+                if inputs.len() != 1 || !is_str_ref(inputs[0]) || own_generics != 0 {
+                    problems.push("must be a non-generic `fn(function: &str)`".to_owned());
+                }
+            }
+            Shape::Callsite => {
+                if !inputs.first().is_some_and(|ty| is_str_ref(*ty)) {
+                    problems.push("must take the callsite `&str` as first argument".to_owned());
+                }
+            }
+        }
+        if !problems.is_empty() {
+            mark_reported(def_id);
+            tcx.dcx().struct_span_err(tcx.def_span(def_id),
+                    format!("solcon: monitor `{monitor}` for hook `{kind}` {}", problems.join(", ")))
+                .with_note(format!("the signature of `{monitor}` is `{sig}`"))
+                .emit();
+        }
+    }
+    stats::monitors_missing(&missing);
+    if !missing.is_empty() {
+        tcx.dcx().warn(format!("solcon: no monitor found for hooks {}, the corresponding operations are not instrumented",
+            missing.iter().map(|kind| format!("`{kind}`")).collect::<Vec<_>>().join(", ")));
+    }
+}
+
+/// Check that `monitor` can be instantiated with `generic_args` and called with arguments of `arg_tys`.
+/// Returns false (after reporting the first mismatch of the monitor) if the call must not be built.
+pub(crate) fn check_monitor_call<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    typing_env: TypingEnv<'tcx>,
+    monitor: DefId,
+    generic_args: &[GenericArg<'tcx>],
+    arg_tys: &[Ty<'tcx>],
+    span: Span,
+) -> bool {
+    let Err(problem) = match_monitor_call(tcx, typing_env, monitor, generic_args, arg_tys) else {
+        return true;
+    };
+    if mark_reported(monitor) {
+        let monitor_path = tcx.def_path_str(monitor);
+        tcx.dcx().struct_span_err(span, format!("solcon: cannot call monitor `{monitor_path}` here, {problem}"))
+            .with_span_note(tcx.def_span(monitor), format!("`{monitor_path}` is defined here"))
+            .with_note(format!("the call passes ({})", arg_tys.iter().map(|ty| format!("{ty}")).collect::<Vec<_>>().join(", ")))
+            .emit();
+    }
+    false
+}
+
+fn match_monitor_call<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    typing_env: TypingEnv<'tcx>,
+    monitor: DefId,
+    generic_args: &[GenericArg<'tcx>],
+    arg_tys: &[Ty<'tcx>],
+) -> Result<(), String> {
+    let generics = tcx.generics_of(monitor);
+    if generics.count() != generic_args.len() {
+        return Err(format!("it has {} generic parameters but the target has {}", generics.count(), generic_args.len()));
+    }
+    for (param, arg) in generics.own_params.iter().zip(generic_args) {
+        let matches = matches!(
+            (&param.kind, arg.kind()),
+            (ty::GenericParamDefKind::Lifetime, ty::GenericArgKind::Lifetime(_))
+                | (ty::GenericParamDefKind::Type { .. }, ty::GenericArgKind::Type(_))
+                | (ty::GenericParamDefKind::Const { .. }, ty::GenericArgKind::Const(_))
+        );
+        if !matches {
+            return Err(format!("its generic parameter `{}` does not match `{arg}` of the target", param.name));
+        }
+    }
+    let sig = compat::fn_sig(tcx, monitor, Some(tcx.mk_args(generic_args)));
+    let normalize = |ty: Ty<'tcx>| compat::normalize_erasing_regions(tcx, typing_env, ty);
+    if sig.inputs().len() != arg_tys.len() {
+        return Err(format!("it takes {} arguments but {} are passed", sig.inputs().len(), arg_tys.len()));
+    }
+    for (index, (expected, passed)) in sig.inputs().iter().zip(arg_tys).enumerate() {
+        if normalize(*expected) != normalize(*passed) {
+            return Err(format!("argument {index} is `{expected}` but `{passed}` is passed"));
+        }
+    }
+    if !sig.output().is_unit() {
+        return Err(format!("it returns `{}` instead of `()`", sig.output()));
+    }
+    Ok(())
+}
+
+fn is_str_ref(ty: Ty<'_>) -> bool {
+    matches!(ty.kind(), ty::Ref(_, pointee, ty::Mutability::Not) if pointee.is_str())
+}
+
+/// Returns true the first time a monitor is reported.
+fn mark_reported(monitor: DefId) -> bool {
+    REPORTED_MONITORS.lock().unwrap().get_or_insert_with(FxHashSet::default).insert(monitor)
+}
@@ -18,6 +18,11 @@ pub(crate) struct MonitorsInfo {
     #[monitor_defpath = "this_is_our_entry_fn_after_handle_function"]
     pub entry_fn_after_fn: Option<DefId>,
 
+    #[monitor_defpath = "this_is_our_fn_enter_handle_function"]
+    pub fn_enter_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_fn_exit_handle_function"]
+    pub fn_exit_fn: Option<DefId>,
+
     #[monitor_defpath = "this_is_our_mutex_lock_before_handle_function"]
     pub mutex_lock_before_fn: Option<DefId>,
     #[monitor_defpath = "this_is_our_mutex_lock_after_handle_function"]
@@ -93,6 +98,72 @@ pub(crate) struct MonitorsInfo {
     #[monitor_defpath = "this_is_our_condvar_wait_timeout_while_after_handle_function"]
     pub condvar_wait_timeout_while_after_fn: Option<DefId>,
 
+    #[monitor_defpath = "this_is_our_condvar_notify_one_before_handle_function"]
+    pub condvar_notify_one_before_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_condvar_notify_one_after_handle_function"]
+    pub condvar_notify_one_after_fn: Option<DefId>,
+
+    #[monitor_defpath = "this_is_our_condvar_notify_all_before_handle_function"]
+    pub condvar_notify_all_before_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_condvar_notify_all_after_handle_function"]
+    pub condvar_notify_all_after_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_thread_spawn_before_handle_function"]
+    pub thread_spawn_before_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_thread_spawn_after_handle_function"]
+    pub thread_spawn_after_fn: Option<DefId>,
+
+    #[monitor_defpath = "this_is_our_thread_join_before_handle_function"]
+    pub thread_join_before_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_thread_join_after_handle_function"]
+    pub thread_join_after_fn: Option<DefId>,
+
+    #[monitor_defpath = "this_is_our_channel_send_before_handle_function"]
+    pub channel_send_before_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_channel_send_after_handle_function"]
+    pub channel_send_after_fn: Option<DefId>,
+
+    #[monitor_defpath = "this_is_our_channel_recv_before_handle_function"]
+    pub channel_recv_before_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_channel_recv_after_handle_function"]
+    pub channel_recv_after_fn: Option<DefId>,
+
+    #[monitor_defpath = "this_is_our_channel_recv_timeout_before_handle_function"]
+    pub channel_recv_timeout_before_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_channel_recv_timeout_after_handle_function"]
+    pub channel_recv_timeout_after_fn: Option<DefId>,
+
+    #[monitor_defpath = "this_is_our_channel_try_recv_before_handle_function"]
+    pub channel_try_recv_before_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_channel_try_recv_after_handle_function"]
+    pub channel_try_recv_after_fn: Option<DefId>,
+
+    #[monitor_defpath = "this_is_our_once_call_before_handle_function"]
+    pub once_call_before_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_once_call_after_handle_function"]
+    pub once_call_after_fn: Option<DefId>,
+
+    #[monitor_defpath = "this_is_our_lazy_force_before_handle_function"]
+    pub lazy_force_before_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_lazy_force_after_handle_function"]
+    pub lazy_force_after_fn: Option<DefId>,
+
+    #[monitor_defpath = "this_is_our_refcount_new_after_handle_function"]
+    pub refcount_new_after_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_refcount_clone_after_handle_function"]
+    pub refcount_clone_after_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_refcount_try_unwrap_before_handle_function"]
+    pub refcount_try_unwrap_before_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_refcount_try_unwrap_after_handle_function"]
+    pub refcount_try_unwrap_after_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_refcount_get_mut_after_handle_function"]
+    pub refcount_get_mut_after_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_refcount_upgrade_after_handle_function"]
+    pub refcount_upgrade_after_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_arc_drop_before_handle_function"]
+    pub arc_drop_before_fn: Option<DefId>,
+    #[monitor_defpath = "this_is_our_rc_drop_before_handle_function"]
+    pub rc_drop_before_fn: Option<DefId>,
+
     #[monitor_defpath = "this_is_our_mem_read_before_function"]
     pub mem_read_before_fn: Option<DefId>,
     #[monitor_defpath = "this_is_our_mem_write_before_function"]
@@ -106,4 +177,7 @@ pub(crate) struct MonitorsInfo {
 
 pub trait MonitorsFinder {
     fn try_match_with_our_function(&mut self, tcx: TyCtxt<'_>, fn_def_id: &DefId) -> bool;
+    /// kind is the field name without `_fn`, e.g. `mutex_lock_before`
+    fn try_match_with_monitor_kind(&mut self, kind: &str, fn_def_id: &DefId) -> bool;
+    fn monitors_by_kind(&self) -> Vec<(&'static str, Option<DefId>)>;
 }
@@ -1,14 +1,12 @@
 use crate::monitors_finder::MonitorsInfo;
 use rustc_span::def_id::DefId;
 use rustc_span::DUMMY_SP;
-use rustc_span::source_map::Spanned;
 use rustc_span::Span;
 use rustc_middle::span_bug;
 use rustc_middle::ty::Ty;
 use rustc_middle::ty::TyCtxt;
 use rustc_middle::ty::TyKind;
 use rustc_middle::mir::BasicBlock;
-use rustc_middle::mir::BasicBlockData;
 use rustc_middle::mir::Body;
 use rustc_middle::mir::BorrowKind;
 use rustc_middle::mir::CallSource;
@@ -18,25 +16,20 @@ use rustc_middle::mir::ConstValue;
 use rustc_middle::mir::Location;
 use rustc_middle::mir::Operand;
 use rustc_middle::mir::Place;
-use rustc_middle::mir::patch::MirPatch;
+use crate::compat::{self, MirPatch, Spanned};
 use rustc_middle::mir::Rvalue;
 use rustc_middle::mir::Terminator;
 use rustc_middle::mir::TerminatorKind;
 
-use crate::utils;
+use crate::{monitor_signature, utils};
 
 fn build_drop_callsite_str_operand<'tcx>(
     tcx: TyCtxt<'tcx>, 
     drop_span: Span
 ) -> Spanned<Operand<'tcx>> {
     let fn_callsite_span_str = utils::span_to_string(tcx, drop_span);
-    let alloc = rustc_middle::mir::interpret::Allocation::from_bytes_byte_aligned_immutable(fn_callsite_span_str.as_bytes());
-    let const_alloc = tcx.mk_const_alloc(alloc);
-    let const_val = ConstValue::Slice{
-        data: const_alloc,
-        meta: fn_callsite_span_str.len() as u64,
-    };
-    let const_ty = Ty::new_static_str(tcx);
+    let const_val = compat::str_const_value(tcx, &fn_callsite_span_str);
+    let const_ty = utils::callsite_str_ty(tcx);
     Spanned {
         node: Operand::Constant(
             Box::new(ConstOperand{
@@ -63,6 +56,14 @@ fn build_drop_span<'tcx>(
     source_info.span
 }
 
+/// Drop monitors are `fn<generics of the dropped type>(callsite: &str, &dropped type)`.
+fn check_drop_monitor<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, monitor: DefId,
+    generic_args: &[rustc_middle::ty::GenericArg<'tcx>], dropped_ty: Ty<'tcx>, span: Span,
+) -> bool {
+    let arg_tys = [utils::callsite_str_ty(tcx), Ty::new_imm_ref(tcx, tcx.lifetimes.re_erased, dropped_ty)];
+    monitor_signature::check_monitor_call(tcx, compat::typing_env(tcx, body.source.def_id()), monitor, generic_args, &arg_tys, span)
+}
+
 pub trait ObjectDropInstrumenter {
     fn target_ty(&self) -> &'static str;
     fn before_monitor_def_id(&self, monitors: &MonitorsInfo) -> Option<DefId>;
@@ -77,17 +78,20 @@ pub trait ObjectDropInstrumenter {
         let drop_at_block_data = &body.basic_blocks[drop_at_block];
         let terminator = drop_at_block_data.terminator();
             match &terminator.kind {
-                TerminatorKind::Drop { place, target: _, unwind, replace: _} => {
+                TerminatorKind::Drop { place, unwind, .. } => {
                     let ty = place.ty(&body.local_decls, tcx).ty;
                     let TyKind::Adt(_adt_def, generic_args) = ty.kind() else {
                         unreachable!();
                     };
+                    if !check_drop_monitor(tcx, body, our_func_def_id, generic_args, ty, build_drop_span(tcx, body, drop_at_block)) {
+                        return None;
+                    }
                     let mut patch = MirPatch::new(body);
-                    let new_bb_run_drop = patch.new_block(BasicBlockData{
-                        statements: vec![],
-                        terminator: Some(terminator.clone()),
-                        is_cleanup: drop_at_block_data.is_cleanup,
-                    });
+                    let new_bb_run_drop = patch.new_block(compat::basic_block_data(
+                        vec![],
+                        Some(terminator.clone()),
+                        drop_at_block_data.is_cleanup,
+                    ));
                     let temp_ref_to_droping_obj = Place::from(patch.new_temp(Ty::new_imm_ref(tcx, tcx.lifetimes.re_erased, ty), DUMMY_SP));
                     patch.add_assign(patch.terminator_loc(body, drop_at_block), temp_ref_to_droping_obj, Rvalue::Ref(
                         tcx.lifetimes.re_erased,
@@ -104,7 +108,7 @@ pub trait ObjectDropInstrumenter {
                     let temp_ret = patch.new_temp(tcx.types.unit, DUMMY_SP);
                     patch.patch_terminator(drop_at_block, TerminatorKind::Call{
                         func: crate::utils::instantiate_our_func(tcx, our_func_def_id, *generic_args, DUMMY_SP),
-                        args: our_call_args,
+                        args: our_call_args.into(),
                         destination: Place::from(temp_ret),
                         target: Some(new_bb_run_drop),
                         unwind: unwind.clone(),
@@ -122,15 +126,18 @@ pub trait ObjectDropInstrumenter {
                     let TyKind::Adt(_adt_def, generic_args) = arg_ty.kind() else {
                         unreachable!()
                     };
+                    if !check_drop_monitor(tcx, body, our_func_def_id, generic_args, arg_ty, *fn_span) {
+                        return None;
+                    }
                     let mut patch = MirPatch::new(body);
-                    let new_bb_run_drop = patch.new_block(BasicBlockData{
-                        statements: vec![],
-                        terminator: Some(terminator.clone()),
-                        is_cleanup: drop_at_block_data.is_cleanup,
-                    });
+                    let new_bb_run_drop = patch.new_block(compat::basic_block_data(
+                        vec![],
+                        Some(terminator.clone()),
+                        drop_at_block_data.is_cleanup,
+                    ));
                     let place_droping_obj = match args[0].node {
                         Operand::Copy(place) | Operand::Move(place) => place,
-                        Operand::Constant(..) => span_bug!(*fn_span, "running drop on constant")
+                        Operand::Constant(..) | Operand::RuntimeChecks(_) => span_bug!(*fn_span, "running drop on constant")
                     };
                     let temp_ref_to_droping_obj = Place::from(patch.new_temp(Ty::new_imm_ref(tcx, tcx.lifetimes.re_erased, arg_ty), *fn_span));
                     patch.add_assign(patch.terminator_loc(body, drop_at_block), temp_ref_to_droping_obj, Rvalue::Ref(
@@ -148,7 +155,7 @@ pub trait ObjectDropInstrumenter {
                     let temp_ret = patch.new_temp(tcx.types.unit, *fn_span);
                     patch.patch_terminator(drop_at_block, TerminatorKind::Call{
                         func: crate::utils::instantiate_our_func(tcx, our_func_def_id, *generic_args, *fn_span),
-                        args: our_call_args,
+                        args: our_call_args.into(),
                         destination: Place::from(temp_ret),
                         target: Some(new_bb_run_drop),
                         unwind: unwind.clone(),
@@ -170,11 +177,14 @@ pub trait ObjectDropInstrumenter {
         let drop_at_block_data = &body.basic_blocks[drop_at_block];
         let terminator = drop_at_block_data.terminator();
         match &terminator.kind {
-            TerminatorKind::Drop { place, target, unwind, replace} => {
+            TerminatorKind::Drop { place, target, unwind, replace, drop, async_fut } => {
                 let ty = place.ty(&body.local_decls, tcx).ty;
                 let TyKind::Adt(_adt_def, generic_args) = ty.kind() else {
                     unreachable!();
                 };
+                if !check_drop_monitor(tcx, body, our_func_def_id, generic_args, ty, build_drop_span(tcx, body, drop_at_block)) {
+                    return None;
+                }
                 let mut patch = MirPatch::new(body);
                 let temp_ref_to_droping_obj = Place::from(patch.new_temp(Ty::new_imm_ref(tcx, tcx.lifetimes.re_erased, ty), DUMMY_SP));
                 patch.add_assign(patch.terminator_loc(body, drop_at_block), temp_ref_to_droping_obj, Rvalue::Ref(
@@ -192,26 +202,38 @@ pub trait ObjectDropInstrumenter {
                 ];
 
                 let temp_ret = patch.new_temp(tcx.types.unit, DUMMY_SP);
-                let new_bb_run_call = patch.new_block(BasicBlockData {
-                    statements: vec![],
-                    terminator: Some(Terminator {
+                // the target of a drop may have other predecessors, but a call must not have a critical edge,
+                // so our call returns to a block of its own which jumps to the target
+                let new_bb_goto_target = patch.new_block(compat::basic_block_data(
+                    vec![],
+                    Some(Terminator {
+                        kind: TerminatorKind::Goto { target: *target },
+                        source_info: terminator.source_info,
+                    }),
+                    drop_at_block_data.is_cleanup,
+                ));
+                let new_bb_run_call = patch.new_block(compat::basic_block_data(
+                    vec![],
+                    Some(Terminator {
                         kind: TerminatorKind::Call { 
                             func: crate::utils::instantiate_our_func(tcx, our_func_def_id, *generic_args, DUMMY_SP),
-                            args: our_call_args, 
+                            args: our_call_args.into(), 
                             destination: temp_ret.into(), 
-                            target: Some(target.clone()),
+                            target: Some(new_bb_goto_target),
                             unwind: unwind.clone(), 
                             call_source: CallSource::Misc, 
                             fn_span: DUMMY_SP },
                         source_info: terminator.source_info.clone(),
                     }),
-                    is_cleanup: false,
-                });
+                    drop_at_block_data.is_cleanup,
+                ));
                 patch.patch_terminator(drop_at_block, TerminatorKind::Drop{
                     place: *place,
                     target: new_bb_run_call,
                     unwind: unwind.clone(),
                     replace: replace.clone(),
+                    drop: *drop,
+                    async_fut: *async_fut,
                 });
                 return Some((patch, drop_at_block));
             },
@@ -224,10 +246,13 @@ pub trait ObjectDropInstrumenter {
                 let TyKind::Adt(_adt_def, generic_args) = arg_ty.kind() else {
                     unreachable!()
                 };
+                if !check_drop_monitor(tcx, body, our_func_def_id, generic_args, arg_ty, *fn_span) {
+                    return None;
+                }
                 let mut patch = MirPatch::new(body);
                 let place_droping_obj = match args[0].node {
                     Operand::Copy(place) | Operand::Move(place) => place,
-                    Operand::Constant(..) => span_bug!(*fn_span, "running drop on constant")
+                    Operand::Constant(..) | Operand::RuntimeChecks(_) => span_bug!(*fn_span, "running drop on constant")
                 };
                 let temp_ref_to_droping_obj = Place::from(patch.new_temp(Ty::new_imm_ref(tcx, tcx.lifetimes.re_erased, arg_ty), *fn_span));
                 patch.add_assign(patch.terminator_loc(body, drop_at_block), temp_ref_to_droping_obj, Rvalue::Ref(
@@ -243,12 +268,12 @@ pub trait ObjectDropInstrumenter {
                     }
                 ];
                 let temp_ret = patch.new_temp(tcx.types.unit, *fn_span);
-                let new_bb_run_call = patch.new_block(BasicBlockData {
-                    statements: vec![],
-                    terminator: Some(Terminator {
+                let new_bb_run_call = patch.new_block(compat::basic_block_data(
+                    vec![],
+                    Some(Terminator {
                         kind: TerminatorKind::Call { 
                             func: crate::utils::instantiate_our_func(tcx, our_func_def_id, *generic_args, *fn_span),
-                            args: our_call_args, 
+                            args: our_call_args.into(), 
                             destination: temp_ret.into(), 
                             target: target.clone(),
                             unwind: unwind.clone(), 
@@ -256,8 +281,8 @@ pub trait ObjectDropInstrumenter {
                             fn_span: fn_span.clone() },
                         source_info: terminator.source_info.clone(),
                     }),
-                    is_cleanup: false,
-                });
+                    drop_at_block_data.is_cleanup,
+                ));
                 patch.patch_terminator(drop_at_block, TerminatorKind::Call{
                     func: func.clone(),
                     args: args.clone(),
@@ -0,0 +1,219 @@
+// Declarative call interception rules, so that teams can trace their own APIs without writing a handler.
+// SOLCON_RULES=<path> points to a rules file, one rule per line, `#` starts a comment:
+//   monitor_crate <crate name> <path to lib<crate name>.rlib>
+//   call <target function path> <before monitor path or -> <after monitor path or -> [args=all|none]
+// e.g.
+//   monitor_crate my_monitors /path/to/target/debug/libmy_monitors.rlib
+//   call my_crate::pool::Pool::get my_monitors::pool_get_before my_monitors::pool_get_after args=none
+// With args=all (the default) monitors follow the convention of our monitors: `(callsite: &str, args...)`, arguments
+// which are not pointers are passed by reference, the after monitor gets `&mut` return value last, and the monitors have
+// the same generic parameters as the target. With args=none they are non-generic `fn(callsite: &str)`.
+// The monitor crates are built beforehand, without solcon_instrumenter.
+use std::path::Path;
+use std::sync::OnceLock;
+
+use rustc_hir::def::DefKind;
+use rustc_metadata::creader::CStore;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::def_id::{DefId, DefIndex, LOCAL_CRATE};
+
+#[derive(Debug)]
+pub(crate) struct MonitorCrate {
+    pub name: String,
+    pub rlib_path: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct CallRule {
+    pub target: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub takes_args: bool,
+    line: usize,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Rules {
+    pub monitor_crates: Vec<MonitorCrate>,
+    pub calls: Vec<CallRule>,
+    path: String,
+}
+
+/// A call rule whose monitors were found in the monitor crates.
+#[derive(Debug)]
+pub(crate) struct ResolvedCallRule {
+    pub target: &'static str,
+    pub before: Option<DefId>,
+    pub after: Option<DefId>,
+    pub takes_args: bool,
+}
+
+static RULES: OnceLock<Rules> = OnceLock::new();
+static RESOLVED_CALL_RULES: OnceLock<Vec<ResolvedCallRule>> = OnceLock::new();
+
+/// Load the rules file named by SOLCON_RULES, called once at startup.
+pub(crate) fn load() -> Result<&'static Rules, String> {
+    let rules = match std::env::var("SOLCON_RULES") {
+        Ok(path) => {
+            let content = std::fs::read_to_string(&path).map_err(|err| format!("cannot read rules file {path}: {err}"))?;
+            parse(&content, &path)?
+        }
+        Err(_) => Rules::default(),
+    };
+    Ok(RULES.get_or_init(|| rules))
+}
+
+pub(crate) fn rules() -> &'static Rules {
+    RULES.get().expect("rules::load should be called at startup")
+}
+
+fn parse(content: &str, path: &str) -> Result<Rules, String> {
+    let mut rules = Rules { path: path.to_owned(), ..Default::default() };
+    for (index, line) in content.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let monitor = |word: &str| (word != "-").then(|| word.to_owned());
+        match words.as_slice() {
+            [] => {}
+            ["monitor_crate", name, rlib_path] => {
+                if !Path::new(rlib_path).exists() {
+                    return Err(format!("{path}:{line_number}: monitor crate {rlib_path} does not exist"));
+                }
+                rules.monitor_crates.push(MonitorCrate { name: name.to_string(), rlib_path: rlib_path.to_string() });
+            }
+            ["call", target, before, after, options @ ..] => {
+                let mut takes_args = true;
+                for option in options {
+                    match *option {
+                        "args=all" => takes_args = true,
+                        "args=none" => takes_args = false,
+                        _ => return Err(format!("{path}:{line_number}: unknown option {option}, expect args=all or args=none")),
+                    }
+                }
+                if *before == "-" && *after == "-" {
+                    return Err(format!("{path}:{line_number}: rule for {target} has neither a before nor an after monitor"));
+                }
+                rules.calls.push(CallRule {
+                    target: target.to_string(),
+                    before: monitor(before),
+                    after: monitor(after),
+                    takes_args,
+                    line: line_number,
+                });
+            }
+            _ => return Err(format!("{path}:{line_number}: cannot parse rule `{line}`, \
+                expect `monitor_crate <name> <rlib path>` or `call <target> <before|-> <after|-> [args=all|none]`")),
+        }
+    }
+    Ok(rules)
+}
+
+/// Find the monitors named by the call rules in the monitor crates, like mirpass::find_all_monitors does for ours.
+pub(crate) fn resolve_monitors(tcx: TyCtxt<'_>) {
+    let rules = rules();
+    let mut found: Vec<(String, DefId)> = Vec::new();
+    if !rules.calls.is_empty() {
+        let crate_store_untracked = tcx.cstore_untracked();
+        let crate_store = crate_store_untracked.as_any().downcast_ref::<CStore>().unwrap();
+        for &krate in tcx.crates(()) {
+            if krate == LOCAL_CRATE || !crate::utils::is_monitor_crate(tcx.crate_name(krate).as_str()) {
+                continue;
+            }
+            for index in 0..crate_store.num_def_ids_untracked(krate) {
+                let def_id = DefId { krate, index: DefIndex::from_usize(index) };
+                if matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn) {
+                    found.push((tcx.def_path_str(def_id), def_id));
+                }
+            }
+        }
+    }
+    let resolve = |name: &Option<String>, rule: &CallRule| -> Option<DefId> {
+        let name = name.as_ref()?;
+        let def_id = found.iter().find(|(path, _)| path == name).map(|(_, def_id)| *def_id);
+        if def_id.is_none() {
+            tcx.dcx().warn(format!("solcon: monitor `{name}` of the rule at {}:{} is not found in the monitor crates, \
+                calls to `{}` are not instrumented by it", rules.path, rule.line, rule.target));
+        }
+        def_id
+    };
+    let resolved = rules.calls.iter()
+        .map(|rule| ResolvedCallRule {
+            target: rule.target.as_str(),
+            before: resolve(&rule.before, rule),
+            after: resolve(&rule.after, rule),
+            takes_args: rule.takes_args,
+        })
+        .collect();
+    info!("resolved call rules {resolved:#?}");
+    RESOLVED_CALL_RULES.set(resolved).unwrap();
+}
+
+pub(crate) fn resolved_call_rules() -> &'static [ResolvedCallRule] {
+    RESOLVED_CALL_RULES.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH: &str = "rules.txt";
+
+    fn parse_error(content: &str) -> String {
+        parse(content, PATH).expect_err("the rules should not parse")
+    }
+
+    #[test]
+    fn comments_and_monitors() {
+        let rlib = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        let content = format!("# monitors of the pool\n\
+            \n\
+            monitor_crate my_monitors {rlib}  # built beforehand\n\
+            call my_crate::Pool::get my_monitors::get_before my_monitors::get_after\n\
+            \tcall my_crate::checksum - my_monitors::checksum_after args=none\n\
+            call my_crate::reset my_monitors::reset_before - args=none args=all\n");
+        let rules = parse(&content, PATH).unwrap();
+        assert_eq!(rules.path, PATH);
+        assert_eq!(rules.monitor_crates.len(), 1);
+        assert_eq!((rules.monitor_crates[0].name.as_str(), rules.monitor_crates[0].rlib_path.as_str()), ("my_monitors", rlib));
+        let calls: Vec<_> = rules.calls.iter()
+            .map(|rule| (rule.target.as_str(), rule.before.as_deref(), rule.after.as_deref(), rule.takes_args, rule.line))
+            .collect();
+        assert_eq!(calls, [
+            ("my_crate::Pool::get", Some("my_monitors::get_before"), Some("my_monitors::get_after"), true, 4),
+            ("my_crate::checksum", None, Some("my_monitors::checksum_after"), false, 5),
+            ("my_crate::reset", Some("my_monitors::reset_before"), None, true, 6),
+        ]);
+    }
+
+    #[test]
+    fn empty_rules() {
+        let rules = parse("# nothing yet\n\n", PATH).unwrap();
+        assert!(rules.monitor_crates.is_empty() && rules.calls.is_empty());
+    }
+
+    #[test]
+    fn missing_monitor_crate() {
+        assert_eq!(parse_error("\nmonitor_crate my_monitors /nonexistent/libmy_monitors.rlib"),
+            "rules.txt:2: monitor crate /nonexistent/libmy_monitors.rlib does not exist");
+    }
+
+    #[test]
+    fn unknown_option() {
+        assert_eq!(parse_error("call my_crate::f my_monitors::f_before - args=some"),
+            "rules.txt:1: unknown option args=some, expect args=all or args=none");
+    }
+
+    #[test]
+    fn rule_without_monitors() {
+        assert_eq!(parse_error("call my_crate::f - -"), "rules.txt:1: rule for my_crate::f has neither a before nor an after monitor");
+    }
+
+    #[test]
+    fn malformed_rules() {
+        for rule in ["call my_crate::f my_monitors::f_before", "monitor_crate my_monitors", "intercept my_crate::f"] {
+            assert_eq!(parse_error(&format!("# {rule}\n{rule} # comment")), format!("rules.txt:2: cannot parse rule `{rule}`, \
+                expect `monitor_crate <name> <rlib path>` or `call <target> <before|-> <after|-> [args=all|none]`"));
+        }
+    }
+}
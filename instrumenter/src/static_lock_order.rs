@@ -0,0 +1,554 @@
+// Static lock-order analysis on MIR, run inside rustc without running the program (lockbud-like).
+// For every function body of the local crate, a forward dataflow tracks which locals hold a lock guard
+// (Mutex::lock / RwLock::read / RwLock::write -> unwrap -> ... -> drop).
+// Whenever a lock is acquired, directly or through a call to a local function, while guards are held,
+// we add held -> acquired edges to a crate-wide lock-order graph.
+// Double locks and cycles in the graph are reported as rustc warnings.
+//
+// Locks are identified by where they come from: a static, a struct field (by type, like lockdep classes),
+// or the local where the lock (or the Arc holding it) was created. References, clones, derefs and closure captures
+// are followed back to that origin, so `Arc::clone`d locks moved into different thread closures are the same lock.
+//
+// Enabled by SOLCON_STATIC_CHECK:
+//   "1" / "on": run the analysis and instrument the crate as usual
+//   "only":     only run the analysis, no instrumentation and no monitor lib needed (used by `cargo solcon check`)
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::LazyLock;
+
+use rustc_hir::BodyOwnerKind;
+use rustc_index::IndexVec;
+use rustc_middle::mir::{BasicBlock, Body, Local, Operand, Place, ProjectionElem, Rvalue, StatementKind, TerminatorKind, AggregateKind, VarDebugInfoContents};
+use rustc_middle::ty::{self, Ty, TyCtxt, TyKind};
+use rustc_span::def_id::{DefId, LocalDefId};
+use rustc_span::Span;
+
+use crate::{compat, def_paths, utils};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaticCheckMode {
+    Off,
+    WithInstrument,
+    Only,
+}
+
+static MODE: LazyLock<StaticCheckMode> = LazyLock::new(|| match std::env::var("SOLCON_STATIC_CHECK").as_deref() {
+    Ok("only") => StaticCheckMode::Only,
+    Ok("1") | Ok("on") => StaticCheckMode::WithInstrument,
+    Ok("0") | Ok("off") | Err(_) => StaticCheckMode::Off,
+    Ok(other) => {
+        warn!("unknown SOLCON_STATIC_CHECK {other}, expect on, only or off");
+        StaticCheckMode::Off
+    }
+});
+
+pub fn mode() -> StaticCheckMode {
+    *MODE
+}
+
+const LOCK_FUNCTIONS: [(&str, LockMode); 3] = [
+    ("std::sync::Mutex::<T>::lock", LockMode::Exclusive),
+    ("std::sync::RwLock::<T>::read", LockMode::Shared),
+    ("std::sync::RwLock::<T>::write", LockMode::Exclusive),
+];
+const GUARD_TYPES: [&str; 3] = ["std::sync::MutexGuard", "std::sync::RwLockReadGuard", "std::sync::RwLockWriteGuard"];
+/// calls returning (a reference to) the same object as their first argument
+const ALIASING_METHODS: [&str; 4] = [
+    "std::clone::Clone::clone", "std::ops::Deref::deref", "std::ops::DerefMut::deref_mut", "std::convert::AsRef::as_ref",
+];
+/// cycles longer than this are not reported
+const MAX_CYCLE_LEN: usize = 4;
+/// bound of the recursion following aliases
+const MAX_RESOLVE_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockMode {
+    Exclusive,
+    Shared,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum LockBase {
+    Static(DefId),
+    /// a field of a struct, all instances of the struct share the class
+    Field(DefId, usize),
+    /// the local where the lock (or its Arc/Box) was created, or a parameter
+    Local(LocalDefId, Local),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct LockClass {
+    base: LockBase,
+    /// tuple fields below the base, e.g. the Mutex in Arc<(Condvar, Mutex<T>)>
+    path: Vec<usize>,
+}
+
+type ClassId = usize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HeldGuard {
+    class: ClassId,
+    mode: LockMode,
+    span: Span,
+}
+
+/// locals holding a guard at some program point
+type GuardState = BTreeMap<Local, HeldGuard>;
+
+#[derive(Debug, Clone)]
+struct EdgeExample {
+    held_span: Span,
+    acquire_span: Span,
+    /// the second lock is acquired inside this callee
+    via_call: Option<DefId>,
+}
+
+struct Analysis<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    bodies: Vec<LocalDefId>,
+    /// closure -> (creating function, captured operands)
+    closure_captures: HashMap<LocalDefId, (LocalDefId, Vec<Operand<'tcx>>)>,
+    classes: Vec<LockClass>,
+    class_ids: HashMap<LockClass, ClassId>,
+    /// locks a function may acquire, directly or through local callees
+    summaries: HashMap<LocalDefId, Vec<(ClassId, LockMode, Span)>>,
+    edges: BTreeMap<(ClassId, ClassId), EdgeExample>,
+    reported_double_locks: HashSet<Span>,
+}
+
+pub fn check_crate(tcx: TyCtxt<'_>) {
+    let bodies: Vec<LocalDefId> = tcx.hir_body_owners()
+        .filter(|&def_id| matches!(tcx.hir_body_owner_kind(def_id), BodyOwnerKind::Fn | BodyOwnerKind::Closure))
+        .filter(|&def_id| tcx.is_mir_available(def_id))
+        .collect();
+    info!("static lock-order analysis on {} bodies", bodies.len());
+    let mut analysis = Analysis {
+        tcx,
+        bodies,
+        closure_captures: HashMap::new(),
+        classes: Vec::new(),
+        class_ids: HashMap::new(),
+        summaries: HashMap::new(),
+        edges: BTreeMap::new(),
+        reported_double_locks: HashSet::new(),
+    };
+    analysis.collect_closure_captures();
+    analysis.compute_summaries();
+    for def_id in analysis.bodies.clone() {
+        analysis.check_body(def_id);
+    }
+    analysis.report_cycles();
+}
+
+impl<'tcx> Analysis<'tcx> {
+    fn body(&self, def_id: LocalDefId) -> &'tcx Body<'tcx> {
+        self.tcx.optimized_mir(def_id)
+    }
+
+    fn intern(&mut self, class: LockClass) -> ClassId {
+        if let Some(&id) = self.class_ids.get(&class) {
+            return id;
+        }
+        self.classes.push(class.clone());
+        self.class_ids.insert(class, self.classes.len() - 1);
+        self.classes.len() - 1
+    }
+
+    fn collect_closure_captures(&mut self) {
+        for &def_id in &self.bodies {
+            let body = self.body(def_id);
+            for bb_data in body.basic_blocks.iter() {
+                for statement in &bb_data.statements {
+                    let StatementKind::Assign(box (_, Rvalue::Aggregate(box AggregateKind::Closure(closure_def_id, _), operands))) = &statement.kind else {
+                        continue;
+                    };
+                    if let Some(closure_def_id) = closure_def_id.as_local() {
+                        self.closure_captures.entry(closure_def_id).or_insert_with(|| (def_id, operands.iter().cloned().collect()));
+                    }
+                }
+            }
+        }
+    }
+
+    // ---------- lock identity ----------
+
+    fn resolve_operand(&self, def_id: LocalDefId, operand: &Operand<'tcx>, depth: usize) -> Option<LockClass> {
+        match operand {
+            Operand::Copy(place) | Operand::Move(place) => self.resolve_place(def_id, place, depth),
+            Operand::Constant(constant) => {
+                let static_def_id = constant.check_static_ptr(self.tcx)?;
+                Some(LockClass { base: LockBase::Static(static_def_id), path: vec![] })
+            }
+            Operand::RuntimeChecks(_) => None,
+        }
+    }
+
+    fn resolve_place(&self, def_id: LocalDefId, place: &Place<'tcx>, depth: usize) -> Option<LockClass> {
+        if depth > MAX_RESOLVE_DEPTH {
+            return None;
+        }
+        let body = self.body(def_id);
+        let mut class: Option<LockClass> = None;
+        for (i, elem) in place.projection.iter().enumerate() {
+            let ProjectionElem::Field(field, _) = elem else {
+                // derefs reach the same object, indexing and downcasts keep the current class
+                continue;
+            };
+            let parent_ty = Place::ty_from(place.local, &place.projection[..i], &body.local_decls, self.tcx).ty;
+            match parent_ty.kind() {
+                TyKind::Adt(adt_def, _) if adt_def.is_struct() => {
+                    class = Some(LockClass { base: LockBase::Field(adt_def.did(), field.as_usize()), path: vec![] });
+                }
+                TyKind::Closure(..) if class.is_none() => {
+                    // a captured variable of the closure we are in, continue in the function creating the closure
+                    class = self.resolve_upvar(def_id, field.as_usize(), depth + 1)
+                        .or_else(|| Some(LockClass { base: LockBase::Local(def_id, place.local), path: vec![field.as_usize()] }));
+                }
+                TyKind::Tuple(..) => {
+                    let mut current = match class.take() {
+                        Some(class) => class,
+                        None => self.resolve_local(def_id, place.local, depth + 1)?,
+                    };
+                    current.path.push(field.as_usize());
+                    class = Some(current);
+                }
+                _ => {}
+            }
+        }
+        match class {
+            Some(class) => Some(class),
+            None => self.resolve_local(def_id, place.local, depth + 1),
+        }
+    }
+
+    fn resolve_upvar(&self, closure: LocalDefId, upvar: usize, depth: usize) -> Option<LockClass> {
+        let (parent, operands) = self.closure_captures.get(&closure)?;
+        let operand = operands.get(upvar)?;
+        self.resolve_operand(*parent, operand, depth)
+    }
+
+    /// Follow the definition of a local back to the origin of the object it refers to.
+    fn resolve_local(&self, def_id: LocalDefId, local: Local, depth: usize) -> Option<LockClass> {
+        if depth > MAX_RESOLVE_DEPTH {
+            return None;
+        }
+        let body = self.body(def_id);
+        let origin = Some(LockClass { base: LockBase::Local(def_id, local), path: vec![] });
+        if local.as_usize() <= body.arg_count {
+            return origin;
+        }
+        for bb_data in body.basic_blocks.iter() {
+            for statement in &bb_data.statements {
+                let StatementKind::Assign(box (dest, rvalue)) = &statement.kind else {
+                    continue;
+                };
+                if dest.local != local || !dest.projection.is_empty() {
+                    continue;
+                }
+                return match rvalue {
+                    Rvalue::Ref(_, _, place) | Rvalue::CopyForDeref(place) | Rvalue::RawPtr(_, place) => self.resolve_place(def_id, place, depth + 1),
+                    Rvalue::Use(operand, _) => self.resolve_operand(def_id, operand, depth + 1).or(origin),
+                    _ => origin,
+                };
+            }
+            if let TerminatorKind::Call { func, args, destination, .. } = &bb_data.terminator().kind {
+                if destination.local != local || !destination.projection.is_empty() {
+                    continue;
+                }
+                let aliasing = utils::get_function_def_id(self.tcx, &body.local_decls, func)
+                    .is_some_and(|callee| def_paths::matches_any(self.tcx, callee, &ALIASING_METHODS));
+                if aliasing {
+                    if let Some(arg) = args.first() {
+                        return self.resolve_operand(def_id, &arg.node, depth + 1).or(origin);
+                    }
+                }
+                return origin;
+            }
+        }
+        origin
+    }
+
+    fn is_relative_to_caller(&self, class: &LockClass) -> bool {
+        // parameters and unresolved captures of a callee are different objects for each call
+        match class.base {
+            LockBase::Local(def_id, local) => local.as_usize() <= self.body(def_id).arg_count,
+            LockBase::Static(..) | LockBase::Field(..) => false,
+        }
+    }
+
+    fn class_name(&self, id: ClassId) -> String {
+        let class = &self.classes[id];
+        let mut name = match class.base {
+            LockBase::Static(def_id) => format!("static `{}`", self.tcx.def_path_str(def_id)),
+            LockBase::Field(adt, field) => {
+                let adt_def = self.tcx.adt_def(adt);
+                let field_name = adt_def.non_enum_variant().fields.iter().nth(field).map(|f| f.name.to_string()).unwrap_or_else(|| field.to_string());
+                format!("field `{}.{field_name}`", self.tcx.def_path_str(adt))
+            }
+            LockBase::Local(def_id, local) => {
+                let body = self.body(def_id);
+                let var_name = body.var_debug_info.iter().find_map(|info| match &info.value {
+                    VarDebugInfoContents::Place(place) if place.local == local && place.projection.is_empty() => Some(info.name.to_string()),
+                    _ => None,
+                });
+                match var_name {
+                    Some(var_name) => format!("`{var_name}` in `{}`", self.tcx.def_path_str(def_id)),
+                    None => format!("`{local:?}` in `{}`", self.tcx.def_path_str(def_id)),
+                }
+            }
+        };
+        for field in &class.path {
+            name.push_str(&format!(".{field}"));
+        }
+        name
+    }
+
+    // ---------- summaries ----------
+
+    fn lock_call(&self, body: &Body<'tcx>, func: &Operand<'tcx>) -> Option<LockMode> {
+        let callee = utils::get_function_def_id(self.tcx, &body.local_decls, func)?;
+        LOCK_FUNCTIONS.iter().find(|(path, _)| def_paths::resolve(self.tcx, path) == Some(callee)).map(|(_, mode)| *mode)
+    }
+
+    fn local_callee(&self, caller: LocalDefId, func: &Operand<'tcx>) -> Option<LocalDefId> {
+        let (callee, args) = func.const_fn_def()?;
+        let callee = match compat::resolve_instance(self.tcx, compat::typing_env(self.tcx, caller.to_def_id()), callee, args) {
+            Some(instance) => instance.def_id(),
+            None => callee,
+        };
+        let callee = callee.as_local()?;
+        self.summaries.contains_key(&callee).then_some(callee)
+    }
+
+    fn compute_summaries(&mut self) {
+        // every local body gets a summary, local_callee relies on it
+        for def_id in self.bodies.clone() {
+            self.summaries.insert(def_id, Vec::new());
+        }
+        let mut direct = self.summaries.clone();
+        let mut callees: HashMap<LocalDefId, Vec<LocalDefId>> = HashMap::new();
+        for def_id in self.bodies.clone() {
+            let body = self.body(def_id);
+            for bb_data in body.basic_blocks.iter() {
+                let TerminatorKind::Call { func, args, fn_span, .. } = &bb_data.terminator().kind else {
+                    continue;
+                };
+                if let Some(mode) = self.lock_call(body, func) {
+                    let Some(class) = args.first().and_then(|arg| self.resolve_operand(def_id, &arg.node, 0)) else {
+                        continue;
+                    };
+                    if self.is_relative_to_caller(&class) {
+                        continue;
+                    }
+                    let id = self.intern(class);
+                    direct.entry(def_id).or_default().push((id, mode, *fn_span));
+                } else if let Some(callee) = self.local_callee(def_id, func) {
+                    callees.entry(def_id).or_default().push(callee);
+                }
+            }
+        }
+        // propagate through the call graph until nothing changes
+        self.summaries = direct;
+        loop {
+            let mut changed = false;
+            for def_id in self.bodies.clone() {
+                let mut summary = self.summaries.get(&def_id).cloned().unwrap_or_default();
+                for callee in callees.get(&def_id).into_iter().flatten() {
+                    for acquired in self.summaries.get(callee).into_iter().flatten() {
+                        if !summary.iter().any(|(class, mode, _)| *class == acquired.0 && *mode == acquired.1) {
+                            summary.push(*acquired);
+                            changed = true;
+                        }
+                    }
+                }
+                self.summaries.insert(def_id, summary);
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    // ---------- guard dataflow ----------
+
+    fn holds_guard(&self, ty: Ty<'tcx>) -> bool {
+        ty.walk().any(|arg| match arg.kind() {
+            ty::GenericArgKind::Type(ty) => match ty.kind() {
+                TyKind::Adt(adt_def, _) => def_paths::matches_any(self.tcx, adt_def.did(), &GUARD_TYPES),
+                _ => false,
+            },
+            _ => false,
+        })
+    }
+
+    fn check_body(&mut self, def_id: LocalDefId) {
+        let body = self.body(def_id);
+        let mut entry_states: IndexVec<BasicBlock, Option<GuardState>> = IndexVec::from_elem_n(None, body.basic_blocks.len());
+        entry_states[rustc_middle::mir::START_BLOCK] = Some(GuardState::new());
+        let mut worklist = vec![rustc_middle::mir::START_BLOCK];
+        while let Some(bb) = worklist.pop() {
+            let mut state = entry_states[bb].clone().unwrap_or_default();
+            let bb_data = &body.basic_blocks[bb];
+            if bb_data.is_cleanup {
+                continue;
+            }
+            for statement in &bb_data.statements {
+                let StatementKind::Assign(box (dest, rvalue)) = &statement.kind else {
+                    continue;
+                };
+                // the guard follows a move into another local or into an aggregate, e.g. Some(guard)
+                let operands: Vec<&Operand<'tcx>> = match rvalue {
+                    Rvalue::Use(operand, _) => vec![operand],
+                    Rvalue::Aggregate(_, operands) => operands.iter().collect(),
+                    _ => continue,
+                };
+                if !dest.projection.is_empty() {
+                    continue;
+                }
+                if let Some(guard) = operands.into_iter().find_map(|operand| moved_guard(&mut state, operand)) {
+                    state.insert(dest.local, guard);
+                }
+            }
+            let terminator = bb_data.terminator();
+            let successors: Vec<BasicBlock> = match &terminator.kind {
+                TerminatorKind::Call { target, .. } => {
+                    self.transfer_call(def_id, body, &mut state, &terminator.kind);
+                    target.iter().copied().collect()
+                }
+                TerminatorKind::Drop { place, target, .. } => {
+                    if place.projection.is_empty() {
+                        state.remove(&place.local);
+                    }
+                    vec![*target]
+                }
+                _ => terminator.successors().collect(),
+            };
+            for succ in successors {
+                let entry = &mut entry_states[succ];
+                let merged = match entry {
+                    None => Some(state.clone()),
+                    Some(old) => {
+                        let mut merged = old.clone();
+                        for (local, guard) in &state {
+                            merged.entry(*local).or_insert_with(|| guard.clone());
+                        }
+                        (merged != *old).then_some(merged)
+                    }
+                };
+                if let Some(merged) = merged {
+                    *entry = Some(merged);
+                    worklist.push(succ);
+                }
+            }
+        }
+    }
+
+    fn transfer_call(&mut self, def_id: LocalDefId, body: &'tcx Body<'tcx>, state: &mut GuardState, call: &TerminatorKind<'tcx>) {
+        let TerminatorKind::Call { func, args, destination, fn_span, .. } = call else {
+            return;
+        };
+        let fn_span = *fn_span;
+        let args: Vec<&Operand<'tcx>> = args.iter().map(|arg| &arg.node).collect();
+        if let Some(mode) = self.lock_call(body, func) {
+            let Some(class) = args.first().and_then(|arg| self.resolve_operand(def_id, arg, 0)) else {
+                return;
+            };
+            let class = self.intern(class);
+            self.on_acquire(state, class, mode, fn_span, None);
+            if destination.projection.is_empty() {
+                state.insert(destination.local, HeldGuard { class, mode, span: fn_span });
+            }
+            return;
+        }
+        if !state.is_empty() {
+            if let Some(callee) = self.local_callee(def_id, func) {
+                for (class, mode, _) in self.summaries.get(&callee).cloned().unwrap_or_default() {
+                    self.on_acquire(state, class, mode, fn_span, Some(callee.to_def_id()));
+                }
+            }
+        }
+        // a guard moved into a call is gone, unless the call gives it back (unwrap, Condvar::wait, ...)
+        let returns_guard = self.holds_guard(destination.ty(&body.local_decls, self.tcx).ty);
+        for arg in args {
+            if let Some(guard) = moved_guard(state, arg) {
+                if returns_guard && destination.projection.is_empty() {
+                    state.insert(destination.local, guard);
+                }
+            }
+        }
+    }
+
+    fn on_acquire(&mut self, state: &GuardState, class: ClassId, mode: LockMode, acquire_span: Span, via_call: Option<DefId>) {
+        for held in state.values() {
+            if held.class == class {
+                if (held.mode == LockMode::Exclusive || mode == LockMode::Exclusive) && self.reported_double_locks.insert(acquire_span) {
+                    let mut diag = self.tcx.dcx().struct_span_warn(acquire_span,
+                        format!("solcon: {} may be locked again while it is already held (self deadlock)", self.class_name(class)));
+                    diag = diag.with_span_note(held.span, "the lock is first acquired here");
+                    if let Some(callee) = via_call {
+                        diag = diag.with_note(format!("the second acquisition happens in `{}`", self.tcx.def_path_str(callee)));
+                    }
+                    diag.emit();
+                }
+                continue;
+            }
+            self.edges.entry((held.class, class)).or_insert(EdgeExample { held_span: held.span, acquire_span, via_call });
+        }
+    }
+
+    // ---------- report ----------
+
+    fn report_cycles(&self) {
+        let mut graph: BTreeMap<ClassId, Vec<ClassId>> = BTreeMap::new();
+        for &(from, to) in self.edges.keys() {
+            graph.entry(from).or_default().push(to);
+        }
+        let mut cycles = Vec::new();
+        for &start in graph.keys() {
+            search_cycles(&graph, start, &mut vec![start], &mut cycles);
+        }
+        for cycle in cycles {
+            let names: Vec<String> = cycle.iter().map(|&class| self.class_name(class)).collect();
+            let first_edge = &self.edges[&(cycle[0], cycle[1 % cycle.len()])];
+            let mut diag = self.tcx.dcx().struct_span_warn(first_edge.acquire_span,
+                format!("solcon: potential deadlock, conflicting lock order {} -> {}", names.join(" -> "), names[0]));
+            for (i, &from) in cycle.iter().enumerate() {
+                let to = cycle[(i + 1) % cycle.len()];
+                let edge = &self.edges[&(from, to)];
+                diag = diag.with_span_note(edge.acquire_span, format!("{} is acquired here", names[(i + 1) % cycle.len()]));
+                diag = diag.with_span_note(edge.held_span, format!("while {} acquired here is still held", names[i]));
+                if let Some(callee) = edge.via_call {
+                    diag = diag.with_note(format!("{} is acquired in `{}`", names[(i + 1) % cycle.len()], self.tcx.def_path_str(callee)));
+                }
+            }
+            diag.emit();
+        }
+    }
+}
+
+fn search_cycles(graph: &BTreeMap<ClassId, Vec<ClassId>>, start: ClassId, path: &mut Vec<ClassId>, cycles: &mut Vec<Vec<ClassId>>) {
+    let current = *path.last().unwrap();
+    let Some(successors) = graph.get(&current) else {
+        return;
+    };
+    for &next in successors {
+        if next == start {
+            cycles.push(path.clone());
+        } else if next > start && !path.contains(&next) && path.len() < MAX_CYCLE_LEN {
+            path.push(next);
+            search_cycles(graph, start, path, cycles);
+            path.pop();
+        }
+    }
+}
+
+/// The guard held by a local used as a whole. Guards are not Copy, a copy of one is a move turned into a copy by
+/// CopyProp at its last use.
+fn moved_guard(state: &mut GuardState, operand: &Operand<'_>) -> Option<HeldGuard> {
+    let (Operand::Move(place) | Operand::Copy(place)) = operand else {
+        return None;
+    };
+    if !place.projection.is_empty() {
+        return None;
+    }
+    state.remove(&place.local)
+}
@@ -0,0 +1,229 @@
+// Statistics of what our passes did on the crate being compiled: bodies processed or skipped (and why), instrumented
+// call and drop sites per handler, memory access sites, functions traced (SOLCON_TRACE_CALLS) and monitors not found.
+// After the compilation they are printed as a note and written to <output dir>/<crate>.solcon_stats.json
+// (e.g. target/debug/deps/foo-0123abcd.solcon_stats.json).
+// A handler with 0 sites is still listed, so a handler which stopped matching after a refactor is easy to spot.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use rustc_session::EarlyDiagCtxt;
+
+static STATS: Mutex<Stats> = Mutex::new(Stats::new());
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum SkipReason {
+    FilteredCrate,
+    Promoted,
+    Foreign,
+    ConstOrStatic,
+}
+
+impl SkipReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            SkipReason::FilteredCrate => "filtered_crate",
+            SkipReason::Promoted => "promoted",
+            SkipReason::Foreign => "foreign",
+            SkipReason::ConstOrStatic => "const_or_static",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Hook {
+    Before,
+    After,
+}
+
+#[derive(Default)]
+struct HandlerStats {
+    /// sites matching the target of the handler
+    sites: usize,
+    /// sites where the before/after monitor call was inserted
+    before: usize,
+    after: usize,
+}
+
+impl HandlerStats {
+    fn record(&mut self, hook: Hook) {
+        match hook {
+            Hook::Before => self.before += 1,
+            Hook::After => self.after += 1,
+        }
+    }
+}
+
+struct Stats {
+    crate_name: Option<String>,
+    json_path: Option<PathBuf>,
+    bodies_processed: usize,
+    bodies_skipped: BTreeMap<SkipReason, usize>,
+    /// by target function of the FunctionCallInstrumenter
+    calls: BTreeMap<String, HandlerStats>,
+    /// by target type of the ObjectDropInstrumenter
+    drops: BTreeMap<String, HandlerStats>,
+    mem_reads: usize,
+    mem_writes: usize,
+    /// bodies instrumented for call tracing
+    traced_fns: usize,
+    missing_monitors: Vec<&'static str>,
+}
+
+impl Stats {
+    const fn new() -> Self {
+        Stats {
+            crate_name: None,
+            json_path: None,
+            bodies_processed: 0,
+            bodies_skipped: BTreeMap::new(),
+            calls: BTreeMap::new(),
+            drops: BTreeMap::new(),
+            mem_reads: 0,
+            mem_writes: 0,
+            traced_fns: 0,
+            missing_monitors: Vec::new(),
+        }
+    }
+}
+
+fn with_stats(f: impl FnOnce(&mut Stats)) {
+    f(&mut STATS.lock().unwrap())
+}
+
+/// Called when instrumentation starts, a report is only emitted for crates which were instrumented.
+pub(crate) fn start(crate_name: String, json_path: PathBuf) {
+    with_stats(|stats| {
+        stats.crate_name = Some(crate_name);
+        stats.json_path = Some(json_path);
+    });
+}
+
+pub(crate) fn body_processed() {
+    with_stats(|stats| stats.bodies_processed += 1);
+}
+
+pub(crate) fn body_skipped(reason: SkipReason) {
+    with_stats(|stats| *stats.bodies_skipped.entry(reason).or_default() += 1);
+}
+
+/// Register the handlers, so that handlers without any site are reported too.
+pub(crate) fn register_handlers<'a>(call_targets: impl Iterator<Item = &'a str>, drop_targets: impl Iterator<Item = &'a str>) {
+    with_stats(|stats| {
+        for target in call_targets {
+            stats.calls.entry(target.to_owned()).or_default();
+        }
+        for target in drop_targets {
+            stats.drops.entry(target.to_owned()).or_default();
+        }
+    });
+}
+
+pub(crate) fn call_site_found(target: &str) {
+    with_stats(|stats| stats.calls.entry(target.to_owned()).or_default().sites += 1);
+}
+
+pub(crate) fn call_instrumented(target: &str, hook: Hook) {
+    with_stats(|stats| stats.calls.entry(target.to_owned()).or_default().record(hook));
+}
+
+pub(crate) fn drop_site_found(target: &str) {
+    with_stats(|stats| stats.drops.entry(target.to_owned()).or_default().sites += 1);
+}
+
+pub(crate) fn drop_instrumented(target: &str, hook: Hook) {
+    with_stats(|stats| stats.drops.entry(target.to_owned()).or_default().record(hook));
+}
+
+pub(crate) fn mem_read_instrumented() {
+    with_stats(|stats| stats.mem_reads += 1);
+}
+
+pub(crate) fn mem_write_instrumented() {
+    with_stats(|stats| stats.mem_writes += 1);
+}
+
+pub(crate) fn fn_traced() {
+    with_stats(|stats| stats.traced_fns += 1);
+}
+
+pub(crate) fn monitors_missing(kinds: &[&'static str]) {
+    with_stats(|stats| stats.missing_monitors.extend_from_slice(kinds));
+}
+
+/// Called after the compilation: print the summary as a note and write the json file.
+pub(crate) fn report(early_dcx: &EarlyDiagCtxt) {
+    let stats = STATS.lock().unwrap();
+    let (Some(crate_name), Some(json_path)) = (&stats.crate_name, &stats.json_path) else {
+        return;
+    };
+    early_dcx.early_note(summary(crate_name, &stats));
+    if let Err(err) = std::fs::write(json_path, to_json(crate_name, &stats)) {
+        early_dcx.early_warn(format!("solcon: cannot write instrumentation statistics to {}: {err}", json_path.display()));
+    }
+}
+
+fn summary(crate_name: &str, stats: &Stats) -> String {
+    let mut summary = format!("solcon: instrumented crate `{crate_name}`: {} bodies processed", stats.bodies_processed);
+    let skipped: usize = stats.bodies_skipped.values().sum();
+    if skipped > 0 {
+        let reasons: Vec<_> = stats.bodies_skipped.iter().map(|(reason, count)| format!("{count} {}", reason.as_str())).collect();
+        write!(summary, ", {skipped} skipped ({})", reasons.join(", ")).unwrap();
+    }
+    write!(summary, "; {} mem read and {} mem write sites", stats.mem_reads, stats.mem_writes).unwrap();
+    if stats.traced_fns > 0 {
+        write!(summary, "; {} functions traced", stats.traced_fns).unwrap();
+    }
+    for (kind, handlers) in [("call to", &stats.calls), ("drop of", &stats.drops)] {
+        for (target, handler) in handlers.iter().filter(|(_, handler)| handler.sites > 0) {
+            write!(summary, "\n  {kind} {target}: {} sites, {} before and {} after monitors",
+                handler.sites, handler.before, handler.after).unwrap();
+        }
+    }
+    if !stats.missing_monitors.is_empty() {
+        write!(summary, "\n  monitors not found: {}", stats.missing_monitors.join(", ")).unwrap();
+    }
+    summary
+}
+
+fn to_json(crate_name: &str, stats: &Stats) -> String {
+    let handlers_json = |handlers: &BTreeMap<String, HandlerStats>| {
+        let entries: Vec<_> = handlers.iter().map(|(target, handler)| {
+            format!("{}: {{\"sites\": {}, \"before\": {}, \"after\": {}}}", json_str(target), handler.sites, handler.before, handler.after)
+        }).collect();
+        format!("{{{}}}", entries.join(", "))
+    };
+    let skipped: Vec<_> = stats.bodies_skipped.iter()
+        .map(|(reason, count)| format!("{}: {count}", json_str(reason.as_str())))
+        .collect();
+    let missing: Vec<_> = stats.missing_monitors.iter().map(|kind| json_str(kind)).collect();
+    let mut json = String::new();
+    writeln!(json, "{{").unwrap();
+    writeln!(json, "  \"crate\": {},", json_str(crate_name)).unwrap();
+    writeln!(json, "  \"bodies_processed\": {},", stats.bodies_processed).unwrap();
+    writeln!(json, "  \"bodies_skipped\": {{{}}},", skipped.join(", ")).unwrap();
+    writeln!(json, "  \"calls\": {},", handlers_json(&stats.calls)).unwrap();
+    writeln!(json, "  \"drops\": {},", handlers_json(&stats.drops)).unwrap();
+    writeln!(json, "  \"mem_reads\": {},", stats.mem_reads).unwrap();
+    writeln!(json, "  \"mem_writes\": {},", stats.mem_writes).unwrap();
+    writeln!(json, "  \"traced_fns\": {},", stats.traced_fns).unwrap();
+    writeln!(json, "  \"missing_monitors\": [{}]", missing.join(", ")).unwrap();
+    writeln!(json, "}}").unwrap();
+    json
+}
+
+fn json_str(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
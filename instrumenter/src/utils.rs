@@ -7,7 +7,7 @@ use rustc_span::DUMMY_SP;
 use rustc_span::def_id::DefId;
 use rustc_hir::definitions::DefPath;
 use tracing::{trace, info};
-use crate::config;
+use crate::{compat, config};
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 pub fn jemalloc_magic() {
@@ -65,22 +65,89 @@ pub fn find_sysroot() -> Option<String> {
     None
 }
 
-pub fn find_our_monitor_lib() -> Option<(String, String, String)>  {
-    if let Ok(lib_path) = env::var("SOLCON_MONITOR_LIB_PATH") {
-        info!("find env SOLCON_MONITOR_LIB_PATH: {}", lib_path);
-        let dir_path = Path::new(&lib_path).parent()?;
-        let lib_deps_path: String = dir_path.join("deps").to_str()?.to_owned();
-        return Some((lib_path.to_owned(), dir_path.to_str()?.to_owned(), lib_deps_path));
+/// A monitor crate linked into every instrumented crate.
+#[derive(Debug, Clone)]
+pub struct MonitorLib {
+    pub crate_name: String,
+    pub rlib_path: String,
+    pub dir_path: String,
+    pub deps_dir_path: String,
+    /// listed in SOLCON_MONITOR_LIB_PATH, its monitors implement our hooks; otherwise a monitor crate of SOLCON_RULES
+    pub provides_hooks: bool,
+}
+
+impl MonitorLib {
+    fn new(crate_name: &str, rlib_path: &Path, provides_hooks: bool) -> Option<MonitorLib> {
+        let dir_path = rlib_path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        Some(MonitorLib {
+            crate_name: crate_name.to_owned(),
+            rlib_path: rlib_path.to_str()?.to_owned(),
+            dir_path: dir_path.to_str()?.to_owned(),
+            deps_dir_path: dir_path.join("deps").to_str()?.to_owned(),
+            provides_hooks,
+        })
     }
-    let current_dir = env::current_dir().ok()?;
-    let lib_file_path = current_dir.join(config::MONITORS_LIB_DEFAULT_FILEPATH);
-    if lib_file_path.exists() {
-        let dir_path = Path::new(&lib_file_path).parent()?;
-        let lib_deps_path: String = dir_path.join("deps").to_str()?.to_owned();
-        return Some((String::from(lib_file_path.to_str()?), dir_path.to_str()?.to_owned(), lib_deps_path));
+
+    fn from_rlib_path(rlib_path: &Path) -> Option<MonitorLib> {
+        // lib<crate name>.rlib or lib<crate name>-<hash>.rlib
+        let file_stem = rlib_path.file_stem()?.to_str()?;
+        let crate_name = file_stem.strip_prefix("lib")?;
+        let crate_name = crate_name.split_once('-').map_or(crate_name, |(name, _hash)| name);
+        MonitorLib::new(crate_name, rlib_path, true)
     }
-    info!("fail to find our monitor lib {}", String::from(lib_file_path.to_str()?));
-    None
+}
+
+static MONITOR_LIBS: std::sync::OnceLock<Vec<MonitorLib>> = std::sync::OnceLock::new();
+
+/// All monitor crates: those of SOLCON_MONITOR_LIB_PATH (ours by default) then the monitor crates of the SOLCON_RULES
+/// file, rules::load must have been called.
+pub fn monitor_libs() -> &'static [MonitorLib] {
+    MONITOR_LIBS.get_or_init(|| {
+        let mut libs = find_hook_monitor_libs().unwrap_or_default();
+        for monitor_crate in &crate::rules::rules().monitor_crates {
+            match MonitorLib::new(&monitor_crate.name, Path::new(&monitor_crate.rlib_path), false) {
+                Some(lib) => libs.push(lib),
+                None => info!("monitor crate {} is not a valid path", monitor_crate.rlib_path),
+            }
+        }
+        libs
+    })
+}
+
+/// SOLCON_MONITOR_LIB_PATH is one rlib or a list of rlibs separated like PATH. Monitors are looked up in the order of
+/// the list, so a custom monitor crate listed before ours replaces the monitors it provides.
+fn find_hook_monitor_libs() -> Option<Vec<MonitorLib>> {
+    if let Some(lib_paths) = env::var_os("SOLCON_MONITOR_LIB_PATH") {
+        info!("find env SOLCON_MONITOR_LIB_PATH: {:?}", lib_paths);
+        let mut libs = Vec::new();
+        for lib_path in env::split_paths(&lib_paths).filter(|path| !path.as_os_str().is_empty()) {
+            let Some(lib) = MonitorLib::from_rlib_path(&lib_path) else {
+                info!("monitor lib {} is not named lib<crate name>.rlib", lib_path.display());
+                return None;
+            };
+            libs.push(lib);
+        }
+        Some(libs)
+    } else {
+        let current_dir = env::current_dir().ok()?;
+        let lib_file_path = current_dir.join(config::MONITORS_LIB_DEFAULT_FILEPATH);
+        if !lib_file_path.exists() {
+            info!("fail to find our monitor lib {}", String::from(lib_file_path.to_str()?));
+            return None;
+        }
+        Some(vec![MonitorLib::from_rlib_path(&lib_file_path)?])
+    }
+}
+
+/// Crates whose monitors implement our hooks, ours when SOLCON_MONITOR_LIB_PATH is not used.
+pub fn hook_monitor_crate_names() -> Vec<&'static str> {
+    let names: Vec<&str> = monitor_libs().iter().filter(|lib| lib.provides_hooks).map(|lib| lib.crate_name.as_str()).collect();
+    if names.is_empty() { vec![config::MONITORS_LIB_CRATE_NAME] } else { names }
+}
+
+/// Monitor crates are linked into the instrumented crates but never instrumented themselves.
+pub fn is_monitor_crate(crate_name: &str) -> bool {
+    crate_name == config::MONITORS_LIB_CRATE_NAME || monitor_libs().iter().any(|lib| lib.crate_name == crate_name)
 }
 
 pub fn rustc_logger_config() -> rustc_log::LoggerConfig {
@@ -141,6 +208,17 @@ pub fn get_function_path_str<'tcx, 'operand>(tcx: TyCtxt<'tcx>, local_decls: &ru
     return get_function_path_str_from_ty(tcx, &operand.ty(local_decls, tcx));
 }
 
+pub fn get_function_def_id<'tcx>(tcx: TyCtxt<'tcx>, local_decls: &rustc_index::IndexVec<Local, LocalDecl<'tcx>>, operand: &Operand<'tcx>) -> Option<DefId> {
+    // 通过Operand获取被调用函数的DefId, 用于和def_paths解析出的目标比较
+    match operand.ty(local_decls, tcx).kind() {
+        ty::TyKind::FnDef(def_id, _args)
+        | ty::Closure(def_id, _args)
+        | ty::TyKind::CoroutineClosure(def_id, _args)
+        | ty::TyKind::Coroutine(def_id, _args) => Some(*def_id),
+        _ => None,
+    }
+}
+
 pub fn get_function_generic_args<'tcx, 'operand>(tcx: TyCtxt<'tcx>, local_decls: &rustc_index::IndexVec<Local, LocalDecl<'tcx>>, operand: &'operand Operand<'tcx>) -> Option<&'tcx GenericArgs<'tcx>> {
     // 通过Operand获取函数调用的GenericArg
     return get_function_generic_args_from_ty(&operand.ty(local_decls, tcx));
@@ -161,11 +239,11 @@ pub fn get_function_generic_args_from_ty<'tcx>(ty: &ty::Ty<'tcx>) -> Option<&'tc
         => {
             return Some(&args);
         }
-        ty::FnPtr(_) => {
+        ty::FnPtr(..) => {
             info!("get_function_generic_args_from_ty failed: we cannot infer FnPtr point to what");   
         },
         // A trait object. Written as dyn for<'b> Trait<'b, Assoc = u32> + Send + 'a.
-        ty::TyKind::Dynamic(_, _, _) => unimplemented!(),
+        ty::TyKind::Dynamic(..) => unimplemented!(),
         // A placeholder for a type which could not be computed; this is propagated to avoid useless error messages.
         ty::TyKind::Error(_) => {
             return None;
@@ -199,7 +277,7 @@ pub fn get_function_generic_args_from_ty<'tcx>(ty: &ty::Ty<'tcx>) -> Option<&'tc
         // A tuple type. For example, (i32, bool).
         ty::TyKind::Tuple(_) => unreachable!(),
         // A projection, opaque type, weak type alias, or inherent associated type.
-        ty::TyKind::Alias(_, _) => unreachable!(),
+        ty::TyKind::Alias(_) => unreachable!(),
         // A type parameter; for example, T in fn f<T>(x: T) {}.
         ty::TyKind::Param(_) => unreachable!(),
         // Bound type variable, used to represent the 'a in for<'a> fn(&'a ()).
@@ -208,6 +286,8 @@ pub fn get_function_generic_args_from_ty<'tcx>(ty: &ty::Ty<'tcx>) -> Option<&'tc
         ty::TyKind::Placeholder(_) => unreachable!(),
         // A type variable used during type checking.
         ty::TyKind::Infer(_) => unreachable!(),
+        // An unsafe binder type, e.g. unsafe<'a> &'a u32.
+        ty::TyKind::UnsafeBinder(_) => unreachable!(),
     }
     info!("get_function_generic_args_from_ty: failed!!!!!!!!!!!!!!");
     None
@@ -227,11 +307,11 @@ pub fn get_function_path_str_from_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: &ty::Ty<'tcx>)
             let func_def_path_str = tcx.def_path_str(*def_id);
             return Some(func_def_path_str);
         }
-        ty::FnPtr(_) => {
+        ty::FnPtr(..) => {
             info!("get_function_path_str_from_ty failed: we cannot infer FnPtr point to what");   
         },
         // A trait object. Written as dyn for<'b> Trait<'b, Assoc = u32> + Send + 'a.
-        ty::TyKind::Dynamic(_, _, _) => unimplemented!(),
+        ty::TyKind::Dynamic(..) => unimplemented!(),
         // A placeholder for a type which could not be computed; this is propagated to avoid useless error messages.
         ty::TyKind::Error(_) => {
             return None;
@@ -265,7 +345,7 @@ pub fn get_function_path_str_from_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: &ty::Ty<'tcx>)
         // A tuple type. For example, (i32, bool).
         ty::TyKind::Tuple(_) => unreachable!(),
         // A projection, opaque type, weak type alias, or inherent associated type.
-        ty::TyKind::Alias(_, _) => unreachable!(),
+        ty::TyKind::Alias(_) => unreachable!(),
         // A type parameter; for example, T in fn f<T>(x: T) {}.
         ty::TyKind::Param(_) => unreachable!(),
         // Bound type variable, used to represent the 'a in for<'a> fn(&'a ()).
@@ -274,6 +354,8 @@ pub fn get_function_path_str_from_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: &ty::Ty<'tcx>)
         ty::TyKind::Placeholder(_) => unreachable!(),
         // A type variable used during type checking.
         ty::TyKind::Infer(_) => unreachable!(),
+        // An unsafe binder type, e.g. unsafe<'a> &'a u32.
+        ty::TyKind::UnsafeBinder(_) => unreachable!(),
     }
     info!("get_function_path_str_from_ty: failed!!!!!!!!!!!!!!");
     None
@@ -294,11 +376,11 @@ pub fn get_function_path_from_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: &ty::Ty<'tcx>) ->
             let def_path = tcx.def_path(def_id.clone());
             return Some(def_path);
         }
-        ty::FnPtr(_) => {
+        ty::FnPtr(..) => {
             info!("get_function_path_from_ty failed: we cannot infer FnPtr point to what");   
         },
         // A trait object. Written as dyn for<'b> Trait<'b, Assoc = u32> + Send + 'a.
-        ty::TyKind::Dynamic(_, _, _) => unimplemented!(),
+        ty::TyKind::Dynamic(..) => unimplemented!(),
         // A placeholder for a type which could not be computed; this is propagated to avoid useless error messages.
         ty::TyKind::Error(_) => {
             return None;
@@ -332,7 +414,7 @@ pub fn get_function_path_from_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: &ty::Ty<'tcx>) ->
         // A tuple type. For example, (i32, bool).
         ty::TyKind::Tuple(_) => unreachable!(),
         // A projection, opaque type, weak type alias, or inherent associated type.
-        ty::TyKind::Alias(_, _) => unreachable!(),
+        ty::TyKind::Alias(_) => unreachable!(),
         // A type parameter; for example, T in fn f<T>(x: T) {}.
         ty::TyKind::Param(_) => unreachable!(),
         // Bound type variable, used to represent the 'a in for<'a> fn(&'a ()).
@@ -341,6 +423,8 @@ pub fn get_function_path_from_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: &ty::Ty<'tcx>) ->
         ty::TyKind::Placeholder(_) => unreachable!(),
         // A type variable used during type checking.
         ty::TyKind::Infer(_) => unreachable!(),
+        // An unsafe binder type, e.g. unsafe<'a> &'a u32.
+        ty::TyKind::UnsafeBinder(_) => unreachable!(),
     }
     info!("get_function_path_from_ty: failed!!!!!!!!!!!!!!");
     None
@@ -352,6 +436,11 @@ pub fn alloc_unit_local<'tcx>(tcx: TyCtxt<'tcx>, local_decls: &mut rustc_index::
     return new_local
 }
 
+/// The type of the callsite string passed to monitors. Optimized MIR has no regions but erased ones, not even 'static.
+pub fn callsite_str_ty(tcx: TyCtxt<'_>) -> Ty<'_> {
+    Ty::new_imm_ref(tcx, tcx.lifetimes.re_erased, tcx.types.str_)
+}
+
 pub fn instantiate_our_func<'tcx>(tcx: TyCtxt<'tcx>, our_func_def_id: DefId, generic_args: impl IntoIterator<Item = GenericArg<'tcx>>, fn_span: rustc_span::Span) -> Operand<'tcx> {
     Operand::function_handle(tcx, our_func_def_id, generic_args, fn_span.clone())
 }
@@ -359,9 +448,10 @@ pub fn instantiate_our_func<'tcx>(tcx: TyCtxt<'tcx>, our_func_def_id: DefId, gen
 pub fn is_fn_like_def(tcx: TyCtxt<'_>, def_id: &DefId) -> bool {
     use rustc_hir::def::DefKind::*;
     use rustc_hir::def::CtorKind;
-    let def_kind = tcx.def_kind(def_id);
+    let def_kind = tcx.def_kind(*def_id);
     match def_kind {
-        Fn | AssocFn | Closure => true,
+        Fn | AssocFn | Closure
+        | SyntheticCoroutineBody => true, // The body of an async closure, synthesized from its coroutine-closure.
         // Refers to the struct or enum variant’s constructor.
         Ctor(_ctor_of, ctor_kind) => {
             match ctor_kind{
@@ -375,10 +465,10 @@ pub fn is_fn_like_def(tcx: TyCtxt<'_>, def_id: &DefId) -> bool {
         | TraitAlias // Trait alias: trait IntIterator = Iterator<Item = i32>;
         | AssocTy // Associated type: trait MyTrait { type Assoc; }
         | TyParam // Type parameter: the T in struct Vec<T> { ... }
-        | Const 
+        | Const{..}
         | ConstParam // Constant generic parameter: struct Foo<const N: usize> { ... }
         | Static{..} 
-        | AssocConst // Associated constant: trait MyTrait { const ASSOC: usize; }
+        | AssocConst{..} // Associated constant: trait MyTrait { const ASSOC: usize; }
         | Macro(..) | ExternCrate | Use
         | ForeignMod 
         | AnonConst // Anonymous constant, e.g. the 1 + 2 in [u8; 1 + 2]
@@ -392,8 +482,7 @@ pub fn is_fn_like_def(tcx: TyCtxt<'_>, def_id: &DefId) -> bool {
 }
 
 pub fn span_to_string(tcx: TyCtxt<'_>, span: rustc_span::Span) -> String {
-    let source_map = tcx.sess.source_map();
-    let str = source_map.span_to_string(span, rustc_span::FileNameDisplayPreference::Remapped);
+    let str = compat::span_to_string(tcx, span);
     trace!("span_to_string: {str}");
     str
 }
@@ -0,0 +1,158 @@
+// Shared by the integration tests which run solcon_instrumenter and compare its output with checked-in snapshots.
+//   SOLCON_TESTCASES=a,b   only run the cases with these names
+//   SOLCON_BLESS=1         write the actual output to the snapshots instead of comparing (also creates missing ones)
+// Not every test uses every helper.
+#![allow(dead_code)]
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+pub struct Toolchain {
+    pub cargo: PathBuf,
+    pub rustc: PathBuf,
+    pub wrapper: PathBuf,
+    pub monitor_lib: PathBuf,
+    pub ld_library_path: String,
+}
+
+pub fn repo_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().to_owned()
+}
+
+pub fn bless() -> bool {
+    std::env::var("SOLCON_BLESS").is_ok_and(|value| value != "0")
+}
+
+/// Whether the case was selected by SOLCON_TESTCASES (all cases if it is not set).
+pub fn selected(name: &str) -> bool {
+    match std::env::var("SOLCON_TESTCASES") {
+        Ok(names) => names.split(',').any(|selected| selected.trim() == name),
+        Err(_) => true,
+    }
+}
+
+/// Use the toolchain running the tests for the cases too, and (re)build our monitor library with it.
+pub fn toolchain() -> Toolchain {
+    let cargo = PathBuf::from(std::env::var_os("CARGO").expect("tests are run by cargo"));
+    let rustc = cargo.with_file_name(format!("rustc{}", std::env::consts::EXE_SUFFIX));
+    let sysroot = output_of(Command::new(&rustc).arg("--print=sysroot"));
+    let mut ld_library_path = format!("{}/lib", sysroot.trim());
+    if let Ok(old) = std::env::var("LD_LIBRARY_PATH") {
+        ld_library_path = format!("{ld_library_path}:{old}");
+    }
+    let monitor_dir = repo_root().join("this_is_our_monitor_function");
+    let output = Command::new(&cargo).arg("build").current_dir(&monitor_dir)
+        .env("RUSTC", &rustc).env_remove("RUSTC_WRAPPER").env_remove("RUSTFLAGS")
+        .output().expect("cannot run cargo");
+    assert!(output.status.success(), "cannot build the monitor library in {}:\n{}",
+        monitor_dir.display(), String::from_utf8_lossy(&output.stderr));
+    Toolchain {
+        cargo,
+        rustc,
+        wrapper: PathBuf::from(env!("CARGO_BIN_EXE_solcon_instrumenter")),
+        monitor_lib: monitor_dir.join("target/debug/libthis_is_our_monitor_function.rlib"),
+        ld_library_path,
+    }
+}
+
+impl Toolchain {
+    /// A command without the SOLCON_* variables of the environment (rules, schedules, dumps ...), which would change
+    /// the output, and with the rustc libraries on the library path.
+    pub fn command(&self, program: &Path) -> Command {
+        let mut command = Command::new(program);
+        for (name, _) in std::env::vars_os() {
+            if name.to_string_lossy().starts_with("SOLCON_") {
+                command.env_remove(name);
+            }
+        }
+        command.env("LD_LIBRARY_PATH", &self.ld_library_path).env_remove("RUSTFLAGS");
+        command
+    }
+}
+
+/// Run every case and compare its output with its snapshot, or write the snapshots with SOLCON_BLESS.
+/// Panics after all cases ran if any of them failed.
+pub fn check_snapshots(cases: &[(String, PathBuf)], run: impl Fn(&str) -> Result<String, String>) {
+    let bless = bless();
+    let cases: Vec<_> = cases.iter().filter(|(name, _)| selected(name)).collect();
+    assert!(!cases.is_empty(), "no case selected");
+    let mut failures = Vec::new();
+    for (name, snapshot) in &cases {
+        eprint!("{name} ... ");
+        let result = run(name).and_then(|actual| {
+            if bless {
+                std::fs::write(snapshot, &actual).map_err(|err| format!("cannot write {}: {err}", snapshot.display()))?;
+                return Ok("blessed");
+            }
+            let expected = std::fs::read_to_string(snapshot)
+                .map_err(|err| format!("cannot read {} (run with SOLCON_BLESS=1 to create it): {err}", snapshot.display()))?;
+            if expected == actual {
+                Ok("ok")
+            } else {
+                Err(format!("output differs from {} (run with SOLCON_BLESS=1 to update it):\n{}",
+                    snapshot.display(), line_diff(&expected, &actual)))
+            }
+        });
+        match result {
+            Ok(status) => eprintln!("{status}"),
+            Err(err) => {
+                eprintln!("FAILED");
+                failures.push(format!("---- {name} ----\n{err}"));
+            }
+        }
+    }
+    if !failures.is_empty() {
+        panic!("{} of {} cases failed\n\n{}", failures.len(), cases.len(), failures.join("\n\n"));
+    }
+}
+
+pub fn run_with_timeout(command: &mut Command, timeout: Duration) -> Result<(), String> {
+    let mut child = command.spawn().map_err(|err| format!("cannot run: {err}"))?;
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait().map_err(|err| err.to_string())? {
+            return if status.success() { Ok(()) } else { Err(format!("exited with {status}")) };
+        }
+        if start.elapsed() > timeout {
+            let _ = child.kill();
+            return Err(format!("killed after {timeout:?}"));
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// A minimal line diff: the lines which are only in the expected or only in the actual output, with their positions.
+pub fn line_diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    // longest common subsequence
+    let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            writeln!(diff, "-{:>5} {}", i + 1, expected[i]).unwrap();
+            i += 1;
+        } else {
+            writeln!(diff, "+{:>5} {}", j + 1, actual[j]).unwrap();
+            j += 1;
+        }
+    }
+    diff
+}
+
+fn output_of(command: &mut Command) -> String {
+    let output = command.output().unwrap_or_else(|err| panic!("cannot run {command:?}: {err}"));
+    assert!(output.status.success(), "{command:?} failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
@@ -0,0 +1,16 @@
+// Monitor crate of the rule_call snippet, built by the harness before the snippet.
+pub fn pool_get_before(callsite: &str) {
+    println!("{callsite}: Pool::get");
+}
+
+pub fn pool_get_after(callsite: &str) {
+    println!("{callsite}: Pool::get returned");
+}
+
+pub fn checksum_before(callsite: &str, items: &[u32]) {
+    println!("{callsite}: checksum of {} items", items.len());
+}
+
+pub fn checksum_after(callsite: &str, _items: &[u32], ret: &mut u32) {
+    println!("{callsite}: checksum {ret}");
+}
@@ -0,0 +1,10 @@
+// BarrierWaitCallHandler
+use std::sync::Barrier;
+
+fn instrumented(barrier: &Barrier) -> bool {
+    barrier.wait().is_leader()
+}
+
+fn main() {
+    instrumented(&Barrier::new(1));
+}
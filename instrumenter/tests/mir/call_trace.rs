@@ -0,0 +1,18 @@
+// FnEntryExitInstrumenter, the enter monitor runs once before the loop and the exit monitor before the return
+//@ env: SOLCON_TRACE_CALLS=instrumented
+fn done(n: &mut u32) -> bool {
+    *n /= 2;
+    *n < 2
+}
+
+fn instrumented(mut n: u32) -> u32 {
+    loop {
+        if done(&mut n) {
+            return n;
+        }
+    }
+}
+
+fn main() {
+    instrumented(100);
+}
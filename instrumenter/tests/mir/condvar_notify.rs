@@ -0,0 +1,11 @@
+// CondvarNotifyOneCallHandler and CondvarNotifyAllCallHandler
+use std::sync::Condvar;
+
+fn instrumented(condvar: &Condvar) {
+    condvar.notify_one();
+    condvar.notify_all();
+}
+
+fn main() {
+    instrumented(&Condvar::new());
+}
@@ -0,0 +1,17 @@
+// CondvarWaitCallHandler, CondvarWaitTimeoutCallHandler and CondvarWaitTimeoutMsCallHandler
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+#[allow(deprecated)]
+fn instrumented(condvar: &Condvar, mutex: &Mutex<bool>) {
+    let mut guard = mutex.lock().unwrap();
+    while !*guard {
+        guard = condvar.wait(guard).unwrap();
+    }
+    guard = condvar.wait_timeout(guard, Duration::from_millis(1)).unwrap().0;
+    let _guard = condvar.wait_timeout_ms(guard, 1).unwrap().0;
+}
+
+fn main() {
+    instrumented(&Condvar::new(), &Mutex::new(true));
+}
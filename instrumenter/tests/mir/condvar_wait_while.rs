@@ -0,0 +1,13 @@
+// CondvarWaitWhileCallHandler and CondvarWaitTimeoutWhileCallHandler, the condition closures are not instrumented
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+fn instrumented(condvar: &Condvar, mutex: &Mutex<bool>) {
+    let guard = mutex.lock().unwrap();
+    let guard = condvar.wait_while(guard, |ready| !*ready).unwrap();
+    let _guard = condvar.wait_timeout_while(guard, Duration::from_millis(1), |ready| !*ready).unwrap();
+}
+
+fn main() {
+    instrumented(&Condvar::new(), &Mutex::new(true));
+}
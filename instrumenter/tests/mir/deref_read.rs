@@ -0,0 +1,8 @@
+// mem_instrumenter: a read through a pointer gets a mem_read monitor call with the address before the statement
+fn instrumented(p: &i32) -> i32 {
+    *p
+}
+
+fn main() {
+    instrumented(&1);
+}
@@ -0,0 +1,8 @@
+// mem_instrumenter: a write through a pointer gets a mem_write monitor call with the address before the statement
+fn instrumented(p: &mut i32) {
+    *p = 1;
+}
+
+fn main() {
+    instrumented(&mut 0);
+}
@@ -0,0 +1,11 @@
+// MutexLockCallHandler: the lock call gets a before and an after monitor call, the guard drop is instrumented too.
+use std::sync::Mutex;
+
+fn instrumented(mutex: &Mutex<i32>) -> i32 {
+    let guard = mutex.lock().unwrap();
+    *guard
+}
+
+fn main() {
+    instrumented(&Mutex::new(1));
+}
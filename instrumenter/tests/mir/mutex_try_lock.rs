@@ -0,0 +1,10 @@
+// MutexTryLockCallHandler
+use std::sync::Mutex;
+
+fn instrumented(mutex: &Mutex<i32>) -> bool {
+    mutex.try_lock().is_ok()
+}
+
+fn main() {
+    instrumented(&Mutex::new(1));
+}
@@ -0,0 +1,12 @@
+// MutexGuardDropInstrumenter: an explicit `drop(guard)` and the drop at the end of the scope.
+use std::sync::Mutex;
+
+fn instrumented(first: &Mutex<i32>, second: &Mutex<i32>) {
+    let guard = first.lock().unwrap();
+    drop(guard);
+    let _guard = second.lock().unwrap();
+}
+
+fn main() {
+    instrumented(&Mutex::new(1), &Mutex::new(2));
+}
@@ -0,0 +1,26 @@
+// RuleCallHandler: call rules of a SOLCON_RULES file with the monitors of a small monitor crate
+//@ aux-build: rule_monitors.rs
+//@ rule: monitor_crate rule_monitors {out_dir}/librule_monitors.rlib
+//@ rule: call rule_call::Pool::get rule_monitors::pool_get_before rule_monitors::pool_get_after args=none
+//@ rule: call rule_call::checksum rule_monitors::checksum_before rule_monitors::checksum_after
+struct Pool {
+    items: Vec<u32>,
+}
+
+impl Pool {
+    fn get(&self, index: usize) -> Option<u32> {
+        self.items.get(index).copied()
+    }
+}
+
+fn checksum(items: &[u32]) -> u32 {
+    items.iter().sum()
+}
+
+fn instrumented(pool: &Pool) -> u32 {
+    pool.get(0).unwrap_or(0) + checksum(&pool.items)
+}
+
+fn main() {
+    println!("{}", instrumented(&Pool { items: vec![1, 2] }));
+}
@@ -0,0 +1,11 @@
+// RwLockReadCallHandler and RwLockReadGuardDropInstrumenter
+use std::sync::RwLock;
+
+fn instrumented(rwlock: &RwLock<i32>) -> i32 {
+    let guard = rwlock.read().unwrap();
+    *guard
+}
+
+fn main() {
+    instrumented(&RwLock::new(1));
+}
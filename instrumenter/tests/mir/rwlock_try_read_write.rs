@@ -0,0 +1,12 @@
+// RwLockTryReadCallHandler and RwLockTryWriteCallHandler
+use std::sync::RwLock;
+
+fn instrumented(rwlock: &RwLock<i32>) -> bool {
+    let read = rwlock.try_read().is_ok();
+    let write = rwlock.try_write().is_ok();
+    read && write
+}
+
+fn main() {
+    instrumented(&RwLock::new(1));
+}
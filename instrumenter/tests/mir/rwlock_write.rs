@@ -0,0 +1,11 @@
+// RwLockWriteCallHandler and RwLockWriteGuardDropInstrumenter
+use std::sync::RwLock;
+
+fn instrumented(rwlock: &RwLock<i32>) {
+    let mut guard = rwlock.write().unwrap();
+    *guard += 1;
+}
+
+fn main() {
+    instrumented(&RwLock::new(1));
+}
@@ -0,0 +1,10 @@
+// ThreadSpawnCallHandler
+use std::thread;
+
+fn instrumented() {
+    thread::spawn(|| {}).join().unwrap();
+}
+
+fn main() {
+    instrumented();
+}
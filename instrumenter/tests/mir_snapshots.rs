@@ -0,0 +1,89 @@
+// Compiles each snippet of tests/mir/ through solcon_instrumenter and compares the instrumented MIR of its function
+// `instrumented` (and of closures in it) with tests/mir/<snippet>.mir, so that changes of the handlers, of the monitor
+// arguments or of the memory access instrumentation show up as reviewable diffs.
+//   SOLCON_TESTCASES=mutex_lock,deref_write   only check these snippets
+//   SOLCON_BLESS=1                            update the snapshots (or create them for new snippets)
+// The MIR is also validated after each of our passes (SOLCON_VALIDATE_MIR). A snippet sets further environment
+// variables of the instrumenter with `//@ env: NAME=VALUE` lines, e.g. SOLCON_TRACE_CALLS. `//@ aux-build: <file>` compiles
+// tests/mir/auxiliary/<file> to an rlib first (a monitor crate), and `//@ rule: <rule>` lines make up the SOLCON_RULES
+// file of the snippet, `{out_dir}` in a rule is replaced by the directory of the rlibs.
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use common::Toolchain;
+
+#[test]
+fn mir_snapshots() {
+    let snippets_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/mir");
+    let mut snippets: Vec<(String, PathBuf)> = std::fs::read_dir(&snippets_dir)
+        .unwrap_or_else(|err| panic!("cannot read {}: {err}", snippets_dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+        .map(|path| (path.file_stem().unwrap().to_string_lossy().into_owned(), path.with_extension("mir")))
+        .collect();
+    snippets.sort();
+    let toolchain = common::toolchain();
+    let out_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("mir_snapshots");
+    common::check_snapshots(&snippets, |name| instrumented_mir(&toolchain, &snippets_dir, name, &out_root.join(name)));
+}
+
+fn instrumented_mir(toolchain: &Toolchain, snippets_dir: &Path, name: &str, out_dir: &Path) -> Result<String, String> {
+    let dump_dir = out_dir.join("mir");
+    let _ = std::fs::remove_dir_all(out_dir);
+    let source_path = snippets_dir.join(format!("{name}.rs"));
+    let source = std::fs::read_to_string(&source_path).map_err(|err| format!("cannot read {}: {err}", source_path.display()))?;
+    let envs: Vec<(&str, &str)> = source.lines()
+        .filter_map(|line| line.strip_prefix("//@ env: "))
+        .filter_map(|assignment| assignment.split_once('='))
+        .collect();
+    for aux in source.lines().filter_map(|line| line.strip_prefix("//@ aux-build: ")) {
+        let output = toolchain.command(&toolchain.rustc)
+            .arg(aux)
+            .args(["--edition", "2021", "--crate-type", "rlib", "--out-dir"])
+            .arg(out_dir)
+            .current_dir(snippets_dir.join("auxiliary"))
+            .output()
+            .map_err(|err| format!("cannot run {}: {err}", toolchain.rustc.display()))?;
+        if !output.status.success() {
+            return Err(format!("compilation of {aux} failed:\n{}", String::from_utf8_lossy(&output.stderr)));
+        }
+    }
+    let rules: String = source.lines()
+        .filter_map(|line| line.strip_prefix("//@ rule: "))
+        .map(|rule| format!("{}\n", rule.replace("{out_dir}", &out_dir.to_string_lossy())))
+        .collect();
+    let rules_path = out_dir.join("rules.txt");
+    if !rules.is_empty() {
+        std::fs::create_dir_all(out_dir).map_err(|err| format!("cannot create {}: {err}", out_dir.display()))?;
+        std::fs::write(&rules_path, rules).map_err(|err| format!("cannot write {}: {err}", rules_path.display()))?;
+    }
+    // relative to the snippets, so that the spans in the MIR do not depend on where the repo is
+    let output = toolchain.command(&toolchain.wrapper)
+        .arg(format!("{name}.rs"))
+        .args(["--edition", "2021", "--crate-type", "bin", "--crate-name", name, "--out-dir"])
+        .arg(out_dir)
+        .current_dir(snippets_dir)
+        .env("SOLCON_MONITOR_LIB_PATH", &toolchain.monitor_lib)
+        .env("SOLCON_DUMP_MIR", "instrumented")
+        .env("SOLCON_DUMP_MIR_DIR", &dump_dir)
+        .env("SOLCON_VALIDATE_MIR", "1")
+        .envs(rules_path.exists().then_some(("SOLCON_RULES", &rules_path)))
+        .envs(envs)
+        .output()
+        .map_err(|err| format!("cannot run {}: {err}", toolchain.wrapper.display()))?;
+    if !output.status.success() {
+        return Err(format!("compilation failed:\n{}", String::from_utf8_lossy(&output.stderr)));
+    }
+    let mut dumps: Vec<PathBuf> = std::fs::read_dir(&dump_dir)
+        .map_err(|err| format!("no MIR dumped to {}: {err}", dump_dir.display()))?
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.to_string_lossy().ends_with(".after.mir"))
+        .collect();
+    dumps.sort();
+    let mut mir = String::new();
+    for dump in dumps {
+        mir.push_str(&std::fs::read_to_string(&dump).map_err(|err| format!("cannot read {}: {err}", dump.display()))?);
+    }
+    Ok(mir)
+}
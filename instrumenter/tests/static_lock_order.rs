@@ -0,0 +1,46 @@
+// Compiles each snippet of tests/static_lock_order/ with SOLCON_STATIC_CHECK=only and compares the warnings of the
+// static lock-order analysis with tests/static_lock_order/<snippet>.stderr (empty when nothing is reported).
+//   SOLCON_TESTCASES=self_deadlock   only check these snippets
+//   SOLCON_BLESS=1                   update the snapshots (or create them for new snippets)
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use common::Toolchain;
+
+#[test]
+fn static_lock_order() {
+    let snippets_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/static_lock_order");
+    let mut snippets: Vec<(String, PathBuf)> = std::fs::read_dir(&snippets_dir)
+        .unwrap_or_else(|err| panic!("cannot read {}: {err}", snippets_dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+        .map(|path| (path.file_stem().unwrap().to_string_lossy().into_owned(), path.with_extension("stderr")))
+        .collect();
+    snippets.sort();
+    let toolchain = common::toolchain();
+    let out_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("static_lock_order");
+    common::check_snapshots(&snippets, |name| warnings(&toolchain, &snippets_dir, name, &out_root.join(name)));
+}
+
+fn warnings(toolchain: &Toolchain, snippets_dir: &Path, name: &str, out_dir: &Path) -> Result<String, String> {
+    let _ = std::fs::remove_dir_all(out_dir);
+    // relative to the snippets, so that the spans in the warnings do not depend on where the repo is
+    let output = toolchain.command(&toolchain.wrapper)
+        .arg(format!("{name}.rs"))
+        .args(["--edition", "2021", "--crate-type", "bin", "--crate-name", name, "--out-dir"])
+        .arg(out_dir)
+        .current_dir(snippets_dir)
+        .env("SOLCON_STATIC_CHECK", "only")
+        .output()
+        .map_err(|err| format!("cannot run {}: {err}", toolchain.wrapper.display()))?;
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    if !output.status.success() {
+        return Err(format!("compilation failed:\n{stderr}"));
+    }
+    // only the diagnostics, not the log lines of the instrumenter
+    Ok(stderr.lines()
+        .skip_while(|line| !line.starts_with("warning: solcon"))
+        .map(|line| format!("{line}\n"))
+        .collect())
+}
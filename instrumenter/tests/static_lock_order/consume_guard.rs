@@ -0,0 +1,14 @@
+// a guard moved into a function which does not return it is released there
+use std::sync::{Mutex, MutexGuard};
+
+static A: Mutex<i32> = Mutex::new(0);
+
+fn consume(g: MutexGuard<'_, i32>) {
+    println!("{}", *g);
+}
+
+fn main() {
+    let g = A.lock().unwrap();
+    consume(g);
+    *A.lock().unwrap() += 1;
+}
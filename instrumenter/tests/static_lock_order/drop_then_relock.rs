@@ -0,0 +1,11 @@
+// the guard passed to drop() is released, also when CopyProp turns the move into a copy
+use std::sync::Mutex;
+
+static A: Mutex<i32> = Mutex::new(0);
+
+fn main() {
+    let g = A.lock().unwrap();
+    drop(g);
+    let g = A.lock().unwrap();
+    drop(g);
+}
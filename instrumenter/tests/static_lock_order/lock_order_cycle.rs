@@ -0,0 +1,17 @@
+// two threads taking two mutexes in opposite orders
+use std::sync::Mutex;
+use std::thread;
+
+static A: Mutex<i32> = Mutex::new(0);
+static B: Mutex<i32> = Mutex::new(0);
+
+fn main() {
+    let t = thread::spawn(|| {
+        let a = A.lock().unwrap();
+        *B.lock().unwrap() += *a;
+    });
+    let b = B.lock().unwrap();
+    *A.lock().unwrap() += *b;
+    drop(b);
+    t.join().unwrap();
+}
@@ -0,0 +1,11 @@
+// a guard wrapped into an Option is released when the Option is dropped
+use std::sync::Mutex;
+
+static A: Mutex<i32> = Mutex::new(0);
+
+fn main() {
+    let c = std::env::args().count() > 1;
+    let g = if c { Some(A.lock().unwrap()) } else { None };
+    drop(g);
+    *A.lock().unwrap() += 1;
+}
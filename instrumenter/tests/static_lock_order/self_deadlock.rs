@@ -0,0 +1,9 @@
+// locking a mutex again while its guard is alive
+use std::sync::Mutex;
+
+static A: Mutex<i32> = Mutex::new(0);
+
+fn main() {
+    let g = A.lock().unwrap();
+    *A.lock().unwrap() += *g;
+}
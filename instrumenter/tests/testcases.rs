@@ -0,0 +1,322 @@
+// Builds every crate of testcases/ which has an `expected.trace` with solcon_instrumenter as RUSTC_WRAPPER, runs its
+// binaries with SOLCON_TRACE_FILE set and compares the normalised traces with `expected.trace`.
+//   SOLCON_TESTCASES=closure,memacess   only run these testcases
+//   SOLCON_BLESS=1                      write the traces to `expected.trace` instead of comparing (also for new testcases)
+// Normalisation makes traces independent of the run: timestamps and thread ids are dropped, addresses are replaced by
+// `#N` in order of appearance, and the events are grouped per thread, the threads other than main sorted by their
+// events, so that the interleaving of threads does not matter.
+// `record_then_replay` runs testcases/testcase_replay with SOLCON_RECORD_SCHEDULE and then with SOLCON_REPLAY_SCHEDULE.
+// A testcase with a `monitors/` crate is built with that monitor crate listed before ours in SOLCON_MONITOR_LIB_PATH.
+mod common;
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use common::Toolchain;
+
+const EXPECTED_FILE: &str = "expected.trace";
+const RUN_TIMEOUT: Duration = Duration::from_secs(60);
+const REPLAY_TESTCASE: &str = "testcase_replay";
+
+#[test]
+fn testcases() {
+    let testcases_dir = common::repo_root().join("testcases");
+    let mut testcases: Vec<(String, PathBuf)> = std::fs::read_dir(&testcases_dir)
+        .unwrap_or_else(|err| panic!("cannot read {}: {err}", testcases_dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.join("Cargo.toml").exists())
+        .filter(|path| common::bless() || path.join(EXPECTED_FILE).exists())
+        .map(|path| (path.file_name().unwrap().to_string_lossy().into_owned(), path.join(EXPECTED_FILE)))
+        .collect();
+    testcases.sort();
+    let toolchain = common::toolchain();
+    let target_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("testcases");
+    common::check_snapshots(&testcases, |name| run_testcase(&toolchain, &testcases_dir.join(name), &target_root.join(name)));
+}
+
+/// Replaying the recorded schedule makes the threads of the testcase take its mutex in the recorded order, which the
+/// testcase prints.
+#[test]
+fn record_then_replay() {
+    if !common::selected(REPLAY_TESTCASE) {
+        return;
+    }
+    let toolchain = common::toolchain();
+    let testcase = common::repo_root().join("testcases").join(REPLAY_TESTCASE);
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("replay");
+    let executables = build_testcase(&toolchain, &testcase, &target_dir).unwrap_or_else(|err| panic!("{err}"));
+    let [executable] = executables.as_slice() else {
+        panic!("expected one binary in {}, got {executables:?}", testcase.display());
+    };
+    let schedule = target_dir.join("schedule.txt");
+    let run = |mode: &str| {
+        let output = toolchain.command(executable)
+            .current_dir(&testcase)
+            .env(mode, &schedule)
+            .env("SOLCON_REPLAY_TIMEOUT_MS", "10000")
+            .output()
+            .unwrap_or_else(|err| panic!("cannot run {}: {err}", executable.display()));
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        assert!(output.status.success(), "{} with {mode} failed:\n{stderr}", executable.display());
+        let order = String::from_utf8_lossy(&output.stdout).lines()
+            .find(|line| line.contains(" turns: "))
+            .unwrap_or_else(|| panic!("no order printed with {mode}"))
+            .to_owned();
+        (order, stderr)
+    };
+    let (recorded_order, _) = run("SOLCON_RECORD_SCHEDULE");
+    let events = std::fs::read_to_string(&schedule).unwrap_or_else(|err| panic!("cannot read {}: {err}", schedule.display()))
+        .lines().count();
+    assert!(events > 0, "empty schedule {}", schedule.display());
+    let (replayed_order, stderr) = run("SOLCON_REPLAY_SCHEDULE");
+    assert!(stderr.contains(&format!("solcon: replaying {events} events")), "schedule not loaded:\n{stderr}");
+    assert!(!stderr.contains("solcon: replay diverged") && !stderr.contains("solcon: replay incomplete"),
+        "the replay did not follow the schedule:\n{stderr}");
+    assert_eq!(replayed_order, recorded_order);
+}
+
+/// Build and run a testcase, returns its normalised traces.
+fn run_testcase(toolchain: &Toolchain, testcase: &Path, target_dir: &Path) -> Result<String, String> {
+    let executables = build_testcase(toolchain, testcase, target_dir)?;
+    let mut traces = String::new();
+    for executable in &executables {
+        let bin_name = executable.file_name().unwrap().to_string_lossy();
+        let trace_file = target_dir.join(format!("{bin_name}.trace.jsonl"));
+        let stderr_file = target_dir.join(format!("{bin_name}.stderr"));
+        let _ = std::fs::remove_file(&trace_file);
+        let stderr = std::fs::File::create(&stderr_file).map_err(|err| format!("cannot create {}: {err}", stderr_file.display()))?;
+        common::run_with_timeout(toolchain.command(executable)
+            .current_dir(testcase)
+            .env("SOLCON_TRACE_FILE", &trace_file)
+            .stdout(Stdio::null())
+            .stderr(stderr), RUN_TIMEOUT)
+            .map_err(|err| format!("{bin_name}: {err}, see its stderr in {}", stderr_file.display()))?;
+        let trace = std::fs::read_to_string(&trace_file)
+            .map_err(|err| format!("{bin_name}: cannot read trace {}: {err}", trace_file.display()))?;
+        writeln!(traces, "== {bin_name}").unwrap();
+        traces.push_str(&normalize_trace(&trace)?);
+    }
+    Ok(traces)
+}
+
+/// Build a testcase with the instrumenter, returns its binaries.
+fn build_testcase(toolchain: &Toolchain, testcase: &Path, target_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut monitor_libs = vec![toolchain.monitor_lib.clone()];
+    if testcase.join("monitors/Cargo.toml").exists() {
+        monitor_libs.insert(0, build_custom_monitors(toolchain, &testcase.join("monitors"), &target_dir.join("monitors"))?);
+    }
+    let monitor_lib_path = std::env::join_paths(&monitor_libs).map_err(|err| format!("cannot join {monitor_libs:?}: {err}"))?;
+    // incremental compilation and cargo's fingerprints do not know about changes of the instrumenter,
+    // build into our own target dir and touch the sources to get the testcase instrumented again
+    touch_sources(testcase);
+    let output = toolchain.command(&toolchain.cargo)
+        .args(["build", "--message-format=json-render-diagnostics", "--target-dir"])
+        .arg(target_dir)
+        .current_dir(testcase)
+        .env("RUSTC", &toolchain.rustc)
+        .env("RUSTC_WRAPPER", &toolchain.wrapper)
+        .env("SOLCON_MONITOR_LIB_PATH", monitor_lib_path)
+        .env("CARGO_INCREMENTAL", "0")
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|err| format!("cannot run cargo: {err}"))?;
+    if !output.status.success() {
+        return Err(format!("build failed:\n{}", String::from_utf8_lossy(&output.stderr)));
+    }
+    let mut executables: Vec<PathBuf> = String::from_utf8_lossy(&output.stdout).lines()
+        .filter(|line| line.contains("\"reason\":\"compiler-artifact\""))
+        .filter_map(|line| json_field(line, "executable"))
+        .filter_map(|value| match value {
+            JsonValue::Str(path) => Some(PathBuf::from(path)),
+            _ => None,
+        })
+        .collect();
+    executables.sort();
+    Ok(executables)
+}
+
+/// Build the monitor crate of a testcase like ours (without the instrumenter), returns its rlib.
+fn build_custom_monitors(toolchain: &Toolchain, monitors: &Path, target_dir: &Path) -> Result<PathBuf, String> {
+    let output = toolchain.command(&toolchain.cargo)
+        .args(["build", "--message-format=json-render-diagnostics", "--target-dir"])
+        .arg(target_dir)
+        .current_dir(monitors)
+        .env("RUSTC", &toolchain.rustc)
+        .env_remove("RUSTC_WRAPPER")
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|err| format!("cannot run cargo: {err}"))?;
+    if !output.status.success() {
+        return Err(format!("build of {} failed:\n{}", monitors.display(), String::from_utf8_lossy(&output.stderr)));
+    }
+    let debug_dir = target_dir.join("debug");
+    std::fs::read_dir(&debug_dir)
+        .map_err(|err| format!("cannot read {}: {err}", debug_dir.display()))?
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|ext| ext == "rlib"))
+        .ok_or_else(|| format!("no rlib built in {}", debug_dir.display()))
+}
+
+fn touch_sources(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() && !path.ends_with("target") {
+            touch_sources(&path);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            if let Ok(file) = std::fs::File::options().append(true).open(&path) {
+                let _ = file.set_modified(std::time::SystemTime::now());
+            }
+        }
+    }
+}
+
+/// One line per event: `event callsite addr=#N lock=#N ok=bool msg=N refs=N`, grouped by thread.
+fn normalize_trace(trace: &str) -> Result<String, String> {
+    let mut threads: Vec<(u64, Option<String>, Vec<Event>)> = Vec::new();
+    for line in trace.lines().filter(|line| !line.trim().is_empty()) {
+        let field = |name| json_field(line, name).ok_or_else(|| format!("malformed trace line `{line}`"));
+        let JsonValue::Num(thread) = field("thread")? else {
+            return Err(format!("malformed thread in `{line}`"));
+        };
+        let thread_name = match field("thread_name")? {
+            JsonValue::Str(name) => Some(name),
+            _ => None,
+        };
+        let event = Event {
+            name: format!("{} {}", field("event")?.to_plain(), field("callsite")?.to_plain()).trim_end().to_owned(),
+            addrs: ["addr", "lock"].into_iter()
+                .filter_map(|name| match json_field(line, name) {
+                    Some(JsonValue::Num(addr)) => Some((name, addr)),
+                    _ => None,
+                })
+                .collect(),
+            ok: json_field(line, "ok").map(|ok| ok.to_plain().to_owned()),
+            msg: json_field(line, "msg").map(|msg| msg.to_plain().to_owned()),
+            refs: json_field(line, "refs").map(|refs| refs.to_plain().to_owned()),
+        };
+        let thread = thread.parse().map_err(|_| format!("malformed thread in `{line}`"))?;
+        match threads.iter_mut().find(|(id, _, _)| *id == thread) {
+            Some((_, _, events)) => events.push(event),
+            None => threads.push((thread, thread_name, vec![event])),
+        }
+    }
+    // main first, then the other threads by their events
+    threads.sort_by(|(_, name_a, events_a), (_, name_b, events_b)| {
+        let key_a = (name_a.as_deref() != Some("main"), events_a.iter().map(|event| (&event.name, &event.ok)).collect::<Vec<_>>());
+        let key_b = (name_b.as_deref() != Some("main"), events_b.iter().map(|event| (&event.name, &event.ok)).collect::<Vec<_>>());
+        key_a.cmp(&key_b)
+    });
+    let mut addr_names: HashMap<String, usize> = HashMap::new();
+    let mut normalized = String::new();
+    for (_, thread_name, events) in threads {
+        writeln!(normalized, "thread {}", thread_name.as_deref().unwrap_or("<unnamed>")).unwrap();
+        for event in events {
+            let mut line = event.name;
+            for (name, addr) in event.addrs {
+                let next = addr_names.len();
+                let id = *addr_names.entry(addr).or_insert(next);
+                write!(line, " {name}=#{id}").unwrap();
+            }
+            if let Some(ok) = event.ok {
+                write!(line, " ok={ok}").unwrap();
+            }
+            if let Some(msg) = event.msg {
+                write!(line, " msg={msg}").unwrap();
+            }
+            if let Some(refs) = event.refs {
+                write!(line, " refs={refs}").unwrap();
+            }
+            writeln!(normalized, "    {line}").unwrap();
+        }
+    }
+    Ok(normalized)
+}
+
+struct Event {
+    /// event and callsite
+    name: String,
+    addrs: Vec<(&'static str, String)>,
+    ok: Option<String>,
+    msg: Option<String>,
+    refs: Option<String>,
+}
+
+enum JsonValue {
+    Str(String),
+    Num(String),
+    Other(String),
+}
+
+impl JsonValue {
+    fn to_plain(&self) -> &str {
+        match self {
+            JsonValue::Str(s) | JsonValue::Num(s) | JsonValue::Other(s) => s,
+        }
+    }
+}
+
+/// The value of `name` in a flat JSON object, as written by the monitor's trace and by cargo (top level fields only).
+fn json_field(object: &str, name: &str) -> Option<JsonValue> {
+    let key = format!("\"{name}\":");
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let bytes = object.as_bytes();
+    for (i, &byte) in bytes.iter().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            b'"' if depth == 1 && object[i..].starts_with(&key) => return Some(parse_value(object[i + key.len()..].trim_start())),
+            b'"' => in_string = true,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_value(value: &str) -> JsonValue {
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut s = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('u') => {
+                        let code: String = chars.by_ref().take(4).collect();
+                        s.extend(u32::from_str_radix(&code, 16).ok().and_then(char::from_u32));
+                    }
+                    Some(c) => s.push(c),
+                    None => break,
+                },
+                c => s.push(c),
+            }
+        }
+        return JsonValue::Str(s);
+    }
+    let end = value.find([',', '}', ']']).unwrap_or(value.len());
+    let token = value[..end].trim().to_owned();
+    if token.starts_with(|c: char| c == '-' || c.is_ascii_digit()) {
+        JsonValue::Num(token)
+    } else {
+        JsonValue::Other(token)
+    }
+}
@@ -0,0 +1,24 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    let (sender, receiver) = mpsc::channel();
+    let producer = thread::spawn(move || {
+        for i in 0..3 {
+            sender.send(i).unwrap();
+        }
+    });
+    println!("recv {}", receiver.recv().unwrap());
+    println!("recv_timeout {}", receiver.recv_timeout(Duration::from_secs(10)).unwrap());
+    producer.join().unwrap();
+    println!("try_recv {}", receiver.try_recv().unwrap());
+    println!("try_recv on an empty channel: {:?}", receiver.try_recv());
+
+    let (sender, receiver) = mpsc::sync_channel(1);
+    let producer = thread::spawn(move || {
+        sender.send("hello").unwrap();
+    });
+    println!("recv {}", receiver.recv().unwrap());
+    producer.join().unwrap();
+}
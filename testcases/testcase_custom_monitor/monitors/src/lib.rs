@@ -0,0 +1,10 @@
+// A monitor crate replacing one hook of this_is_our_monitor_function, see testcase_custom_monitor.
+#![feature(register_tool)]
+#![register_tool(solcon)]
+
+use std::sync::Mutex;
+
+#[solcon::monitor(kind = "mutex_lock_before")]
+pub fn on_lock<T: ?Sized>(callsite: &str, _mutex: &Mutex<T>) {
+    println!("custom monitor: locking at {callsite}");
+}
@@ -0,0 +1,11 @@
+// Built with the monitor crate of monitors/ listed before ours in SOLCON_MONITOR_LIB_PATH: its
+// #[solcon::monitor(kind = "mutex_lock_before")] replaces our hook, so the trace has no mutex_lock_before event,
+// the other hooks are still ours.
+use std::sync::Mutex;
+
+fn main() {
+    let mutex = Mutex::new(1);
+    *mutex.lock().unwrap() += 1;
+    let value = *mutex.lock().unwrap();
+    println!("{value}");
+}
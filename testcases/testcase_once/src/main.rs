@@ -0,0 +1,17 @@
+use std::sync::{LazyLock, Once, OnceLock};
+use std::thread;
+
+static INIT: Once = Once::new();
+static ANSWER: OnceLock<u32> = OnceLock::new();
+static GREETING: LazyLock<String> = LazyLock::new(|| "hello".to_owned());
+
+fn main() {
+    INIT.call_once(|| println!("initialized once"));
+    INIT.call_once(|| unreachable!());
+    println!("answer {}", ANSWER.get_or_init(|| 42));
+    let other = thread::spawn(|| *ANSWER.get_or_init(|| 0));
+    println!("answer seen by another thread {}", other.join().unwrap());
+    println!("greeting {}", *GREETING);
+    println!("greeting length {}", GREETING.len());
+    println!("forced {}", LazyLock::force(&GREETING));
+}
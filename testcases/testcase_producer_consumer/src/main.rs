@@ -0,0 +1,37 @@
+// Items written by a producer thread and handed over to the consumer through a channel: the consumer writes them
+// without a lock, but after receiving them, so `solcon-analyze -d race` must not report a race.
+use std::sync::mpsc;
+use std::thread;
+
+struct Item {
+    id: u64,
+    value: u64,
+}
+
+fn produce(item: &mut Item, id: u64) {
+    item.id = id;
+    item.value = id * 10;
+}
+
+fn consume(item: &mut Item) -> u64 {
+    item.value += 1;
+    item.id + item.value
+}
+
+fn main() {
+    let (sender, receiver) = mpsc::channel::<Box<Item>>();
+    let producer = thread::spawn(move || {
+        for id in 0..3 {
+            let mut item = Box::new(Item { id: 0, value: 0 });
+            produce(&mut item, id);
+            sender.send(item).unwrap();
+        }
+    });
+    let mut total = 0;
+    for _ in 0..3 {
+        let mut item = receiver.recv().unwrap();
+        total += consume(&mut item);
+    }
+    producer.join().unwrap();
+    println!("total {total}");
+}
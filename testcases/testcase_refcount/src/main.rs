@@ -0,0 +1,32 @@
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+fn main() {
+    let shared = Arc::new(Mutex::new(0));
+    let for_thread = Arc::clone(&shared);
+    let worker = thread::spawn(move || {
+        *for_thread.lock().unwrap() += 1;
+        drop(for_thread);
+    });
+    worker.join().unwrap();
+    let weak = Arc::downgrade(&shared);
+    let upgraded = weak.upgrade().unwrap();
+    let value = *upgraded.lock().unwrap();
+    println!("value {value}");
+    drop(upgraded);
+    let mut shared = shared;
+    println!("unique {}", Arc::get_mut(&mut shared).is_some());
+    let counter = Arc::try_unwrap(shared).unwrap();
+    println!("unwrapped {}", counter.into_inner().unwrap());
+    println!("upgrade after the last drop {}", weak.upgrade().is_none());
+
+    let local = Rc::new(String::from("local"));
+    let local2 = local.clone();
+    println!("rc {local2}");
+    drop(local2);
+    match Rc::try_unwrap(local) {
+        Ok(value) => println!("rc unwrapped {value}"),
+        Err(_) => unreachable!(),
+    }
+}
@@ -0,0 +1,26 @@
+// Two threads taking a mutex in turns, in an order which changes from run to run. The program prints the order, the
+// record-then-replay test of instrumenter/tests/testcases.rs checks that a replayed run prints the recorded one.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+fn main() {
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let turns = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..2)
+        .map(|worker| {
+            let (order, turns) = (Arc::clone(&order), Arc::clone(&turns));
+            thread::spawn(move || {
+                for _ in 0..5 {
+                    order.lock().unwrap().push(worker);
+                    turns.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let order = order.lock().unwrap();
+    println!("{} turns: {:?}", turns.load(Ordering::SeqCst), *order);
+}
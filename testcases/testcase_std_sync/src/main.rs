@@ -7,14 +7,15 @@ use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
-fn spwan_notify_thread(cm_pair2: Arc<(Condvar, Mutex<bool>)> ) {
+// joined after the wait, so that the thread's clone of the Arc is gone before the next Arc::clone
+fn spwan_notify_thread(cm_pair2: Arc<(Condvar, Mutex<bool>)> ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
         let (condvar, mutex) = &*cm_pair2;
         let mut guard = mutex.lock().unwrap();
         *guard = true;
         drop(guard);
         condvar.notify_one();
-    });
+    })
 }
 
 
@@ -62,26 +63,31 @@ fn testconvar() {
     let guard = mutex.lock().unwrap();
 
     println!("test Condvar wait");
-    spwan_notify_thread(Arc::clone(&cm_pair));
+    let notifier = spwan_notify_thread(Arc::clone(&cm_pair));
     let guard = condvar.wait(guard).unwrap();
+    notifier.join().unwrap();
 
     println!("test Condvar wait_timeout");
-    spwan_notify_thread(Arc::clone(&cm_pair));
+    let notifier = spwan_notify_thread(Arc::clone(&cm_pair));
     let (guard, _timeoutresult) = condvar.wait_timeout(guard, Duration::MAX).unwrap();
+    notifier.join().unwrap();
 
     println!("test Condvar wait_timeout_ms");
-    spwan_notify_thread(Arc::clone(&cm_pair));
+    let notifier = spwan_notify_thread(Arc::clone(&cm_pair));
     let (mut guard, _timeoutresult) = condvar.wait_timeout_ms(guard, u32::MAX).unwrap();
+    notifier.join().unwrap();
 
     println!("test Condvar wait_while");
     *guard = false;
-    spwan_notify_thread(Arc::clone(&cm_pair));
+    let notifier = spwan_notify_thread(Arc::clone(&cm_pair));
     let mut guard = condvar.wait_while(guard, |val| !*val).unwrap();
+    notifier.join().unwrap();
 
     println!("test Condvar wait_timeout_while");
     *guard = false;
-    spwan_notify_thread(Arc::clone(&cm_pair));
+    let notifier = spwan_notify_thread(Arc::clone(&cm_pair));
     let guard = condvar.wait_timeout_while(guard, Duration::MAX, |val| !*val).unwrap();
+    notifier.join().unwrap();
 }
 
 fn test_multimutex() {
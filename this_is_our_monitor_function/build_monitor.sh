@@ -4,4 +4,4 @@ set -e
 
 source ../init.sh
 export SOLCON_BE_RUSTC=1
-cargo build
+cargo build "$@"
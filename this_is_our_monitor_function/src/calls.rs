@@ -0,0 +1,184 @@
+// Shadow call stacks of the functions traced at build time (SOLCON_TRACE_CALLS of the instrumenter): every traced
+// function pushes its def path on entry and pops it before returning, so the monitor knows the traced callers of an
+// event without walking the stack.
+//   SOLCON_CALL_STACK=<N>            record the innermost N traced functions as the stack of events without a stack of
+//                                    SOLCON_BACKTRACE (e.g. memory accesses for race reports)
+//   SOLCON_CALL_PROFILE=text|json    call-graph profile at program exit: calls, total and self time per function and
+//                                    calls per caller -> callee
+//   SOLCON_CALL_PROFILE_TOP=<N>      number of entries per table (default 20)
+//   SOLCON_CALL_PROFILE_FILE=<PATH>  write the profile to PATH instead of stderr, {pid} is replaced by the process id
+// Unwinding is not captured: a function left by a panic stays on the stack until a caller of it returns.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+
+use crate::utils;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReportFormat {
+    Text,
+    Json,
+}
+
+struct ProfileConfig {
+    format: ReportFormat,
+    top: usize,
+    output: Option<String>,
+}
+
+static STACK_DEPTH: Lazy<usize> = Lazy::new(|| {
+    std::env::var("SOLCON_CALL_STACK").ok().and_then(|depth| depth.parse().ok()).unwrap_or(0)
+});
+
+static PROFILE_CONFIG: Lazy<Option<ProfileConfig>> = Lazy::new(|| {
+    let format = match std::env::var("SOLCON_CALL_PROFILE").ok()?.as_str() {
+        "text" | "1" => ReportFormat::Text,
+        "json" => ReportFormat::Json,
+        other => {
+            eprintln!("solcon: unknown SOLCON_CALL_PROFILE {other}, expect text or json");
+            return None;
+        }
+    };
+    Some(ProfileConfig {
+        format,
+        top: std::env::var("SOLCON_CALL_PROFILE_TOP").ok().and_then(|v| v.parse().ok()).unwrap_or(20),
+        output: std::env::var("SOLCON_CALL_PROFILE_FILE").ok().map(|path| path.replace("{pid}", &std::process::id().to_string())),
+    })
+});
+
+struct Frame {
+    function: &'static str,
+    /// time of the entry, 0 without profile
+    start_ns: u128,
+    /// time spent in traced callees
+    callees_ns: u128,
+}
+
+thread_local! {
+    static SHADOW_STACK: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug, Default, Clone)]
+struct FunctionStats {
+    calls: u64,
+    /// time between entry and return, recursive calls are counted once per call
+    total_ns: u128,
+    /// total time without the traced callees
+    self_ns: u128,
+}
+
+#[derive(Default)]
+struct ProfileState {
+    per_function: HashMap<&'static str, FunctionStats>,
+    per_edge: HashMap<(&'static str, &'static str), u64>,
+}
+
+static PROFILE: Lazy<Mutex<ProfileState>> = Lazy::new(|| Mutex::new(ProfileState::default()));
+
+pub(crate) fn enter(function: &'static str) {
+    let start_ns = if PROFILE_CONFIG.is_some() { utils::get_timestamp_nanos() } else { 0 };
+    // the thread local is gone while the thread exits
+    let _ = SHADOW_STACK.try_with(|stack| {
+        let mut stack = stack.borrow_mut();
+        if PROFILE_CONFIG.is_some() {
+            let caller = stack.last().map(|frame| frame.function);
+            let mut profile = PROFILE.lock().unwrap();
+            if let Some(caller) = caller {
+                *profile.per_edge.entry((caller, function)).or_default() += 1;
+            }
+            profile.per_function.entry(function).or_default().calls += 1;
+        }
+        stack.push(Frame { function, start_ns, callees_ns: 0 });
+    });
+}
+
+pub(crate) fn exit(function: &'static str) {
+    let now = if PROFILE_CONFIG.is_some() { utils::get_timestamp_nanos() } else { 0 };
+    let _ = SHADOW_STACK.try_with(|stack| {
+        let mut stack = stack.borrow_mut();
+        // frames above the returning function were left by unwinding
+        let Some(index) = stack.iter().rposition(|frame| frame.function == function) else {
+            return;
+        };
+        stack.truncate(index + 1);
+        let Some(frame) = stack.pop() else {
+            return;
+        };
+        if PROFILE_CONFIG.is_none() {
+            return;
+        }
+        let total = now.saturating_sub(frame.start_ns);
+        if let Some(caller) = stack.last_mut() {
+            caller.callees_ns += total;
+        }
+        let mut profile = PROFILE.lock().unwrap();
+        let stats = profile.per_function.entry(function).or_default();
+        stats.total_ns += total;
+        stats.self_ns += total.saturating_sub(frame.callees_ns);
+    });
+}
+
+/// The innermost traced functions of the current thread to record as the stack of an event, empty unless
+/// SOLCON_CALL_STACK is set.
+pub(crate) fn shadow_stack() -> Vec<String> {
+    if *STACK_DEPTH == 0 {
+        return Vec::new();
+    }
+    SHADOW_STACK.try_with(|stack| {
+        stack.borrow().iter().rev().take(*STACK_DEPTH).map(|frame| frame.function.to_owned()).collect()
+    }).unwrap_or_default()
+}
+
+/// Write the call-graph profile, called once when the program entry fn returns.
+pub(crate) fn report() {
+    let Some(config) = PROFILE_CONFIG.as_ref() else {
+        return;
+    };
+    let profile = PROFILE.lock().unwrap();
+    let mut functions: Vec<(&str, FunctionStats)> = profile.per_function.iter()
+        .map(|(function, stats)| (*function, stats.clone())).collect();
+    functions.sort_by(|a, b| b.1.total_ns.cmp(&a.1.total_ns).then(b.1.calls.cmp(&a.1.calls)).then(a.0.cmp(b.0)));
+    functions.truncate(config.top);
+    let mut edges: Vec<((&str, &str), u64)> = profile.per_edge.iter().map(|(edge, calls)| (*edge, *calls)).collect();
+    edges.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    edges.truncate(config.top);
+    let report = match config.format {
+        ReportFormat::Text => {
+            let mut report = String::new();
+            let _ = writeln!(report, "==== solcon call profile (top {}) ====", config.top);
+            let _ = writeln!(report, "-- per function, by total time --");
+            let _ = writeln!(report, "{:>10} {:>14} {:>14}  function", "calls", "total(ns)", "self(ns)");
+            for (function, stats) in &functions {
+                let _ = writeln!(report, "{:>10} {:>14} {:>14}  {function}", stats.calls, stats.total_ns, stats.self_ns);
+            }
+            let _ = writeln!(report, "-- calls, by count --");
+            let _ = writeln!(report, "{:>10}  caller -> callee", "calls");
+            for ((caller, callee), calls) in &edges {
+                let _ = writeln!(report, "{calls:>10}  {caller} -> {callee}");
+            }
+            report
+        }
+        ReportFormat::Json => {
+            let functions: Vec<String> = functions.iter().map(|(function, stats)| format!(
+                "{{\"function\":{},\"calls\":{},\"total_ns\":{},\"self_ns\":{}}}",
+                crate::trace::json_string(function), stats.calls, stats.total_ns, stats.self_ns)).collect();
+            let edges: Vec<String> = edges.iter().map(|((caller, callee), calls)| format!(
+                "{{\"caller\":{},\"callee\":{},\"calls\":{calls}}}",
+                crate::trace::json_string(caller), crate::trace::json_string(callee))).collect();
+            format!("{{\"functions\":[{}],\"calls\":[{}]}}\n", functions.join(","), edges.join(","))
+        }
+    };
+    match &config.output {
+        Some(path) => {
+            if let Err(err) = std::fs::write(path, report) {
+                eprintln!("solcon: cannot write call profile {path}: {err}");
+            }
+        }
+        None => {
+            let _ = std::io::stderr().write_all(report.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,57 @@
+// Channels of std::sync::mpsc and crossbeam_channel, the state lives in MonitorLockedInformation so it is protected by
+// MONITOR_LOCK. Messages are numbered per channel in the order the sends and receives returned, the n-th successful
+// receive is linked with the n-th successful send (exact with one sender; with several concurrent senders the order in
+// which their sends returned may differ from the order in which the channel queued the messages).
+use std::collections::HashMap;
+use std::mem::{align_of, size_of};
+
+/// The result of a channel operation, so that the after monitors can tell success for the different error types
+/// of std and crossbeam.
+pub trait ChannelResult {
+    fn succeeded(&self) -> bool;
+}
+
+impl<V, E> ChannelResult for Result<V, E> {
+    fn succeeded(&self) -> bool {
+        self.is_ok()
+    }
+}
+
+/// The identity of the channel of a sender or receiver, the same for all endpoints of a channel.
+/// std and crossbeam endpoints are an enum of flavors which all hold one pointer to the channel shared by the
+/// endpoints, next to the small discriminant, so the first word looking like a pointer is the channel.
+/// Falls back to the address of the endpoint, e.g. for crossbeam's `never()` receiver.
+pub(crate) fn channel_addr<C>(endpoint: &C) -> usize {
+    let words = endpoint as *const C as *const usize;
+    (0..size_of::<C>() / size_of::<usize>())
+        .map(|i| unsafe { words.add(i).read_unaligned() })
+        .find(|word| *word >= 4096 && word % align_of::<usize>() == 0)
+        .unwrap_or(endpoint as *const C as usize)
+}
+
+#[derive(Default)]
+struct Messages {
+    sent: u64,
+    received: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct ChannelMessages {
+    by_channel: HashMap<usize, Messages>,
+}
+
+impl ChannelMessages {
+    /// The sequence number of a message sent successfully.
+    pub(crate) fn on_sent(&mut self, channel: usize) -> u64 {
+        let messages = self.by_channel.entry(channel).or_default();
+        messages.sent += 1;
+        messages.sent - 1
+    }
+
+    /// The sequence number of a message received successfully.
+    pub(crate) fn on_received(&mut self, channel: usize) -> u64 {
+        let messages = self.by_channel.entry(channel).or_default();
+        messages.received += 1;
+        messages.received - 1
+    }
+}
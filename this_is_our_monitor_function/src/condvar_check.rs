@@ -0,0 +1,254 @@
+// Runtime checks of Condvar usage, the state lives in MonitorLockedInformation so it is protected by MONITOR_LOCK.
+// 1. a condvar waited on with different mutexes (std panics on some platforms, and it is a logic error anyway).
+// 2. notify without lock: a condvar notified by a thread not holding the mutex its waiters use. The condition may
+//    then change between the check of a waiter and its wait, reported once per notify callsite.
+// 3. possible lost wakeup: a notify found no waiting thread, and the wait in the first critical section of the mutex
+//    started after the notify blocked longer than SOLCON_CONDVAR_BLOCK_NS (default 10ms), timed out, or was still
+//    blocked at program exit. A critical section which ends without waiting (e.g. it saw the condition and did not
+//    wait) consumes the notify.
+// Calling plain Condvar::wait outside a loop is checked statically by the instrumenter.
+use std::collections::{HashMap, HashSet};
+use once_cell::sync::Lazy;
+
+use crate::{stack, suppress};
+use crate::utils::{self, ThreadId};
+
+static BLOCK_THRESHOLD_NS: Lazy<u128> = Lazy::new(|| {
+    std::env::var("SOLCON_CONDVAR_BLOCK_NS").ok().and_then(|v| v.parse().ok()).unwrap_or(10_000_000)
+});
+
+struct SuspiciousWait {
+    start: u128,
+    wait_callsite: String,
+    notify_callsite: String,
+}
+
+struct UnmatchedNotify {
+    callsite: String,
+    /// critical sections numbered above this started after the notify
+    section: u64,
+    /// mutexes held by the notifying thread, the mutex of the condvar if no thread waited on it yet
+    held: Vec<usize>,
+}
+
+#[derive(Default)]
+pub(crate) struct CondvarChecker {
+    /// condvar -> (mutex, callsite of the first wait)
+    mutex_of: HashMap<usize, (usize, String)>,
+    reported_mutex_mismatch: HashSet<(usize, usize)>,
+    reported_notify_without_lock: HashSet<String>,
+    waiters: HashMap<usize, usize>,
+    /// (thread, mutex) -> number of the critical section the thread is in
+    sections: HashMap<(ThreadId, usize), u64>,
+    last_section: u64,
+    /// condvar -> the latest notify which found no waiter
+    unmatched_notify: HashMap<usize, UnmatchedNotify>,
+    suspicious_waits: HashMap<(ThreadId, usize), SuspiciousWait>,
+}
+
+impl CondvarChecker {
+    /// The checks return the misuse they found, to be printed by `report`.
+    pub(crate) fn on_wait_before(&mut self, callsite: &str, thread: ThreadId, condvar: usize, mutex: Option<usize>) -> Option<String> {
+        let mut misuse = None;
+        if let Some(mutex) = mutex {
+            let (first_mutex, first_callsite) = self.mutex_of.entry(condvar).or_insert_with(|| (mutex, callsite.to_owned()));
+            if *first_mutex != mutex && self.reported_mutex_mismatch.insert((condvar, mutex))
+                && !suppress::is_suppressed("condvar", &[callsite, first_callsite], &[condvar, mutex, *first_mutex], true) {
+                misuse = Some(format!("Condvar {condvar:#x} waited with mutex {mutex:#x} at {callsite}, \
+                    but with mutex {first_mutex:#x} at {first_callsite}"));
+            }
+        }
+        *self.waiters.entry(condvar).or_default() += 1;
+        // the critical section ends with the wait, it does not consume the notify
+        if let Some(mutex) = mutex {
+            self.sections.remove(&(thread, mutex));
+        }
+        if let Some(notify) = self.unmatched_notify.remove(&condvar) {
+            self.suspicious_waits.insert((thread, condvar), SuspiciousWait {
+                start: utils::get_timestamp_nanos(),
+                wait_callsite: callsite.to_owned(),
+                notify_callsite: notify.callsite,
+            });
+        }
+        misuse
+    }
+
+    pub(crate) fn on_wait_after(&mut self, thread: ThreadId, condvar: usize, mutex: Option<usize>, timed_out: bool) -> Option<String> {
+        if let Some(waiters) = self.waiters.get_mut(&condvar) {
+            *waiters = waiters.saturating_sub(1);
+        }
+        if let Some(mutex) = mutex {
+            self.on_mutex_locked(thread, mutex);
+        }
+        let wait = self.suspicious_waits.remove(&(thread, condvar))?;
+        let blocked = utils::get_timestamp_nanos().saturating_sub(wait.start);
+        let callsites = [wait.notify_callsite.as_str(), wait.wait_callsite.as_str()];
+        if !(timed_out || blocked >= *BLOCK_THRESHOLD_NS) || suppress::is_suppressed("condvar", &callsites, &[condvar], true) {
+            return None;
+        }
+        let how = if timed_out { "timed out".to_owned() } else { format!("blocked {blocked}ns") };
+        Some(format!("possible lost wakeup on Condvar {condvar:#x}: notify at {} found no waiter, \
+            then the wait at {} in thread {thread} {how}", wait.notify_callsite, wait.wait_callsite))
+    }
+
+    pub(crate) fn on_notify(&mut self, callsite: &str, thread: ThreadId, condvar: usize) -> Option<String> {
+        let held: Vec<usize> = self.sections.keys().filter(|(holder, _)| *holder == thread).map(|(_, mutex)| *mutex).collect();
+        let mut misuse = None;
+        if let Some((mutex, wait_callsite)) = self.mutex_of.get(&condvar) {
+            if !held.contains(mutex) && self.reported_notify_without_lock.insert(callsite.to_owned())
+                && !suppress::is_suppressed("condvar", &[callsite, wait_callsite], &[condvar, *mutex], true) {
+                misuse = Some(format!("Condvar {condvar:#x} notified at {callsite} without holding its mutex {mutex:#x} \
+                    (waited on at {wait_callsite})"));
+            }
+        }
+        if self.waiters.get(&condvar).copied().unwrap_or(0) == 0 {
+            let held = match self.mutex_of.get(&condvar) {
+                Some((mutex, _)) => vec![*mutex],
+                None => held,
+            };
+            self.unmatched_notify.insert(condvar, UnmatchedNotify { callsite: callsite.to_owned(), section: self.last_section, held });
+        } else {
+            self.unmatched_notify.remove(&condvar);
+        }
+        misuse
+    }
+
+    pub(crate) fn on_mutex_locked(&mut self, thread: ThreadId, mutex: usize) {
+        self.last_section += 1;
+        self.sections.insert((thread, mutex), self.last_section);
+    }
+
+    /// A critical section started after a notify which ends without waiting consumes the notify.
+    pub(crate) fn on_mutex_unlocked(&mut self, thread: ThreadId, mutex: usize) {
+        let Some(section) = self.sections.remove(&(thread, mutex)) else {
+            return;
+        };
+        let mutex_of = &self.mutex_of;
+        self.unmatched_notify.retain(|condvar, notify| {
+            let same_mutex = match mutex_of.get(condvar) {
+                Some((waited_with, _)) => *waited_with == mutex,
+                None => notify.held.contains(&mutex),
+            };
+            !same_mutex || section <= notify.section
+        });
+    }
+
+    /// Report the waits which are still blocked, called at program exit.
+    pub(crate) fn report_pending(&self) {
+        for ((thread, condvar), wait) in &self.suspicious_waits {
+            let callsites = [wait.notify_callsite.as_str(), wait.wait_callsite.as_str()];
+            if suppress::is_suppressed("condvar", &callsites, &[*condvar], false) {
+                continue;
+            }
+            eprintln!("solcon: condvar misuse: possible lost wakeup on Condvar {condvar:#x}: notify at {} found no waiter, \
+                then the wait at {} in thread {thread} was still blocked at exit", wait.notify_callsite, wait.wait_callsite);
+        }
+    }
+}
+
+pub(crate) fn report(misuse: Option<String>) {
+    if let Some(misuse) = misuse {
+        eprintln!("solcon: condvar misuse: {misuse}");
+        stack::print_for_report();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONDVAR: usize = 0x10;
+    const MUTEX: usize = 0x20;
+
+    /// `thread` locks MUTEX, waits on CONDVAR until it times out and unlocks.
+    fn timed_out_wait(checker: &mut CondvarChecker, thread: ThreadId) -> Option<String> {
+        checker.on_mutex_locked(thread, MUTEX);
+        assert_eq!(checker.on_wait_before("wait.rs:1", thread, CONDVAR, Some(MUTEX)), None);
+        let misuse = checker.on_wait_after(thread, CONDVAR, Some(MUTEX), true);
+        checker.on_mutex_unlocked(thread, MUTEX);
+        misuse
+    }
+
+    /// `thread` locks MUTEX, notifies CONDVAR and unlocks.
+    fn locked_notify(checker: &mut CondvarChecker, thread: ThreadId) -> Option<String> {
+        checker.on_mutex_locked(thread, MUTEX);
+        let misuse = checker.on_notify("notify.rs:1", thread, CONDVAR);
+        checker.on_mutex_unlocked(thread, MUTEX);
+        misuse
+    }
+
+    #[test]
+    fn different_mutexes_are_reported_once() {
+        let mut checker = CondvarChecker::default();
+        assert_eq!(checker.on_wait_before("a.rs:1", 1, CONDVAR, Some(MUTEX)), None);
+        checker.on_wait_after(1, CONDVAR, Some(MUTEX), false);
+        let misuse = checker.on_wait_before("b.rs:1", 2, CONDVAR, Some(0x30));
+        assert_eq!(misuse.as_deref(), Some("Condvar 0x10 waited with mutex 0x30 at b.rs:1, but with mutex 0x20 at a.rs:1"));
+        checker.on_wait_after(2, CONDVAR, Some(0x30), false);
+        assert_eq!(checker.on_wait_before("b.rs:1", 2, CONDVAR, Some(0x30)), None);
+    }
+
+    #[test]
+    fn notify_without_lock_is_reported_once_per_callsite() {
+        let mut checker = CondvarChecker::default();
+        // the mutex of the condvar is not known before the first wait
+        assert_eq!(checker.on_notify("notify.rs:1", 1, CONDVAR), None);
+        timed_out_wait(&mut checker, 2);
+        let misuse = checker.on_notify("notify.rs:1", 1, CONDVAR);
+        assert_eq!(misuse.as_deref(), Some("Condvar 0x10 notified at notify.rs:1 without holding its mutex 0x20 (waited on at wait.rs:1)"));
+        assert_eq!(checker.on_notify("notify.rs:1", 1, CONDVAR), None);
+        assert_eq!(locked_notify(&mut checker, 1), None);
+    }
+
+    #[test]
+    fn wait_after_an_unmatched_notify_is_a_lost_wakeup() {
+        let mut checker = CondvarChecker::default();
+        assert_eq!(locked_notify(&mut checker, 1), None);
+        let misuse = timed_out_wait(&mut checker, 2);
+        assert_eq!(misuse.as_deref(), Some("possible lost wakeup on Condvar 0x10: notify at notify.rs:1 found no waiter, \
+            then the wait at wait.rs:1 in thread 2 timed out"));
+        // the notify was only reported for the first wait
+        assert_eq!(timed_out_wait(&mut checker, 2), None);
+    }
+
+    #[test]
+    fn notify_with_a_waiter_is_matched() {
+        let mut checker = CondvarChecker::default();
+        checker.on_mutex_locked(2, MUTEX);
+        checker.on_wait_before("wait.rs:1", 2, CONDVAR, Some(MUTEX));
+        assert_eq!(locked_notify(&mut checker, 1), None);
+        assert_eq!(checker.on_wait_after(2, CONDVAR, Some(MUTEX), false), None);
+        checker.on_mutex_unlocked(2, MUTEX);
+        assert_eq!(timed_out_wait(&mut checker, 2), None);
+    }
+
+    #[test]
+    fn critical_section_without_wait_consumes_the_notify() {
+        let mut checker = CondvarChecker::default();
+        locked_notify(&mut checker, 1);
+        // e.g. the waiter saw the condition and did not wait
+        checker.on_mutex_locked(2, MUTEX);
+        checker.on_mutex_unlocked(2, MUTEX);
+        assert_eq!(timed_out_wait(&mut checker, 3), None);
+    }
+
+    #[test]
+    fn critical_section_of_another_mutex_does_not_consume_the_notify() {
+        let mut checker = CondvarChecker::default();
+        locked_notify(&mut checker, 1);
+        checker.on_mutex_locked(2, 0x30);
+        checker.on_mutex_unlocked(2, 0x30);
+        assert!(timed_out_wait(&mut checker, 3).is_some());
+    }
+
+    #[test]
+    fn critical_section_open_at_the_notify_does_not_consume_it() {
+        let mut checker = CondvarChecker::default();
+        timed_out_wait(&mut checker, 2);
+        // thread 2 checked the condition, thread 1 changed it and notified without the lock before thread 2 waited
+        checker.on_mutex_locked(2, MUTEX);
+        assert!(checker.on_notify("notify.rs:2", 1, CONDVAR).is_some());
+        checker.on_wait_before("wait.rs:2", 2, CONDVAR, Some(MUTEX));
+        assert!(checker.on_wait_after(2, CONDVAR, Some(MUTEX), true).is_some());
+    }
+}
@@ -0,0 +1,132 @@
+// Heap allocation tracking. With the alloc_tracking feature, TrackingAllocator wraps the allocator of the program and
+// writes every allocation, deallocation and reallocation of the program between program_start and program_exit to the
+// trace, with the size and, if SOLCON_ALLOC_BACKTRACE=<frames> is set, the innermost frames of the allocating code.
+// A program with its own #[global_allocator] wraps it, alloc_tracking_system installs TrackingAllocator<System> as the
+// global allocator for programs without one.
+// Allocations made while the monitor records an event are not recorded.
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "alloc_tracking")]
+use std::alloc::{GlobalAlloc, Layout};
+#[cfg(feature = "alloc_tracking")]
+use once_cell::sync::Lazy;
+
+#[cfg(feature = "alloc_tracking")]
+use crate::{stack, trace::{self, EventKind, EventDetail}};
+
+static TRACKING: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "alloc_tracking")]
+static STACK_DEPTH: Lazy<usize> = Lazy::new(|| {
+    std::env::var("SOLCON_ALLOC_BACKTRACE").ok().and_then(|depth| depth.parse().ok()).unwrap_or(0)
+});
+
+thread_local! {
+    /// set while the monitor records an event on this thread
+    static IN_MONITOR: Cell<bool> = const { Cell::new(false) };
+}
+
+struct MonitorScope;
+
+impl MonitorScope {
+    /// None if the thread is already in the monitor
+    fn enter() -> Option<Self> {
+        let entered = IN_MONITOR.try_with(|flag| !flag.replace(true)).unwrap_or(false);
+        entered.then(|| MonitorScope)
+    }
+}
+
+impl Drop for MonitorScope {
+    fn drop(&mut self) {
+        let _ = IN_MONITOR.try_with(|flag| flag.set(false));
+    }
+}
+
+/// Run f without recording the allocations it makes.
+pub(crate) fn untracked<R>(f: impl FnOnce() -> R) -> R {
+    let _scope = MonitorScope::enter();
+    f()
+}
+
+pub(crate) fn start_tracking() {
+    TRACKING.store(true, Ordering::Relaxed);
+}
+
+pub(crate) fn stop_tracking() {
+    TRACKING.store(false, Ordering::Relaxed);
+}
+
+#[cfg(feature = "alloc_tracking")]
+fn record(kind: EventKind, addr: usize, size: usize, old_addr: Option<usize>) {
+    if !TRACKING.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(_scope) = MonitorScope::enter() else {
+        return;
+    };
+    // thread local data may already be gone when a thread frees memory while exiting
+    if crate::THREAD.try_with(|_| ()).is_err() {
+        return;
+    }
+    let stack = match *STACK_DEPTH {
+        0 => Vec::new(),
+        depth => stack::capture(depth),
+    };
+    let detail = EventDetail { addr: Some(addr), size: Some(size), old_addr, ..Default::default() };
+    trace::record("", kind, detail, &stack);
+}
+
+/// Forwards to the allocator `A` and records its heap events, wrap the global allocator of the program in it:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: this_is_our_monitor_function::TrackingAllocator<MyAllocator> =
+///     this_is_our_monitor_function::TrackingAllocator::new(MyAllocator);
+/// ```
+#[cfg(feature = "alloc_tracking")]
+pub struct TrackingAllocator<A: GlobalAlloc>(A);
+
+#[cfg(feature = "alloc_tracking")]
+impl<A: GlobalAlloc> TrackingAllocator<A> {
+    pub const fn new(allocator: A) -> Self {
+        TrackingAllocator(allocator)
+    }
+
+    /// The wrapped allocator, its allocations are not recorded.
+    pub fn inner(&self) -> &A {
+        &self.0
+    }
+}
+
+#[cfg(feature = "alloc_tracking")]
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.0.alloc(layout) };
+        if !ptr.is_null() {
+            record(EventKind::HeapAlloc, ptr as usize, layout.size(), None);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.0.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            record(EventKind::HeapAlloc, ptr as usize, layout.size(), None);
+        }
+        ptr
+    }
+
+    // recorded before the memory is freed, another thread may get the same address right after
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        record(EventKind::HeapDealloc, ptr as usize, layout.size(), None);
+        unsafe { self.0.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { self.0.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            record(EventKind::HeapRealloc, new_ptr as usize, new_size, Some(ptr as usize));
+        }
+        new_ptr
+    }
+}
@@ -5,21 +5,48 @@
 #![feature(rustc_attrs)]
 #![feature(thread_id_value)]
 #![feature(allocator_api)]
+#![feature(layout_for_ptr)]
 #![allow(internal_features)]
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, LockResult, TryLockResult};
 use std::sync::{Barrier, BarrierWaitResult};
 use std::sync::{Condvar, WaitTimeoutResult};
+use std::collections::HashMap;
 use std::ptr::addr_of;
+use std::alloc::Allocator;
+use std::rc::Rc;
+use std::thread::JoinHandle;
 use std::time::Duration;
 use once_cell::sync::Lazy;
 
 mod utils;
-use utils::ThreadInfo;
+use utils::{ThreadId, ThreadInfo};
+mod trace;
+use trace::{EventKind, EventDetail};
+mod profile;
+mod condvar_check;
+mod channel;
+pub use channel::ChannelResult;
+mod once;
+mod refcount;
+pub use refcount::{RefCounted, RefCountResult};
+mod heap;
+#[cfg(feature = "alloc_tracking")]
+pub use heap::TrackingAllocator;
+mod stack;
+mod suppress;
+mod calls;
+mod scheduler;
+mod replay;
+mod noise;
+use noise::NoisePoint;
+use scheduler::LockMode;
 
 thread_local! {
     static THREAD : ThreadInfo = utils::get_current_thread_info();
+    /// thread joined by JoinHandle::join, the after hook cannot look at the consumed handle
+    static JOINING: std::cell::Cell<Option<ThreadId>> = const { std::cell::Cell::new(None) };
 }
 
 fn print_leading_info(callsite: &str) {
@@ -52,12 +79,61 @@ macro_rules! my_println {
 pub type GlobalSystemAllocatorType = std::alloc::System;
 pub static GLOBAL_SYSTEM_ALLOCATOR : GlobalSystemAllocatorType = GlobalSystemAllocatorType{};
 
-struct MonitorLockedInformation;
+// records the heap events of programs without a #[global_allocator] of their own, see heap.rs
+#[cfg(feature = "alloc_tracking_system")]
+#[global_allocator]
+static SOLCON_TRACKING_ALLOCATOR: TrackingAllocator<GlobalSystemAllocatorType> = TrackingAllocator::new(GLOBAL_SYSTEM_ALLOCATOR);
+
+struct MonitorLockedInformation {
+    // a guard only exposes the protected data, so we remember which lock owns the data when the guard is created
+    guarded_data_owner: HashMap<usize, usize>,
+    condvars: condvar_check::CondvarChecker,
+    channels: channel::ChannelMessages,
+    onces: once::OnceCells,
+}
+
+impl MonitorLockedInformation {
+    fn remember_guard<T: ?Sized>(&mut self, lock_addr: usize, data: &T) {
+        self.guarded_data_owner.insert(data_addr(data), lock_addr);
+    }
+
+    fn lock_of_guard<T: ?Sized>(&self, data: &T) -> Option<usize> {
+        self.guarded_data_owner.get(&data_addr(data)).copied()
+    }
+}
+
+fn data_addr<T: ?Sized>(data: &T) -> usize {
+    data as *const T as *const () as usize
+}
+
+fn obj_addr<T: ?Sized>(obj: &T) -> usize {
+    addr_of!(*obj) as *const () as usize
+}
+
+// every event observed by our monitors goes to the trace sink and the contention profiler, both are no-ops unless enabled
+fn record_event(callsite: &str, kind: EventKind, mut detail: EventDetail) {
+    heap::untracked(|| {
+        // refcount events are about heap allocations
+        if !kind.is_refcount() && stack::names_statics() {
+            detail.name = detail.addr.and_then(stack::static_name);
+        }
+        let mut stack = stack::for_event(kind);
+        if stack.is_empty() && !kind.is_call() {
+            stack = calls::shadow_stack();
+        }
+        trace::record(callsite, kind, detail, &stack);
+        profile::observe(callsite, kind, &detail);
+    });
+}
 
 static START_TIME: Lazy<chrono::DateTime<chrono::Local>> = Lazy::new(|| chrono::Local::now());
 static START_TIME_FORMATED: Lazy<String> = Lazy::new(|| START_TIME.format("%Y-%m-%d_%H-%M-%S%.6f").to_string() );
 static MONITOR_LOCK: Lazy<Arc<Mutex<MonitorLockedInformation>, GlobalSystemAllocatorType>> = Lazy::new(|| {
     Arc::new_in(Mutex::new(MonitorLockedInformation{
+        guarded_data_owner: HashMap::new(),
+        condvars: Default::default(),
+        channels: Default::default(),
+        onces: Default::default(),
     }), GLOBAL_SYSTEM_ALLOCATOR)
 });
 
@@ -66,11 +142,33 @@ static PROGRAM_EXITED: Lazy<Arc<AtomicBool, GlobalSystemAllocatorType>> = Lazy::
 pub fn this_is_our_entry_fn_before_handle_function() {
     let mut lock = MONITOR_LOCK.lock().unwrap();
     println!("Hello enter program entry fn");
+    record_event("", EventKind::ProgramStart, EventDetail::default());
+    heap::start_tracking();
 }
 
 pub fn this_is_our_entry_fn_after_handle_function() {
     let mut lock = MONITOR_LOCK.lock().unwrap();
     println!("program entry fn return captured");
+    heap::stop_tracking();
+    record_event("", EventKind::ProgramExit, EventDetail::default());
+    lock.condvars.report_pending();
+    suppress::report();
+    trace::flush();
+    profile::report();
+    calls::report();
+    scheduler::finish();
+    replay::finish();
+}
+
+// call tracing, see calls.rs, without the monitor lock and output to keep traced functions cheap
+pub fn this_is_our_fn_enter_handle_function(function: &'static str) {
+    heap::untracked(|| calls::enter(function));
+    record_event("", EventKind::FnEnter, EventDetail{ function: Some(function), ..Default::default() });
+}
+
+pub fn this_is_our_fn_exit_handle_function(function: &'static str) {
+    record_event("", EventKind::FnExit, EventDetail{ function: Some(function), ..Default::default() });
+    heap::untracked(|| calls::exit(function));
 }
 
 #[rustc_std_internal_symbol]
@@ -95,29 +193,583 @@ pub fn this_is_our_test_target_after_handle_function<T: std::fmt::Display>(calls
 
 #[inline(always)]
 pub fn this_is_our_mutex_lock_before_handle_function<T: ?Sized>(callsite: &str, mutex: &Mutex<T>) {
+    noise::inject(NoisePoint::BeforeLock);
+    scheduler::before_acquire(obj_addr(mutex), LockMode::Exclusive, false);
+    replay::before("mutex_lock");
     let mut lock = MONITOR_LOCK.lock().unwrap();
     let mutex_addr =  addr_of!(*mutex);
     my_println_with_callsite!(callsite, "Mutex locking {:?}, this is before.", mutex_addr);
+    record_event(callsite, EventKind::MutexLockBefore, EventDetail::addr(obj_addr(mutex)));
 }
 
 #[inline(always)]
 pub fn this_is_our_mutex_lock_after_handle_function<T: ?Sized>(callsite: &str, mutex: &Mutex<T>, ret: &mut LockResult<MutexGuard<'_, T>>) {
+    replay::after("mutex_lock");
     let mut lock = MONITOR_LOCK.lock().unwrap();
     let mutex_addr =  addr_of!(*mutex);
     my_println_with_callsite!(callsite, "Mutex locking {:?}, this is after, ret addr {:?}", mutex_addr, addr_of!(*ret));
+    let guard = match ret {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.get_ref(),
+    };
+    lock.remember_guard(obj_addr(mutex), &**guard);
+    lock.condvars.on_mutex_locked(THREAD.with(|thread| thread.id), obj_addr(mutex));
+    scheduler::acquired(obj_addr(mutex), LockMode::Exclusive);
+    record_event(callsite, EventKind::MutexLockAfter, EventDetail{ addr: Some(obj_addr(mutex)), ok: Some(ret.is_ok()), ..Default::default() });
 }
 
 #[inline(always)]
 pub fn this_is_our_mutex_try_lock_before_handle_function<T: ?Sized>(callsite: &str, mutex: &Mutex<T>) {
+    noise::inject(NoisePoint::BeforeLock);
+    scheduler::before_acquire(obj_addr(mutex), LockMode::Exclusive, true);
+    replay::before("mutex_try_lock");
     let mut lock = MONITOR_LOCK.lock().unwrap();
     my_println_with_callsite!(callsite, "Mutex try-locking {:?}, this is before.", addr_of!(*mutex));
+    record_event(callsite, EventKind::MutexTryLockBefore, EventDetail::addr(obj_addr(mutex)));
 }
 
 #[inline(always)]
 pub fn this_is_our_mutex_try_lock_after_handle_function<T: ?Sized>(callsite: &str, mutex: &Mutex<T>, ret: &mut TryLockResult<MutexGuard<'_, T>>) {
+    replay::after("mutex_try_lock");
     let mut lock = MONITOR_LOCK.lock().unwrap();
     let locked = ret.is_ok();
     my_println_with_callsite!(callsite, "Mutex try-locking {:?}, this is after, ret addr {:?}, result {locked}", addr_of!(*mutex), addr_of!(*ret));
+    let guard = match ret {
+        Ok(guard) => Some(&*guard),
+        Err(std::sync::TryLockError::Poisoned(poisoned)) => Some(poisoned.get_ref()),
+        Err(std::sync::TryLockError::WouldBlock) => None,
+    };
+    if let Some(guard) = guard {
+        lock.remember_guard(obj_addr(mutex), &**guard);
+        lock.condvars.on_mutex_locked(THREAD.with(|thread| thread.id), obj_addr(mutex));
+        scheduler::acquired(obj_addr(mutex), LockMode::Exclusive);
+    }
+    record_event(callsite, EventKind::MutexTryLockAfter, EventDetail{ addr: Some(obj_addr(mutex)), ok: Some(guard.is_some()), ..Default::default() });
+}
+
+#[inline(always)]
+pub fn this_is_our_mutexguard_drop_before_handle_function<'a, T: ?Sized + 'a>(callsite: &str, guard: &MutexGuard<'a, T>) {
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    let mutex_addr = lock.lock_of_guard(&**guard);
+    if let Some(mutex_addr) = mutex_addr {
+        lock.condvars.on_mutex_unlocked(THREAD.with(|thread| thread.id), mutex_addr);
+        scheduler::released(mutex_addr, LockMode::Exclusive);
+    }
+    my_println_with_callsite!(callsite, "MutexGuard of {:?} dropping, this is before.", mutex_addr);
+    record_event(callsite, EventKind::MutexGuardDrop, EventDetail{ addr: mutex_addr, ..Default::default() });
+}
+
+// the guard is already dropped here, only its address may be used
+#[inline(always)]
+pub fn this_is_our_mutexguard_drop_after_handle_function<'a, T: ?Sized + 'a>(_callsite: &str, _guard: &MutexGuard<'a, T>) {
+    noise::inject(NoisePoint::AfterUnlock);
+}
+
+#[inline(always)]
+pub fn this_is_our_rwlock_read_before_handle_function<T: ?Sized>(callsite: &str, rwlock: &RwLock<T>) {
+    noise::inject(NoisePoint::BeforeLock);
+    scheduler::before_acquire(obj_addr(rwlock), LockMode::Shared, false);
+    replay::before("rwlock_read");
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "RwLock reading {:?}, this is before.", addr_of!(*rwlock));
+    record_event(callsite, EventKind::RwLockReadBefore, EventDetail::addr(obj_addr(rwlock)));
+}
+
+#[inline(always)]
+pub fn this_is_our_rwlock_read_after_handle_function<T: ?Sized>(callsite: &str, rwlock: &RwLock<T>, ret: &mut LockResult<RwLockReadGuard<'_, T>>) {
+    replay::after("rwlock_read");
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "RwLock reading {:?}, this is after, ret addr {:?}", addr_of!(*rwlock), addr_of!(*ret));
+    let guard = match ret {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.get_ref(),
+    };
+    lock.remember_guard(obj_addr(rwlock), &**guard);
+    scheduler::acquired(obj_addr(rwlock), LockMode::Shared);
+    record_event(callsite, EventKind::RwLockReadAfter, EventDetail{ addr: Some(obj_addr(rwlock)), ok: Some(ret.is_ok()), ..Default::default() });
+}
+
+#[inline(always)]
+pub fn this_is_our_rwlock_try_read_before_handle_function<T: ?Sized>(callsite: &str, rwlock: &RwLock<T>) {
+    noise::inject(NoisePoint::BeforeLock);
+    scheduler::before_acquire(obj_addr(rwlock), LockMode::Shared, true);
+    replay::before("rwlock_try_read");
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "RwLock try-reading {:?}, this is before.", addr_of!(*rwlock));
+    record_event(callsite, EventKind::RwLockTryReadBefore, EventDetail::addr(obj_addr(rwlock)));
+}
+
+#[inline(always)]
+pub fn this_is_our_rwlock_try_read_after_handle_function<T: ?Sized>(callsite: &str, rwlock: &RwLock<T>, ret: &mut TryLockResult<RwLockReadGuard<'_, T>>) {
+    replay::after("rwlock_try_read");
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    let guard = match ret {
+        Ok(guard) => Some(&*guard),
+        Err(std::sync::TryLockError::Poisoned(poisoned)) => Some(poisoned.get_ref()),
+        Err(std::sync::TryLockError::WouldBlock) => None,
+    };
+    let locked = guard.is_some();
+    my_println_with_callsite!(callsite, "RwLock try-reading {:?}, this is after, result {locked}", addr_of!(*rwlock));
+    if let Some(guard) = guard {
+        lock.remember_guard(obj_addr(rwlock), &**guard);
+        scheduler::acquired(obj_addr(rwlock), LockMode::Shared);
+    }
+    record_event(callsite, EventKind::RwLockTryReadAfter, EventDetail{ addr: Some(obj_addr(rwlock)), ok: Some(locked), ..Default::default() });
+}
+
+#[inline(always)]
+pub fn this_is_our_rwlock_readguard_drop_before_handle_function<'a, T: ?Sized + 'a>(callsite: &str, guard: &RwLockReadGuard<'a, T>) {
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    let rwlock_addr = lock.lock_of_guard(&**guard);
+    if let Some(rwlock_addr) = rwlock_addr {
+        scheduler::released(rwlock_addr, LockMode::Shared);
+    }
+    my_println_with_callsite!(callsite, "RwLockReadGuard of {:?} dropping, this is before.", rwlock_addr);
+    record_event(callsite, EventKind::RwLockReadGuardDrop, EventDetail{ addr: rwlock_addr, ..Default::default() });
+}
+
+#[inline(always)]
+pub fn this_is_our_rwlock_readguard_drop_after_handle_function<'a, T: ?Sized + 'a>(_callsite: &str, _guard: &RwLockReadGuard<'a, T>) {
+    noise::inject(NoisePoint::AfterUnlock);
+}
+
+#[inline(always)]
+pub fn this_is_our_rwlock_write_before_handle_function<T: ?Sized>(callsite: &str, rwlock: &RwLock<T>) {
+    noise::inject(NoisePoint::BeforeLock);
+    scheduler::before_acquire(obj_addr(rwlock), LockMode::Exclusive, false);
+    replay::before("rwlock_write");
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "RwLock writing {:?}, this is before.", addr_of!(*rwlock));
+    record_event(callsite, EventKind::RwLockWriteBefore, EventDetail::addr(obj_addr(rwlock)));
+}
+
+#[inline(always)]
+pub fn this_is_our_rwlock_write_after_handle_function<T: ?Sized>(callsite: &str, rwlock: &RwLock<T>, ret: &mut LockResult<RwLockWriteGuard<'_, T>>) {
+    replay::after("rwlock_write");
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "RwLock writing {:?}, this is after, ret addr {:?}", addr_of!(*rwlock), addr_of!(*ret));
+    let guard = match ret {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.get_ref(),
+    };
+    lock.remember_guard(obj_addr(rwlock), &**guard);
+    scheduler::acquired(obj_addr(rwlock), LockMode::Exclusive);
+    record_event(callsite, EventKind::RwLockWriteAfter, EventDetail{ addr: Some(obj_addr(rwlock)), ok: Some(ret.is_ok()), ..Default::default() });
+}
+
+#[inline(always)]
+pub fn this_is_our_rwlock_try_write_before_handle_function<T: ?Sized>(callsite: &str, rwlock: &RwLock<T>) {
+    noise::inject(NoisePoint::BeforeLock);
+    scheduler::before_acquire(obj_addr(rwlock), LockMode::Exclusive, true);
+    replay::before("rwlock_try_write");
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "RwLock try-writing {:?}, this is before.", addr_of!(*rwlock));
+    record_event(callsite, EventKind::RwLockTryWriteBefore, EventDetail::addr(obj_addr(rwlock)));
+}
+
+#[inline(always)]
+pub fn this_is_our_rwlock_try_write_after_handle_function<T: ?Sized>(callsite: &str, rwlock: &RwLock<T>, ret: &mut TryLockResult<RwLockWriteGuard<'_, T>>) {
+    replay::after("rwlock_try_write");
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    let guard = match ret {
+        Ok(guard) => Some(&*guard),
+        Err(std::sync::TryLockError::Poisoned(poisoned)) => Some(poisoned.get_ref()),
+        Err(std::sync::TryLockError::WouldBlock) => None,
+    };
+    let locked = guard.is_some();
+    my_println_with_callsite!(callsite, "RwLock try-writing {:?}, this is after, result {locked}", addr_of!(*rwlock));
+    if let Some(guard) = guard {
+        lock.remember_guard(obj_addr(rwlock), &**guard);
+        scheduler::acquired(obj_addr(rwlock), LockMode::Exclusive);
+    }
+    record_event(callsite, EventKind::RwLockTryWriteAfter, EventDetail{ addr: Some(obj_addr(rwlock)), ok: Some(locked), ..Default::default() });
+}
+
+#[inline(always)]
+pub fn this_is_our_rwlock_writeguard_drop_before_handle_function<'a, T: ?Sized + 'a>(callsite: &str, guard: &RwLockWriteGuard<'a, T>) {
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    let rwlock_addr = lock.lock_of_guard(&**guard);
+    if let Some(rwlock_addr) = rwlock_addr {
+        scheduler::released(rwlock_addr, LockMode::Exclusive);
+    }
+    my_println_with_callsite!(callsite, "RwLockWriteGuard of {:?} dropping, this is before.", rwlock_addr);
+    record_event(callsite, EventKind::RwLockWriteGuardDrop, EventDetail{ addr: rwlock_addr, ..Default::default() });
+}
+
+#[inline(always)]
+pub fn this_is_our_rwlock_writeguard_drop_after_handle_function<'a, T: ?Sized + 'a>(_callsite: &str, _guard: &RwLockWriteGuard<'a, T>) {
+    noise::inject(NoisePoint::AfterUnlock);
+}
+
+#[inline(always)]
+pub fn this_is_our_barrier_wait_before_handle_function(callsite: &str, barrier: &Barrier) {
+    scheduler::yield_point();
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "Barrier waiting {:?}, this is before.", addr_of!(*barrier));
+    record_event(callsite, EventKind::BarrierWaitBefore, EventDetail::addr(obj_addr(barrier)));
+    drop(lock);
+    scheduler::detach();
+}
+
+#[inline(always)]
+pub fn this_is_our_barrier_wait_after_handle_function(callsite: &str, barrier: &Barrier, ret: &mut BarrierWaitResult) {
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    let is_leader = ret.is_leader();
+    my_println_with_callsite!(callsite, "Barrier waiting {:?}, this is after, leader {is_leader}", addr_of!(*barrier));
+    record_event(callsite, EventKind::BarrierWaitAfter, EventDetail::addr(obj_addr(barrier)));
+    drop(lock);
+    scheduler::reattach();
+}
+
+// Condvar::wait* consume the guard, so the after handlers must not touch the guard argument and take the mutex from the returned guard instead.
+// wait_while variants evaluate the condition with the mutex held, so they are traced as their own event kinds.
+
+fn condvar_wait_before<T>(callsite: &str, condvar: &Condvar, guard: &MutexGuard<'_, T>, kind: EventKind) {
+    scheduler::yield_point();
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    let mutex_addr = lock.lock_of_guard(&**guard);
+    my_println_with_callsite!(callsite, "Condvar {:?} waiting with mutex {:?}, this is before.", addr_of!(*condvar), mutex_addr);
+    let thread = THREAD.with(|thread| thread.id);
+    condvar_check::report(lock.condvars.on_wait_before(callsite, thread, obj_addr(condvar), mutex_addr));
+    record_event(callsite, kind, EventDetail{ addr: Some(obj_addr(condvar)), lock: mutex_addr, ..Default::default() });
+    // the mutex is released while waiting, and we do not know when the wait returns
+    if let Some(mutex_addr) = mutex_addr {
+        scheduler::released(mutex_addr, LockMode::Exclusive);
+    }
+    drop(lock);
+    scheduler::detach();
+}
+
+fn condvar_wait_after<T>(callsite: &str, condvar: &Condvar, guard: &MutexGuard<'_, T>, ok: bool, kind: EventKind) {
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    let mutex_addr = lock.lock_of_guard(&**guard);
+    my_println_with_callsite!(callsite, "Condvar {:?} waiting with mutex {:?}, this is after.", addr_of!(*condvar), mutex_addr);
+    let thread = THREAD.with(|thread| thread.id);
+    condvar_check::report(lock.condvars.on_wait_after(thread, obj_addr(condvar), mutex_addr, !ok));
+    record_event(callsite, kind, EventDetail{ addr: Some(obj_addr(condvar)), lock: mutex_addr, ok: Some(ok), ..Default::default() });
+    if let Some(mutex_addr) = mutex_addr {
+        scheduler::acquired(mutex_addr, LockMode::Exclusive);
+    }
+    drop(lock);
+    scheduler::reattach();
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_wait_before_handle_function<T>(callsite: &str, condvar: &Condvar, guard: &MutexGuard<'_, T>) {
+    condvar_wait_before(callsite, condvar, guard, EventKind::CondvarWaitBefore);
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_wait_after_handle_function<T>(callsite: &str, condvar: &Condvar, _guard: &MutexGuard<'_, T>, ret: &mut LockResult<MutexGuard<'_, T>>) {
+    let ok = ret.is_ok();
+    let guard = match ret {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.get_ref(),
+    };
+    condvar_wait_after(callsite, condvar, guard, ok, EventKind::CondvarWaitAfter);
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_wait_timeout_before_handle_function<T>(callsite: &str, condvar: &Condvar, guard: &MutexGuard<'_, T>, _dur: &Duration) {
+    condvar_wait_before(callsite, condvar, guard, EventKind::CondvarWaitBefore);
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_wait_timeout_after_handle_function<T>(callsite: &str, condvar: &Condvar, _guard: &MutexGuard<'_, T>, _dur: &Duration, ret: &mut LockResult<(MutexGuard<'_, T>, WaitTimeoutResult)>) {
+    let (guard, timeout) = match ret {
+        Ok((guard, timeout)) => (&*guard, timeout.timed_out()),
+        Err(poisoned) => (&poisoned.get_ref().0, poisoned.get_ref().1.timed_out()),
+    };
+    condvar_wait_after(callsite, condvar, guard, !timeout, EventKind::CondvarWaitAfter);
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_wait_timeout_ms_before_handle_function<T>(callsite: &str, condvar: &Condvar, guard: &MutexGuard<'_, T>, _ms: &u32) {
+    condvar_wait_before(callsite, condvar, guard, EventKind::CondvarWaitBefore);
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_wait_timeout_ms_after_handle_function<T>(callsite: &str, condvar: &Condvar, _guard: &MutexGuard<'_, T>, _ms: &u32, ret: &mut LockResult<(MutexGuard<'_, T>, bool)>) {
+    let (guard, notified) = match ret {
+        Ok((guard, notified)) => (&*guard, *notified),
+        Err(poisoned) => (&poisoned.get_ref().0, poisoned.get_ref().1),
+    };
+    condvar_wait_after(callsite, condvar, guard, notified, EventKind::CondvarWaitAfter);
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_wait_while_before_handle_function<T, F>(callsite: &str, condvar: &Condvar, guard: &MutexGuard<'_, T>, _condition: &F) {
+    condvar_wait_before(callsite, condvar, guard, EventKind::CondvarWaitWhileBefore);
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_wait_while_after_handle_function<T, F>(callsite: &str, condvar: &Condvar, _guard: &MutexGuard<'_, T>, _condition: &F, ret: &mut LockResult<MutexGuard<'_, T>>) {
+    let ok = ret.is_ok();
+    let guard = match ret {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.get_ref(),
+    };
+    condvar_wait_after(callsite, condvar, guard, ok, EventKind::CondvarWaitWhileAfter);
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_wait_timeout_while_before_handle_function<T, F>(callsite: &str, condvar: &Condvar, guard: &MutexGuard<'_, T>, _dur: &Duration, _condition: &F) {
+    condvar_wait_before(callsite, condvar, guard, EventKind::CondvarWaitWhileBefore);
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_wait_timeout_while_after_handle_function<T, F>(callsite: &str, condvar: &Condvar, _guard: &MutexGuard<'_, T>, _dur: &Duration, _condition: &F, ret: &mut LockResult<(MutexGuard<'_, T>, WaitTimeoutResult)>) {
+    let (guard, timeout) = match ret {
+        Ok((guard, timeout)) => (&*guard, timeout.timed_out()),
+        Err(poisoned) => (&poisoned.get_ref().0, poisoned.get_ref().1.timed_out()),
+    };
+    condvar_wait_after(callsite, condvar, guard, !timeout, EventKind::CondvarWaitWhileAfter);
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_notify_one_before_handle_function(callsite: &str, condvar: &Condvar) {
+    scheduler::yield_point();
+    replay::step("condvar_notify_one");
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "Condvar {:?} notify_one, this is before.", addr_of!(*condvar));
+    condvar_check::report(lock.condvars.on_notify(callsite, THREAD.with(|thread| thread.id), obj_addr(condvar)));
+    record_event(callsite, EventKind::CondvarNotifyOneBefore, EventDetail::addr(obj_addr(condvar)));
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_notify_one_after_handle_function(callsite: &str, condvar: &Condvar, _ret: &mut ()) {
+    let _lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "Condvar {:?} notify_one, this is after.", addr_of!(*condvar));
+    record_event(callsite, EventKind::CondvarNotifyOneAfter, EventDetail::addr(obj_addr(condvar)));
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_notify_all_before_handle_function(callsite: &str, condvar: &Condvar) {
+    scheduler::yield_point();
+    replay::step("condvar_notify_all");
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "Condvar {:?} notify_all, this is before.", addr_of!(*condvar));
+    condvar_check::report(lock.condvars.on_notify(callsite, THREAD.with(|thread| thread.id), obj_addr(condvar)));
+    record_event(callsite, EventKind::CondvarNotifyAllBefore, EventDetail::addr(obj_addr(condvar)));
+}
+
+#[inline(always)]
+pub fn this_is_our_condvar_notify_all_after_handle_function(callsite: &str, condvar: &Condvar, _ret: &mut ()) {
+    let _lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "Condvar {:?} notify_all, this is after.", addr_of!(*condvar));
+    record_event(callsite, EventKind::CondvarNotifyAllAfter, EventDetail::addr(obj_addr(condvar)));
+}
+
+// Channel monitors are shared by std::sync::mpsc and crossbeam_channel: C is the endpoint type (Sender<T>, SyncSender<T>
+// or Receiver<T>) and R the return type of the operation. A blocking send or receive gives the scheduler token up.
+
+fn channel_before<C>(callsite: &str, endpoint: &C, kind: EventKind, blocking: bool) {
+    scheduler::yield_point();
+    let lock = MONITOR_LOCK.lock().unwrap();
+    let channel = channel::channel_addr(endpoint);
+    my_println_with_callsite!(callsite, "Channel {channel:#x} {}, this is before.", kind.name());
+    record_event(callsite, kind, EventDetail::addr(channel));
+    drop(lock);
+    if blocking {
+        scheduler::detach();
+    }
+}
+
+fn channel_after<C, R: ChannelResult>(callsite: &str, endpoint: &C, ret: &R, kind: EventKind, blocking: bool) {
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    let channel = channel::channel_addr(endpoint);
+    let ok = ret.succeeded();
+    let msg = match (ok, kind) {
+        (false, _) => None,
+        (true, EventKind::ChannelSendAfter) => Some(lock.channels.on_sent(channel)),
+        (true, _) => Some(lock.channels.on_received(channel)),
+    };
+    my_println_with_callsite!(callsite, "Channel {channel:#x} {}, this is after, ok {ok}, message {msg:?}", kind.name());
+    record_event(callsite, kind, EventDetail{ addr: Some(channel), ok: Some(ok), msg, ..Default::default() });
+    drop(lock);
+    if blocking {
+        scheduler::reattach();
+    }
+}
+
+#[inline(always)]
+pub fn this_is_our_channel_send_before_handle_function<C, T>(callsite: &str, sender: &C, _msg: &T) {
+    channel_before(callsite, sender, EventKind::ChannelSendBefore, true);
+}
+
+#[inline(always)]
+pub fn this_is_our_channel_send_after_handle_function<C, T, R: ChannelResult>(callsite: &str, sender: &C, _msg: &T, ret: &mut R) {
+    channel_after(callsite, sender, ret, EventKind::ChannelSendAfter, true);
+}
+
+#[inline(always)]
+pub fn this_is_our_channel_recv_before_handle_function<C, T>(callsite: &str, receiver: &C) {
+    channel_before(callsite, receiver, EventKind::ChannelRecvBefore, true);
+}
+
+#[inline(always)]
+pub fn this_is_our_channel_recv_after_handle_function<C, T, R: ChannelResult>(callsite: &str, receiver: &C, ret: &mut R) {
+    channel_after(callsite, receiver, ret, EventKind::ChannelRecvAfter, true);
+}
+
+#[inline(always)]
+pub fn this_is_our_channel_recv_timeout_before_handle_function<C, T>(callsite: &str, receiver: &C, _timeout: &Duration) {
+    channel_before(callsite, receiver, EventKind::ChannelRecvBefore, true);
+}
+
+#[inline(always)]
+pub fn this_is_our_channel_recv_timeout_after_handle_function<C, T, R: ChannelResult>(callsite: &str, receiver: &C, _timeout: &Duration, ret: &mut R) {
+    channel_after(callsite, receiver, ret, EventKind::ChannelRecvAfter, true);
+}
+
+#[inline(always)]
+pub fn this_is_our_channel_try_recv_before_handle_function<C, T>(callsite: &str, receiver: &C) {
+    channel_before(callsite, receiver, EventKind::ChannelRecvBefore, false);
+}
+
+#[inline(always)]
+pub fn this_is_our_channel_try_recv_after_handle_function<C, T, R: ChannelResult>(callsite: &str, receiver: &C, ret: &mut R) {
+    channel_after(callsite, receiver, ret, EventKind::ChannelRecvAfter, false);
+}
+
+// Once, OnceLock, LazyLock and once_cell monitors: C is the type of the cell and R the return type of the call.
+// A thread waiting for the initialization by another thread gives the scheduler token up.
+
+fn once_enter<C>(callsite: &str, cell: &C) {
+    scheduler::yield_point();
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    let thread = THREAD.with(|thread| thread.id);
+    let kind = lock.onces.on_enter(callsite, thread, obj_addr(cell));
+    my_println_with_callsite!(callsite, "Once {:?} {}, this is before.", addr_of!(*cell), kind.name());
+    record_event(callsite, kind, EventDetail::addr(obj_addr(cell)));
+    drop(lock);
+    if kind == EventKind::OnceWait {
+        scheduler::detach();
+    }
+}
+
+fn once_return<C>(callsite: &str, cell: &C) {
+    let mut lock = MONITOR_LOCK.lock().unwrap();
+    let thread = THREAD.with(|thread| thread.id);
+    let kind = lock.onces.on_return(thread, obj_addr(cell));
+    if let Some(kind) = kind {
+        my_println_with_callsite!(callsite, "Once {:?} {}, this is after.", addr_of!(*cell), kind.name());
+        record_event(callsite, kind, EventDetail::addr(obj_addr(cell)));
+    }
+    drop(lock);
+    if kind == Some(EventKind::OnceObserved) {
+        scheduler::reattach();
+    }
+}
+
+#[inline(always)]
+pub fn this_is_our_once_call_before_handle_function<C, F>(callsite: &str, cell: &C, _f: &F) {
+    once_enter(callsite, cell);
+}
+
+#[inline(always)]
+pub fn this_is_our_once_call_after_handle_function<C, F, R>(callsite: &str, cell: &C, _f: &F, _ret: &mut R) {
+    once_return(callsite, cell);
+}
+
+#[inline(always)]
+pub fn this_is_our_lazy_force_before_handle_function<C>(callsite: &str, lazy: &C) {
+    once_enter(callsite, lazy);
+}
+
+#[inline(always)]
+pub fn this_is_our_lazy_force_after_handle_function<C, R>(callsite: &str, lazy: &C, _ret: &mut R) {
+    once_return(callsite, lazy);
+}
+
+// Arc and Rc monitors: P is the pointer type (Arc<T>, Rc<T> or their Weak), R the return type of the call.
+// Events are about the allocation (the address of the value) and carry the strong count after the operation.
+
+fn record_refcount(callsite: &str, kind: EventKind, alloc: usize, ok: Option<bool>, refs: Option<usize>) {
+    let _lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "Shared allocation {alloc:#x} {}, ok {ok:?}, strong count {refs:?}", kind.name());
+    record_event(callsite, kind, EventDetail{ addr: Some(alloc), ok, refs, ..Default::default() });
+}
+
+#[inline(always)]
+pub fn this_is_our_refcount_new_after_handle_function<D, R: RefCounted>(callsite: &str, _data: &D, ret: &mut R) {
+    record_refcount(callsite, EventKind::RefCountNew, ret.alloc_addr(), None, Some(ret.strong_count()));
+}
+
+#[inline(always)]
+pub fn this_is_our_refcount_clone_after_handle_function<P: RefCounted, R: RefCounted>(callsite: &str, _this: &P, ret: &mut R) {
+    record_refcount(callsite, EventKind::RefCountClone, ret.alloc_addr(), None, Some(ret.strong_count()));
+}
+
+#[inline(always)]
+pub fn this_is_our_refcount_try_unwrap_before_handle_function<P: RefCounted>(_callsite: &str, this: &P) {
+    refcount::try_unwrap_started(this.alloc_addr());
+}
+
+// `this` was moved into try_unwrap, its allocation was saved by the before monitor
+#[inline(always)]
+pub fn this_is_our_refcount_try_unwrap_after_handle_function<P, R: RefCountResult>(callsite: &str, _this: &P, ret: &mut R) {
+    let alloc = refcount::try_unwrap_finished();
+    record_refcount(callsite, EventKind::RefCountTryUnwrap, alloc, Some(ret.succeeded()), ret.strong_count());
+}
+
+#[inline(always)]
+pub fn this_is_our_refcount_get_mut_after_handle_function<P: RefCounted, R: RefCountResult>(callsite: &str, this: &mut P, ret: &mut R) {
+    record_refcount(callsite, EventKind::RefCountGetMut, this.alloc_addr(), Some(ret.succeeded()), ret.strong_count());
+}
+
+#[inline(always)]
+pub fn this_is_our_refcount_upgrade_after_handle_function<W: RefCounted, R: RefCountResult>(callsite: &str, weak: &W, ret: &mut R) {
+    record_refcount(callsite, EventKind::RefCountUpgrade, weak.alloc_addr(), Some(ret.succeeded()), ret.strong_count());
+}
+
+#[inline(always)]
+pub fn this_is_our_arc_drop_before_handle_function<T: ?Sized, A: Allocator>(callsite: &str, arc: &Arc<T, A>) {
+    record_refcount(callsite, EventKind::RefCountDrop, arc.alloc_addr(), None, Some(Arc::strong_count(arc) - 1));
+}
+
+#[inline(always)]
+pub fn this_is_our_rc_drop_before_handle_function<T: ?Sized, A: Allocator>(callsite: &str, rc: &Rc<T, A>) {
+    record_refcount(callsite, EventKind::RefCountDrop, rc.alloc_addr(), None, Some(Rc::strong_count(rc) - 1));
+}
+
+// spawn and join order the threads: what the parent did before spawn happens before the child, and what the child
+// did happens before join returns. The child is named by its thread id in the after hook of spawn and in both join hooks.
+
+#[inline(always)]
+pub fn this_is_our_thread_spawn_before_handle_function<F, T>(callsite: &str, _f: &F) {
+    replay::step("thread_spawn");
+    let _lock = MONITOR_LOCK.lock().unwrap();
+    my_println_with_callsite!(callsite, "thread spawning, this is before.");
+    record_event(callsite, EventKind::ThreadSpawnBefore, EventDetail::default());
+    scheduler::spawning();
+}
+
+// the closure was moved into the new thread, only the returned handle may be touched
+#[inline(always)]
+pub fn this_is_our_thread_spawn_after_handle_function<F, T>(callsite: &str, _f: &F, ret: &mut JoinHandle<T>) {
+    let _lock = MONITOR_LOCK.lock().unwrap();
+    let child = ret.thread().id().as_u64().get();
+    my_println_with_callsite!(callsite, "thread {child} spawned, this is after.");
+    record_event(callsite, EventKind::ThreadSpawnAfter, EventDetail { child: Some(child), ..Default::default() });
+}
+
+#[inline(always)]
+pub fn this_is_our_thread_join_before_handle_function<T>(callsite: &str, handle: &JoinHandle<T>) {
+    let _lock = MONITOR_LOCK.lock().unwrap();
+    let child = handle.thread().id().as_u64().get();
+    let _ = JOINING.try_with(|joining| joining.set(Some(child)));
+    my_println_with_callsite!(callsite, "joining thread {child}, this is before.");
+    record_event(callsite, EventKind::ThreadJoinBefore, EventDetail { child: Some(child), ..Default::default() });
+}
+
+// join consumes the handle, the child comes from the before hook
+#[inline(always)]
+pub fn this_is_our_thread_join_after_handle_function<T>(callsite: &str, _handle: &JoinHandle<T>, ret: &mut std::thread::Result<T>) {
+    let _lock = MONITOR_LOCK.lock().unwrap();
+    let child = JOINING.try_with(|joining| joining.take()).ok().flatten();
+    my_println_with_callsite!(callsite, "joined thread {child:?}, this is after.");
+    record_event(callsite, EventKind::ThreadJoinAfter, EventDetail { child, ok: Some(ret.is_ok()), ..Default::default() });
 }
 
 #[inline(always)]
@@ -126,28 +778,38 @@ pub fn this_is_our_mem_read_before_function(addr:usize) {
     THREAD.with(|thread| {
         my_println!("mem read {addr} in thread {thread:?}");
     });
+    record_event("", EventKind::MemRead, EventDetail::addr(addr));
 }
 
 #[inline(always)]
 pub fn this_is_our_mem_write_before_function(addr:usize) {
+    noise::inject(NoisePoint::BeforeMemWrite);
     let mut lock = MONITOR_LOCK.lock().unwrap();
     THREAD.with(|thread| {
         my_println!("mem write {addr} in thread {thread:?}");
     });
+    record_event("", EventKind::MemWrite, EventDetail::addr(addr));
 }
 
 #[inline(always)]
 pub fn this_is_our_mem_atomic_read_before_function(addr:usize) {
+    scheduler::yield_point();
+    replay::step("mem_atomic_read");
     let mut lock = MONITOR_LOCK.lock().unwrap();
     THREAD.with(|thread| {
         my_println!("mem atomic-read {addr} in thread {thread:?}");
     });
+    record_event("", EventKind::MemAtomicRead, EventDetail::addr(addr));
 }
 
 #[inline(always)]
 pub fn this_is_our_mem_atomic_write_before_function(addr:usize) {
+    noise::inject(NoisePoint::BeforeAtomicStore);
+    scheduler::yield_point();
+    replay::step("mem_atomic_write");
     let mut lock = MONITOR_LOCK.lock().unwrap();
     THREAD.with(|thread| {
         my_println!("mem atomic-write {addr} in thread {thread:?}");
     });
+    record_event("", EventKind::MemAtomicWrite, EventDetail::addr(addr));
 }
\ No newline at end of file
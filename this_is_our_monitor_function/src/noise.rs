@@ -0,0 +1,110 @@
+// Noise injection: random yields and short sleeps at synchronization points, to shake out races in stress tests
+// without controlling the whole schedule.
+//   SOLCON_NOISE=<points>           comma separated: before_lock, after_unlock, before_atomic_store, before_mem_write, or all
+//   SOLCON_NOISE_PROB=<P>           probability to inject noise at a point (default 0.1)
+//   SOLCON_NOISE_MAX_SLEEP_US=<US>  noise is a yield or a sleep of 1..=US microseconds, 0 means only yields (default 100)
+//   SOLCON_NOISE_SEED=<N>           seed of the random choices, printed at start. Every thread draws from its own
+//                                   generator seeded from it, the timing of the threads still varies between runs.
+use std::cell::RefCell;
+use std::time::Duration;
+use once_cell::sync::Lazy;
+
+use crate::utils::{self, Rng};
+use crate::THREAD;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoisePoint {
+    BeforeLock,
+    AfterUnlock,
+    BeforeAtomicStore,
+    BeforeMemWrite,
+}
+
+impl NoisePoint {
+    const ALL: [NoisePoint; 4] = [
+        NoisePoint::BeforeLock,
+        NoisePoint::AfterUnlock,
+        NoisePoint::BeforeAtomicStore,
+        NoisePoint::BeforeMemWrite,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            NoisePoint::BeforeLock => "before_lock",
+            NoisePoint::AfterUnlock => "after_unlock",
+            NoisePoint::BeforeAtomicStore => "before_atomic_store",
+            NoisePoint::BeforeMemWrite => "before_mem_write",
+        }
+    }
+}
+
+struct NoiseConfig {
+    points: Vec<NoisePoint>,
+    /// probability scaled to u64::MAX
+    threshold: u64,
+    max_sleep_us: u64,
+    seed: u64,
+}
+
+static CONFIG: Lazy<Option<NoiseConfig>> = Lazy::new(|| {
+    let spec = std::env::var("SOLCON_NOISE").ok()?;
+    let mut points = Vec::new();
+    for name in spec.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        if name == "all" {
+            points.extend(NoisePoint::ALL);
+        } else if let Some(point) = NoisePoint::ALL.into_iter().find(|point| point.name() == name) {
+            points.push(point);
+        } else {
+            eprintln!("solcon: unknown SOLCON_NOISE point {name}, expect before_lock, after_unlock, before_atomic_store, before_mem_write or all");
+        }
+    }
+    if points.is_empty() {
+        return None;
+    }
+    let probability: f64 = std::env::var("SOLCON_NOISE_PROB").ok().and_then(|v| v.parse().ok()).unwrap_or(0.1);
+    let max_sleep_us = std::env::var("SOLCON_NOISE_MAX_SLEEP_US").ok().and_then(|v| v.parse().ok()).unwrap_or(100);
+    let seed = std::env::var("SOLCON_NOISE_SEED").ok().and_then(|v| v.parse().ok())
+        .unwrap_or(utils::get_timestamp_nanos() as u64);
+    eprintln!("solcon noise: {:?} with probability {probability}, seed {seed} (set SOLCON_NOISE_SEED to reuse it)", points);
+    Some(NoiseConfig {
+        points,
+        threshold: (probability.clamp(0.0, 1.0) * u64::MAX as f64) as u64,
+        max_sleep_us,
+        seed,
+    })
+});
+
+thread_local! {
+    static RNG: RefCell<Option<Rng>> = const { RefCell::new(None) };
+}
+
+/// Maybe yield or sleep a little, call it without holding MONITOR_LOCK.
+#[inline(always)]
+pub fn inject(point: NoisePoint) {
+    let Some(config) = CONFIG.as_ref() else {
+        return;
+    };
+    if !config.points.contains(&point) {
+        return;
+    }
+    let Ok(Some(sleep_us)) = RNG.try_with(|rng| {
+        let mut rng = rng.borrow_mut();
+        let rng = rng.get_or_insert_with(|| Rng(config.seed ^ THREAD.with(|thread| thread.id).wrapping_mul(0x9E3779B97F4A7C15)));
+        if rng.next() >= config.threshold {
+            return None;
+        }
+        // half of the noise is a plain yield
+        if config.max_sleep_us == 0 || rng.below(2) == 0 {
+            Some(0)
+        } else {
+            Some(1 + rng.below(config.max_sleep_us))
+        }
+    }) else {
+        return;
+    };
+    if sleep_us == 0 {
+        std::thread::yield_now();
+    } else {
+        std::thread::sleep(Duration::from_micros(sleep_us));
+    }
+}
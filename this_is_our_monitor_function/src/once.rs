@@ -0,0 +1,68 @@
+// One-time initialization of Once, OnceLock, LazyLock and once_cell, the state lives in MonitorLockedInformation so it
+// is protected by MONITOR_LOCK. We see the calls but not whether their closure ran, so the first thread entering a cell
+// we have not seen initialized is taken as its initializer, and the threads entering it until the initializer returns
+// wait for it. A cell initialized without a call we instrument (e.g. OnceLock::set, or in a crate which is not
+// instrumented) looks initialized by the first instrumented call on it.
+use std::collections::{HashMap, HashSet};
+
+use crate::{stack, suppress};
+use crate::trace::EventKind;
+use crate::utils::ThreadId;
+
+#[derive(Default)]
+struct Cell {
+    initialized: bool,
+    /// thread running the initialization and where it entered
+    initializer: Option<(ThreadId, String)>,
+    waiters: HashSet<ThreadId>,
+}
+
+#[derive(Default)]
+pub(crate) struct OnceCells {
+    by_addr: HashMap<usize, Cell>,
+}
+
+impl OnceCells {
+    /// A thread calls into the cell, returns OnceInitBegin, OnceWait or OnceObserved.
+    pub(crate) fn on_enter(&mut self, callsite: &str, thread: ThreadId, cell_addr: usize) -> EventKind {
+        let cell = self.by_addr.entry(cell_addr).or_default();
+        if cell.initialized {
+            return EventKind::OnceObserved;
+        }
+        match &cell.initializer {
+            None => {
+                cell.initializer = Some((thread, callsite.to_owned()));
+                EventKind::OnceInitBegin
+            }
+            Some((initializer, first_callsite)) if *initializer == thread => {
+                // reported right away, the thread never returns from a re-entrant Once or OnceLock
+                if !suppress::is_suppressed("once", &[callsite, first_callsite], &[cell_addr], true) {
+                    eprintln!("solcon: re-entrant initialization: {cell_addr:#x} is entered again at {callsite} \
+                        by the thread initializing it at {first_callsite}, this deadlocks or panics");
+                    stack::print_for_report();
+                }
+                EventKind::OnceWait
+            }
+            Some(_) => {
+                cell.waiters.insert(thread);
+                EventKind::OnceWait
+            }
+        }
+    }
+
+    /// The call of a thread returned, None if OnceObserved was already returned by on_enter.
+    pub(crate) fn on_return(&mut self, thread: ThreadId, cell_addr: usize) -> Option<EventKind> {
+        let cell = self.by_addr.entry(cell_addr).or_default();
+        if cell.initializer.as_ref().is_some_and(|(initializer, _)| *initializer == thread) {
+            cell.initializer = None;
+            cell.initialized = true;
+            Some(EventKind::OnceInitEnd)
+        } else if cell.waiters.remove(&thread) {
+            // returning means the cell is initialized, even if the initializer did not return yet
+            cell.initialized = true;
+            Some(EventKind::OnceObserved)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,322 @@
+// Lock contention profiler.
+// When SOLCON_PROFILE is set to "text" or "json", lock events are aggregated per lock and per acquiring callsite:
+// acquisitions, contended acquisitions (a failed try_lock or a wait longer than SOLCON_PROFILE_CONTENDED_NS, default 10000ns),
+// total/max wait time (before-lock hook to after-lock hook) and total/max hold time (after-lock hook to guard drop).
+// The report is written at program exit to SOLCON_PROFILE_FILE ("{pid}" is replaced by the process id) or to stderr,
+// the top SOLCON_PROFILE_TOP (default 20) entries sorted by total wait time.
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+
+use crate::trace::{EventKind, EventDetail};
+use crate::utils::{self, ThreadId};
+use crate::THREAD;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReportFormat {
+    Text,
+    Json,
+}
+
+struct ProfileConfig {
+    format: ReportFormat,
+    top: usize,
+    contended_threshold_ns: u128,
+    output: Option<String>,
+}
+
+static CONFIG: Lazy<Option<ProfileConfig>> = Lazy::new(|| {
+    let format = match std::env::var("SOLCON_PROFILE").ok()?.as_str() {
+        "text" | "1" => ReportFormat::Text,
+        "json" => ReportFormat::Json,
+        other => {
+            eprintln!("solcon: unknown SOLCON_PROFILE {other}, expect text or json");
+            return None;
+        }
+    };
+    let env_number = |name: &str, default| std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+    Some(ProfileConfig {
+        format,
+        top: env_number("SOLCON_PROFILE_TOP", 20) as usize,
+        contended_threshold_ns: env_number("SOLCON_PROFILE_CONTENDED_NS", 10_000),
+        output: std::env::var("SOLCON_PROFILE_FILE").ok().map(|path| path.replace("{pid}", &std::process::id().to_string())),
+    })
+});
+
+#[derive(Debug, Default, Clone)]
+struct LockStats {
+    acquisitions: u64,
+    contended: u64,
+    failed_try_locks: u64,
+    total_wait_ns: u128,
+    max_wait_ns: u128,
+    total_hold_ns: u128,
+    max_hold_ns: u128,
+}
+
+impl LockStats {
+    fn add_wait(&mut self, wait: u128, contended: bool) {
+        self.acquisitions += 1;
+        self.total_wait_ns += wait;
+        self.max_wait_ns = self.max_wait_ns.max(wait);
+        if contended {
+            self.contended += 1;
+        }
+    }
+
+    fn add_failed_try_lock(&mut self) {
+        self.contended += 1;
+        self.failed_try_locks += 1;
+    }
+
+    fn add_hold(&mut self, hold: u128) {
+        self.total_hold_ns += hold;
+        self.max_hold_ns = self.max_hold_ns.max(hold);
+    }
+}
+
+#[derive(Default)]
+struct ProfileState {
+    /// thread, lock -> time of the before-lock hook
+    acquiring: HashMap<(ThreadId, usize), u128>,
+    /// thread, lock -> (time of acquisition, acquiring callsite), a stack because read locks may be taken recursively
+    holding: HashMap<(ThreadId, usize), Vec<(u128, String)>>,
+    per_lock: HashMap<usize, LockStats>,
+    per_callsite: HashMap<String, LockStats>,
+}
+
+static STATE: Lazy<Mutex<ProfileState>> = Lazy::new(|| Mutex::new(ProfileState::default()));
+
+enum LockOp {
+    Acquiring(usize),
+    Acquired(usize),
+    AcquireFailed(usize),
+    Released(usize),
+}
+
+fn lock_op(kind: EventKind, detail: &EventDetail) -> Option<LockOp> {
+    use EventKind::*;
+    let op = match kind {
+        MutexLockBefore | MutexTryLockBefore | RwLockReadBefore | RwLockTryReadBefore | RwLockWriteBefore | RwLockTryWriteBefore => LockOp::Acquiring(detail.addr?),
+        // a poisoned lock is still acquired, only try_* may fail
+        MutexTryLockAfter | RwLockTryReadAfter | RwLockTryWriteAfter if detail.ok == Some(false) => LockOp::AcquireFailed(detail.addr?),
+        MutexLockAfter | MutexTryLockAfter | RwLockReadAfter | RwLockTryReadAfter | RwLockWriteAfter | RwLockTryWriteAfter => LockOp::Acquired(detail.addr?),
+        MutexGuardDrop | RwLockReadGuardDrop | RwLockWriteGuardDrop => LockOp::Released(detail.addr?),
+        // Condvar::wait and wait_while release the mutex while waiting and reacquire it before returning
+        CondvarWaitBefore | CondvarWaitWhileBefore => LockOp::Released(detail.lock?),
+        CondvarWaitAfter | CondvarWaitWhileAfter => LockOp::Acquired(detail.lock?),
+        _ => return None,
+    };
+    Some(op)
+}
+
+pub fn observe(callsite: &str, kind: EventKind, detail: &EventDetail) {
+    let Some(config) = CONFIG.as_ref() else {
+        return;
+    };
+    let Some(op) = lock_op(kind, detail) else {
+        return;
+    };
+    let now = utils::get_timestamp_nanos();
+    let thread = THREAD.with(|thread| thread.id);
+    STATE.lock().unwrap().update(thread, now, callsite, op, config.contended_threshold_ns);
+}
+
+impl ProfileState {
+    fn update(&mut self, thread: ThreadId, now: u128, callsite: &str, op: LockOp, contended_threshold_ns: u128) {
+        match op {
+            LockOp::Acquiring(lock) => {
+                self.acquiring.insert((thread, lock), now);
+            }
+            LockOp::Acquired(lock) => {
+                // reacquiring after Condvar::wait has no before-lock hook, its wait is the condvar wait and is not counted
+                if let Some(start) = self.acquiring.remove(&(thread, lock)) {
+                    let wait = now.saturating_sub(start);
+                    let contended = wait > contended_threshold_ns;
+                    self.per_lock.entry(lock).or_default().add_wait(wait, contended);
+                    self.per_callsite.entry(callsite.to_owned()).or_default().add_wait(wait, contended);
+                }
+                self.holding.entry((thread, lock)).or_default().push((now, callsite.to_owned()));
+            }
+            LockOp::AcquireFailed(lock) => {
+                self.acquiring.remove(&(thread, lock));
+                self.per_lock.entry(lock).or_default().add_failed_try_lock();
+                self.per_callsite.entry(callsite.to_owned()).or_default().add_failed_try_lock();
+            }
+            LockOp::Released(lock) => {
+                let Some((start, acquired_at)) = self.holding.get_mut(&(thread, lock)).and_then(Vec::pop) else {
+                    return;
+                };
+                let hold = now.saturating_sub(start);
+                self.per_lock.entry(lock).or_default().add_hold(hold);
+                // hold time is attributed to the callsite that acquired the lock
+                self.per_callsite.entry(acquired_at).or_default().add_hold(hold);
+            }
+        }
+    }
+}
+
+fn top_entries<K: Clone + Ord>(stats: &HashMap<K, LockStats>, top: usize) -> Vec<(K, LockStats)> {
+    let mut entries: Vec<_> = stats.iter().map(|(k, s)| (k.clone(), s.clone())).collect();
+    entries.sort_by(|a, b| b.1.total_wait_ns.cmp(&a.1.total_wait_ns)
+        .then(b.1.contended.cmp(&a.1.contended))
+        .then(b.1.total_hold_ns.cmp(&a.1.total_hold_ns))
+        .then(a.0.cmp(&b.0)));
+    entries.truncate(top);
+    entries
+}
+
+fn text_table(report: &mut String, title: &str, rows: &[(String, LockStats)]) {
+    let _ = writeln!(report, "{title}");
+    let _ = writeln!(report, "  acquired  contended try-failed wait-total(ns) wait-max(ns) hold-total(ns) hold-max(ns)  where");
+    for (name, s) in rows {
+        let _ = writeln!(report, "{:>10} {:>10} {:>10} {:>14} {:>12} {:>14} {:>12}  {}",
+            s.acquisitions, s.contended, s.failed_try_locks, s.total_wait_ns, s.max_wait_ns, s.total_hold_ns, s.max_hold_ns, name);
+    }
+}
+
+fn json_rows(key: &str, rows: &[(String, LockStats)]) -> String {
+    let rows: Vec<String> = rows.iter().map(|(name, s)| format!(
+        "{{\"{key}\":{},\"acquisitions\":{},\"contended\":{},\"failed_try_locks\":{},\"total_wait_ns\":{},\"max_wait_ns\":{},\"total_hold_ns\":{},\"max_hold_ns\":{}}}",
+        crate::trace::json_string(name), s.acquisitions, s.contended, s.failed_try_locks, s.total_wait_ns, s.max_wait_ns, s.total_hold_ns, s.max_hold_ns)).collect();
+    format!("[{}]", rows.join(","))
+}
+
+/// Write the report, called once when the program entry fn returns.
+pub fn report() {
+    let Some(config) = CONFIG.as_ref() else {
+        return;
+    };
+    let state = STATE.lock().unwrap();
+    let locks: Vec<(String, LockStats)> = top_entries(&state.per_lock, config.top).into_iter()
+        .map(|(addr, s)| (format!("{addr:#x}"), s)).collect();
+    let callsites = top_entries(&state.per_callsite, config.top);
+    let report = match config.format {
+        ReportFormat::Text => {
+            let mut report = String::new();
+            let _ = writeln!(report, "==== solcon lock contention profile (top {}, by total wait time) ====", config.top);
+            text_table(&mut report, "-- per lock --", &locks);
+            text_table(&mut report, "-- per callsite --", &callsites);
+            report
+        }
+        ReportFormat::Json => format!("{{\"contended_threshold_ns\":{},\"locks\":{},\"callsites\":{}}}\n",
+            config.contended_threshold_ns, json_rows("lock", &locks), json_rows("callsite", &callsites)),
+    };
+    match &config.output {
+        Some(path) => {
+            if let Err(err) = std::fs::write(path, report) {
+                eprintln!("solcon: cannot write profile {path}: {err}");
+            }
+        }
+        None => {
+            let _ = std::io::stderr().write_all(report.as_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCK: usize = 0x10;
+
+    fn profile(ops: Vec<(ThreadId, u128, &str, LockOp)>) -> ProfileState {
+        let mut state = ProfileState::default();
+        for (thread, now, callsite, op) in ops {
+            state.update(thread, now, callsite, op, 20);
+        }
+        state
+    }
+
+    #[test]
+    fn wait_and_hold_per_lock_and_callsite() {
+        let state = profile(vec![
+            (1, 100, "a.rs:1", LockOp::Acquiring(LOCK)),
+            (1, 110, "a.rs:1", LockOp::Acquired(LOCK)),
+            (2, 120, "b.rs:2", LockOp::Acquiring(LOCK)),
+            (1, 150, "a.rs:3", LockOp::Released(LOCK)),
+            (2, 160, "b.rs:2", LockOp::Acquired(LOCK)),
+            (2, 165, "b.rs:4", LockOp::Released(LOCK)),
+        ]);
+        let lock = &state.per_lock[&LOCK];
+        assert_eq!((lock.acquisitions, lock.contended), (2, 1));
+        assert_eq!((lock.total_wait_ns, lock.max_wait_ns), (50, 40));
+        assert_eq!((lock.total_hold_ns, lock.max_hold_ns), (45, 40));
+        // the hold time goes to the acquiring callsite, not to the one dropping the guard
+        let first = &state.per_callsite["a.rs:1"];
+        assert_eq!((first.acquisitions, first.total_wait_ns, first.total_hold_ns), (1, 10, 40));
+        let second = &state.per_callsite["b.rs:2"];
+        assert_eq!((second.acquisitions, second.contended, second.total_wait_ns, second.total_hold_ns), (1, 1, 40, 5));
+        assert!(!state.per_callsite.contains_key("a.rs:3"));
+    }
+
+    #[test]
+    fn failed_try_lock() {
+        let state = profile(vec![
+            (1, 1, "a.rs:1", LockOp::Acquiring(LOCK)),
+            (1, 2, "a.rs:1", LockOp::AcquireFailed(LOCK)),
+        ]);
+        let lock = &state.per_lock[&LOCK];
+        assert_eq!((lock.acquisitions, lock.contended, lock.failed_try_locks), (0, 1, 1));
+        assert!(state.acquiring.is_empty());
+    }
+
+    #[test]
+    fn recursive_read_locks_are_held_separately() {
+        let state = profile(vec![
+            (1, 0, "a.rs:1", LockOp::Acquiring(LOCK)),
+            (1, 10, "a.rs:1", LockOp::Acquired(LOCK)),
+            (1, 20, "a.rs:2", LockOp::Acquiring(LOCK)),
+            (1, 20, "a.rs:2", LockOp::Acquired(LOCK)),
+            (1, 25, "a.rs:3", LockOp::Released(LOCK)),
+            (1, 40, "a.rs:3", LockOp::Released(LOCK)),
+        ]);
+        assert_eq!(state.per_callsite["a.rs:2"].total_hold_ns, 5);
+        assert_eq!(state.per_callsite["a.rs:1"].total_hold_ns, 30);
+    }
+
+    #[test]
+    fn condvar_waits_release_the_mutex() {
+        let detail = EventDetail { addr: Some(0x20), lock: Some(LOCK), ..Default::default() };
+        for kind in [EventKind::CondvarWaitBefore, EventKind::CondvarWaitWhileBefore] {
+            assert!(matches!(lock_op(kind, &detail), Some(LockOp::Released(LOCK))));
+        }
+        for kind in [EventKind::CondvarWaitAfter, EventKind::CondvarWaitWhileAfter] {
+            assert!(matches!(lock_op(kind, &detail), Some(LockOp::Acquired(LOCK))));
+        }
+        // the wait on the condvar is not a wait for the mutex
+        let state = profile(vec![
+            (1, 0, "a.rs:1", LockOp::Acquiring(LOCK)),
+            (1, 10, "a.rs:1", LockOp::Acquired(LOCK)),
+            (1, 30, "a.rs:2", LockOp::Released(LOCK)),
+            (1, 1000, "a.rs:2", LockOp::Acquired(LOCK)),
+            (1, 1005, "a.rs:3", LockOp::Released(LOCK)),
+        ]);
+        let lock = &state.per_lock[&LOCK];
+        assert_eq!((lock.acquisitions, lock.total_wait_ns, lock.total_hold_ns), (1, 10, 25));
+    }
+
+    #[test]
+    fn top_entries_by_total_wait() {
+        let stats = |total_wait_ns| LockStats { total_wait_ns, ..Default::default() };
+        let entries = HashMap::from([("a", stats(5)), ("b", stats(50)), ("c", stats(20))]);
+        let top: Vec<&str> = top_entries(&entries, 2).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(top, vec!["b", "c"]);
+    }
+
+    #[test]
+    fn text_table_columns() {
+        let mut report = String::new();
+        let stats = LockStats { acquisitions: 3, total_wait_ns: 12, ..Default::default() };
+        text_table(&mut report, "-- per lock --", &[("0x10".to_owned(), stats)]);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "-- per lock --");
+        assert!(lines[1].ends_with("  where"));
+        assert!(lines[2].ends_with("  0x10"));
+        // the values are right aligned below their header
+        assert_eq!(lines[1].find("acquired").map(|i| i + "acquired".len()), lines[2].find('3').map(|i| i + 1));
+        assert_eq!(lines[1].len() - "where".len(), lines[2].len() - "0x10".len());
+    }
+}
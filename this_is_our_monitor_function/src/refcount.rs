@@ -0,0 +1,124 @@
+// Arc and Rc, so that the same monitors serve both. A shared allocation is identified by its address, the same for all
+// its strong and weak pointers and different from the address of the value (e.g. of a Mutex in an Arc).
+// Counts are read right before or after the operation, with concurrent clones and drops on other threads they may be
+// off by the operations in between.
+use std::alloc::Allocator;
+use std::cell::Cell;
+use std::rc::{self, Rc};
+use std::sync::{self, Arc};
+
+/// Arc and Rc allocate a `#[repr(C)]` (strong count, weak count, value), the allocation starts before the value.
+fn alloc_of<T: ?Sized>(value: *const T) -> usize {
+    // the value of a dangling Weak is never read, only its alignment is needed
+    let align = unsafe { std::mem::align_of_val_raw(value) };
+    (value as *const () as usize).wrapping_sub((2 * std::mem::size_of::<usize>()).next_multiple_of(align))
+}
+
+/// A strong or weak pointer to a reference counted allocation.
+pub trait RefCounted {
+    fn alloc_addr(&self) -> usize;
+    fn strong_count(&self) -> usize;
+}
+
+impl<T: ?Sized, A: Allocator> RefCounted for Arc<T, A> {
+    fn alloc_addr(&self) -> usize {
+        alloc_of(Arc::as_ptr(self))
+    }
+    fn strong_count(&self) -> usize {
+        Arc::strong_count(self)
+    }
+}
+
+impl<T: ?Sized, A: Allocator> RefCounted for Rc<T, A> {
+    fn alloc_addr(&self) -> usize {
+        alloc_of(Rc::as_ptr(self))
+    }
+    fn strong_count(&self) -> usize {
+        Rc::strong_count(self)
+    }
+}
+
+impl<T: ?Sized, A: Allocator> RefCounted for sync::Weak<T, A> {
+    fn alloc_addr(&self) -> usize {
+        alloc_of(self.as_ptr())
+    }
+    fn strong_count(&self) -> usize {
+        self.strong_count()
+    }
+}
+
+impl<T: ?Sized, A: Allocator> RefCounted for rc::Weak<T, A> {
+    fn alloc_addr(&self) -> usize {
+        alloc_of(self.as_ptr())
+    }
+    fn strong_count(&self) -> usize {
+        self.strong_count()
+    }
+}
+
+/// The result of try_unwrap, get_mut and upgrade: whether it succeeded and the strong count after it, if known.
+pub trait RefCountResult {
+    fn succeeded(&self) -> bool;
+    fn strong_count(&self) -> Option<usize>;
+}
+
+// try_unwrap: the value when the pointer was the last one, the pointer back otherwise
+impl<T, A: Allocator> RefCountResult for Result<T, Arc<T, A>> {
+    fn succeeded(&self) -> bool {
+        self.is_ok()
+    }
+    fn strong_count(&self) -> Option<usize> {
+        Some(self.as_ref().err().map_or(0, Arc::strong_count))
+    }
+}
+
+impl<T, A: Allocator> RefCountResult for Result<T, Rc<T, A>> {
+    fn succeeded(&self) -> bool {
+        self.is_ok()
+    }
+    fn strong_count(&self) -> Option<usize> {
+        Some(self.as_ref().err().map_or(0, Rc::strong_count))
+    }
+}
+
+// get_mut: only succeeds on the only pointer
+impl<T: ?Sized> RefCountResult for Option<&mut T> {
+    fn succeeded(&self) -> bool {
+        self.is_some()
+    }
+    fn strong_count(&self) -> Option<usize> {
+        self.is_some().then_some(1)
+    }
+}
+
+// upgrade
+impl<T: ?Sized, A: Allocator> RefCountResult for Option<Arc<T, A>> {
+    fn succeeded(&self) -> bool {
+        self.is_some()
+    }
+    fn strong_count(&self) -> Option<usize> {
+        Some(self.as_ref().map_or(0, Arc::strong_count))
+    }
+}
+
+impl<T: ?Sized, A: Allocator> RefCountResult for Option<Rc<T, A>> {
+    fn succeeded(&self) -> bool {
+        self.is_some()
+    }
+    fn strong_count(&self) -> Option<usize> {
+        Some(self.as_ref().map_or(0, Rc::strong_count))
+    }
+}
+
+thread_local! {
+    /// allocation of the pointer moved into the running try_unwrap
+    static TRY_UNWRAP_ALLOC: Cell<usize> = const { Cell::new(0) };
+}
+
+pub(crate) fn try_unwrap_started(alloc: usize) {
+    TRY_UNWRAP_ALLOC.with(|cell| cell.set(alloc));
+}
+
+pub(crate) fn try_unwrap_finished() -> usize {
+    TRY_UNWRAP_ALLOC.with(|cell| cell.replace(0))
+}
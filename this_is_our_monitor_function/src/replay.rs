@@ -0,0 +1,255 @@
+// Record and replay of the global order of synchronization events.
+//   SOLCON_RECORD_SCHEDULE=<path>  write "<thread id> <op>" per event, in the order the events happened ({pid} is replaced).
+//                                  Lines are written immediately, so the schedule of a run which hangs is kept.
+//   SOLCON_REPLAY_SCHEDULE=<path>  enforce the recorded order: a thread blocks in the before hook of a lock, try_lock,
+//                                  notify, spawn or memory access until it is the next event of the schedule.
+// Thread ids are std ids, which are given in spawn order, so they match between runs as long as spawns are replayed too.
+// When the run diverges (another op than recorded, or the expected thread does not come within SOLCON_REPLAY_TIMEOUT_MS,
+// default 1000) replay stops and the program continues freely. Mutexes re-acquired by Condvar::wait are not ordered.
+// A program which exits before the end of the schedule is reported at exit.
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+use once_cell::sync::Lazy;
+
+use crate::utils::ThreadId;
+use crate::THREAD;
+
+enum Mode {
+    Record(Mutex<LineWriter<File>>),
+    Replay(Replayer),
+}
+
+struct Replayer {
+    events: Vec<(ThreadId, String)>,
+    state: Mutex<ReplayState>,
+    turn: Condvar,
+    timeout: Duration,
+}
+
+struct ReplayState {
+    /// index of the next event, the event is in progress when `admitted` is set
+    cursor: usize,
+    admitted: bool,
+    /// set on divergence or at the end of the schedule, then nobody is blocked any more
+    stopped: bool,
+    progress_at: Instant,
+}
+
+static MODE: Lazy<Option<Mode>> = Lazy::new(|| {
+    if let Ok(path) = std::env::var("SOLCON_RECORD_SCHEDULE") {
+        let path = path.replace("{pid}", &std::process::id().to_string());
+        return match File::create(&path) {
+            Ok(file) => {
+                eprintln!("solcon: recording the schedule to {path}");
+                Some(Mode::Record(Mutex::new(LineWriter::new(file))))
+            }
+            Err(err) => {
+                eprintln!("solcon: cannot create schedule file {path}: {err}");
+                None
+            }
+        };
+    }
+    let path = std::env::var("SOLCON_REPLAY_SCHEDULE").ok()?;
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) => {
+            eprintln!("solcon: cannot read schedule file {path}: {err}");
+            return None;
+        }
+    };
+    let events = parse(&content);
+    let timeout = std::env::var("SOLCON_REPLAY_TIMEOUT_MS").ok().and_then(|v| v.parse().ok()).unwrap_or(1000);
+    eprintln!("solcon: replaying {} events from {path}", events.len());
+    Some(Mode::Replay(Replayer::new(events, Duration::from_millis(timeout))))
+});
+
+/// "<thread id> <op>" lines, malformed lines are skipped.
+fn parse(content: &str) -> Vec<(ThreadId, String)> {
+    content.lines()
+        .filter_map(|line| {
+            let (thread, op) = line.split_once(' ')?;
+            Some((thread.parse().ok()?, op.to_owned()))
+        })
+        .collect()
+}
+
+fn current_thread_id() -> ThreadId {
+    THREAD.with(|thread| thread.id)
+}
+
+impl Replayer {
+    fn new(events: Vec<(ThreadId, String)>, timeout: Duration) -> Self {
+        Replayer {
+            events,
+            state: Mutex::new(ReplayState { cursor: 0, admitted: false, stopped: false, progress_at: Instant::now() }),
+            turn: Condvar::new(),
+            timeout,
+        }
+    }
+
+    fn before(&self, me: ThreadId, op: &str) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.stopped {
+                return;
+            }
+            let Some((thread, expected_op)) = self.events.get(state.cursor) else {
+                eprintln!("solcon: replay finished after {} events, continue freely", state.cursor);
+                self.stop(&mut state);
+                return;
+            };
+            if !state.admitted && *thread == me {
+                if expected_op != op {
+                    eprintln!("solcon: replay diverged at event {}: thread {me} does {op}, {expected_op} was recorded",
+                        state.cursor);
+                    self.stop(&mut state);
+                    return;
+                }
+                state.admitted = true;
+                state.progress_at = Instant::now();
+                return;
+            }
+            let (guard, result) = self.turn.wait_timeout(state, self.timeout).unwrap();
+            state = guard;
+            if result.timed_out() && !state.stopped && state.progress_at.elapsed() >= self.timeout {
+                let (thread, expected_op) = &self.events[state.cursor];
+                eprintln!("solcon: replay diverged at event {}: thread {thread} did not do {expected_op} in time", state.cursor);
+                self.stop(&mut state);
+            }
+        }
+    }
+
+    fn after(&self, me: ThreadId) {
+        let mut state = self.state.lock().unwrap();
+        if state.stopped || !state.admitted || self.events.get(state.cursor).map(|(thread, _)| *thread) != Some(me) {
+            return;
+        }
+        state.cursor += 1;
+        state.admitted = false;
+        state.progress_at = Instant::now();
+        self.turn.notify_all();
+    }
+
+    fn stop(&self, state: &mut ReplayState) {
+        state.stopped = true;
+        self.turn.notify_all();
+    }
+}
+
+/// Before a synchronization event, in replay mode block until it is our turn.
+pub fn before(op: &str) {
+    if let Some(Mode::Replay(replayer)) = MODE.as_ref() {
+        replayer.before(current_thread_id(), op);
+    }
+}
+
+/// After a synchronization event: record it, or let the next thread of the schedule go.
+pub fn after(op: &str) {
+    match MODE.as_ref() {
+        None => {}
+        Some(Mode::Record(writer)) => {
+            let me = current_thread_id();
+            let _ = writeln!(writer.lock().unwrap(), "{me} {op}");
+        }
+        Some(Mode::Replay(replayer)) => replayer.after(current_thread_id()),
+    }
+}
+
+/// An event which cannot block, e.g. a memory access.
+pub fn step(op: &str) {
+    before(op);
+    after(op);
+}
+
+/// At program exit, tell when the program ended before the schedule did.
+pub fn finish() {
+    let Some(Mode::Replay(replayer)) = MODE.as_ref() else {
+        return;
+    };
+    let state = replayer.state.lock().unwrap();
+    if !state.stopped && state.cursor < replayer.events.len() {
+        eprintln!("solcon: replay incomplete, the program exited after {} of {} events", state.cursor, replayer.events.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replayer(events: &[(ThreadId, &str)], timeout_ms: u64) -> Replayer {
+        let events = events.iter().map(|(thread, op)| (*thread, op.to_string())).collect();
+        Replayer::new(events, Duration::from_millis(timeout_ms))
+    }
+
+    fn cursor_and_stopped(replayer: &Replayer) -> (usize, bool) {
+        let state = replayer.state.lock().unwrap();
+        (state.cursor, state.stopped)
+    }
+
+    #[test]
+    fn parse_skips_malformed_lines() {
+        let events = parse("1 mutex_lock\n2 thread_spawn\n\nmutex_lock\nx mem_read\n3 mem_write\n");
+        let expected: Vec<(ThreadId, String)> = vec![(1, "mutex_lock".into()), (2, "thread_spawn".into()), (3, "mem_write".into())];
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn threads_follow_the_schedule() {
+        let schedule = [(2, "a"), (1, "b"), (2, "c"), (1, "d")];
+        let replayer = replayer(&schedule, 10_000);
+        let done = Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            for (me, ops) in [(1, ["b", "d"]), (2, ["a", "c"])] {
+                let (replayer, done) = (&replayer, &done);
+                scope.spawn(move || {
+                    for op in ops {
+                        replayer.before(me, op);
+                        done.lock().unwrap().push((me, op));
+                        replayer.after(me);
+                    }
+                });
+            }
+        });
+        assert_eq!(done.into_inner().unwrap(), schedule);
+        assert_eq!(cursor_and_stopped(&replayer), (4, false));
+        // past the end of the schedule the threads run freely
+        replayer.before(1, "e");
+        assert_eq!(cursor_and_stopped(&replayer), (4, true));
+    }
+
+    #[test]
+    fn other_op_than_recorded_stops_the_replay() {
+        let replayer = replayer(&[(1, "mutex_lock"), (2, "mutex_lock")], 10_000);
+        replayer.before(1, "rwlock_read");
+        assert_eq!(cursor_and_stopped(&replayer), (0, true));
+        // thread 2 is not blocked any more
+        replayer.before(2, "mutex_lock");
+        replayer.after(2);
+        assert_eq!(cursor_and_stopped(&replayer), (0, true));
+    }
+
+    #[test]
+    fn missing_thread_stops_the_replay() {
+        let replayer = replayer(&[(2, "mutex_lock"), (1, "mutex_lock")], 50);
+        let start = Instant::now();
+        replayer.before(1, "mutex_lock");
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(cursor_and_stopped(&replayer), (0, true));
+    }
+
+    #[test]
+    fn only_the_admitted_thread_advances_the_cursor() {
+        let replayer = replayer(&[(1, "mutex_lock"), (2, "mutex_lock")], 10_000);
+        // e.g. the after hook of an event which was not replayed (try_lock which failed in another thread)
+        replayer.after(2);
+        replayer.after(1);
+        assert_eq!(cursor_and_stopped(&replayer), (0, false));
+        replayer.before(1, "mutex_lock");
+        replayer.after(2);
+        assert_eq!(cursor_and_stopped(&replayer), (0, false));
+        replayer.after(1);
+        assert_eq!(cursor_and_stopped(&replayer), (1, false));
+    }
+}
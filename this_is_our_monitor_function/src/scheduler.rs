@@ -0,0 +1,513 @@
+// Controlled scheduler for systematic interleaving exploration.
+// When SOLCON_SCHED is set, the lock/condvar/barrier/atomic hooks become scheduling points:
+// only the thread holding the run token executes, and at each scheduling point the next thread is picked by a strategy.
+//   random  pick uniformly among the enabled threads
+//   pct     probabilistic concurrency testing: random thread priorities, lowered at SOLCON_SCHED_PCT_DEPTH - 1 random steps
+//   dfs     bounded-preemption depth first search over schedules, one schedule per run, see below
+// SOLCON_SCHED_SEED fixes the random choices (the seed used is printed at start), so a failing schedule can be reproduced.
+//
+// The scheduler models lock ownership, so a thread waiting to acquire a lock held by another thread is not picked.
+// Threads blocked in operations we know (Condvar::wait, Barrier::wait, channel send/recv) give the token up until they return.
+// A thread keeping the token longer than SOLCON_SCHED_TIMEOUT_MS (default 100) without reaching a scheduling point
+// is assumed blocked somewhere we do not see (join, I/O...) and loses the token until its next scheduling point.
+// After std::thread::spawn, the next decision waits (up to the same timeout) for the new thread to reach its first
+// scheduling point, so that it is one of the choices.
+//
+// dfs: the decisions of a run are written to SOLCON_SCHED_DFS_FILE (default solcon_dfs_schedule.txt) at exit, as the next
+// schedule to explore. Running the program again replays that prefix and explores one more alternative, with at most
+// SOLCON_SCHED_PREEMPTION_BOUND (default 2) preemptions. When everything was explored, the file contains "done": the
+// next runs leave it as is and run without the scheduler until it is removed.
+use std::cell::RefCell;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use once_cell::sync::Lazy;
+
+use crate::stack;
+use crate::utils::{self, Rng, ThreadId};
+use crate::THREAD;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    Exclusive,
+    Shared,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Strategy {
+    Random,
+    Pct,
+    Dfs,
+}
+
+struct SchedConfig {
+    strategy: Strategy,
+    seed: u64,
+    timeout: Duration,
+    pct_depth: u64,
+    pct_steps: u64,
+    dfs_file: String,
+    preemption_bound: usize,
+}
+
+static CONFIG: Lazy<Option<SchedConfig>> = Lazy::new(|| load_config(|name| std::env::var(name).ok()));
+
+/// The scheduler configuration from the variables returned by `var`, None when the scheduler is off.
+fn load_config(var: impl Fn(&str) -> Option<String>) -> Option<SchedConfig> {
+    let strategy = match var("SOLCON_SCHED")?.as_str() {
+        "random" => Strategy::Random,
+        "pct" => Strategy::Pct,
+        "dfs" => Strategy::Dfs,
+        other => {
+            eprintln!("solcon: unknown SOLCON_SCHED {other}, expect random, pct or dfs");
+            return None;
+        }
+    };
+    let env_number = |name: &str, default: u64| var(name).and_then(|v| v.parse().ok()).unwrap_or(default);
+    let seed = env_number("SOLCON_SCHED_SEED", utils::get_timestamp_nanos() as u64);
+    let dfs_file = var("SOLCON_SCHED_DFS_FILE").unwrap_or_else(|| "solcon_dfs_schedule.txt".to_owned());
+    if strategy == Strategy::Dfs && dfs_exploration_done(&dfs_file) {
+        eprintln!("solcon scheduler: {dfs_file} says exploration is done, remove it to start again, running without the scheduler");
+        return None;
+    }
+    Some(SchedConfig {
+        strategy,
+        seed,
+        timeout: Duration::from_millis(env_number("SOLCON_SCHED_TIMEOUT_MS", 100)),
+        pct_depth: env_number("SOLCON_SCHED_PCT_DEPTH", 3).max(1),
+        pct_steps: env_number("SOLCON_SCHED_PCT_STEPS", 1000).max(1),
+        dfs_file,
+        preemption_bound: env_number("SOLCON_SCHED_PREEMPTION_BOUND", 2) as usize,
+    })
+}
+
+pub fn enabled() -> bool {
+    CONFIG.is_some()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// any operation which cannot block
+    Step,
+    Acquire { lock: usize, mode: LockMode },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Running,
+    Waiting(Op),
+    /// blocked outside of our control, not schedulable until its next scheduling point
+    Detached,
+}
+
+#[derive(Debug, Default)]
+struct LockOwner {
+    writer: Option<ThreadId>,
+    readers: usize,
+}
+
+/// One decision of a dfs run: which of the enabled threads was picked.
+/// Index 0 is the non-preempting choice (the yielding thread if enabled, else the lowest thread id).
+#[derive(Debug, Clone, Copy)]
+struct Decision {
+    choice: usize,
+    options: usize,
+    /// whether the yielding thread could have continued, then any other choice is a preemption
+    yielding_enabled: bool,
+}
+
+impl Decision {
+    fn preempts(&self, choice: usize) -> bool {
+        choice != 0 && self.yielding_enabled
+    }
+}
+
+struct SchedState {
+    threads: BTreeMap<ThreadId, Status>,
+    current: Option<ThreadId>,
+    granted_at: Instant,
+    locks: HashMap<usize, LockOwner>,
+    rng: Rng,
+    steps: u64,
+    pct_priorities: HashMap<ThreadId, u64>,
+    pct_change_points: Vec<u64>,
+    dfs_prefix: Vec<usize>,
+    dfs_decisions: Vec<Decision>,
+    /// spawned threads which did not reach a scheduling point yet
+    pending_spawns: usize,
+}
+
+static STATE: Lazy<(Mutex<SchedState>, Condvar)> = Lazy::new(|| {
+    let config = CONFIG.as_ref().unwrap();
+    eprintln!("solcon scheduler: strategy {:?}, seed {} (set SOLCON_SCHED_SEED to reproduce)", config.strategy, config.seed);
+    let mut rng = Rng(config.seed);
+    let pct_change_points = (1..config.pct_depth).map(|_| 1 + rng.below(config.pct_steps)).collect();
+    let dfs_prefix = if config.strategy == Strategy::Dfs { load_dfs_prefix(&config.dfs_file) } else { Vec::new() };
+    (Mutex::new(SchedState {
+        threads: BTreeMap::new(),
+        current: None,
+        granted_at: Instant::now(),
+        locks: HashMap::new(),
+        rng,
+        steps: 0,
+        pct_priorities: HashMap::new(),
+        pct_change_points,
+        dfs_prefix,
+        dfs_decisions: Vec::new(),
+        pending_spawns: 0,
+    }), Condvar::new())
+});
+
+/// Set when we stop the program on a deadlock, exit() runs the destructors of the exiting thread while it holds STATE.
+static EXITING: AtomicBool = AtomicBool::new(false);
+
+/// Removes the thread from the scheduler when it exits, so that it does not keep the token until the timeout.
+struct ThreadExitGuard(ThreadId);
+
+impl Drop for ThreadExitGuard {
+    fn drop(&mut self) {
+        if EXITING.load(Ordering::SeqCst) {
+            return;
+        }
+        let (state, cv) = &*STATE;
+        let mut state = state.lock().unwrap();
+        state.threads.remove(&self.0);
+        if state.current == Some(self.0) {
+            pick_next(&mut state, None);
+        }
+        cv.notify_all();
+    }
+}
+
+thread_local! {
+    static EXIT_GUARD: RefCell<Option<ThreadExitGuard>> = const { RefCell::new(None) };
+}
+
+fn current_thread(state: &mut SchedState) -> ThreadId {
+    let me = THREAD.with(|thread| thread.id);
+    if let Entry::Vacant(entry) = state.threads.entry(me) {
+        entry.insert(Status::Running);
+        let priority = CONFIG.as_ref().unwrap().pct_depth + state.rng.below(1 << 32);
+        state.pct_priorities.insert(me, priority);
+        let _ = EXIT_GUARD.try_with(|guard| *guard.borrow_mut() = Some(ThreadExitGuard(me)));
+        if state.pending_spawns > 0 {
+            state.pending_spawns -= 1;
+            STATE.1.notify_all();
+        }
+    }
+    me
+}
+
+/// Wait until the spawned threads are registered, so that the next decision can pick them.
+fn wait_for_spawned<'a>(mut state: MutexGuard<'a, SchedState>, cv: &Condvar) -> MutexGuard<'a, SchedState> {
+    let timeout = CONFIG.as_ref().unwrap().timeout;
+    while state.pending_spawns > 0 {
+        let (guard, result) = cv.wait_timeout(state, timeout).unwrap();
+        state = guard;
+        if result.timed_out() {
+            // the new thread never reached a scheduling point (or did not start at all)
+            state.pending_spawns = 0;
+        }
+    }
+    state
+}
+
+fn is_enabled(state: &SchedState, thread: ThreadId, op: Op) -> bool {
+    match op {
+        Op::Step => true,
+        Op::Acquire { lock, mode } => match state.locks.get(&lock) {
+            None => true,
+            // re-locking a lock held by the thread itself deadlocks for real, we let it happen
+            Some(owner) if owner.writer == Some(thread) => true,
+            Some(owner) => owner.writer.is_none() && (mode == LockMode::Shared || owner.readers == 0),
+        },
+    }
+}
+
+/// Give the token to the next thread, `yielding` is the thread giving it up at a scheduling point.
+fn pick_next(state: &mut SchedState, yielding: Option<ThreadId>) {
+    let config = CONFIG.as_ref().unwrap();
+    let enabled: Vec<ThreadId> = state.threads.iter()
+        .filter_map(|(&thread, &status)| match status {
+            Status::Waiting(op) if is_enabled(state, thread, op) => Some(thread),
+            _ => None,
+        })
+        .collect();
+    if enabled.is_empty() {
+        state.current = None;
+        let all_waiting = !state.threads.is_empty() && state.threads.values().all(|status| matches!(status, Status::Waiting(..)));
+        if all_waiting {
+            // nobody can make progress any more, report and stop instead of hanging
+            eprintln!("solcon scheduler: deadlock, every thread waits for a lock held by another one: {:?}", state.threads);
+            stack::print_for_report();
+            save_schedule(state, config);
+            crate::trace::flush();
+            EXITING.store(true, Ordering::SeqCst);
+            std::process::exit(1);
+        }
+        return;
+    }
+    state.steps += 1;
+    let chosen = match config.strategy {
+        Strategy::Random => enabled[state.rng.below(enabled.len() as u64) as usize],
+        Strategy::Pct => {
+            let chosen = *enabled.iter().max_by_key(|thread| state.pct_priorities.get(thread).copied().unwrap_or(0)).unwrap();
+            if let Some(i) = state.pct_change_points.iter().position(|&step| step == state.steps) {
+                state.pct_priorities.insert(chosen, i as u64);
+            }
+            chosen
+        }
+        Strategy::Dfs => {
+            // alternatives in a stable order, the non-preempting choice first
+            let mut options = enabled.clone();
+            let default = yielding.filter(|thread| enabled.contains(thread)).unwrap_or(enabled[0]);
+            options.retain(|&thread| thread != default);
+            options.insert(0, default);
+            let index = state.dfs_decisions.len();
+            let choice = state.dfs_prefix.get(index).copied().filter(|&choice| choice < options.len()).unwrap_or(0);
+            let yielding_enabled = yielding.is_some_and(|thread| enabled.contains(&thread));
+            state.dfs_decisions.push(Decision { choice, options: options.len(), yielding_enabled });
+            options[choice]
+        }
+    };
+    state.current = Some(chosen);
+    state.granted_at = Instant::now();
+}
+
+fn wait_for_turn<'a>(mut state: MutexGuard<'a, SchedState>, cv: &Condvar, me: ThreadId) -> MutexGuard<'a, SchedState> {
+    let timeout = CONFIG.as_ref().unwrap().timeout;
+    loop {
+        if state.current == Some(me) {
+            state.threads.insert(me, Status::Running);
+            return state;
+        }
+        if state.current.is_none() {
+            pick_next(&mut state, None);
+            if state.current.is_some() {
+                cv.notify_all();
+                continue;
+            }
+            // nobody can run in our model, wait for a detached thread to come back
+        }
+        let (guard, result) = cv.wait_timeout(state, timeout).unwrap();
+        state = guard;
+        if result.timed_out() && state.current != Some(me) && state.granted_at.elapsed() >= timeout {
+            // the running thread is blocked somewhere we cannot see
+            if let Some(stalled) = state.current.take() {
+                if state.threads.get(&stalled) == Some(&Status::Running) {
+                    state.threads.insert(stalled, Status::Detached);
+                }
+            }
+            if state.current.is_none() {
+                pick_next(&mut state, None);
+                cv.notify_all();
+            }
+        }
+    }
+}
+
+fn scheduling_point(op: Op) {
+    if !enabled() {
+        return;
+    }
+    let (state, cv) = &*STATE;
+    let mut state = state.lock().unwrap();
+    let me = current_thread(&mut state);
+    state.threads.insert(me, Status::Waiting(op));
+    if state.current == Some(me) || state.current.is_none() {
+        state.current = Some(me);
+        state = wait_for_spawned(state, cv);
+        pick_next(&mut state, Some(me));
+        cv.notify_all();
+    }
+    let _state = wait_for_turn(state, cv, me);
+}
+
+/// A thread is about to be spawned by the running thread.
+pub fn spawning() {
+    if !enabled() {
+        return;
+    }
+    let (state, _) = &*STATE;
+    let mut state = state.lock().unwrap();
+    let me = current_thread(&mut state);
+    state.pending_spawns += 1;
+    // keep the token until our next scheduling point, where the new thread becomes a choice
+    if state.current.is_none() {
+        state.current = Some(me);
+        state.granted_at = Instant::now();
+    }
+}
+
+/// Scheduling point before an operation which cannot block.
+pub fn yield_point() {
+    scheduling_point(Op::Step);
+}
+
+/// Scheduling point before acquiring a lock, the thread only runs again once the lock is free in our model.
+pub fn before_acquire(lock: usize, mode: LockMode, try_lock: bool) {
+    if try_lock {
+        scheduling_point(Op::Step);
+    } else {
+        scheduling_point(Op::Acquire { lock, mode });
+    }
+}
+
+pub fn acquired(lock: usize, mode: LockMode) {
+    if !enabled() {
+        return;
+    }
+    let (state, _) = &*STATE;
+    let mut state = state.lock().unwrap();
+    let me = current_thread(&mut state);
+    let owner = state.locks.entry(lock).or_default();
+    match mode {
+        LockMode::Exclusive => owner.writer = Some(me),
+        LockMode::Shared => owner.readers += 1,
+    }
+}
+
+pub fn released(lock: usize, mode: LockMode) {
+    if !enabled() {
+        return;
+    }
+    let (state, cv) = &*STATE;
+    let mut state = state.lock().unwrap();
+    if let Some(owner) = state.locks.get_mut(&lock) {
+        match mode {
+            LockMode::Exclusive => owner.writer = None,
+            LockMode::Shared => owner.readers = owner.readers.saturating_sub(1),
+        }
+        if owner.writer.is_none() && owner.readers == 0 {
+            state.locks.remove(&lock);
+        }
+    }
+    // threads waiting for this lock may be picked now if nobody holds the token
+    if state.current.is_none() {
+        pick_next(&mut state, None);
+        cv.notify_all();
+    }
+}
+
+/// The thread is about to block in an operation we know (Condvar::wait, Barrier::wait, channels), give the token up.
+pub fn detach() {
+    if !enabled() {
+        return;
+    }
+    let (state, cv) = &*STATE;
+    let mut state = state.lock().unwrap();
+    let me = current_thread(&mut state);
+    state.threads.insert(me, Status::Detached);
+    if state.current == Some(me) || state.current.is_none() {
+        pick_next(&mut state, None);
+        cv.notify_all();
+    }
+}
+
+/// Back from a blocking operation, wait for the token again.
+pub fn reattach() {
+    yield_point();
+}
+
+/// Called at program exit, saves the next schedule to explore in dfs mode.
+pub fn finish() {
+    let Some(config) = CONFIG.as_ref() else {
+        return;
+    };
+    let (state, _) = &*STATE;
+    let state = state.lock().unwrap();
+    save_schedule(&state, config);
+}
+
+fn save_schedule(state: &SchedState, config: &SchedConfig) {
+    eprintln!("solcon scheduler: {} scheduling decisions, seed {}", state.steps, config.seed);
+    if config.strategy != Strategy::Dfs {
+        return;
+    }
+    let next = next_dfs_prefix(&state.dfs_decisions, config.preemption_bound);
+    let content = match &next {
+        Some(prefix) => prefix.iter().map(|choice| choice.to_string()).collect::<Vec<_>>().join(" "),
+        None => "done".to_owned(),
+    };
+    if let Err(err) = std::fs::write(&config.dfs_file, content + "\n") {
+        eprintln!("solcon scheduler: cannot write {}: {err}", config.dfs_file);
+    } else if next.is_none() {
+        eprintln!("solcon scheduler: all schedules within the preemption bound were explored");
+    }
+}
+
+fn load_dfs_prefix(path: &str) -> Vec<usize> {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    content.split_whitespace().filter_map(|choice| choice.parse().ok()).collect()
+}
+
+/// Whether a previous run explored every schedule, then the file is kept and the scheduler stays off.
+fn dfs_exploration_done(path: &str) -> bool {
+    std::fs::read_to_string(path).is_ok_and(|content| content.trim() == "done")
+}
+
+/// The next schedule in depth first order: change the deepest decision which still has an unexplored alternative.
+fn next_dfs_prefix(decisions: &[Decision], preemption_bound: usize) -> Option<Vec<usize>> {
+    for depth in (0..decisions.len()).rev() {
+        let decision = decisions[depth];
+        if decision.choice + 1 >= decision.options {
+            continue;
+        }
+        let preemptions = decisions[..depth].iter().filter(|d| d.preempts(d.choice)).count();
+        if preemptions + decision.preempts(decision.choice + 1) as usize > preemption_bound {
+            continue;
+        }
+        let mut prefix: Vec<usize> = decisions[..depth].iter().map(|d| d.choice).collect();
+        prefix.push(decision.choice + 1);
+        return Some(prefix);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dfs_env(dfs_file: &std::path::Path) -> impl Fn(&str) -> Option<String> + '_ {
+        move |name| match name {
+            "SOLCON_SCHED" => Some("dfs".to_owned()),
+            "SOLCON_SCHED_SEED" => Some("1".to_owned()),
+            "SOLCON_SCHED_DFS_FILE" => Some(dfs_file.to_string_lossy().into_owned()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn dfs_done_turns_the_scheduler_off_and_keeps_the_file() {
+        let dfs_file = std::env::temp_dir().join(format!("solcon_dfs_done_{}.txt", std::process::id()));
+        std::fs::write(&dfs_file, "done\n").unwrap();
+        let config = load_config(dfs_env(&dfs_file));
+        let content = std::fs::read_to_string(&dfs_file).unwrap();
+        let _ = std::fs::remove_file(&dfs_file);
+        assert!(config.is_none());
+        assert_eq!(content, "done\n");
+    }
+
+    #[test]
+    fn dfs_prefix_is_replayed() {
+        let dfs_file = std::env::temp_dir().join(format!("solcon_dfs_prefix_{}.txt", std::process::id()));
+        std::fs::write(&dfs_file, "0 1 0\n").unwrap();
+        let config = load_config(dfs_env(&dfs_file));
+        let prefix = load_dfs_prefix(&dfs_file.to_string_lossy());
+        let _ = std::fs::remove_file(&dfs_file);
+        assert!(config.is_some_and(|config| config.strategy == Strategy::Dfs));
+        assert_eq!(prefix, vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn next_dfs_prefix_changes_the_deepest_decision() {
+        let decision = |choice, options| Decision { choice, options, yielding_enabled: false };
+        assert_eq!(next_dfs_prefix(&[decision(0, 2), decision(0, 2)], 2), Some(vec![0, 1]));
+        assert_eq!(next_dfs_prefix(&[decision(0, 2), decision(1, 2)], 2), Some(vec![1]));
+        assert_eq!(next_dfs_prefix(&[decision(1, 2), decision(1, 2)], 2), None);
+    }
+}
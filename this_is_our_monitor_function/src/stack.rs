@@ -0,0 +1,175 @@
+// Symbolized call stacks for the trace. The frames are walked without locks or allocations, so that this also works
+// inside the allocator, then resolved with the backtrace crate, every instruction pointer only once.
+//   SOLCON_BACKTRACE=<hooks>    comma separated: locks (lock acquisitions, condvar waits, one-time initializations),
+//                               mem (memory accesses), report (printed below the reports of the monitor), or all
+//   SOLCON_BACKTRACE_DEPTH=<N>  innermost frames per stack (default 16)
+// Heap allocations have their own SOLCON_ALLOC_BACKTRACE, see heap.rs. With SOLCON_BACKTRACE or SOLCON_SUPPRESSIONS
+// events also name the static they are about (a static Mutex, or a memory access to a static) from the symbol table.
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+
+use crate::{heap, suppress};
+use crate::trace::EventKind;
+
+const MAX_FRAMES: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StackHook {
+    Locks,
+    Mem,
+    Report,
+}
+
+impl StackHook {
+    const ALL: [StackHook; 3] = [StackHook::Locks, StackHook::Mem, StackHook::Report];
+
+    fn name(self) -> &'static str {
+        match self {
+            StackHook::Locks => "locks",
+            StackHook::Mem => "mem",
+            StackHook::Report => "report",
+        }
+    }
+
+    fn of_event(kind: EventKind) -> Option<Self> {
+        use EventKind::*;
+        match kind {
+            MutexLockBefore | MutexLockAfter | MutexTryLockBefore | MutexTryLockAfter
+            | RwLockReadBefore | RwLockReadAfter | RwLockTryReadBefore | RwLockTryReadAfter
+            | RwLockWriteBefore | RwLockWriteAfter | RwLockTryWriteBefore | RwLockTryWriteAfter
+            | CondvarWaitAfter | CondvarWaitWhileAfter | OnceInitBegin | OnceWait => Some(StackHook::Locks),
+            MemRead | MemWrite | MemAtomicRead | MemAtomicWrite => Some(StackHook::Mem),
+            _ => None,
+        }
+    }
+}
+
+struct StackConfig {
+    hooks: Vec<StackHook>,
+    depth: usize,
+}
+
+static CONFIG: Lazy<Option<StackConfig>> = Lazy::new(|| {
+    let spec = std::env::var("SOLCON_BACKTRACE").ok()?;
+    let mut hooks = Vec::new();
+    for name in spec.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        if name == "all" {
+            hooks.extend(StackHook::ALL);
+        } else if let Some(hook) = StackHook::ALL.into_iter().find(|hook| hook.name() == name) {
+            hooks.push(hook);
+        } else {
+            eprintln!("solcon: unknown SOLCON_BACKTRACE hook {name}, expect locks, mem, report or all");
+        }
+    }
+    if hooks.is_empty() {
+        return None;
+    }
+    let depth = std::env::var("SOLCON_BACKTRACE_DEPTH").ok().and_then(|v| v.parse().ok()).unwrap_or(16);
+    Some(StackConfig { hooks, depth })
+});
+
+fn enabled_depth(hook: StackHook) -> Option<usize> {
+    CONFIG.as_ref().filter(|config| config.hooks.contains(&hook)).map(|config| config.depth)
+}
+
+/// The stack to record with an event, empty unless SOLCON_BACKTRACE selects its hook.
+pub(crate) fn for_event(kind: EventKind) -> Vec<String> {
+    match StackHook::of_event(kind).and_then(enabled_depth) {
+        Some(depth) => heap::untracked(|| capture(depth)),
+        None => Vec::new(),
+    }
+}
+
+pub(crate) fn names_statics() -> bool {
+    CONFIG.is_some() || suppress::enabled()
+}
+
+/// Name of the static containing addr, e.g. "my_crate::STATE".
+pub(crate) fn static_name(addr: usize) -> Option<&'static str> {
+    heap::untracked(|| {
+        *STATIC_NAMES.lock().unwrap().entry(addr).or_insert_with(|| {
+            let mut name = None;
+            // resolve looks up the byte before the address, as for a return address
+            backtrace::resolve((addr + 1) as *mut c_void, |symbol| {
+                if name.is_none() {
+                    // leaked once per static
+                    name = symbol.name().map(|symbol| &*format!("{symbol:#}").leak());
+                }
+            });
+            name
+        })
+    })
+}
+
+/// Print the stack of the current thread below a report of the monitor, with SOLCON_BACKTRACE=report.
+pub(crate) fn print_for_report() {
+    let Some(depth) = enabled_depth(StackHook::Report) else {
+        return;
+    };
+    for (i, frame) in heap::untracked(|| capture(depth)).iter().enumerate() {
+        eprintln!("    #{i} {frame}");
+    }
+}
+
+/// Frames of the monitor, the allocator and the standard library code between them and the code calling them.
+const INTERNAL_FRAME_PREFIXES: &[&str] = &[
+    "backtrace::", "this_is_our_monitor_function::", "<this_is_our_monitor_function::",
+    "__rustc::", "__rust_", "alloc::", "<alloc::", "core::", "<core::",
+];
+
+/// Frames of the runtime calling main or the closure of a thread, the stack ends before them.
+const OUTER_FRAME_PREFIXES: &[&str] = &[
+    "std::sys::backtrace::__rust_begin_short_backtrace", "<fn() as core::ops::function::FnOnce<()>>::call_once",
+];
+
+/// frames (innermost first, inlined functions included) of every instruction pointer seen so far
+static SYMBOLS: Lazy<Mutex<HashMap<usize, Vec<String>>>> = Lazy::new(Default::default);
+
+/// static of every address seen so far, None outside of the statics
+static STATIC_NAMES: Lazy<Mutex<HashMap<usize, Option<&'static str>>>> = Lazy::new(Default::default);
+
+/// The innermost `depth` frames of the current thread outside of the monitor, as "function at file:line".
+/// Must run with heap tracking disabled, allocations while resolving would record their own stacks.
+pub(crate) fn capture(depth: usize) -> Vec<String> {
+    let mut ips = [0usize; MAX_FRAMES];
+    let mut len = 0;
+    // SAFETY: no other thread can be in the unsynchronized walk of this thread's stack
+    unsafe {
+        backtrace::trace_unsynchronized(|frame| {
+            ips[len] = frame.ip() as usize;
+            len += 1;
+            len < MAX_FRAMES
+        });
+    }
+    let mut symbols = SYMBOLS.lock().unwrap();
+    let mut frames = Vec::with_capacity(depth);
+    for &ip in &ips[..len] {
+        for frame in symbols.entry(ip).or_insert_with(|| resolve(ip)).iter() {
+            if frames.is_empty() && INTERNAL_FRAME_PREFIXES.iter().any(|prefix| frame.starts_with(prefix)) {
+                continue;
+            }
+            if frames.len() == depth || OUTER_FRAME_PREFIXES.iter().any(|prefix| frame.starts_with(prefix)) {
+                return frames;
+            }
+            frames.push(frame.clone());
+        }
+    }
+    frames
+}
+
+fn resolve(ip: usize) -> Vec<String> {
+    let mut frames = Vec::new();
+    backtrace::resolve(ip as *mut c_void, |symbol| {
+        let name = symbol.name().map_or_else(|| format!("{ip:#x}"), |name| format!("{name:#}"));
+        frames.push(match (symbol.filename(), symbol.lineno()) {
+            (Some(file), Some(line)) => format!("{name} at {}:{line}", file.display()),
+            _ => name,
+        });
+    });
+    if frames.is_empty() {
+        frames.push(format!("{ip:#x}"));
+    }
+    frames
+}
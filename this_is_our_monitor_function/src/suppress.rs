@@ -0,0 +1,120 @@
+// Suppressions of known or accepted reports, in the style of ThreadSanitizer suppression files.
+//   SOLCON_SUPPRESSIONS=<file>  one `<kind>:<pattern>` per line, lines starting with `#` are comments
+// The monitor suppresses its own reports of kind `condvar` (condvar misuse) and `once` (re-entrant initialization),
+// solcon-analyze reads the same file for race, deadlock and use-after-free. A pattern matches a callsite, a frame of
+// the stack of the reporting thread ("function at file:line") or the name of a static involved, anywhere in the string
+// unless anchored with ^ or $, `*` matches any characters. Suppressed reports are not printed, only counted at exit.
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+
+use crate::{heap, stack};
+
+/// frames of the reporting thread matched against the patterns
+const STACK_DEPTH: usize = 32;
+
+struct Suppression {
+    kind: String,
+    pattern: String,
+    hits: usize,
+}
+
+static SUPPRESSIONS: Lazy<Option<Mutex<Vec<Suppression>>>> = Lazy::new(|| {
+    let path = std::env::var("SOLCON_SUPPRESSIONS").ok()?;
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) => {
+            eprintln!("solcon: cannot read suppressions {path}: {err}");
+            return None;
+        }
+    };
+    let mut suppressions = Vec::new();
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        match line.split_once(':') {
+            Some((kind, pattern)) if !pattern.trim().is_empty() => suppressions.push(Suppression {
+                kind: kind.trim().to_owned(),
+                pattern: pattern.trim().to_owned(),
+                hits: 0,
+            }),
+            _ => eprintln!("solcon: malformed suppression {line} in {path}, expect <kind>:<pattern>"),
+        }
+    }
+    Some(Mutex::new(suppressions))
+});
+
+pub(crate) fn enabled() -> bool {
+    SUPPRESSIONS.is_some()
+}
+
+/// Whether a report of `kind` about these callsites and objects is suppressed, counts the hit if so.
+/// with_stack: the report is about the current thread, its stack is matched too
+pub(crate) fn is_suppressed(kind: &str, callsites: &[&str], objects: &[usize], with_stack: bool) -> bool {
+    let Some(suppressions) = SUPPRESSIONS.as_ref() else {
+        return false;
+    };
+    let mut suppressions = suppressions.lock().unwrap();
+    if !suppressions.iter().any(|suppression| suppression.kind == kind) {
+        return false;
+    }
+    let mut candidates: Vec<String> = callsites.iter().map(|callsite| callsite.to_string()).collect();
+    candidates.extend(objects.iter().filter_map(|addr| stack::static_name(*addr)).map(str::to_owned));
+    if with_stack {
+        candidates.extend(heap::untracked(|| stack::capture(STACK_DEPTH)));
+    }
+    let matching = suppressions.iter_mut()
+        .filter(|suppression| suppression.kind == kind)
+        .find(|suppression| candidates.iter().any(|candidate| matches(&suppression.pattern, candidate)));
+    match matching {
+        Some(suppression) => {
+            suppression.hits += 1;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Print how often each suppression was used, called at program exit.
+pub(crate) fn report() {
+    let Some(suppressions) = SUPPRESSIONS.as_ref() else {
+        return;
+    };
+    let suppressions = suppressions.lock().unwrap();
+    let total: usize = suppressions.iter().map(|suppression| suppression.hits).sum();
+    if total == 0 {
+        return;
+    }
+    eprintln!("solcon: {total} report(s) suppressed");
+    for suppression in suppressions.iter().filter(|suppression| suppression.hits > 0) {
+        eprintln!("  {} {}:{}", suppression.hits, suppression.kind, suppression.pattern);
+    }
+}
+
+/// Glob match of `pattern` anywhere in `text`, ^ and $ anchor it at the start and end.
+fn matches(pattern: &str, text: &str) -> bool {
+    let (anchored_start, pattern) = match pattern.strip_prefix('^') {
+        Some(pattern) => (true, pattern),
+        None => (false, pattern),
+    };
+    let (anchored_end, pattern) = match pattern.strip_suffix('$') {
+        Some(pattern) => (true, pattern),
+        None => (false, pattern),
+    };
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let mut rest = text;
+    for (i, part) in parts.iter().enumerate() {
+        if i == 0 && anchored_start {
+            let Some(after) = rest.strip_prefix(part) else {
+                return false;
+            };
+            rest = after;
+        } else if i == parts.len() - 1 && anchored_end {
+            return rest.ends_with(part);
+        } else {
+            let Some(pos) = rest.find(part) else {
+                return false;
+            };
+            rest = &rest[pos + part.len()..];
+        }
+    }
+    // a single part anchored at both ends
+    !anchored_end || rest.is_empty()
+}
@@ -0,0 +1,267 @@
+// Machine-readable trace of the events seen by our monitors.
+// When SOLCON_TRACE_FILE is set, every event is appended to that file as one JSON object per line,
+// so that heavy analysis can be done offline (see solcon-analyze) instead of inside the monitored program.
+// "{pid}" in SOLCON_TRACE_FILE is replaced by the process id, which keeps traces of concurrently running processes apart.
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+
+use crate::utils::{self, ThreadId};
+use crate::THREAD;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    ProgramStart,
+    ProgramExit,
+    MutexLockBefore,
+    MutexLockAfter,
+    MutexTryLockBefore,
+    MutexTryLockAfter,
+    MutexGuardDrop,
+    RwLockReadBefore,
+    RwLockReadAfter,
+    RwLockTryReadBefore,
+    RwLockTryReadAfter,
+    RwLockReadGuardDrop,
+    RwLockWriteBefore,
+    RwLockWriteAfter,
+    RwLockTryWriteBefore,
+    RwLockTryWriteAfter,
+    RwLockWriteGuardDrop,
+    BarrierWaitBefore,
+    BarrierWaitAfter,
+    CondvarWaitBefore,
+    CondvarWaitAfter,
+    CondvarWaitWhileBefore,
+    CondvarWaitWhileAfter,
+    CondvarNotifyOneBefore,
+    CondvarNotifyOneAfter,
+    CondvarNotifyAllBefore,
+    CondvarNotifyAllAfter,
+    ThreadSpawnBefore,
+    ThreadSpawnAfter,
+    ThreadJoinBefore,
+    ThreadJoinAfter,
+    ChannelSendBefore,
+    ChannelSendAfter,
+    ChannelRecvBefore,
+    ChannelRecvAfter,
+    OnceInitBegin,
+    OnceInitEnd,
+    OnceWait,
+    OnceObserved,
+    RefCountNew,
+    RefCountClone,
+    RefCountDrop,
+    RefCountTryUnwrap,
+    RefCountGetMut,
+    RefCountUpgrade,
+    FnEnter,
+    FnExit,
+    // only recorded by TrackingAllocator (alloc_tracking feature)
+    #[cfg_attr(not(feature = "alloc_tracking"), allow(dead_code))]
+    HeapAlloc,
+    #[cfg_attr(not(feature = "alloc_tracking"), allow(dead_code))]
+    HeapDealloc,
+    #[cfg_attr(not(feature = "alloc_tracking"), allow(dead_code))]
+    HeapRealloc,
+    MemRead,
+    MemWrite,
+    MemAtomicRead,
+    MemAtomicWrite,
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::ProgramStart => "program_start",
+            EventKind::ProgramExit => "program_exit",
+            EventKind::MutexLockBefore => "mutex_lock_before",
+            EventKind::MutexLockAfter => "mutex_lock_after",
+            EventKind::MutexTryLockBefore => "mutex_try_lock_before",
+            EventKind::MutexTryLockAfter => "mutex_try_lock_after",
+            EventKind::MutexGuardDrop => "mutexguard_drop",
+            EventKind::RwLockReadBefore => "rwlock_read_before",
+            EventKind::RwLockReadAfter => "rwlock_read_after",
+            EventKind::RwLockTryReadBefore => "rwlock_try_read_before",
+            EventKind::RwLockTryReadAfter => "rwlock_try_read_after",
+            EventKind::RwLockReadGuardDrop => "rwlock_readguard_drop",
+            EventKind::RwLockWriteBefore => "rwlock_write_before",
+            EventKind::RwLockWriteAfter => "rwlock_write_after",
+            EventKind::RwLockTryWriteBefore => "rwlock_try_write_before",
+            EventKind::RwLockTryWriteAfter => "rwlock_try_write_after",
+            EventKind::RwLockWriteGuardDrop => "rwlock_writeguard_drop",
+            EventKind::BarrierWaitBefore => "barrier_wait_before",
+            EventKind::BarrierWaitAfter => "barrier_wait_after",
+            EventKind::CondvarWaitBefore => "condvar_wait_before",
+            EventKind::CondvarWaitAfter => "condvar_wait_after",
+            EventKind::CondvarWaitWhileBefore => "condvar_wait_while_before",
+            EventKind::CondvarWaitWhileAfter => "condvar_wait_while_after",
+            EventKind::CondvarNotifyOneBefore => "condvar_notify_one_before",
+            EventKind::CondvarNotifyOneAfter => "condvar_notify_one_after",
+            EventKind::CondvarNotifyAllBefore => "condvar_notify_all_before",
+            EventKind::CondvarNotifyAllAfter => "condvar_notify_all_after",
+            EventKind::ThreadSpawnBefore => "thread_spawn_before",
+            EventKind::ThreadSpawnAfter => "thread_spawn_after",
+            EventKind::ThreadJoinBefore => "thread_join_before",
+            EventKind::ThreadJoinAfter => "thread_join_after",
+            EventKind::ChannelSendBefore => "channel_send_before",
+            EventKind::ChannelSendAfter => "channel_send_after",
+            EventKind::ChannelRecvBefore => "channel_recv_before",
+            EventKind::ChannelRecvAfter => "channel_recv_after",
+            EventKind::OnceInitBegin => "once_init_begin",
+            EventKind::OnceInitEnd => "once_init_end",
+            EventKind::OnceWait => "once_wait",
+            EventKind::OnceObserved => "once_observed",
+            EventKind::RefCountNew => "refcount_new",
+            EventKind::RefCountClone => "refcount_clone",
+            EventKind::RefCountDrop => "refcount_drop",
+            EventKind::RefCountTryUnwrap => "refcount_try_unwrap",
+            EventKind::RefCountGetMut => "refcount_get_mut",
+            EventKind::RefCountUpgrade => "refcount_upgrade",
+            EventKind::FnEnter => "fn_enter",
+            EventKind::FnExit => "fn_exit",
+            EventKind::HeapAlloc => "heap_alloc",
+            EventKind::HeapDealloc => "heap_dealloc",
+            EventKind::HeapRealloc => "heap_realloc",
+            EventKind::MemRead => "mem_read",
+            EventKind::MemWrite => "mem_write",
+            EventKind::MemAtomicRead => "mem_atomic_read",
+            EventKind::MemAtomicWrite => "mem_atomic_write",
+        }
+    }
+
+    pub fn is_call(&self) -> bool {
+        matches!(self, EventKind::FnEnter | EventKind::FnExit)
+    }
+
+    pub fn is_refcount(&self) -> bool {
+        matches!(self, EventKind::RefCountNew | EventKind::RefCountClone | EventKind::RefCountDrop
+            | EventKind::RefCountTryUnwrap | EventKind::RefCountGetMut | EventKind::RefCountUpgrade)
+    }
+}
+
+/// Optional fields of an event, omitted from the record when None.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EventDetail {
+    /// address of the object the event is about (lock, barrier, condvar, channel, once cell,
+    /// Arc/Rc allocation, heap block or accessed memory)
+    pub addr: Option<usize>,
+    /// address of the mutex associated with the event, e.g. the mutex released by Condvar::wait
+    pub lock: Option<usize>,
+    /// whether the operation succeeded, e.g. result of try_lock
+    pub ok: Option<bool>,
+    /// sequence number of the message on its channel, the n-th successful receive gets the message of the n-th send
+    pub msg: Option<u64>,
+    /// strong references to the Arc/Rc allocation left after the event, 0 when the last one went away
+    pub refs: Option<usize>,
+    /// size in bytes of the heap block
+    pub size: Option<usize>,
+    /// previous address of a reallocated heap block
+    pub old_addr: Option<usize>,
+    /// static containing `addr`, see stack.rs
+    pub name: Option<&'static str>,
+    /// def path of the traced function entered or left
+    pub function: Option<&'static str>,
+    /// thread spawned or joined
+    pub child: Option<ThreadId>,
+}
+
+impl EventDetail {
+    pub fn addr(addr: usize) -> Self {
+        Self { addr: Some(addr), ..Default::default() }
+    }
+}
+
+static TRACE_WRITER: Lazy<Option<Mutex<BufWriter<File>>>> = Lazy::new(|| {
+    let path = std::env::var("SOLCON_TRACE_FILE").ok()?;
+    let path = path.replace("{pid}", &std::process::id().to_string());
+    match File::create(&path) {
+        Ok(file) => Some(Mutex::new(BufWriter::new(file))),
+        Err(err) => {
+            eprintln!("solcon: cannot create trace file {path}: {err}");
+            None
+        }
+    }
+});
+
+/// stack: frames of the calling code, innermost first, omitted when empty
+pub fn record(callsite: &str, kind: EventKind, detail: EventDetail, stack: &[String]) {
+    let Some(writer) = TRACE_WRITER.as_ref() else {
+        return;
+    };
+    let timestamp = utils::get_timestamp_nanos();
+    let mut line = THREAD.with(|thread| {
+        format!("{{\"time\":{timestamp},\"thread\":{},\"thread_name\":{},\"event\":\"{}\",\"callsite\":{}",
+            thread.id, json_string_or_null(thread.name.as_deref()), kind.name(), json_string(callsite))
+    });
+    if let Some(addr) = detail.addr {
+        line.push_str(&format!(",\"addr\":{addr}"));
+    }
+    if let Some(lock) = detail.lock {
+        line.push_str(&format!(",\"lock\":{lock}"));
+    }
+    if let Some(ok) = detail.ok {
+        line.push_str(&format!(",\"ok\":{ok}"));
+    }
+    if let Some(msg) = detail.msg {
+        line.push_str(&format!(",\"msg\":{msg}"));
+    }
+    if let Some(refs) = detail.refs {
+        line.push_str(&format!(",\"refs\":{refs}"));
+    }
+    if let Some(size) = detail.size {
+        line.push_str(&format!(",\"size\":{size}"));
+    }
+    if let Some(old_addr) = detail.old_addr {
+        line.push_str(&format!(",\"old_addr\":{old_addr}"));
+    }
+    if let Some(name) = detail.name {
+        line.push_str(&format!(",\"name\":{}", json_string(name)));
+    }
+    if let Some(function) = detail.function {
+        line.push_str(&format!(",\"function\":{}", json_string(function)));
+    }
+    if let Some(child) = detail.child {
+        line.push_str(&format!(",\"child\":{child}"));
+    }
+    if !stack.is_empty() {
+        let frames: Vec<String> = stack.iter().map(|frame| json_string(frame)).collect();
+        line.push_str(&format!(",\"stack\":[{}]", frames.join(",")));
+    }
+    line.push_str("}\n");
+    let mut writer = writer.lock().unwrap();
+    let _ = writer.write_all(line.as_bytes());
+}
+
+pub fn flush() {
+    if let Some(writer) = TRACE_WRITER.as_ref() {
+        let _ = writer.lock().unwrap().flush();
+    }
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn json_string_or_null(s: Option<&str>) -> String {
+    match s {
+        Some(s) => json_string(s),
+        None => "null".to_owned(),
+    }
+}
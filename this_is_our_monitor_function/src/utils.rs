@@ -10,6 +10,12 @@ pub struct ThreadInfo {
 #[derive(Debug)]
 pub struct ThreadName (Option<String>);
 
+impl ThreadName {
+    pub fn as_deref(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
 impl std::fmt::Display for ThreadName {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         match &self.0 {
@@ -31,3 +37,20 @@ pub fn get_timestamp_nanos() -> u128 {
     let duruation = now.duration_since(SystemTime::UNIX_EPOCH).unwrap();
     duruation.as_nanos()
 }
+
+/// splitmix64, good enough for random scheduling decisions and reproducible from the seed
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}